@@ -0,0 +1,147 @@
+//! A dump of the spans that are still open, to see what Rust is doing when
+//! something hangs.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, PoisonError},
+    time::Instant,
+};
+
+use pyo3::{
+    exceptions::PyLookupError,
+    prelude::*,
+    types::{PyDict, PyList},
+};
+use tracing_core::{dispatcher, span};
+use tracing_subscriber::{registry::LookupSpan, Registry};
+
+use crate::{PythonCallbackLayerBridge, PythonLayerState};
+
+/// When each open span was created, by registry id.
+#[derive(Default)]
+pub(crate) struct ActiveSpans(Mutex<BTreeMap<u64, Instant>>);
+
+impl ActiveSpans {
+    pub(crate) fn opened(&self, span_id: &span::Id, now: Instant) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(span_id.into_u64(), now);
+    }
+
+    pub(crate) fn closed(&self, span_id: &span::Id) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&span_id.into_u64());
+    }
+
+    /// Returns the open spans' ids and creation times, oldest first.
+    fn snapshot(&self) -> Vec<(u64, Instant)> {
+        let mut spans = self
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(id, opened)| (*id, *opened))
+            .collect::<Vec<_>>();
+        spans.sort_by_key(|(_, opened)| *opened);
+        spans
+    }
+}
+
+/// Returns the spans of the current subscriber that are still open as a tree,
+/// or `None` if it has no [`PythonCallbackLayerBridge`].
+///
+/// The tree is a `list` of the root spans, as `dict`s with their `id`, `name`,
+/// `target`, `level`, `age` in seconds, the `repr()` of the `state` the Python
+/// layer returned for them (or `None`), and a `list` of their open
+/// `children`, oldest first.
+#[pyfunction]
+pub fn dump_active_spans(py: Python<'_>) -> PyResult<Option<Bound<'_, PyList>>> {
+    dispatcher::get_default(|dispatch| {
+        let Some(bridge) = dispatch.downcast_ref::<PythonCallbackLayerBridge>() else {
+            return Ok(None);
+        };
+        let registry = dispatch
+            .downcast_ref::<Registry>()
+            .ok_or_else(|| PyLookupError::new_err("the current subscriber has no span registry"))?;
+
+        let now = bridge.clock.instant();
+        let mut nodes = Vec::new();
+        let mut children = BTreeMap::new();
+        for (id, opened) in bridge.active_spans.snapshot() {
+            let Some(span) = registry.span(&span::Id::from_u64(id)) else {
+                continue;
+            };
+            let node = PyDict::new_bound(py);
+            node.set_item("id", id)?;
+            node.set_item("name", span.name())?;
+            node.set_item("target", span.metadata().target())?;
+            node.set_item("level", span.metadata().level().as_str())?;
+            node.set_item("age", now.saturating_duration_since(opened).as_secs_f64())?;
+            let state = span
+                .extensions()
+                .get::<PythonLayerState>()
+                .map(|state| state.clone_ref(py));
+            let state = state
+                .map(|state| state.bind(py).repr().map(|repr| repr.to_string()))
+                .transpose()?;
+            node.set_item("state", state)?;
+            let node_children = PyList::empty_bound(py);
+            node.set_item("children", &node_children)?;
+            children.insert(id, node_children);
+            nodes.push((node, span.parent().map(|parent| parent.id().into_u64())));
+        }
+
+        let roots = PyList::empty_bound(py);
+        for (node, parent) in nodes {
+            match parent.and_then(|parent| children.get(&parent)) {
+                Some(parent_children) => parent_children.append(node)?,
+                None => roots.append(node)?,
+            }
+        }
+        Ok(Some(roots))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::info_span;
+
+    use super::*;
+    use crate::tests::initialize_tracing;
+
+    fn get<'py>(node: &Bound<'py, PyAny>, key: &str) -> Bound<'py, PyAny> {
+        node.get_item(key).unwrap()
+    }
+
+    #[test]
+    fn test_dump_active_spans() {
+        let (_py_layer, _dispatcher) = initialize_tracing();
+
+        let parent = info_span!("parent");
+        drop(info_span!("closed"));
+        parent.in_scope(|| {
+            let _child = info_span!("child");
+            Python::with_gil(|py| {
+                let roots = dump_active_spans(py).unwrap().unwrap();
+                assert_eq!(1, roots.len());
+                let root = roots.get_item(0).unwrap();
+                assert_eq!("parent", get(&root, "name").extract::<String>().unwrap());
+                assert_eq!("0", get(&root, "state").extract::<String>().unwrap());
+                assert!(get(&root, "age").extract::<f64>().unwrap() >= 0.0);
+                let children = get(&root, "children");
+                assert_eq!(1, children.len().unwrap());
+                let child = children.get_item(0).unwrap();
+                assert_eq!("child", get(&child, "name").extract::<String>().unwrap());
+                assert_eq!("2", get(&child, "state").extract::<String>().unwrap());
+            });
+        });
+        drop(parent);
+
+        Python::with_gil(|py| {
+            assert_eq!(0, dump_active_spans(py).unwrap().unwrap().len());
+        });
+    }
+}
@@ -0,0 +1,344 @@
+//! Mirrors `tracing` spans as Datadog spans through the `ddtrace` Python
+//! package.
+
+use pyo3::{
+    prelude::*,
+    sync::GILOnceCell,
+    types::{PyDict, PyModule},
+};
+use tracing_core::{span, Event, Level, Subscriber};
+use tracing_subscriber::{
+    layer::{Context, Layer},
+    registry::LookupSpan,
+};
+
+use crate::{
+    fields::{FieldCollector, PyFieldValue},
+    log_bridge,
+};
+
+/// The field whose value, if recorded, is used as a span's Datadog resource.
+const RESOURCE_FIELD: &str = "resource";
+
+/// The Datadog span started for a `tracing` span, stored in the span's
+/// extensions.
+struct DatadogSpan(Py<PyAny>);
+
+/// A [`Layer`] that mirrors `tracing` spans as Datadog spans using the global
+/// `ddtrace.tracer`, so they show up in the traces of a Datadog-instrumented
+/// Python service.
+///
+/// - New spans start Datadog spans named after the `tracing` span, as children
+///   of the Datadog span of the `tracing` parent or of the tracer's current
+///   span for root spans. The resource is the span's `resource` field if it
+///   has one, and its name otherwise; the service is inherited from the parent
+///   unless set with [`PythonDatadogLayer::with_service`].
+/// - Span fields and recorded values are set as tags.
+/// - `ERROR` events mark the Datadog span they occur in (or the tracer's
+///   current span outside any `tracing` span) as an error, with the event's
+///   message as `error.message` and its target as `error.type`. Other events
+///   are ignored.
+/// - Closing a span finishes its Datadog span.
+pub struct PythonDatadogLayer {
+    service: Option<String>,
+    tracer: GILOnceCell<Py<PyAny>>,
+}
+
+impl PythonDatadogLayer {
+    pub fn new() -> PythonDatadogLayer {
+        PythonDatadogLayer {
+            service: None,
+            tracer: GILOnceCell::new(),
+        }
+    }
+
+    /// Sets the Datadog service of the spans this layer starts.
+    pub fn with_service(mut self, service: impl Into<String>) -> PythonDatadogLayer {
+        self.service = Some(service.into());
+        self
+    }
+
+    fn tracer<'py>(&'py self, py: Python<'py>) -> PyResult<&'py Bound<'py, PyAny>> {
+        self.tracer
+            .get_or_try_init(py, || {
+                let tracer = PyModule::import_bound(py, "ddtrace")?.getattr("tracer")?;
+                Ok::<_, PyErr>(tracer.unbind())
+            })
+            .map(|tracer| tracer.bind(py))
+    }
+
+    fn start_span(
+        &self,
+        py: Python<'_>,
+        attrs: &span::Attributes<'_>,
+        parent: Option<Py<PyAny>>,
+    ) -> PyResult<Py<PyAny>> {
+        let mut fields = FieldCollector::default();
+        attrs.record(&mut fields);
+        let name = attrs.metadata().name();
+        let tracer = self.tracer(py)?;
+
+        let kwargs = PyDict::new_bound(py);
+        let child_of = match parent {
+            Some(parent) => parent.into_bound(py),
+            None => tracer.call_method0("current_span")?,
+        };
+        kwargs.set_item("child_of", child_of)?;
+        kwargs.set_item("service", &self.service)?;
+        let resource = fields.fields.iter().find_map(|(field, value)| match value {
+            PyFieldValue::Str(resource) if *field == RESOURCE_FIELD => Some(resource.as_str()),
+            _ => None,
+        });
+        kwargs.set_item("resource", resource.unwrap_or(name))?;
+
+        let span = tracer.call_method("start_span", (name,), Some(&kwargs))?;
+        set_tags(py, &span, &fields)?;
+        Ok(span.unbind())
+    }
+
+    fn flag_error(
+        &self,
+        py: Python<'_>,
+        event: &Event<'_>,
+        datadog_span: Option<&Py<PyAny>>,
+    ) -> PyResult<()> {
+        let datadog_span = match datadog_span {
+            Some(datadog_span) => datadog_span.bind(py).clone(),
+            None => self.tracer(py)?.call_method0("current_span")?,
+        };
+        if datadog_span.is_none() {
+            return Ok(());
+        }
+
+        let mut fields = FieldCollector::default();
+        event.record(&mut fields);
+        let normalized_metadata = log_bridge::normalized_metadata(event);
+        let metadata = normalized_metadata
+            .as_ref()
+            .unwrap_or_else(|| event.metadata());
+        let message = fields
+            .take_message()
+            .unwrap_or_else(|| metadata.name().to_owned());
+
+        datadog_span.setattr("error", 1)?;
+        datadog_span.call_method1("set_tag", ("error.message", message))?;
+        datadog_span.call_method1("set_tag", ("error.type", metadata.target()))?;
+        Ok(())
+    }
+}
+
+impl Default for PythonDatadogLayer {
+    fn default() -> PythonDatadogLayer {
+        PythonDatadogLayer::new()
+    }
+}
+
+fn set_tags(py: Python<'_>, span: &Bound<'_, PyAny>, fields: &FieldCollector) -> PyResult<()> {
+    for (name, value) in &fields.fields {
+        if *name == RESOURCE_FIELD {
+            span.setattr("resource", value.to_object(py))?;
+        }
+        span.call_method1("set_tag", (*name, value.to_object(py)))?;
+    }
+    Ok(())
+}
+
+impl<S> Layer<S> for PythonDatadogLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, span_id: &span::Id, ctx: Context<'_, S>) {
+        let Some(current_span) = ctx.span(span_id) else {
+            return;
+        };
+
+        let parent = current_span.parent();
+        let parent_extensions = parent.as_ref().map(|parent| parent.extensions());
+
+        Python::with_gil(|py| {
+            let parent = parent_extensions
+                .as_ref()
+                .and_then(|ext| ext.get::<DatadogSpan>())
+                .map(|datadog_span| datadog_span.0.clone_ref(py));
+            let Ok(datadog_span) = self.start_span(py, attrs, parent) else {
+                return;
+            };
+
+            current_span
+                .extensions_mut()
+                .insert(DatadogSpan(datadog_span));
+        })
+    }
+
+    fn on_record(&self, span_id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let Some(current_span) = ctx.span(span_id) else {
+            return;
+        };
+
+        let mut fields = FieldCollector::default();
+        values.record(&mut fields);
+        let extensions = current_span.extensions();
+        let Some(datadog_span) = extensions.get::<DatadogSpan>() else {
+            return;
+        };
+
+        Python::with_gil(|py| {
+            let _ = set_tags(py, datadog_span.0.bind(py), &fields);
+        })
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if *event.metadata().level() != Level::ERROR {
+            return;
+        }
+
+        let current_span = event
+            .parent()
+            .and_then(|id| ctx.span(id))
+            .or_else(|| ctx.lookup_current());
+        let extensions = current_span.as_ref().map(|span| span.extensions());
+
+        Python::with_gil(|py| {
+            let datadog_span = extensions
+                .as_ref()
+                .and_then(|ext| ext.get::<DatadogSpan>())
+                .map(|datadog_span| &datadog_span.0);
+            let _ = self.flag_error(py, event, datadog_span);
+        })
+    }
+
+    fn on_close(&self, span_id: span::Id, ctx: Context<'_, S>) {
+        let Some(current_span) = ctx.span(&span_id) else {
+            return;
+        };
+        let Some(datadog_span) = current_span.extensions_mut().remove::<DatadogSpan>() else {
+            return;
+        };
+
+        Python::with_gil(|py| {
+            let _ = datadog_span.0.bind(py).call_method0("finish");
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{error, info_span};
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::tests::prepare_python;
+
+    /// A minimal stand-in for the parts of `ddtrace.tracer` the layer uses,
+    /// recording the spans it starts.
+    const FAKE_DDTRACE: &str = "
+spans = []
+
+class Span:
+    def __init__(self, name, child_of, service, resource):
+        self.name = name
+        self.parent = child_of
+        self.service = service
+        self.resource = resource
+        self.tags = {}
+        self.error = 0
+        self.finished = False
+        spans.append(self)
+
+    def set_tag(self, key, value):
+        self.tags[key] = value
+
+    def finish(self):
+        self.finished = True
+
+class Tracer:
+    current = None
+
+    def current_span(self):
+        return self.current
+
+    def start_span(self, name, child_of=None, service=None, resource=None):
+        return Span(name, child_of, service, resource)
+
+tracer = Tracer()
+";
+
+    fn get<'py>(span: &Bound<'py, PyAny>, attr: &str) -> Bound<'py, PyAny> {
+        span.getattr(attr).unwrap()
+    }
+
+    #[test]
+    fn test_mirrors_spans() {
+        prepare_python();
+        let _dispatcher = tracing_subscriber::registry()
+            .with(PythonDatadogLayer::new().with_service("rust-core"))
+            .set_default();
+
+        Python::with_gil(|py| {
+            let ddtrace =
+                PyModule::from_code_bound(py, FAKE_DDTRACE, "ddtrace.py", "ddtrace").unwrap();
+            let sys_modules = py.import_bound("sys").unwrap().getattr("modules").unwrap();
+            sys_modules.set_item("ddtrace", &ddtrace).unwrap();
+            let request = ddtrace
+                .getattr("Span")
+                .unwrap()
+                .call1(("web.request", py.None(), "web", "GET /"))
+                .unwrap();
+            ddtrace
+                .getattr("tracer")
+                .unwrap()
+                .setattr("current", &request)
+                .unwrap();
+
+            info_span!(
+                "query",
+                resource = "SELECT users",
+                rows = tracing::field::Empty
+            )
+            .in_scope(|| {
+                tracing::Span::current().record("rows", 3);
+                info_span!("decode").in_scope(|| error!("bad row"));
+            });
+
+            let spans = get(&ddtrace, "spans")
+                .extract::<Vec<Bound<'_, PyAny>>>()
+                .unwrap();
+            let (query, decode) = (&spans[1], &spans[2]);
+            assert_eq!("query", get(query, "name").extract::<String>().unwrap());
+            assert_eq!(
+                "SELECT users",
+                get(query, "resource").extract::<String>().unwrap()
+            );
+            assert_eq!(
+                "rust-core",
+                get(query, "service").extract::<String>().unwrap()
+            );
+            assert!(get(query, "parent").is(&request));
+            assert_eq!(
+                3,
+                get(query, "tags")
+                    .get_item("rows")
+                    .unwrap()
+                    .extract::<i64>()
+                    .unwrap()
+            );
+            assert!(get(query, "finished").is_truthy().unwrap());
+
+            assert_eq!(
+                "decode",
+                get(decode, "resource").extract::<String>().unwrap()
+            );
+            assert!(get(decode, "parent").is(query));
+            assert_eq!(1, get(decode, "error").extract::<i64>().unwrap());
+            assert_eq!(
+                "bad row",
+                get(decode, "tags")
+                    .get_item("error.message")
+                    .unwrap()
+                    .extract::<String>()
+                    .unwrap()
+            );
+            assert_eq!(0, get(query, "error").extract::<i64>().unwrap());
+        });
+    }
+}
@@ -0,0 +1,379 @@
+//! Forwards `tracing` events to Python's standard `logging` module.
+
+use pyo3::{
+    prelude::*,
+    sync::GILOnceCell,
+    types::{PyDict, PyModule},
+};
+use tracing_core::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+use crate::{exception::ExceptionInfo, fields::FieldCollector, log_bridge};
+
+/// Attributes of `logging.LogRecord` that can't be overridden through `extra`.
+const RESERVED_ATTRS: &[&str] = &[
+    "args",
+    "asctime",
+    "created",
+    "exc_info",
+    "exc_text",
+    "filename",
+    "funcName",
+    "levelname",
+    "levelno",
+    "lineno",
+    "message",
+    "module",
+    "msecs",
+    "msg",
+    "name",
+    "pathname",
+    "process",
+    "processName",
+    "relativeCreated",
+    "stack_info",
+    "taskName",
+    "thread",
+    "threadName",
+];
+
+static GET_LOGGER: GILOnceCell<Py<PyAny>> = GILOnceCell::new();
+
+/// A [`Layer`] that converts `tracing` events into `logging.LogRecord`s and
+/// dispatches them through Python's `logging` module, so Rust logs show up in
+/// an application's existing logging configuration.
+///
+/// - The event's target is used as the logger name, with `::` replaced by `.`
+///   so that e.g. `my_crate::db` logs to a child of the `my_crate` logger.
+///   Targets can be mapped to other logger names with
+///   [`PythonLoggingLayer::with_logger_name`].
+/// - `tracing` levels map to `logging` levels as `ERROR` → `ERROR`, `WARN` →
+///   `WARNING`, `INFO` → `INFO`, `DEBUG` → `DEBUG` and `TRACE` → `5` by
+///   default. See [`PythonLoggingLayer::with_level`].
+/// - The event's fields other than `message` are passed as `extra`, so they are
+///   available as attributes on the record. Fields whose names clash with
+///   `LogRecord` attributes are prefixed with `field_`.
+/// - Events with an `error` field or OpenTelemetry's `exception.type` and
+///   `exception.message` fields are logged with an `exc_info` synthesized from
+///   them, so handlers format them like Python exceptions, with the chain of
+///   the error's sources as `__cause__`s.
+///
+/// Events are only converted if the logger is enabled for their level.
+pub struct PythonLoggingLayer {
+    /// The `logging` level for each `tracing` level, indexed by
+    /// [`level_index`].
+    levels: [i32; 5],
+    /// `(target prefix, logger name)` rules, longest prefix first.
+    logger_names: Vec<(String, String)>,
+    name_unknown_levels: bool,
+    /// Set once the level names are registered. Unlike a `Once`, this doesn't
+    /// block other threads on Python code run while holding the GIL.
+    named_levels: GILOnceCell<()>,
+}
+
+const LEVELS: [Level; 5] = [
+    Level::ERROR,
+    Level::WARN,
+    Level::INFO,
+    Level::DEBUG,
+    Level::TRACE,
+];
+
+fn level_index(level: &Level) -> usize {
+    match *level {
+        Level::ERROR => 0,
+        Level::WARN => 1,
+        Level::INFO => 2,
+        Level::DEBUG => 3,
+        Level::TRACE => 4,
+    }
+}
+
+impl Default for PythonLoggingLayer {
+    fn default() -> PythonLoggingLayer {
+        PythonLoggingLayer::new()
+    }
+}
+
+impl PythonLoggingLayer {
+    pub fn new() -> PythonLoggingLayer {
+        PythonLoggingLayer {
+            levels: [40, 30, 20, 10, 5],
+            logger_names: Vec::new(),
+            name_unknown_levels: true,
+            named_levels: GILOnceCell::new(),
+        }
+    }
+
+    /// Sets the `logging` level that events at the `tracing` level `level` are
+    /// logged at, e.g. to use an organization's custom `logging` levels.
+    pub fn with_level(mut self, level: Level, logging_level: i32) -> PythonLoggingLayer {
+        self.levels[level_index(&level)] = logging_level;
+        self
+    }
+
+    /// Logs events whose target is `target_prefix`, or a module inside it, to
+    /// `logger_name` or a child of it.
+    ///
+    /// For example, with `with_logger_name("my_crate::db", "myapp.rustdb")`
+    /// events from `my_crate::db::pool` are logged to `myapp.rustdb.pool`. When
+    /// several prefixes match, the longest one is used.
+    pub fn with_logger_name(
+        mut self,
+        target_prefix: impl Into<String>,
+        logger_name: impl Into<String>,
+    ) -> PythonLoggingLayer {
+        self.logger_names
+            .push((target_prefix.into(), logger_name.into()));
+        self.logger_names
+            .sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        self
+    }
+
+    /// Sets whether `logging` levels that `tracing` levels are mapped to, but
+    /// which `logging` has no name for (like the default `5` for `TRACE`), are
+    /// registered with `logging.addLevelName` using the `tracing` level's name.
+    ///
+    /// Enabled by default. When disabled, records at those levels are shown by
+    /// `logging` as e.g. `Level 5`.
+    pub fn with_unknown_levels_named(mut self, enabled: bool) -> PythonLoggingLayer {
+        self.name_unknown_levels = enabled;
+        self
+    }
+
+    fn level(&self, level: &Level) -> i32 {
+        self.levels[level_index(level)]
+    }
+
+    /// Registers names for the `logging` levels in use that don't have one.
+    fn name_unknown_levels(&self, logging: &Bound<'_, PyModule>) -> PyResult<()> {
+        for level in &LEVELS {
+            let logging_level = self.level(level);
+            let name = logging.call_method1("getLevelName", (logging_level,))?;
+            if name
+                .extract::<String>()
+                .is_ok_and(|name| name.starts_with("Level "))
+            {
+                logging.call_method1("addLevelName", (logging_level, level.as_str()))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn logger_name(&self, target: &str) -> String {
+        for (prefix, logger_name) in &self.logger_names {
+            let Some(rest) = target.strip_prefix(prefix.as_str()) else {
+                continue;
+            };
+            if rest.is_empty() {
+                return logger_name.clone();
+            }
+            if let Some(rest) = rest.strip_prefix("::") {
+                return format!("{logger_name}.{}", rest.replace("::", "."));
+            }
+        }
+        target.replace("::", ".")
+    }
+
+    fn log(&self, py: Python<'_>, event: &Event<'_>) -> PyResult<()> {
+        let normalized_metadata = log_bridge::normalized_metadata(event);
+        let metadata = normalized_metadata
+            .as_ref()
+            .unwrap_or_else(|| event.metadata());
+        let level = self.level(metadata.level());
+        let name = self.logger_name(metadata.target());
+
+        if self.name_unknown_levels {
+            self.named_levels.get_or_init(py, || {
+                if let Ok(logging) = py.import_bound("logging") {
+                    let _ = self.name_unknown_levels(&logging);
+                }
+            });
+        }
+
+        let get_logger = GET_LOGGER.get_or_try_init(py, || {
+            Ok::<_, PyErr>(py.import_bound("logging")?.getattr("getLogger")?.unbind())
+        })?;
+        let logger = get_logger.bind(py).call1((&name,))?;
+        if !logger.call_method1("isEnabledFor", (level,))?.is_truthy()? {
+            return Ok(());
+        }
+
+        let mut fields = FieldCollector::default();
+        event.record(&mut fields);
+        let message = fields.take_message().unwrap_or_default();
+        let exc_info = match ExceptionInfo::from_fields(&fields) {
+            Some(info) => info.to_exc_info(py)?.into_any(),
+            None => py.None().into_bound(py),
+        };
+
+        let extra = PyDict::new_bound(py);
+        for (field, value) in &fields.fields {
+            if RESERVED_ATTRS.contains(field) {
+                extra.set_item(format!("field_{field}"), value)?;
+            } else {
+                extra.set_item(field, value)?;
+            }
+        }
+
+        let record = logger.call_method1(
+            "makeRecord",
+            (
+                &name,
+                level,
+                metadata.file().unwrap_or("(unknown file)"),
+                metadata.line().unwrap_or(0),
+                message,
+                (),
+                exc_info,
+                py.None(),
+                extra,
+            ),
+        )?;
+        logger.call_method1("handle", (record,))?;
+        Ok(())
+    }
+}
+
+impl<S> Layer<S> for PythonLoggingLayer
+where
+    S: Subscriber,
+{
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        Python::with_gil(|py| {
+            let _ = self.log(py, event);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::types::PyModule;
+    use tracing::{debug, error, info, trace};
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::tests::prepare_python;
+
+    const CAPTURE_HANDLER: &str = "
+import logging
+
+class CaptureHandler(logging.Handler):
+    def __init__(self):
+        super().__init__()
+        self.records = []
+
+    def emit(self, record):
+        self.records.append(record)
+";
+
+    #[test]
+    fn test_forwards_to_logging() {
+        prepare_python();
+        let _dispatcher = tracing_subscriber::registry()
+            .with(PythonLoggingLayer::new())
+            .set_default();
+
+        let handler = Python::with_gil(|py| {
+            let module =
+                PyModule::from_code_bound(py, CAPTURE_HANDLER, "capture.py", "capture").unwrap();
+            let handler = module.getattr("CaptureHandler").unwrap().call0().unwrap();
+            let logger = py
+                .import_bound("logging")
+                .unwrap()
+                .call_method1("getLogger", (module_path!().replace("::", "."),))
+                .unwrap();
+            logger.call_method1("setLevel", (10,)).unwrap();
+            logger.call_method1("addHandler", (&handler,)).unwrap();
+            handler.unbind()
+        });
+
+        info!(user = "alice", attempts = 3, name = "clash", "logged in");
+        error!(error = "connection reset", "failed");
+        debug!(target: "pyo3_python_tracing_subscriber::elsewhere", "other logger");
+        trace!("below the logger's level");
+
+        Python::with_gil(|py| {
+            let records = handler.getattr(py, "records").unwrap();
+            let records = records.bind(py);
+            assert_eq!(2, records.len().unwrap());
+
+            let get =
+                |index: usize, attr: &str| records.get_item(index).unwrap().getattr(attr).unwrap();
+            assert_eq!(
+                "logged in",
+                get(0, "getMessage")
+                    .call0()
+                    .unwrap()
+                    .extract::<String>()
+                    .unwrap()
+            );
+            assert_eq!(20, get(0, "levelno").extract::<u8>().unwrap());
+            assert_eq!(
+                module_path!().replace("::", "."),
+                get(0, "name").extract::<String>().unwrap()
+            );
+            assert_eq!("alice", get(0, "user").extract::<String>().unwrap());
+            assert_eq!(3, get(0, "attempts").extract::<i64>().unwrap());
+            assert_eq!("clash", get(0, "field_name").extract::<String>().unwrap());
+            assert_eq!(40, get(1, "levelno").extract::<u8>().unwrap());
+            let exc_info = get(1, "exc_info");
+            assert_eq!(
+                "connection reset",
+                exc_info.get_item(1).unwrap().str().unwrap().to_string()
+            );
+            assert!(get(0, "exc_info").is_none());
+        });
+    }
+
+    #[test]
+    fn test_custom_level_mapping() {
+        prepare_python();
+        let layer = PythonLoggingLayer::new()
+            .with_level(Level::TRACE, 7)
+            .with_level(Level::INFO, 25);
+        let _dispatcher = tracing_subscriber::registry().with(layer).set_default();
+
+        let target = "pyo3_python_tracing_subscriber::custom_levels";
+        let handler = Python::with_gil(|py| {
+            let module =
+                PyModule::from_code_bound(py, CAPTURE_HANDLER, "capture.py", "capture").unwrap();
+            let handler = module.getattr("CaptureHandler").unwrap().call0().unwrap();
+            let logger = py
+                .import_bound("logging")
+                .unwrap()
+                .call_method1("getLogger", (target.replace("::", "."),))
+                .unwrap();
+            logger.call_method1("setLevel", (1,)).unwrap();
+            logger.call_method1("addHandler", (&handler,)).unwrap();
+            handler.unbind()
+        });
+
+        trace!(target: "pyo3_python_tracing_subscriber::custom_levels", "very detailed");
+        info!(target: "pyo3_python_tracing_subscriber::custom_levels", "notice");
+
+        Python::with_gil(|py| {
+            let records = handler.getattr(py, "records").unwrap();
+            let records = records.bind(py);
+            let get =
+                |index: usize, attr: &str| records.get_item(index).unwrap().getattr(attr).unwrap();
+            assert_eq!(7, get(0, "levelno").extract::<i32>().unwrap());
+            assert_eq!("TRACE", get(0, "levelname").extract::<String>().unwrap());
+            assert_eq!(25, get(1, "levelno").extract::<i32>().unwrap());
+            assert_eq!("INFO", get(1, "levelname").extract::<String>().unwrap());
+        });
+    }
+
+    #[test]
+    fn test_logger_name_rules() {
+        let layer = PythonLoggingLayer::new()
+            .with_logger_name("my_crate", "myapp.rust")
+            .with_logger_name("my_crate::db", "myapp.rustdb");
+
+        assert_eq!("myapp.rustdb", layer.logger_name("my_crate::db"));
+        assert_eq!("myapp.rustdb.pool", layer.logger_name("my_crate::db::pool"));
+        assert_eq!("myapp.rust.dbx", layer.logger_name("my_crate::dbx"));
+        assert_eq!("myapp.rust", layer.logger_name("my_crate"));
+        assert_eq!("my_crate_ext.io", layer.logger_name("my_crate_ext::io"));
+    }
+}
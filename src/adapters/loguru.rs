@@ -0,0 +1,218 @@
+//! Forwards `tracing` events to loguru.
+
+use pyo3::{
+    prelude::*,
+    sync::GILOnceCell,
+    types::{IntoPyDict, PyCFunction, PyDict, PyTuple},
+};
+use tracing_core::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+use crate::{exception::ExceptionInfo, fields::FieldCollector, log_bridge};
+
+/// A [`Layer`] that logs `tracing` events with loguru's `logger`, so Rust logs
+/// reach the sinks, formats and filters a loguru application configured.
+///
+/// - `tracing` levels map to loguru's `TRACE`, `DEBUG`, `INFO`, `WARNING` and
+///   `ERROR` levels by default. See [`PythonLoguruLayer::with_level`].
+/// - The event's fields other than `message` are bound as `extra`, like with
+///   `logger.bind(**fields)`.
+/// - The record's `name` is the event's target with `::` replaced by `.`, and
+///   its `line` the event's line, so loguru filters on module names like
+///   `{"my_crate.db": "DEBUG"}` apply to Rust targets.
+/// - Events with an `error` field or OpenTelemetry's `exception.type` and
+///   `exception.message` fields are logged with an exception synthesized from
+///   them, like with `logger.opt(exception=...)`, so sinks format them like
+///   Python exceptions.
+pub struct PythonLoguruLayer {
+    /// The loguru level name for each `tracing` level, indexed by
+    /// [`level_index`].
+    levels: [String; 5],
+    logger: GILOnceCell<Py<PyAny>>,
+}
+
+fn level_index(level: &Level) -> usize {
+    match *level {
+        Level::ERROR => 0,
+        Level::WARN => 1,
+        Level::INFO => 2,
+        Level::DEBUG => 3,
+        Level::TRACE => 4,
+    }
+}
+
+impl Default for PythonLoguruLayer {
+    fn default() -> PythonLoguruLayer {
+        PythonLoguruLayer::new()
+    }
+}
+
+impl PythonLoguruLayer {
+    pub fn new() -> PythonLoguruLayer {
+        PythonLoguruLayer {
+            levels: ["ERROR", "WARNING", "INFO", "DEBUG", "TRACE"].map(str::to_owned),
+            logger: GILOnceCell::new(),
+        }
+    }
+
+    /// Sets the name of the loguru level that events at the `tracing` level
+    /// `level` are logged at, e.g. a custom level added with `logger.level`.
+    pub fn with_level(
+        mut self,
+        level: Level,
+        loguru_level: impl Into<String>,
+    ) -> PythonLoguruLayer {
+        self.levels[level_index(&level)] = loguru_level.into();
+        self
+    }
+
+    fn logger<'py>(&'py self, py: Python<'py>) -> PyResult<&'py Bound<'py, PyAny>> {
+        self.logger
+            .get_or_try_init(py, || {
+                Ok::<_, PyErr>(py.import_bound("loguru")?.getattr("logger")?.unbind())
+            })
+            .map(|logger| logger.bind(py))
+    }
+
+    fn log(&self, py: Python<'_>, event: &Event<'_>) -> PyResult<()> {
+        let normalized_metadata = log_bridge::normalized_metadata(event);
+        let metadata = normalized_metadata
+            .as_ref()
+            .unwrap_or_else(|| event.metadata());
+        let name = metadata.target().replace("::", ".");
+        let line = metadata.line().unwrap_or(0);
+
+        let mut fields = FieldCollector::default();
+        event.record(&mut fields);
+        let message = fields.take_message().unwrap_or_default();
+        let exception = match ExceptionInfo::from_fields(&fields) {
+            Some(info) => info.to_exc_info(py)?.into_any(),
+            None => py.None().into_bound(py),
+        };
+        let extra = PyDict::new_bound(py);
+        for (field, value) in &fields.fields {
+            extra.set_item(field, value)?;
+        }
+
+        // loguru takes the record's name and line from the calling Python
+        // frame, which isn't the Rust code that emitted the event.
+        let patcher = PyCFunction::new_closure_bound(
+            py,
+            None,
+            None,
+            move |args: &Bound<'_, PyTuple>, _kwargs: Option<&Bound<'_, PyDict>>| {
+                let record = args.get_item(0)?;
+                record.set_item("name", &name)?;
+                record.set_item("line", line)
+            },
+        )?;
+        let options = [("exception", exception)].into_py_dict_bound(py);
+        self.logger(py)?
+            .call_method("opt", (), Some(&options))?
+            .call_method1("patch", (patcher,))?
+            .call_method("bind", (), Some(&extra))?
+            .call_method1(
+                "log",
+                (&self.levels[level_index(metadata.level())], message),
+            )?;
+        Ok(())
+    }
+}
+
+impl<S: Subscriber> Layer<S> for PythonLoguruLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        Python::with_gil(|py| {
+            let _ = self.log(py, event);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{error, info, warn};
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::tests::prepare_python;
+
+    /// A minimal stand-in for loguru's `logger`, applying patchers and bound
+    /// `extra` to a record `dict` and recording it.
+    const FAKE_LOGURU: &str = "
+records = []
+
+class Logger:
+    def __init__(self, exception=None, patchers=(), extra=None):
+        self.exception = exception
+        self.patchers = patchers
+        self.extra = extra or {}
+
+    def opt(self, *, exception=None):
+        return Logger(exception, self.patchers, self.extra)
+
+    def patch(self, patcher):
+        return Logger(self.exception, self.patchers + (patcher,), self.extra)
+
+    def bind(self, **kwargs):
+        return Logger(self.exception, self.patchers, {**self.extra, **kwargs})
+
+    def log(self, level, message):
+        record = {'name': None, 'line': 0, 'level': level, 'message': message}
+        record['extra'] = self.extra
+        record['exception'] = self.exception
+        for patcher in self.patchers:
+            patcher(record)
+        records.append(record)
+
+logger = Logger()
+";
+
+    #[test]
+    fn test_forwards_to_loguru() {
+        prepare_python();
+        let _dispatcher = tracing_subscriber::registry()
+            .with(PythonLoguruLayer::new().with_level(Level::WARN, "NOTICE"))
+            .set_default();
+
+        Python::with_gil(|py| {
+            let loguru = PyModule::from_code_bound(py, FAKE_LOGURU, "loguru.py", "loguru").unwrap();
+            let sys_modules = py.import_bound("sys").unwrap().getattr("modules").unwrap();
+            sys_modules.set_item("loguru", &loguru).unwrap();
+
+            info!(user = "alice", attempts = 3, "logged in");
+            warn!("slow");
+            error!(error = "connection reset", "failed");
+
+            let records = loguru.getattr("records").unwrap();
+            let get =
+                |index: usize, key: &str| records.get_item(index).unwrap().get_item(key).unwrap();
+            assert_eq!("INFO", get(0, "level").extract::<String>().unwrap());
+            assert_eq!("logged in", get(0, "message").extract::<String>().unwrap());
+            assert_eq!(
+                module_path!().replace("::", "."),
+                get(0, "name").extract::<String>().unwrap()
+            );
+            assert!(get(0, "line").extract::<u32>().unwrap() > 0);
+            let extra = get(0, "extra");
+            assert_eq!(
+                "alice",
+                extra.get_item("user").unwrap().extract::<String>().unwrap()
+            );
+            assert_eq!(
+                3,
+                extra
+                    .get_item("attempts")
+                    .unwrap()
+                    .extract::<i64>()
+                    .unwrap()
+            );
+            assert!(get(0, "exception").is_none());
+
+            assert_eq!("NOTICE", get(1, "level").extract::<String>().unwrap());
+
+            assert_eq!("ERROR", get(2, "level").extract::<String>().unwrap());
+            let (_, exception, _): (Bound<'_, PyAny>, Bound<'_, PyAny>, Bound<'_, PyAny>) =
+                get(2, "exception").extract().unwrap();
+            assert_eq!("connection reset", exception.str().unwrap().to_string());
+        });
+    }
+}
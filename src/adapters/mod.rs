@@ -0,0 +1,9 @@
+//! Ready-made layers that forward `tracing` data to popular Python libraries
+//! without any user-written Python layer.
+
+pub mod datadog;
+pub mod logging;
+pub mod loguru;
+pub mod opentelemetry;
+pub mod sentry;
+pub mod structlog;
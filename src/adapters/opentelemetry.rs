@@ -0,0 +1,381 @@
+//! Drives the OpenTelemetry Python API from `tracing` spans and events.
+
+use pyo3::{
+    prelude::*,
+    sync::GILOnceCell,
+    types::{PyDict, PyModule},
+};
+use tracing_core::{span, Event, Level, Metadata, Subscriber};
+use tracing_subscriber::{
+    layer::{Context, Layer},
+    registry::LookupSpan,
+};
+
+use crate::{fields::FieldCollector, log_bridge};
+
+/// The OpenTelemetry span started for a `tracing` span, stored in the span's
+/// extensions.
+struct OtelSpan(Py<PyAny>);
+
+/// A [`Layer`] that mirrors `tracing` spans as OpenTelemetry spans using the
+/// OpenTelemetry Python API (the `opentelemetry-api` package), so they are
+/// exported by whatever `TracerProvider` the Python application installed.
+///
+/// - New spans start an OpenTelemetry span whose parent is the OpenTelemetry
+///   span of the `tracing` parent, or the current Python OpenTelemetry context
+///   for root spans. Span fields become attributes, along with `code.*`
+///   attributes describing the callsite.
+/// - Recorded values are set as attributes.
+/// - Events are added as OpenTelemetry span events named after the event's
+///   message, to the current Python span if they are outside any `tracing`
+///   span. `ERROR` events also set the span's status to error.
+/// - Closing a span ends its OpenTelemetry span.
+pub struct PythonOpenTelemetryLayer {
+    instrumentation_name: String,
+    tracer: GILOnceCell<Py<PyAny>>,
+    trace: GILOnceCell<Py<PyModule>>,
+}
+
+impl PythonOpenTelemetryLayer {
+    /// Creates a layer whose spans are created by the tracer named
+    /// `instrumentation_name`, typically the name of the native extension.
+    pub fn new(instrumentation_name: impl Into<String>) -> PythonOpenTelemetryLayer {
+        PythonOpenTelemetryLayer {
+            instrumentation_name: instrumentation_name.into(),
+            tracer: GILOnceCell::new(),
+            trace: GILOnceCell::new(),
+        }
+    }
+
+    fn trace<'py>(&'py self, py: Python<'py>) -> PyResult<&'py Bound<'py, PyModule>> {
+        self.trace
+            .get_or_try_init(py, || {
+                Ok::<_, PyErr>(PyModule::import_bound(py, "opentelemetry.trace")?.unbind())
+            })
+            .map(|trace| trace.bind(py))
+    }
+
+    fn tracer<'py>(&'py self, py: Python<'py>) -> PyResult<&'py Bound<'py, PyAny>> {
+        self.tracer
+            .get_or_try_init(py, || {
+                let tracer = self
+                    .trace(py)?
+                    .call_method1("get_tracer", (&self.instrumentation_name,))?;
+                Ok::<_, PyErr>(tracer.unbind())
+            })
+            .map(|tracer| tracer.bind(py))
+    }
+
+    fn attributes<'py>(
+        &self,
+        py: Python<'py>,
+        metadata: &Metadata<'_>,
+        fields: &FieldCollector,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let attributes = PyDict::new_bound(py);
+        if let Some(module_path) = metadata.module_path() {
+            attributes.set_item("code.namespace", module_path)?;
+        }
+        if let Some(file) = metadata.file() {
+            attributes.set_item("code.filepath", file)?;
+        }
+        if let Some(line) = metadata.line() {
+            attributes.set_item("code.lineno", line)?;
+        }
+        for (name, value) in &fields.fields {
+            attributes.set_item(name, value)?;
+        }
+        Ok(attributes)
+    }
+
+    fn start_span(
+        &self,
+        py: Python<'_>,
+        attrs: &span::Attributes<'_>,
+        parent: Option<Py<PyAny>>,
+    ) -> PyResult<Py<PyAny>> {
+        let mut fields = FieldCollector::default();
+        attrs.record(&mut fields);
+
+        let kwargs = PyDict::new_bound(py);
+        kwargs.set_item(
+            "attributes",
+            self.attributes(py, attrs.metadata(), &fields)?,
+        )?;
+        if let Some(parent) = parent {
+            let context = self
+                .trace(py)?
+                .call_method1("set_span_in_context", (parent,))?;
+            kwargs.set_item("context", context)?;
+        }
+
+        let span = self.tracer(py)?.call_method(
+            "start_span",
+            (attrs.metadata().name(),),
+            Some(&kwargs),
+        )?;
+        Ok(span.unbind())
+    }
+
+    fn add_event(
+        &self,
+        py: Python<'_>,
+        event: &Event<'_>,
+        otel_span: Option<&Py<PyAny>>,
+    ) -> PyResult<()> {
+        let otel_span = match otel_span {
+            Some(otel_span) => otel_span.bind(py).clone(),
+            None => self.trace(py)?.call_method0("get_current_span")?,
+        };
+
+        let mut fields = FieldCollector::default();
+        event.record(&mut fields);
+        let normalized_metadata = log_bridge::normalized_metadata(event);
+        let metadata = normalized_metadata
+            .as_ref()
+            .unwrap_or_else(|| event.metadata());
+        let message = fields
+            .take_message()
+            .unwrap_or_else(|| metadata.name().to_owned());
+
+        let attributes = self.attributes(py, metadata, &fields)?;
+        attributes.set_item("level", metadata.level().as_str())?;
+        otel_span.call_method1("add_event", (&message, attributes))?;
+
+        if *metadata.level() == Level::ERROR {
+            let trace = self.trace(py)?;
+            let error = trace.getattr("StatusCode")?.getattr("ERROR")?;
+            let status = trace.getattr("Status")?.call1((error, &message))?;
+            otel_span.call_method1("set_status", (status,))?;
+        }
+        Ok(())
+    }
+}
+
+impl<S> Layer<S> for PythonOpenTelemetryLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, span_id: &span::Id, ctx: Context<'_, S>) {
+        let Some(current_span) = ctx.span(span_id) else {
+            return;
+        };
+
+        let parent = current_span.parent();
+        let parent_extensions = parent.as_ref().map(|parent| parent.extensions());
+
+        Python::with_gil(|py| {
+            let parent = parent_extensions
+                .as_ref()
+                .and_then(|ext| ext.get::<OtelSpan>())
+                .map(|otel_span| otel_span.0.clone_ref(py));
+            let Ok(otel_span) = self.start_span(py, attrs, parent) else {
+                return;
+            };
+
+            current_span.extensions_mut().insert(OtelSpan(otel_span));
+        })
+    }
+
+    fn on_record(&self, span_id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let Some(current_span) = ctx.span(span_id) else {
+            return;
+        };
+
+        let mut fields = FieldCollector::default();
+        values.record(&mut fields);
+        let extensions = current_span.extensions();
+        let Some(otel_span) = extensions.get::<OtelSpan>() else {
+            return;
+        };
+
+        Python::with_gil(|py| {
+            for (name, value) in &fields.fields {
+                let _ = otel_span
+                    .0
+                    .bind(py)
+                    .call_method1("set_attribute", (*name, value.to_object(py)));
+            }
+        })
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let current_span = event
+            .parent()
+            .and_then(|id| ctx.span(id))
+            .or_else(|| ctx.lookup_current());
+        let extensions = current_span.as_ref().map(|span| span.extensions());
+
+        Python::with_gil(|py| {
+            let otel_span = extensions
+                .as_ref()
+                .and_then(|ext| ext.get::<OtelSpan>())
+                .map(|otel_span| &otel_span.0);
+            let _ = self.add_event(py, event, otel_span);
+        })
+    }
+
+    fn on_close(&self, span_id: span::Id, ctx: Context<'_, S>) {
+        let Some(current_span) = ctx.span(&span_id) else {
+            return;
+        };
+        let Some(otel_span) = current_span.extensions_mut().remove::<OtelSpan>() else {
+            return;
+        };
+
+        Python::with_gil(|py| {
+            let _ = otel_span.0.bind(py).call_method0("end");
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use tracing::{error, info, info_span};
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::tests::prepare_python;
+
+    /// A minimal stand-in for the parts of `opentelemetry.trace` the layer uses,
+    /// recording what it was asked to do.
+    const FAKE_OTEL_TRACE: &str = "
+calls = []
+
+class StatusCode:
+    ERROR = 'ERROR'
+
+class Status:
+    def __init__(self, code, description):
+        self.code = code
+        self.description = description
+
+class Span:
+    def __init__(self, name, parent, attributes):
+        self.name = name
+        self.parent = parent
+        self.attributes = dict(attributes)
+        self.events = []
+        self.status = None
+        self.ended = False
+        calls.append(self)
+
+    def set_attribute(self, key, value):
+        self.attributes[key] = value
+
+    def add_event(self, name, attributes):
+        self.events.append((name, dict(attributes)))
+
+    def set_status(self, status):
+        self.status = status
+
+    def end(self):
+        self.ended = True
+
+class Tracer:
+    def start_span(self, name, context=None, attributes=None):
+        return Span(name, context, attributes or {})
+
+CURRENT = Span('python-current', None, {})
+
+def get_tracer(name):
+    return Tracer()
+
+def set_span_in_context(span):
+    return span
+
+def get_current_span():
+    return CURRENT
+";
+
+    /// Installs `FAKE_OTEL_TRACE` as `opentelemetry.trace` for this process.
+    pub(crate) fn install_fake_opentelemetry(py: Python<'_>) -> Bound<'_, PyModule> {
+        let sys_modules = py.import_bound("sys").unwrap().getattr("modules").unwrap();
+        if let Ok(trace) = sys_modules.get_item("opentelemetry.trace") {
+            return trace.downcast_into().unwrap();
+        }
+
+        let trace =
+            PyModule::from_code_bound(py, FAKE_OTEL_TRACE, "trace.py", "opentelemetry.trace")
+                .unwrap();
+        let package = PyModule::new_bound(py, "opentelemetry").unwrap();
+        package.setattr("trace", &trace).unwrap();
+        sys_modules.set_item("opentelemetry", package).unwrap();
+        sys_modules.set_item("opentelemetry.trace", &trace).unwrap();
+        trace
+    }
+
+    fn attr<'py>(span: &Bound<'py, PyAny>, key: &str) -> Bound<'py, PyAny> {
+        span.getattr("attributes").unwrap().get_item(key).unwrap()
+    }
+
+    #[test]
+    fn test_mirrors_spans() {
+        prepare_python();
+        let _dispatcher = tracing_subscriber::registry()
+            .with(PythonOpenTelemetryLayer::new("test"))
+            .set_default();
+
+        Python::with_gil(|py| {
+            let trace = install_fake_opentelemetry(py);
+            let calls = trace.getattr("calls").unwrap();
+            let first_call = calls.len().unwrap();
+
+            info_span!("otel_outer", user = tracing::field::Empty).in_scope(|| {
+                tracing::Span::current().record("user", "alice");
+                info_span!("otel_inner", rows = 3).in_scope(|| {
+                    info!(cached = true, "looked up");
+                    error!("failed");
+                });
+            });
+            info!("outside");
+
+            let spans = calls.extract::<Vec<Bound<'_, PyAny>>>().unwrap()[first_call..].to_vec();
+
+            let (outer, inner) = (&spans[0], &spans[1]);
+
+            assert_eq!(
+                "otel_outer",
+                outer.getattr("name").unwrap().extract::<String>().unwrap()
+            );
+            assert!(outer.getattr("parent").unwrap().is_none());
+            assert_eq!("alice", attr(outer, "user").extract::<String>().unwrap());
+            assert!(outer.getattr("ended").unwrap().is_truthy().unwrap());
+
+            assert!(inner.getattr("parent").unwrap().is(outer));
+            assert_eq!(3, attr(inner, "rows").extract::<i64>().unwrap());
+            let events = inner
+                .getattr("events")
+                .unwrap()
+                .extract::<Vec<(String, Bound<'_, PyDict>)>>()
+                .unwrap();
+            assert_eq!("looked up", events[0].0);
+            assert!(events[0]
+                .1
+                .get_item("cached")
+                .unwrap()
+                .unwrap()
+                .is_truthy()
+                .unwrap());
+            assert_eq!("failed", events[1].0);
+            let status = inner.getattr("status").unwrap();
+            assert_eq!(
+                "failed",
+                status
+                    .getattr("description")
+                    .unwrap()
+                    .extract::<String>()
+                    .unwrap()
+            );
+
+            let current_events = trace
+                .getattr("CURRENT")
+                .unwrap()
+                .getattr("events")
+                .unwrap()
+                .extract::<Vec<(String, Bound<'_, PyDict>)>>()
+                .unwrap();
+            assert!(current_events.iter().any(|(name, _)| name == "outside"));
+        });
+    }
+}
@@ -0,0 +1,353 @@
+//! Reports `tracing` data to Sentry through the `sentry_sdk` Python package.
+
+use pyo3::{
+    prelude::*,
+    sync::GILOnceCell,
+    types::{PyDict, PyList, PyModule},
+};
+use tracing_core::{span, Event, Level, Subscriber};
+use tracing_subscriber::{
+    layer::{Context, Layer},
+    registry::LookupSpan,
+};
+
+use crate::{exception::ExceptionInfo, fields::FieldCollector, log_bridge};
+
+/// The Sentry span started for a `tracing` span, stored in the span's
+/// extensions.
+struct SentrySpan(Py<PyAny>);
+
+/// A [`Layer`] that reports `tracing` data to Sentry using the `sentry_sdk`
+/// Python package, so Rust context shows up in the issues of the Python
+/// application that initialized Sentry.
+///
+/// - `ERROR` events are captured as Sentry events. Events with an `error` field
+///   (e.g. `error!(error = %err, "query failed")`) or OpenTelemetry's
+///   `exception.type` and `exception.message` fields are captured as
+///   exceptions, chained to the error's sources if it was recorded as a
+///   `std::error::Error`. Others are captured as messages.
+/// - Other events are added as breadcrumbs, categorized by their target.
+/// - New spans start Sentry performance spans, as children of the Sentry span
+///   of the `tracing` parent or of the current Sentry span for root spans.
+///   Fields and recorded values are set as span data, and closing the `tracing`
+///   span finishes the Sentry span.
+///
+/// In all cases the event's or span's fields are passed along as extra data.
+pub struct PythonSentryLayer {
+    sentry_sdk: GILOnceCell<Py<PyModule>>,
+}
+
+/// The Sentry level name for a `tracing` level.
+fn sentry_level(level: &Level) -> &'static str {
+    match *level {
+        Level::ERROR => "error",
+        Level::WARN => "warning",
+        Level::INFO => "info",
+        _ => "debug",
+    }
+}
+
+impl PythonSentryLayer {
+    pub fn new() -> PythonSentryLayer {
+        PythonSentryLayer {
+            sentry_sdk: GILOnceCell::new(),
+        }
+    }
+
+    fn sentry_sdk<'py>(&'py self, py: Python<'py>) -> PyResult<&'py Bound<'py, PyModule>> {
+        self.sentry_sdk
+            .get_or_try_init(py, || {
+                Ok::<_, PyErr>(PyModule::import_bound(py, "sentry_sdk")?.unbind())
+            })
+            .map(|sentry_sdk| sentry_sdk.bind(py))
+    }
+
+    fn start_span(
+        &self,
+        py: Python<'_>,
+        attrs: &span::Attributes<'_>,
+        parent: Option<Py<PyAny>>,
+    ) -> PyResult<Py<PyAny>> {
+        let metadata = attrs.metadata();
+        let kwargs = PyDict::new_bound(py);
+        kwargs.set_item("op", metadata.name())?;
+        kwargs.set_item("description", metadata.target())?;
+
+        let span = match parent {
+            Some(parent) => parent
+                .bind(py)
+                .call_method("start_child", (), Some(&kwargs))?,
+            None => self
+                .sentry_sdk(py)?
+                .call_method("start_span", (), Some(&kwargs))?,
+        };
+
+        let mut fields = FieldCollector::default();
+        attrs.record(&mut fields);
+        for (name, value) in &fields.fields {
+            span.call_method1("set_data", (*name, value.to_object(py)))?;
+        }
+        Ok(span.unbind())
+    }
+
+    fn report_event(&self, py: Python<'_>, event: &Event<'_>) -> PyResult<()> {
+        let mut fields = FieldCollector::default();
+        event.record(&mut fields);
+        let normalized_metadata = log_bridge::normalized_metadata(event);
+        let metadata = normalized_metadata
+            .as_ref()
+            .unwrap_or_else(|| event.metadata());
+        let message = fields
+            .take_message()
+            .unwrap_or_else(|| metadata.name().to_owned());
+        let level = sentry_level(metadata.level());
+        let data = fields.to_py_dict(py)?;
+        let sentry_sdk = self.sentry_sdk(py)?;
+
+        if *metadata.level() != Level::ERROR {
+            let kwargs = PyDict::new_bound(py);
+            kwargs.set_item("category", metadata.target())?;
+            kwargs.set_item("message", message)?;
+            kwargs.set_item("level", level)?;
+            kwargs.set_item("data", data)?;
+            sentry_sdk.call_method("add_breadcrumb", (), Some(&kwargs))?;
+            return Ok(());
+        }
+
+        let sentry_event = PyDict::new_bound(py);
+        sentry_event.set_item("level", level)?;
+        sentry_event.set_item("logger", metadata.target())?;
+        sentry_event.set_item("message", &message)?;
+        if let Some(info) = ExceptionInfo::from_fields(&fields) {
+            // Sentry lists chained exceptions from the root cause to the one
+            // that was raised last.
+            let values = PyList::empty_bound(py);
+            for message in info.chain.iter().rev() {
+                let exception = PyDict::new_bound(py);
+                exception.set_item("type", "Error")?;
+                exception.set_item("value", message)?;
+                values.append(exception)?;
+            }
+            let exception = PyDict::new_bound(py);
+            exception.set_item("type", &info.exc_type)?;
+            exception.set_item("value", &info.message)?;
+            exception.set_item("module", metadata.target())?;
+            values.append(exception)?;
+            let exceptions = PyDict::new_bound(py);
+            exceptions.set_item("values", values)?;
+            sentry_event.set_item("exception", exceptions)?;
+        }
+        sentry_event.set_item("extra", data)?;
+        sentry_sdk.call_method1("capture_event", (sentry_event,))?;
+        Ok(())
+    }
+}
+
+impl Default for PythonSentryLayer {
+    fn default() -> PythonSentryLayer {
+        PythonSentryLayer::new()
+    }
+}
+
+impl<S> Layer<S> for PythonSentryLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, span_id: &span::Id, ctx: Context<'_, S>) {
+        let Some(current_span) = ctx.span(span_id) else {
+            return;
+        };
+
+        let parent = current_span.parent();
+        let parent_extensions = parent.as_ref().map(|parent| parent.extensions());
+
+        Python::with_gil(|py| {
+            let parent = parent_extensions
+                .as_ref()
+                .and_then(|ext| ext.get::<SentrySpan>())
+                .map(|sentry_span| sentry_span.0.clone_ref(py));
+            let Ok(sentry_span) = self.start_span(py, attrs, parent) else {
+                return;
+            };
+
+            current_span
+                .extensions_mut()
+                .insert(SentrySpan(sentry_span));
+        })
+    }
+
+    fn on_record(&self, span_id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let Some(current_span) = ctx.span(span_id) else {
+            return;
+        };
+
+        let mut fields = FieldCollector::default();
+        values.record(&mut fields);
+        let extensions = current_span.extensions();
+        let Some(sentry_span) = extensions.get::<SentrySpan>() else {
+            return;
+        };
+
+        Python::with_gil(|py| {
+            for (name, value) in &fields.fields {
+                let _ = sentry_span
+                    .0
+                    .bind(py)
+                    .call_method1("set_data", (*name, value.to_object(py)));
+            }
+        })
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        Python::with_gil(|py| {
+            let _ = self.report_event(py, event);
+        })
+    }
+
+    fn on_close(&self, span_id: span::Id, ctx: Context<'_, S>) {
+        let Some(current_span) = ctx.span(&span_id) else {
+            return;
+        };
+        let Some(sentry_span) = current_span.extensions_mut().remove::<SentrySpan>() else {
+            return;
+        };
+
+        Python::with_gil(|py| {
+            let _ = sentry_span.0.bind(py).call_method0("finish");
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{error, info, info_span, warn};
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::tests::prepare_python;
+
+    /// A minimal stand-in for the parts of `sentry_sdk` the layer uses,
+    /// recording what it was asked to do.
+    const FAKE_SENTRY_SDK: &str = "
+breadcrumbs = []
+events = []
+spans = []
+
+class Span:
+    def __init__(self, parent, op, description):
+        self.parent = parent
+        self.op = op
+        self.description = description
+        self.data = {}
+        self.finished = False
+        spans.append(self)
+
+    def start_child(self, op=None, description=None):
+        return Span(self, op, description)
+
+    def set_data(self, key, value):
+        self.data[key] = value
+
+    def finish(self):
+        self.finished = True
+
+def start_span(op=None, description=None):
+    return Span(None, op, description)
+
+def add_breadcrumb(category=None, message=None, level=None, data=None):
+    breadcrumbs.append(dict(category=category, message=message, level=level, data=data))
+
+def capture_event(event):
+    events.append(event)
+";
+
+    fn install_fake_sentry_sdk(py: Python<'_>) -> Bound<'_, PyModule> {
+        let sys_modules = py.import_bound("sys").unwrap().getattr("modules").unwrap();
+        if let Ok(sentry_sdk) = sys_modules.get_item("sentry_sdk") {
+            return sentry_sdk.downcast_into().unwrap();
+        }
+
+        let sentry_sdk =
+            PyModule::from_code_bound(py, FAKE_SENTRY_SDK, "sentry_sdk.py", "sentry_sdk").unwrap();
+        sys_modules.set_item("sentry_sdk", &sentry_sdk).unwrap();
+        sentry_sdk
+    }
+
+    fn item<'py>(dict: &Bound<'py, PyAny>, key: &str) -> Bound<'py, PyAny> {
+        dict.get_item(key).unwrap()
+    }
+
+    #[test]
+    fn test_reports_to_sentry() {
+        prepare_python();
+        let _dispatcher = tracing_subscriber::registry()
+            .with(PythonSentryLayer::new())
+            .set_default();
+
+        Python::with_gil(|py| {
+            let sentry_sdk = install_fake_sentry_sdk(py);
+
+            info_span!("sentry_outer", user = "alice").in_scope(|| {
+                info_span!("sentry_inner").in_scope(|| {
+                    info!(rows = 3, "queried");
+                    warn!("slow");
+                    error!(error = "connection reset", "query failed");
+                    error!("gave up");
+                });
+            });
+
+            let breadcrumbs = sentry_sdk.getattr("breadcrumbs").unwrap();
+            let queried = breadcrumbs.get_item(0).unwrap();
+            assert_eq!(
+                "queried",
+                item(&queried, "message").extract::<String>().unwrap()
+            );
+            assert_eq!("info", item(&queried, "level").extract::<String>().unwrap());
+            assert_eq!(
+                module_path!(),
+                item(&queried, "category").extract::<String>().unwrap()
+            );
+            assert_eq!(
+                3,
+                item(&item(&queried, "data"), "rows")
+                    .extract::<i64>()
+                    .unwrap()
+            );
+            let slow = breadcrumbs.get_item(1).unwrap();
+            assert_eq!("warning", item(&slow, "level").extract::<String>().unwrap());
+
+            let events = sentry_sdk.getattr("events").unwrap();
+            let failed = events.get_item(0).unwrap();
+            assert_eq!("error", item(&failed, "level").extract::<String>().unwrap());
+            let exception = item(&item(&failed, "exception"), "values")
+                .get_item(0)
+                .unwrap();
+            assert_eq!(
+                "connection reset",
+                item(&exception, "value").extract::<String>().unwrap()
+            );
+            let gave_up = events.get_item(1).unwrap();
+            assert_eq!(
+                "gave up",
+                item(&gave_up, "message").extract::<String>().unwrap()
+            );
+            assert!(gave_up.get_item("exception").is_err());
+
+            let spans = sentry_sdk.getattr("spans").unwrap();
+            let (outer, inner) = (spans.get_item(0).unwrap(), spans.get_item(1).unwrap());
+            assert_eq!(
+                "sentry_outer",
+                outer.getattr("op").unwrap().extract::<String>().unwrap()
+            );
+            assert_eq!(
+                "alice",
+                item(&outer.getattr("data").unwrap(), "user")
+                    .extract::<String>()
+                    .unwrap()
+            );
+            assert!(inner.getattr("parent").unwrap().is(&outer));
+            assert!(outer.getattr("finished").unwrap().is_truthy().unwrap());
+            assert!(inner.getattr("finished").unwrap().is_truthy().unwrap());
+        });
+    }
+}
@@ -0,0 +1,136 @@
+//! Forwards `tracing` events to structlog.
+
+use std::time::SystemTime;
+
+use pyo3::{
+    prelude::*,
+    sync::GILOnceCell,
+    types::{PyModule, PyString},
+};
+use tracing_core::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+use crate::payload;
+
+/// A [`Layer`] that turns `tracing` events into structlog `event_dict`s and
+/// logs them with `structlog.get_logger(logger)`, so Rust telemetry runs
+/// through the Python application's structlog processor chain.
+///
+/// The `event_dict`s are those of
+/// [`crate::PayloadMode::StructlogEventDict`]: the event's message is logged as
+/// the `event`, with the `level` name choosing the logger method, and the
+/// `timestamp`, `logger` name and fields passed as keyword arguments.
+pub struct PythonStructlogLayer {
+    structlog: GILOnceCell<Py<PyModule>>,
+}
+
+impl PythonStructlogLayer {
+    pub fn new() -> PythonStructlogLayer {
+        PythonStructlogLayer {
+            structlog: GILOnceCell::new(),
+        }
+    }
+
+    fn structlog<'py>(&'py self, py: Python<'py>) -> PyResult<&'py Bound<'py, PyModule>> {
+        self.structlog
+            .get_or_try_init(py, || {
+                Ok::<_, PyErr>(PyModule::import_bound(py, "structlog")?.unbind())
+            })
+            .map(|structlog| structlog.bind(py))
+    }
+
+    fn log(&self, py: Python<'_>, event: &Event<'_>) -> PyResult<()> {
+        let event_dict = payload::structlog_event_dict(
+            py,
+            event,
+            &payload::EventDictOptions::new(SystemTime::now()),
+        )?;
+        let message = event_dict.as_any().call_method1("pop", ("event",))?;
+        let level = event_dict.as_any().call_method1("pop", ("level",))?;
+        let logger_name = event_dict.get_item("logger")?;
+
+        let logger = self
+            .structlog(py)?
+            .call_method1("get_logger", (logger_name,))?;
+        logger.call_method(
+            &*level.downcast_into::<PyString>()?.to_cow()?,
+            (message,),
+            Some(&event_dict),
+        )?;
+        Ok(())
+    }
+}
+
+impl Default for PythonStructlogLayer {
+    fn default() -> PythonStructlogLayer {
+        PythonStructlogLayer::new()
+    }
+}
+
+impl<S: Subscriber> Layer<S> for PythonStructlogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        Python::with_gil(|py| {
+            let _ = self.log(py, event);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{error, info};
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::tests::prepare_python;
+
+    /// A minimal stand-in for `structlog.get_logger`, recording the calls made
+    /// to each logger.
+    const FAKE_STRUCTLOG: &str = "
+calls = []
+
+class Logger:
+    def __init__(self, name):
+        self.name = name
+
+    def __getattr__(self, method):
+        def log(event, **kw):
+            calls.append((self.name, method, event, kw))
+        return log
+
+def get_logger(name=None):
+    return Logger(name)
+";
+
+    #[test]
+    fn test_forwards_to_structlog() {
+        prepare_python();
+        let _dispatcher = tracing_subscriber::registry()
+            .with(PythonStructlogLayer::new())
+            .set_default();
+
+        Python::with_gil(|py| {
+            let structlog =
+                PyModule::from_code_bound(py, FAKE_STRUCTLOG, "structlog.py", "structlog").unwrap();
+            let sys_modules = py.import_bound("sys").unwrap().getattr("modules").unwrap();
+            sys_modules.set_item("structlog", &structlog).unwrap();
+
+            info!(user = "alice", "logged in");
+            error!("failed");
+
+            let calls = structlog
+                .getattr("calls")
+                .unwrap()
+                .extract::<Vec<(String, String, String, Bound<'_, PyAny>)>>()
+                .unwrap();
+            let logger = module_path!().replace("::", ".");
+            let (name, method, event, kw) = &calls[0];
+            assert_eq!((&logger, "info", "logged in"), (name, &**method, &**event));
+            assert_eq!(
+                "alice",
+                kw.get_item("user").unwrap().extract::<String>().unwrap()
+            );
+            assert!(kw.get_item("timestamp").is_ok());
+            assert_eq!("error", calls[1].1);
+        });
+    }
+}
@@ -0,0 +1,113 @@
+//! Writes logs to rolling files with `tracing-appender`, next to the Python
+//! bridge.
+
+use std::path::PathBuf;
+
+use pyo3::{
+    exceptions::{PyOSError, PyValueError},
+    prelude::*,
+};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_core::Subscriber;
+use tracing_subscriber::{fmt, registry::LookupSpan, Layer};
+
+use crate::{
+    init::{env_config, try_init, TracingGuard},
+    PythonCallbackLayerBridge,
+};
+
+fn parse_rotation(rotation: &str) -> PyResult<Rotation> {
+    match rotation {
+        "minutely" => Ok(Rotation::MINUTELY),
+        "hourly" => Ok(Rotation::HOURLY),
+        "daily" => Ok(Rotation::DAILY),
+        "never" => Ok(Rotation::NEVER),
+        _ => Err(PyValueError::new_err(format!(
+            "unknown rotation {rotation:?}, expected \"minutely\", \"hourly\", \"daily\" or \"never\""
+        ))),
+    }
+}
+
+/// Returns a `fmt` layer writing to files named `prefix` in `directory` from a
+/// background thread, rotated every `rotation`, and the guard keeping the
+/// thread running.
+fn file_layer<S>(
+    directory: PathBuf,
+    prefix: &str,
+    rotation: &str,
+) -> PyResult<(impl Layer<S>, TracingGuard)>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let appender = RollingFileAppender::builder()
+        .rotation(parse_rotation(rotation)?)
+        .filename_prefix(prefix)
+        .build(directory)
+        .map_err(|err| PyOSError::new_err(err.to_string()))?;
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+
+    let layer = fmt::layer().with_ansi(false).with_writer(writer);
+    Ok((layer, TracingGuard::new(guard)))
+}
+
+/// Installs a global subscriber that forwards `tracing` data to `py_impl`, like
+/// [`crate::initialize_tracing`], and also writes it as text logs to rolling
+/// files in `directory`.
+///
+/// The files are named `prefix` followed by the date and time they start at,
+/// and a new one is started every `rotation`: `"minutely"`, `"hourly"`,
+/// `"daily"` or `"never"`. Logs are written from a background thread, which
+/// keeps running as long as the returned [`TracingGuard`] is open; closing it
+/// flushes pending logs and stops writing files. `filter` applies to both
+/// outputs, and it and the `PYO3_TRACING_*` environment variables work as they
+/// do for [`crate::initialize_tracing`].
+#[pyfunction]
+#[pyo3(signature = (py_impl, directory, prefix, rotation = "daily", filter = None))]
+pub fn initialize_tracing_with_file(
+    py_impl: Bound<'_, PyAny>,
+    directory: PathBuf,
+    prefix: &str,
+    rotation: &str,
+    filter: Option<&str>,
+) -> PyResult<TracingGuard> {
+    let (layer, guard) = file_layer(directory, prefix, rotation)?;
+    let config = env_config(filter)?;
+    let bridge = config.apply(PythonCallbackLayerBridge::new(py_impl))?;
+    try_init(bridge.and_then(layer), config.filter())?;
+    Ok(guard)
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{info, info_span};
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::tests::{prepare_python, PythonLayer};
+
+    #[test]
+    fn test_file_layer() {
+        prepare_python();
+        let directory = std::env::temp_dir().join(format!("appender-{}", std::process::id()));
+        let (py_layer, bridge) = Python::with_gil(|py| {
+            let py_layer = Bound::new(py, PythonLayer::new()).unwrap();
+            let bridge = PythonCallbackLayerBridge::new(py_layer.clone().into_any());
+            (py_layer.unbind(), bridge)
+        });
+        let (layer, mut guard) = file_layer(directory.clone(), "app.log", "never").unwrap();
+        let dispatcher = tracing_subscriber::registry()
+            .with(bridge.and_then(layer))
+            .set_default();
+
+        info_span!("appender").in_scope(|| info!(rows = 3, "written to both"));
+        drop(dispatcher);
+        guard.close();
+
+        let log = std::fs::read_to_string(directory.join("app.log")).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert!(log.contains(
+            "appender: pyo3_python_tracing_subscriber::appender::tests: written to both rows=3"
+        ));
+        Python::with_gil(|py| assert_eq!(1, py_layer.borrow(py).events.len()));
+    }
+}
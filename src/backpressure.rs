@@ -0,0 +1,145 @@
+//! Backpressure the Python layer signals to the bridge by returning a
+//! [`Backpressure`] value from its callbacks, e.g. when the exporter it feeds
+//! is falling behind.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use pyo3::{prelude::*, types::PyDict};
+use tracing_core::Level;
+
+/// A value the Python layer's event callbacks and `on_tick` can return to ask
+/// the bridge to slow down, or to tell it that the pressure cleared.
+///
+/// While slowed down, the bridge drops events less severe than the level set
+/// with [`crate::PythonCallbackLayerBridge::with_backpressure_level`], `WARN`
+/// by default, until a callback returns `Backpressure.CLEAR`. Spans are still
+/// forwarded, so the Python layer's view of them stays consistent.
+#[pyclass(module = "pyo3_python_tracing_subscriber")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backpressure {
+    #[pyo3(name = "SLOW_DOWN")]
+    SlowDown,
+    #[pyo3(name = "CLEAR")]
+    Clear,
+}
+
+impl Backpressure {
+    /// Returns the signal of several layers' return values: slowing down if
+    /// any of them asks to, clearing if any of them clears and none asks to
+    /// slow down.
+    pub(crate) fn combine(py: Python<'_>, returned: &[PyObject]) -> Option<Backpressure> {
+        returned
+            .iter()
+            .filter_map(|returned| returned.extract::<Backpressure>(py).ok())
+            .min_by_key(|backpressure| *backpressure != Backpressure::SlowDown)
+    }
+}
+
+/// Whether the Python layer asked the bridge to slow down, and how many events
+/// the bridge dropped since.
+#[derive(Default)]
+pub(crate) struct Pressure {
+    slowed_down: AtomicBool,
+    dropped: AtomicU64,
+}
+
+impl Pressure {
+    /// Updates the pressure if `returned`, the return value of a callback, is
+    /// a [`Backpressure`].
+    pub(crate) fn signal(&self, returned: &Bound<'_, PyAny>) {
+        if returned.is_none() {
+            return;
+        }
+        if let Ok(backpressure) = returned.extract::<Backpressure>() {
+            self.slowed_down
+                .store(backpressure == Backpressure::SlowDown, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether an event at `level` is dropped because the bridge is slowed
+    /// down and it's less severe than `threshold`, counting it if so.
+    pub(crate) fn sheds(&self, level: &Level, threshold: Level) -> bool {
+        let sheds = *level > threshold && self.slowed_down.load(Ordering::Relaxed);
+        if sheds {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        sheds
+    }
+
+    pub(crate) fn insert_stats(&self, stats: &Bound<'_, PyDict>) -> PyResult<()> {
+        stats.set_item("backpressure", self.slowed_down.load(Ordering::Relaxed))?;
+        stats.set_item("backpressure_dropped", self.dropped.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::prelude::*;
+    use tracing::{debug, error, info, info_span, warn};
+    use tracing_subscriber::prelude::*;
+
+    use super::Backpressure;
+    use crate::{get_bridge_stats, tests::prepare_python, PythonCallbackLayerBridge};
+
+    #[test]
+    fn test_backpressure() {
+        prepare_python();
+        let (received, bridge) = Python::with_gil(|py| {
+            let module = PyModule::from_code_bound(
+                py,
+                "
+import json
+
+received = []
+
+class ChokingLayer:
+    def on_event(self, event, state):
+        message = json.loads(event)['message']
+        received.append(message)
+        if message == 'choking':
+            return Backpressure.SLOW_DOWN
+        if message == 'recovered':
+            return Backpressure.CLEAR
+",
+                "choking_layer.py",
+                "choking_layer",
+            )
+            .unwrap();
+            module
+                .setattr("Backpressure", py.get_type_bound::<Backpressure>())
+                .unwrap();
+            let layer = module.getattr("ChokingLayer").unwrap().call0().unwrap();
+            (
+                module.getattr("received").unwrap().unbind(),
+                PythonCallbackLayerBridge::new(layer),
+            )
+        });
+        let _dispatcher = tracing_subscriber::registry().with(bridge).set_default();
+
+        let stat = |key: &str| {
+            Python::with_gil(|py| {
+                let stats = get_bridge_stats(py).unwrap().unwrap();
+                stats.get_item(key).unwrap().unwrap().to_string()
+            })
+        };
+        info_span!("export").in_scope(|| {
+            info!("before");
+            warn!("choking");
+            info!("shed");
+            debug!("shed too");
+            assert_eq!("True", stat("backpressure"));
+            warn!("kept");
+            error!("recovered");
+            info!("after");
+        });
+
+        assert_eq!("False", stat("backpressure"));
+        assert_eq!("2", stat("backpressure_dropped"));
+        Python::with_gil(|py| {
+            assert_eq!(
+                vec!["before", "choking", "kept", "recovered", "after"],
+                received.extract::<Vec<String>>(py).unwrap()
+            );
+        });
+    }
+}
@@ -0,0 +1,73 @@
+//! Rate-limited capture of Rust backtraces for `ERROR` events.
+
+use std::{
+    backtrace::Backtrace,
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// Captures at most `max_per_second` backtraces per second, since capturing
+/// and symbolizing one takes far longer than forwarding an event.
+pub(crate) struct BacktraceSampler {
+    max_per_second: u32,
+    /// The start of the current one-second window, if any, and how many
+    /// backtraces were captured in it.
+    window: Mutex<(Option<Instant>, u32)>,
+}
+
+impl BacktraceSampler {
+    pub(crate) fn new(max_per_second: u32) -> BacktraceSampler {
+        BacktraceSampler {
+            max_per_second,
+            window: Mutex::new((None, 0)),
+        }
+    }
+
+    /// Returns the formatted backtrace of the calling thread, or `None` if the
+    /// limit was reached in the window containing `now`.
+    pub(crate) fn capture(&self, now: Instant) -> Option<String> {
+        {
+            let mut window = self.window.lock().unwrap_or_else(PoisonError::into_inner);
+            if window
+                .0
+                .is_none_or(|start| now.duration_since(start) >= Duration::from_secs(1))
+            {
+                *window = (Some(now), 0);
+            }
+            if window.1 >= self.max_per_second {
+                return None;
+            }
+            window.1 += 1;
+        }
+        Some(Backtrace::force_capture().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{error, info, info_span};
+
+    use crate::tests::initialize_tracing_with;
+
+    #[test]
+    fn test_error_backtraces() {
+        let (py_layer, _dispatcher) =
+            initialize_tracing_with(|bridge| bridge.with_error_backtraces(1));
+
+        info_span!("scope").in_scope(|| {
+            error!("first");
+            info!(rows = 3, "not an error");
+            error!("over the limit");
+        });
+
+        pyo3::Python::with_gil(|py| {
+            let borrowed = py_layer.borrow(py);
+            assert!(borrowed.event_fields[0]["backtrace"]
+                .as_str()
+                .unwrap()
+                .contains("test_error_backtraces"));
+            assert!(borrowed.event_fields[1].get("backtrace").is_none());
+            assert_eq!(2, borrowed.event_fields.len());
+        });
+    }
+}
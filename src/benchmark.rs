@@ -0,0 +1,153 @@
+//! A micro-benchmark of the bridge installed in the current subscriber.
+
+use std::time::{Duration, Instant};
+
+use pyo3::{
+    exceptions::{PyLookupError, PyValueError},
+    prelude::*,
+    types::PyDict,
+};
+use serde_json::json;
+use tracing_core::{dispatcher, field::Value, Dispatch, Event, Level};
+use tracing_serde::AsSerde;
+
+use crate::{callsite, payload, PayloadMode, PythonCallbackLayerBridge};
+
+/// The time spent in each stage of delivering events to Python.
+#[derive(Default)]
+struct Breakdown {
+    serialization: Duration,
+    gil_wait: Duration,
+    python_call: Duration,
+}
+
+/// Measures how long the bridge installed in the current subscriber takes to
+/// deliver an event with a `payload` field of `payload_size` bytes to its
+/// Python layer, over `iterations` calls of its `on_event`.
+///
+/// Returns a `dict` with the mean seconds per event spent serializing the
+/// event into its payload (`serialization`), waiting to acquire the GIL
+/// (`gil_wait`), calling `on_event` (`python_call`), and in `total`. The GIL
+/// is released between events, so other Python threads contend for it like
+/// they would with events from Rust threads.
+///
+/// The Python layer receives the events, with the message `"benchmark"`, target
+/// `"python"` and no span state, so benchmark a layer whose side effects don't
+/// matter.
+#[pyfunction]
+#[pyo3(signature = (iterations = 1000, payload_size = 64))]
+pub fn benchmark_bridge(
+    py: Python<'_>,
+    iterations: u32,
+    payload_size: usize,
+) -> PyResult<Bound<'_, PyDict>> {
+    if iterations == 0 {
+        return Err(PyValueError::new_err("iterations must be positive"));
+    }
+    let dispatch = dispatcher::get_default(Dispatch::clone);
+    let bridge = dispatch
+        .downcast_ref::<PythonCallbackLayerBridge>()
+        .ok_or_else(|| PyLookupError::new_err("the current subscriber has no Python layer"))?;
+    let callbacks = bridge.callbacks();
+    let on_event = callbacks
+        .on_event
+        .as_ref()
+        .ok_or_else(|| PyLookupError::new_err("the Python layer has no on_event method"))?;
+    let metadata = callsite::event_metadata(Level::INFO, &["payload".to_owned()])
+        .ok_or_else(|| PyLookupError::new_err("the benchmark callsite couldn't be created"))?;
+    let payload = "x".repeat(payload_size);
+
+    let breakdown = py.allow_threads(|| {
+        let mut breakdown = Breakdown::default();
+        for _ in 0..iterations {
+            let values = [
+                Some(&"benchmark" as &dyn Value),
+                Some(&payload as &dyn Value),
+            ];
+            let value_set = metadata.fields().value_set_all(&values);
+            let event = Event::new(metadata, &value_set);
+
+            let start = Instant::now();
+            let json_event = (bridge.payload_mode != PayloadMode::StructlogEventDict)
+                .then(|| json!(event.as_serde()));
+            breakdown.serialization += start.elapsed();
+
+            let start = Instant::now();
+            Python::with_gil(|py| {
+                breakdown.gil_wait += start.elapsed();
+                let start = Instant::now();
+                let py_event = match json_event {
+                    Some(json_event) => bridge.json_event_payload(py, &json_event).0,
+                    None => {
+                        payload::structlog_event_dict(py, &event, &bridge.event_dict_options(py))?
+                            .into_any()
+                            .unbind()
+                    }
+                };
+                breakdown.serialization += start.elapsed();
+
+                let start = Instant::now();
+                on_event.bind(py).call1((py_event, py.None()))?;
+                breakdown.python_call += start.elapsed();
+                Ok::<_, PyErr>(())
+            })?;
+        }
+        Ok::<_, PyErr>(breakdown)
+    })?;
+
+    let mean = |total: Duration| total.as_secs_f64() / f64::from(iterations);
+    let result = PyDict::new_bound(py);
+    result.set_item("serialization", mean(breakdown.serialization))?;
+    result.set_item("gil_wait", mean(breakdown.gil_wait))?;
+    result.set_item("python_call", mean(breakdown.python_call))?;
+    result.set_item(
+        "total",
+        mean(breakdown.serialization + breakdown.gil_wait + breakdown.python_call),
+    )?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::{tests::prepare_python, CapturingLayer};
+
+    #[test]
+    fn test_benchmark_bridge() {
+        prepare_python();
+        let capture = Python::with_gil(|py| Py::new(py, CapturingLayer::new(py)).unwrap());
+        let bridge = Python::with_gil(|py| {
+            PythonCallbackLayerBridge::new(capture.bind(py).clone().into_any())
+        });
+        let _dispatcher = tracing_subscriber::registry().with(bridge).set_default();
+
+        Python::with_gil(|py| {
+            let breakdown = benchmark_bridge(py, 10, 100).unwrap();
+            let stage = |key: &str| {
+                breakdown
+                    .get_item(key)
+                    .unwrap()
+                    .unwrap()
+                    .extract::<f64>()
+                    .unwrap()
+            };
+            assert!(stage("python_call") > 0.0);
+            let stages = stage("serialization") + stage("gil_wait") + stage("python_call");
+            assert!((stage("total") - stages).abs() < 1e-9);
+
+            let events = capture.bind(py).getattr("events").unwrap();
+            assert_eq!(10, events.len().unwrap());
+            let payload = events
+                .get_item(0)
+                .unwrap()
+                .get_item("fields")
+                .unwrap()
+                .get_item("payload")
+                .unwrap();
+            assert_eq!("x".repeat(100), payload.extract::<String>().unwrap());
+            assert!(benchmark_bridge(py, 0, 1).is_err());
+        });
+    }
+}
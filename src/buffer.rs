@@ -0,0 +1,80 @@
+//! Payloads passed to Python through the buffer protocol, without copying them
+//! into a `bytes` or `str`.
+
+use std::os::raw::{c_int, c_void};
+
+use pyo3::{exceptions::PyBufferError, ffi, prelude::*};
+
+/// A read-only buffer of a serialized payload, owned by Rust and exposed to
+/// Python through the buffer protocol.
+///
+/// It can be passed to anything accepting bytes-like objects, like
+/// `socket.send` or `memoryview`, without copying it. The payload stays alive
+/// while any `memoryview` of it does, since buffers hold a reference to it.
+#[pyclass(frozen, module = "pyo3_python_tracing_subscriber")]
+pub struct PayloadBuffer {
+    data: Box<[u8]>,
+}
+
+impl PayloadBuffer {
+    pub(crate) fn new(data: impl Into<Box<[u8]>>) -> PayloadBuffer {
+        PayloadBuffer { data: data.into() }
+    }
+}
+
+#[pymethods]
+impl PayloadBuffer {
+    unsafe fn __getbuffer__(
+        slf: Bound<'_, Self>,
+        view: *mut ffi::Py_buffer,
+        flags: c_int,
+    ) -> PyResult<()> {
+        if view.is_null() {
+            return Err(PyBufferError::new_err("the buffer view is null"));
+        }
+        let data = &slf.get().data;
+        // The data is never mutated or moved while the object is alive, and
+        // `PyBuffer_FillInfo` stores a new reference to it in `view.obj`.
+        let filled = ffi::PyBuffer_FillInfo(
+            view,
+            slf.as_ptr(),
+            data.as_ptr() as *mut c_void,
+            data.len() as ffi::Py_ssize_t,
+            1,
+            flags,
+        );
+        if filled == -1 {
+            return Err(PyErr::fetch(slf.py()));
+        }
+        Ok(())
+    }
+
+    fn __len__(&self) -> usize {
+        self.data.len()
+    }
+
+    fn __bytes__<'py>(&self, py: Python<'py>) -> Bound<'py, pyo3::types::PyBytes> {
+        pyo3::types::PyBytes::new_bound(py, &self.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{info, info_span};
+
+    use crate::{tests::initialize_tracing_with, PayloadMode};
+
+    #[test]
+    fn test_buffer_payloads() {
+        let (py_layer, _dispatcher) =
+            initialize_tracing_with(|bridge| bridge.with_payload_mode(PayloadMode::Buffer));
+
+        info_span!("scope").in_scope(|| info!(rows = 3, "queried"));
+
+        pyo3::Python::with_gil(|py| {
+            let borrowed = py_layer.borrow(py);
+            assert_eq!("queried", borrowed.events[0].0);
+            assert_eq!(3, borrowed.event_fields[0]["rows"]);
+        });
+    }
+}
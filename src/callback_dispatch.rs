@@ -0,0 +1,50 @@
+//! The dispatcher of the bridge whose Python layer is being called on this
+//! thread, so Python callbacks can reach its spans and the bridge itself.
+//!
+//! While tracing dispatches a span or event, `dispatcher::get_default` returns
+//! a no-op dispatcher on the same thread, to keep the subscriber from being
+//! re-entered by accident. Python callbacks are called from inside that
+//! dispatch, so they can't find the subscriber through it.
+
+use std::cell::RefCell;
+
+use tracing_core::{dispatcher, dispatcher::WeakDispatch, Dispatch};
+
+thread_local! {
+    static CALLBACK_DISPATCH: RefCell<Option<WeakDispatch>> = const { RefCell::new(None) };
+}
+
+/// Restores the dispatcher of the enclosing callback, if any, when dropped.
+pub(crate) struct Entered(Option<WeakDispatch>);
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        let _ = CALLBACK_DISPATCH.try_with(|cell| *cell.borrow_mut() = previous);
+    }
+}
+
+/// Makes `dispatch` the dispatcher [`current`] returns on this thread until the
+/// returned guard is dropped.
+pub(crate) fn enter(dispatch: Option<&WeakDispatch>) -> Entered {
+    let previous = CALLBACK_DISPATCH
+        .try_with(|cell| cell.replace(dispatch.cloned()))
+        .ok()
+        .flatten();
+    Entered(previous)
+}
+
+/// Returns the dispatcher of the bridge whose callback is running on this
+/// thread, if any.
+pub(crate) fn entered() -> Option<Dispatch> {
+    CALLBACK_DISPATCH
+        .try_with(|cell| cell.borrow().as_ref().and_then(WeakDispatch::upgrade))
+        .ok()
+        .flatten()
+}
+
+/// Returns the dispatcher of the bridge whose callback is running on this
+/// thread, or else the default dispatcher.
+pub(crate) fn current() -> Dispatch {
+    entered().unwrap_or_else(|| dispatcher::get_default(Dispatch::clone))
+}
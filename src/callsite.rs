@@ -0,0 +1,239 @@
+//! Callsites for events that originate in Python, or in `log` records with
+//! structured key/values.
+//!
+//! `tracing` requires every event to come from a callsite with `'static`
+//! metadata, including its field names. Python code can pass arbitrary field
+//! names, so a callsite is created (and leaked) the first time each distinct
+//! combination of level and field names (and, for `log` records, target and
+//! location) is seen and reused afterwards.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU8, AtomicUsize, Ordering},
+        Mutex, OnceLock,
+    },
+};
+
+use pyo3::prelude::*;
+use tracing_core::{
+    callsite::{self, Callsite, Identifier},
+    field::FieldSet,
+    metadata::Kind,
+    subscriber::Interest,
+    Level, Metadata,
+};
+
+use crate::callback_dispatch;
+
+/// The target of events created from Python.
+pub(crate) const PYTHON_TARGET: &str = "python";
+
+const INTEREST_NEVER: u8 = 0;
+const INTEREST_SOMETIMES: u8 = 1;
+const INTEREST_ALWAYS: u8 = 2;
+
+struct PythonCallsite {
+    metadata: OnceLock<Metadata<'static>>,
+    interest: AtomicU8,
+}
+
+impl Callsite for PythonCallsite {
+    fn set_interest(&self, interest: Interest) {
+        let interest = if interest.is_never() {
+            INTEREST_NEVER
+        } else if interest.is_always() {
+            INTEREST_ALWAYS
+        } else {
+            INTEREST_SOMETIMES
+        };
+        self.interest.store(interest, Ordering::Relaxed);
+    }
+
+    fn metadata(&self) -> &Metadata<'_> {
+        self.metadata
+            .get()
+            .expect("metadata is set before the callsite is registered")
+    }
+}
+
+/// Incremented by [`rebuild_interest_cache`] to tell bridges to look up their
+/// Python layer's methods again.
+static CALLBACKS_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn callbacks_generation() -> usize {
+    CALLBACKS_GENERATION.load(Ordering::Relaxed)
+}
+
+/// Recomputes whether each callsite is enabled and makes every bridge look up
+/// its Python layer's methods again.
+///
+/// Call this after changing what a Python layer is interested in, such as
+/// adding or removing callback methods or changing a filter, since `tracing`
+/// caches those decisions per callsite.
+#[pyfunction]
+pub fn rebuild_interest_cache() {
+    CALLBACKS_GENERATION.fetch_add(1, Ordering::Relaxed);
+    crate::disabled::clear_all();
+    callsite::rebuild_interest_cache();
+}
+
+/// Everything that distinguishes one dynamically created event callsite from
+/// another.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct CallsiteKey {
+    name: &'static str,
+    target: String,
+    level: Level,
+    module_path: Option<String>,
+    file: Option<String>,
+    line: Option<u32>,
+    field_names: Vec<String>,
+}
+
+fn callsites() -> &'static Mutex<HashMap<CallsiteKey, &'static PythonCallsite>> {
+    static CALLSITES: OnceLock<Mutex<HashMap<CallsiteKey, &'static PythonCallsite>>> =
+        OnceLock::new();
+    CALLSITES.get_or_init(Default::default)
+}
+
+/// Returns the metadata of the event callsite for `level` whose fields are
+/// `message` followed by `field_names`, or `None` if every subscriber has
+/// declared it will never be interested in it.
+pub(crate) fn event_metadata(
+    level: Level,
+    field_names: &[String],
+) -> Option<&'static Metadata<'static>> {
+    callsite_metadata(CallsiteKey {
+        name: "event from python",
+        target: PYTHON_TARGET.to_owned(),
+        level,
+        module_path: None,
+        file: None,
+        line: None,
+        field_names: field_names.to_vec(),
+    })
+}
+
+/// Like [`event_metadata`], but for events converted from `log` records with
+/// structured key/values, which need a callsite for each place they're logged
+/// from.
+#[cfg(feature = "log")]
+pub(crate) fn log_record_metadata(
+    record: &log::Record<'_>,
+    field_names: &[String],
+) -> Option<&'static Metadata<'static>> {
+    use tracing_log::AsTrace;
+
+    callsite_metadata(CallsiteKey {
+        name: "log event",
+        target: record.target().to_owned(),
+        level: record.level().as_trace(),
+        module_path: record.module_path().map(ToOwned::to_owned),
+        file: record.file().map(ToOwned::to_owned),
+        line: record.line(),
+        field_names: field_names.to_vec(),
+    })
+}
+
+fn callsite_metadata(key: CallsiteKey) -> Option<&'static Metadata<'static>> {
+    let callsite = *callsites()
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .entry(key.clone())
+        .or_insert_with(|| register_event_callsite(key));
+
+    if callsite.interest.load(Ordering::Relaxed) == INTEREST_NEVER {
+        return None;
+    }
+    callsite.metadata.get()
+}
+
+fn leak(s: String) -> &'static str {
+    Box::leak(s.into_boxed_str())
+}
+
+fn register_event_callsite(key: CallsiteKey) -> &'static PythonCallsite {
+    let names = std::iter::once("message")
+        .chain(key.field_names.into_iter().map(leak))
+        .collect::<Vec<&'static str>>();
+    let names = Box::leak(names.into_boxed_slice());
+
+    let callsite: &'static PythonCallsite = Box::leak(Box::new(PythonCallsite {
+        metadata: OnceLock::new(),
+        interest: AtomicU8::new(INTEREST_SOMETIMES),
+    }));
+    let _ = callsite.metadata.set(Metadata::new(
+        key.name,
+        leak(key.target),
+        key.level,
+        key.file.map(leak),
+        key.line,
+        key.module_path.map(leak),
+        FieldSet::new(names, Identifier(callsite)),
+        Kind::EVENT,
+    ));
+    callsite::register(callsite);
+    // With a single dispatcher, `tracing` asks the default dispatcher for the
+    // callsite's interest, which is a no-op one inside a Python callback.
+    if let (INTEREST_NEVER, Some(dispatch)) = (
+        callsite.interest.load(Ordering::Relaxed),
+        callback_dispatch::entered(),
+    ) {
+        let metadata = callsite.metadata.get().expect("the metadata was just set");
+        callsite.set_interest(dispatch.register_callsite(metadata));
+    }
+    callsite
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::types::PyModule;
+    use tracing::info;
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::{tests::prepare_python, PythonCallbackLayerBridge};
+
+    #[test]
+    fn test_rebuild_picks_up_new_callbacks() {
+        prepare_python();
+        let py_layer = Python::with_gil(|py| {
+            let module = PyModule::from_code_bound(
+                py,
+                "class Layer:
+    def __init__(self):
+        self.events = []
+
+    def record_event(self, event, state):
+        self.events.append(event)
+",
+                "layer.py",
+                "layer",
+            )
+            .unwrap();
+            module.getattr("Layer").unwrap().call0().unwrap().unbind()
+        });
+        let bridge =
+            Python::with_gil(|py| PythonCallbackLayerBridge::new(py_layer.bind(py).clone()));
+        let _dispatcher = tracing_subscriber::registry().with(bridge).set_default();
+
+        info!("before");
+        Python::with_gil(|py| {
+            let on_event = py_layer.getattr(py, "record_event").unwrap();
+            py_layer.setattr(py, "on_event", on_event).unwrap();
+        });
+        rebuild_interest_cache();
+        info!("after");
+
+        Python::with_gil(|py| {
+            let events = py_layer
+                .getattr(py, "events")
+                .unwrap()
+                .extract::<Vec<String>>(py)
+                .unwrap();
+            assert_eq!(1, events.len());
+            assert!(events[0].contains("after"));
+        });
+    }
+}
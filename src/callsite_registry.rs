@@ -0,0 +1,352 @@
+//! The callsites a bridge has seen, for Python to discover which spans and
+//! events the Rust code it depends on can emit, and to disable single noisy
+//! ones.
+
+use std::{
+    collections::HashMap,
+    ptr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, OnceLock, PoisonError, RwLock,
+    },
+};
+
+use pyo3::{
+    exceptions::PyValueError,
+    prelude::*,
+    types::{PyDict, PyList},
+};
+use tracing_core::{callsite::Identifier, Metadata};
+
+use crate::{disabled, with_current_bridge};
+
+/// A callsite the bridge has seen, and how many spans or events from it were
+/// forwarded to the Python layer.
+struct SeenCallsite {
+    /// The id Python refers to the callsite by, numbered from 1 in the order
+    /// the bridge saw the callsites.
+    id: u64,
+    metadata: &'static Metadata<'static>,
+    forwarded: Arc<AtomicU64>,
+    enabled: AtomicBool,
+}
+
+/// A callsite's metadata and its forwarded count, cached in the first free
+/// slot from the one its metadata hashes to.
+type CachedCounter = OnceLock<(&'static Metadata<'static>, Arc<AtomicU64>)>;
+
+/// How many slots from the one a callsite hashes to its counter may be cached
+/// in.
+const PROBES: usize = 8;
+
+/// The callsites registered with the subscriber while the bridge was part of
+/// it, or that it was handed spans and events from.
+pub(crate) struct CallsiteRegistry {
+    callsites: RwLock<HashMap<Identifier, SeenCallsite>>,
+    /// The forwarded counts, so counting a span or event takes a relaxed
+    /// `fetch_add` rather than a lookup in `callsites`. A callsite whose
+    /// [`PROBES`] slots are all taken by others is counted through
+    /// `callsites`.
+    counters: Box<[CachedCounter]>,
+    /// How many callsites are disabled, so events aren't looked up while none
+    /// are.
+    disabled: AtomicUsize,
+}
+
+impl Default for CallsiteRegistry {
+    fn default() -> CallsiteRegistry {
+        CallsiteRegistry {
+            callsites: RwLock::default(),
+            counters: (0..disabled::SLOTS).map(|_| OnceLock::new()).collect(),
+            disabled: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl CallsiteRegistry {
+    /// Records the callsite of `metadata`, if it's new.
+    pub(crate) fn register(&self, metadata: &'static Metadata<'static>) {
+        let mut callsites = self
+            .callsites
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        let id = callsites.len() as u64 + 1;
+        let seen = callsites
+            .entry(metadata.callsite())
+            .or_insert_with(|| SeenCallsite {
+                id,
+                metadata,
+                forwarded: Arc::default(),
+                enabled: AtomicBool::new(true),
+            });
+        let forwarded = Arc::clone(&seen.forwarded);
+        drop(callsites);
+        for slot in self.probe(metadata) {
+            let (cached, _) = slot.get_or_init(|| (metadata, Arc::clone(&forwarded)));
+            if ptr::eq(*cached, metadata) {
+                break;
+            }
+        }
+    }
+
+    /// The slots the counter of `metadata`'s callsite may be cached in.
+    fn probe(&self, metadata: &Metadata<'_>) -> impl Iterator<Item = &CachedCounter> {
+        let start = disabled::slot_index(metadata);
+        (start..start + PROBES).map(|index| &self.counters[index % self.counters.len()])
+    }
+
+    /// Whether the callsite of `metadata` was disabled with
+    /// [`set_callsite_enabled`].
+    pub(crate) fn is_disabled(&self, metadata: &Metadata<'_>) -> bool {
+        if self.disabled.load(Ordering::Relaxed) == 0 {
+            return false;
+        }
+        let callsites = self
+            .callsites
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        callsites
+            .get(&metadata.callsite())
+            .is_some_and(|seen| !seen.enabled.load(Ordering::Relaxed))
+    }
+
+    /// Enables or disables the callsite with `id` and returns whether it was
+    /// enabled, or `None` if the bridge hasn't seen a callsite with `id`.
+    pub(crate) fn set_enabled(&self, id: u64, enabled: bool) -> Option<bool> {
+        let callsites = self
+            .callsites
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let seen = callsites.values().find(|seen| seen.id == id)?;
+        let was_enabled = seen.enabled.swap(enabled, Ordering::Relaxed);
+        match (was_enabled, enabled) {
+            (true, false) => self.disabled.fetch_add(1, Ordering::Relaxed),
+            (false, true) => self.disabled.fetch_sub(1, Ordering::Relaxed),
+            _ => 0,
+        };
+        Some(was_enabled)
+    }
+
+    /// Counts a span or event from the callsite of `metadata` forwarded to the
+    /// Python layer.
+    pub(crate) fn forwarded(&self, metadata: &'static Metadata<'static>) {
+        for slot in self.probe(metadata) {
+            match slot.get() {
+                Some((cached, forwarded)) if ptr::eq(*cached, metadata) => {
+                    forwarded.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                Some(_) => {}
+                None => break,
+            }
+        }
+        let callsites = self
+            .callsites
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(seen) = callsites.get(&metadata.callsite()) {
+            seen.forwarded.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        drop(callsites);
+        self.register(metadata);
+        self.forwarded(metadata);
+    }
+
+    /// Returns a `dict` for each callsite, ordered by target and location.
+    /// `forwards` is whether the bridge forwards spans or events from a
+    /// callsite to its Python layer.
+    pub(crate) fn to_py_list<'py>(
+        &self,
+        py: Python<'py>,
+        forwards: impl Fn(&Metadata<'_>) -> bool,
+    ) -> PyResult<Bound<'py, PyList>> {
+        let callsites = self
+            .callsites
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut seen: Vec<_> = callsites.values().collect();
+        seen.sort_by_key(|seen| {
+            let metadata = seen.metadata;
+            (
+                metadata.target(),
+                metadata.file(),
+                metadata.line(),
+                metadata.name(),
+            )
+        });
+        let list = PyList::empty_bound(py);
+        for seen in seen {
+            let metadata = seen.metadata;
+            let callsite = PyDict::new_bound(py);
+            callsite.set_item("id", seen.id)?;
+            callsite.set_item("kind", if metadata.is_span() { "span" } else { "event" })?;
+            callsite.set_item("name", metadata.name())?;
+            callsite.set_item("target", metadata.target())?;
+            callsite.set_item("level", metadata.level().as_str())?;
+            callsite.set_item("module_path", metadata.module_path())?;
+            callsite.set_item("file", metadata.file())?;
+            callsite.set_item("line", metadata.line())?;
+            let fields: Vec<_> = metadata.fields().iter().map(|field| field.name()).collect();
+            callsite.set_item("fields", fields)?;
+            let enabled = seen.enabled.load(Ordering::Relaxed);
+            callsite.set_item("enabled", enabled)?;
+            let interest = if enabled && forwards(metadata) {
+                "always"
+            } else {
+                "never"
+            };
+            callsite.set_item("interest", interest)?;
+            callsite.set_item("forwarded", seen.forwarded.load(Ordering::Relaxed))?;
+            list.append(callsite)?;
+        }
+        Ok(list)
+    }
+}
+
+/// Returns the callsites the bridge in the current subscriber has seen, or
+/// `None` if the subscriber has no bridge.
+///
+/// Each is a `dict` with its `id`, `kind` (`"span"` or `"event"`), `name`,
+/// `target`, `level`, `module_path`, `file`, `line` and `fields`, whether it's
+/// `enabled`, its `interest`, which is `"always"` if the bridge forwards it to
+/// the Python layer and `"never"` if it's disabled or the layer has no method
+/// for it, and how many spans or events from it were `forwarded`.
+#[pyfunction]
+pub fn list_callsites(py: Python<'_>) -> PyResult<Option<Bound<'_, PyList>>> {
+    with_current_bridge(|bridge| bridge.callsites(py)).transpose()
+}
+
+/// Enables or disables the callsite with `callsite_id`, as listed by
+/// [`list_callsites`], in the bridge of the current subscriber, and returns
+/// whether it was enabled.
+///
+/// The bridge doesn't forward events from a disabled callsite, nor spans
+/// created from it, to its Python layer, while other layers of the subscriber
+/// still see them. This mutes a single noisy event without changing the level
+/// or target filters that other events from its target rely on.
+///
+/// Returns `False` if the current subscriber has no bridge, and raises
+/// `ValueError` if its bridge hasn't seen a callsite with `callsite_id`.
+#[pyfunction]
+pub fn set_callsite_enabled(callsite_id: u64, enabled: bool) -> PyResult<bool> {
+    with_current_bridge(|bridge| {
+        bridge
+            .callsite_registry
+            .set_enabled(callsite_id, enabled)
+            .ok_or_else(|| PyValueError::new_err(format!("no callsite with id {callsite_id}")))
+    })
+    .unwrap_or(Ok(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use pyo3::{exceptions::PyValueError, prelude::*, types::PyDict};
+    use tracing::{callsite::Callsite, info, info_span};
+
+    use super::{list_callsites, set_callsite_enabled, CallsiteRegistry};
+    use crate::tests::initialize_tracing;
+
+    #[test]
+    fn test_list_callsites() {
+        let (_py_layer, _dispatcher) = initialize_tracing();
+
+        info_span!("listed", user = 1).in_scope(|| {
+            for _ in 0..3 {
+                info!(count = 1, "listed event");
+            }
+        });
+
+        Python::with_gil(|py| {
+            let callsites = list_callsites(py).unwrap().unwrap();
+            let item = |callsite: &Bound<'_, PyDict>, key: &str| {
+                callsite.get_item(key).unwrap().unwrap().to_string()
+            };
+            let find = |kind: &str| {
+                callsites
+                    .iter()
+                    .map(|callsite| callsite.downcast_into::<PyDict>().unwrap())
+                    .find(|callsite| {
+                        item(callsite, "kind") == kind
+                            && item(callsite, "file").ends_with("callsite_registry.rs")
+                    })
+                    .unwrap_or_else(|| panic!("no {kind} callsite"))
+            };
+            let span = find("span");
+            assert_eq!("listed", item(&span, "name"));
+            assert_eq!("['user']", item(&span, "fields"));
+            assert_eq!("always", item(&span, "interest"));
+            assert_eq!("1", item(&span, "forwarded"));
+
+            let event = find("event");
+            assert_eq!("INFO", item(&event, "level"));
+            assert_eq!("['message', 'count']", item(&event, "fields"));
+            assert_eq!("3", item(&event, "forwarded"));
+        });
+    }
+
+    #[test]
+    fn test_forwarded_counts_are_cached() {
+        let registry = CallsiteRegistry::default();
+        let metadata = tracing::callsite! {
+            name: "counted",
+            kind: tracing::metadata::Kind::EVENT,
+            fields: cached
+        }
+        .metadata();
+
+        registry.forwarded(metadata);
+        registry.forwarded(metadata);
+
+        let (cached, forwarded) = registry.probe(metadata).next().unwrap().get().unwrap();
+        assert!(std::ptr::eq(*cached, metadata));
+        assert_eq!(2, forwarded.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_set_callsite_enabled() {
+        let (py_layer, _dispatcher) = initialize_tracing();
+
+        let emit = || {
+            info_span!("muted span").in_scope(|| {});
+            info_span!("scope").in_scope(|| {
+                info!(noisy = true, "noisy");
+                info!("vital");
+            })
+        };
+        emit();
+
+        Python::with_gil(|py| {
+            let callsites = list_callsites(py).unwrap().unwrap();
+            let id = |name_or_field: &str| -> u64 {
+                let callsite = callsites
+                    .iter()
+                    .map(|callsite| callsite.downcast_into::<PyDict>().unwrap())
+                    .find(|callsite| {
+                        let item = |key| callsite.get_item(key).unwrap().unwrap();
+                        item("name").to_string() == name_or_field
+                            || item("fields").contains(name_or_field).unwrap()
+                    })
+                    .unwrap();
+                callsite.get_item("id").unwrap().unwrap().extract().unwrap()
+            };
+            let (span_id, noisy_id) = (id("muted span"), id("noisy"));
+            assert!(set_callsite_enabled(span_id, false).unwrap());
+            assert!(set_callsite_enabled(noisy_id, false).unwrap());
+            assert!(!set_callsite_enabled(noisy_id, false).unwrap());
+            assert!(set_callsite_enabled(u64::MAX, false)
+                .unwrap_err()
+                .is_instance_of::<PyValueError>(py));
+        });
+        emit();
+
+        Python::with_gil(|py| {
+            let borrowed = py_layer.borrow(py);
+            assert_eq!(3, borrowed.new_spans.len());
+            assert_eq!(3, borrowed.events.len());
+            assert_eq!("vital", borrowed.events[2].0);
+        });
+    }
+}
@@ -0,0 +1,389 @@
+//! A ready-made Python layer that records what it receives, for tests.
+
+use pyo3::{
+    exceptions::PyRuntimeError,
+    prelude::*,
+    pybacked::PyBackedStr,
+    sync::GILOnceCell,
+    types::{PyDict, PyList},
+};
+use tracing_core::dispatcher::DefaultGuard;
+use tracing_subscriber::{prelude::*, EnvFilter};
+
+use crate::{init::parse_filter, PythonCallbackLayerBridge};
+
+static JSON_LOADS: GILOnceCell<Py<PyAny>> = GILOnceCell::new();
+
+fn json_loads<'py>(py: Python<'py>, json: &str) -> PyResult<Bound<'py, PyDict>> {
+    let loads = JSON_LOADS.get_or_try_init(py, || {
+        Ok::<_, PyErr>(py.import_bound("json")?.getattr("loads")?.unbind())
+    })?;
+    Ok(loads.bind(py).call1((json,))?.downcast_into()?)
+}
+
+/// Whether `target` is `prefix` or a module inside it.
+fn target_matches(target: &str, prefix: &str) -> bool {
+    target
+        .strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+/// A Python layer that records the spans and events it's given in Python
+/// lists, to pass to `initialize_tracing` (or a
+/// [`crate::PythonCallbackLayerBridge`]) in Python test suites asserting on
+/// Rust telemetry.
+///
+/// - `spans` holds a `dict` per span with its `name`, `target`, `level`,
+///   `fields` (updated by recorded values) and whether it's `closed`.
+/// - `events` holds a `dict` per event with its `message`, `name`, `target`,
+///   `level`, other `fields`, and the `dict` of the `span` it occurred in, or
+///   `None`.
+///
+/// It expects the default [`crate::PayloadMode::Json`] payloads.
+#[pyclass(module = "pyo3_python_tracing_subscriber")]
+pub struct CapturingLayer {
+    #[pyo3(get)]
+    spans: Py<PyList>,
+    #[pyo3(get)]
+    events: Py<PyList>,
+}
+
+impl CapturingLayer {
+    /// Splits a span's or event's payload into a record with its metadata and
+    /// a `fields` dict with the rest.
+    fn record<'py>(py: Python<'py>, payload: &str) -> PyResult<Bound<'py, PyDict>> {
+        let fields = json_loads(py, payload)?;
+        let record = PyDict::new_bound(py);
+        if let Some(metadata) = fields.get_item("metadata")? {
+            for key in ["name", "target", "level"] {
+                record.set_item(key, metadata.get_item(key)?)?;
+            }
+            fields.del_item("metadata")?;
+        }
+        for key in ["parent", "is_root"] {
+            if fields.contains(key)? {
+                fields.del_item(key)?;
+            }
+        }
+        record.set_item("fields", fields)?;
+        Ok(record)
+    }
+
+    fn matching<'py>(
+        records: &Bound<'py, PyList>,
+        filters: &[(&str, Option<&str>)],
+    ) -> PyResult<Bound<'py, PyList>> {
+        let matching = PyList::empty_bound(records.py());
+        for record in records.iter() {
+            let mut matches = true;
+            for (key, expected) in filters {
+                let Some(expected) = expected else {
+                    continue;
+                };
+                let value = record.get_item(key)?;
+                let Ok(value) = value.extract::<PyBackedStr>() else {
+                    matches = false;
+                    break;
+                };
+                matches &= match *key {
+                    "target" => target_matches(&value, expected),
+                    "level" => value.eq_ignore_ascii_case(expected),
+                    _ => &*value == *expected,
+                };
+            }
+            if matches {
+                matching.append(record)?;
+            }
+        }
+        Ok(matching)
+    }
+}
+
+#[pymethods]
+impl CapturingLayer {
+    #[new]
+    pub(crate) fn new(py: Python<'_>) -> CapturingLayer {
+        CapturingLayer {
+            spans: PyList::empty_bound(py).unbind(),
+            events: PyList::empty_bound(py).unbind(),
+        }
+    }
+
+    #[pyo3(signature = (span_attrs, _span_id, _parent_context = None))]
+    fn on_new_span<'py>(
+        &self,
+        py: Python<'py>,
+        span_attrs: &str,
+        _span_id: &str,
+        _parent_context: Option<Bound<'py, PyAny>>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let span = Self::record(py, span_attrs)?;
+        span.set_item("closed", false)?;
+        self.spans.bind(py).append(&span)?;
+        Ok(span)
+    }
+
+    fn on_record(
+        &self,
+        py: Python<'_>,
+        _span_id: &str,
+        values: &str,
+        state: Option<Bound<'_, PyDict>>,
+    ) -> PyResult<()> {
+        let Some(span) = state else {
+            return Ok(());
+        };
+        let fields = span.as_any().get_item("fields")?;
+        fields.call_method1("update", (json_loads(py, values)?,))?;
+        Ok(())
+    }
+
+    fn on_event(
+        &self,
+        py: Python<'_>,
+        event: &str,
+        state: Option<Bound<'_, PyDict>>,
+    ) -> PyResult<()> {
+        let event = Self::record(py, event)?;
+        let fields = event.as_any().get_item("fields")?;
+        event.set_item(
+            "message",
+            fields.call_method1("pop", ("message", py.None()))?,
+        )?;
+        event.set_item("span", state)?;
+        self.events.bind(py).append(event)?;
+        Ok(())
+    }
+
+    fn on_close(&self, _span_id: &str, state: Option<Bound<'_, PyDict>>) -> PyResult<()> {
+        if let Some(span) = state {
+            span.set_item("closed", true)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the events with the given `level` name (case-insensitive),
+    /// `target` (or a module inside it), `message` and `name`, if given.
+    #[pyo3(signature = (level = None, target = None, message = None, name = None))]
+    fn events_matching<'py>(
+        &self,
+        py: Python<'py>,
+        level: Option<&str>,
+        target: Option<&str>,
+        message: Option<&str>,
+        name: Option<&str>,
+    ) -> PyResult<Bound<'py, PyList>> {
+        Self::matching(
+            self.events.bind(py),
+            &[
+                ("level", level),
+                ("target", target),
+                ("message", message),
+                ("name", name),
+            ],
+        )
+    }
+
+    /// Returns the spans with the given `name`, `target` (or a module inside
+    /// it) and `level` name (case-insensitive), if given.
+    #[pyo3(signature = (name = None, target = None, level = None))]
+    fn spans_matching<'py>(
+        &self,
+        py: Python<'py>,
+        name: Option<&str>,
+        target: Option<&str>,
+        level: Option<&str>,
+    ) -> PyResult<Bound<'py, PyList>> {
+        Self::matching(
+            self.spans.bind(py),
+            &[("name", name), ("target", target), ("level", level)],
+        )
+    }
+
+    /// Forgets the spans and events recorded so far.
+    fn clear(&self, py: Python<'_>) -> PyResult<()> {
+        self.spans.bind(py).call_method0("clear")?;
+        self.events.bind(py).call_method0("clear")?;
+        Ok(())
+    }
+}
+
+/// A context manager that captures the Rust spans and events of the current
+/// thread into a [`CapturingLayer`] while it's entered, returned by
+/// [`captured_rust_tracing`].
+#[pyclass(unsendable, module = "pyo3_python_tracing_subscriber")]
+pub struct CapturedRustTracing {
+    filter: Option<EnvFilter>,
+    guard: Option<DefaultGuard>,
+}
+
+#[pymethods]
+impl CapturedRustTracing {
+    fn __enter__(&mut self, py: Python<'_>) -> PyResult<Py<CapturingLayer>> {
+        let Some(filter) = self.filter.take() else {
+            return Err(PyRuntimeError::new_err(
+                "captured_rust_tracing() can only be entered once",
+            ));
+        };
+        let capture = Py::new(py, CapturingLayer::new(py))?;
+        let bridge = PythonCallbackLayerBridge::new(capture.bind(py).clone().into_any());
+        self.guard = Some(
+            tracing_subscriber::registry()
+                .with(filter)
+                .with(bridge)
+                .set_default(),
+        );
+        Ok(capture)
+    }
+
+    fn __exit__(
+        &mut self,
+        _exc_type: &Bound<'_, PyAny>,
+        _exc_value: &Bound<'_, PyAny>,
+        _traceback: &Bound<'_, PyAny>,
+    ) -> bool {
+        self.guard.take();
+        false
+    }
+}
+
+/// Returns a context manager for tests that captures the Rust spans and events
+/// `filter` enables on the current thread, instead of sending them to the
+/// global subscriber, while it's entered:
+///
+/// ```python
+/// with captured_rust_tracing() as cap:
+///     rust_extension.fibonacci(10, True)
+/// assert cap.spans_matching(name="fibonacci")
+/// ```
+///
+/// `cap` is a [`CapturingLayer`]. Captures only see the thread that entered
+/// them, and nested captures see what happens inside them instead of the
+/// enclosing one.
+#[pyfunction]
+#[pyo3(signature = (filter = "trace"))]
+pub fn captured_rust_tracing(filter: &str) -> PyResult<CapturedRustTracing> {
+    Ok(CapturedRustTracing {
+        filter: Some(parse_filter(filter)?),
+        guard: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{debug, info, info_span, trace, warn};
+
+    use super::*;
+    use crate::tests::prepare_python;
+
+    fn get<'py>(record: &Bound<'py, PyAny>, key: &str) -> Bound<'py, PyAny> {
+        record.get_item(key).unwrap()
+    }
+
+    #[test]
+    fn test_capturing_layer() {
+        prepare_python();
+        let capture = Python::with_gil(|py| Py::new(py, CapturingLayer::new(py)).unwrap());
+        let bridge = Python::with_gil(|py| {
+            PythonCallbackLayerBridge::new(capture.bind(py).clone().into_any())
+        });
+        let _dispatcher = tracing_subscriber::registry().with(bridge).set_default();
+
+        let span = info_span!("request", user = "alice", status = tracing::field::Empty);
+        span.in_scope(|| {
+            info!(rows = 3, "queried");
+            warn!(target: "other::module", "slow");
+        });
+        span.record("status", 200);
+        drop(span);
+
+        Python::with_gil(|py| {
+            let capture = capture.borrow(py);
+
+            let spans = capture
+                .spans_matching(py, Some("request"), None, None)
+                .unwrap();
+            assert_eq!(1, spans.len());
+            let span = spans.get_item(0).unwrap();
+            let fields = get(&span, "fields");
+            assert_eq!("alice", get(&fields, "user").extract::<String>().unwrap());
+            assert_eq!(200, get(&fields, "status").extract::<i64>().unwrap());
+            assert!(get(&span, "closed").is_truthy().unwrap());
+
+            let events = capture
+                .events_matching(
+                    py,
+                    Some("info"),
+                    Some("pyo3_python_tracing_subscriber"),
+                    None,
+                    None,
+                )
+                .unwrap();
+            assert_eq!(1, events.len());
+            let event = events.get_item(0).unwrap();
+            assert_eq!(
+                "queried",
+                get(&event, "message").extract::<String>().unwrap()
+            );
+            assert_eq!(
+                3,
+                get(&get(&event, "fields"), "rows")
+                    .extract::<i64>()
+                    .unwrap()
+            );
+            assert!(get(&event, "span").is(&span));
+
+            let other = capture
+                .events_matching(py, None, Some("other"), None, None)
+                .unwrap();
+            assert_eq!(1, other.len());
+            assert_eq!(
+                0,
+                capture
+                    .events_matching(py, None, Some("oth"), None, None)
+                    .unwrap()
+                    .len()
+            );
+
+            capture.clear(py).unwrap();
+            assert_eq!(0, capture.events.bind(py).len());
+        });
+    }
+
+    #[test]
+    fn test_captured_rust_tracing() {
+        prepare_python();
+        Python::with_gil(|py| {
+            let none = py.None().into_bound(py);
+            let mut outer = captured_rust_tracing("debug").unwrap();
+            let outer_capture = outer.__enter__(py).unwrap();
+            info!("outer before");
+
+            let mut inner = captured_rust_tracing("trace").unwrap();
+            let inner_capture = inner.__enter__(py).unwrap();
+            trace!("inner");
+            assert!(inner.__enter__(py).is_err());
+            inner.__exit__(&none, &none, &none);
+
+            debug!("outer after");
+            trace!("filtered out");
+            outer.__exit__(&none, &none, &none);
+            info!("not captured");
+
+            let messages = |capture: &Py<CapturingLayer>| {
+                capture
+                    .borrow(py)
+                    .events
+                    .bind(py)
+                    .iter()
+                    .map(|event| get(&event, "message").extract::<String>().unwrap())
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(
+                vec!["outer before", "outer after"],
+                messages(&outer_capture)
+            );
+            assert_eq!(vec!["inner"], messages(&inner_capture));
+        });
+    }
+}
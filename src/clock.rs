@@ -0,0 +1,119 @@
+//! The source of the timestamps and durations the bridge produces.
+
+use std::{
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// A source of the current time, set with
+/// [`crate::PythonCallbackLayerBridge::with_clock`].
+///
+/// The bridge reads timestamps, like those of structlog event dicts, from
+/// [`Clock::now`], and measures durations, like callback latencies and the
+/// backtrace rate limit's window, with [`Clock::instant`].
+pub trait Clock: Send + Sync + 'static {
+    /// The current wall-clock time.
+    fn now(&self) -> SystemTime;
+
+    /// The current monotonic time.
+    fn instant(&self) -> Instant;
+}
+
+/// The system's clocks, used by default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when [`MockClock::advance`] is called, to test
+/// time-dependent behavior deterministically.
+///
+/// It starts at the Unix epoch. Clones share the same time, so a test can keep
+/// one to advance the clock of the bridge it gave another to.
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    start: Instant,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl MockClock {
+    pub fn new() -> MockClock {
+        MockClock {
+            start: Instant::now(),
+            elapsed: Arc::default(),
+        }
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap_or_else(PoisonError::into_inner) += duration;
+    }
+
+    fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> MockClock {
+        MockClock::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        UNIX_EPOCH + self.elapsed()
+    }
+
+    fn instant(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pyo3::Python;
+    use tracing::{error, info_span};
+
+    use super::*;
+    use crate::{tests::initialize_tracing_with, PayloadMode};
+
+    #[test]
+    fn test_mock_clock() {
+        let clock = MockClock::new();
+        let (py_layer, _dispatcher) = initialize_tracing_with(|bridge| {
+            bridge
+                .with_clock(clock.clone())
+                .with_error_backtraces(1)
+                .with_payload_mode(PayloadMode::StructlogEventDict)
+        });
+
+        info_span!("scope").in_scope(|| {
+            clock.advance(Duration::from_millis(1500));
+            error!("first");
+            error!("over the limit");
+            clock.advance(Duration::from_secs(1));
+            error!("next window");
+        });
+
+        Python::with_gil(|py| {
+            let borrowed = py_layer.borrow(py);
+            let events = &borrowed.event_fields;
+            assert_eq!("1970-01-01T00:00:01.500000Z", events[0]["timestamp"]);
+            assert_eq!("1970-01-01T00:00:02.500000Z", events[2]["timestamp"]);
+            assert!(events[0].get("backtrace").is_some());
+            assert!(events[1].get("backtrace").is_none());
+            assert!(events[2].get("backtrace").is_some());
+        });
+    }
+}
@@ -0,0 +1,185 @@
+//! Rules for coercing field values that downstream systems type differently.
+
+use std::{error::Error, fmt};
+
+use tracing_core::field::{Field, Visit};
+use tracing_subscriber::field::RecordFields;
+
+use crate::{
+    fields::{FieldCollector, PyFieldValue},
+    log_bridge::LOG_FIELDS,
+};
+
+/// How a [`crate::PythonCallbackLayerBridge`] built with
+/// [`crate::PythonCallbackLayerBridge::with_coercion_rules`] coerces the
+/// values of fields, in every payload mode.
+///
+/// The defaults pass values as `tracing` recorded them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CoercionRules {
+    /// Pass `bool` fields as `1` and `0`, for systems without a boolean type.
+    pub bools_as_ints: bool,
+    /// How to pass `u64` fields above `i64::MAX`, which don't fit the signed
+    /// 64-bit integer columns of systems like BigQuery.
+    pub large_ints: LargeInts,
+    /// Format fields recorded with `Debug` (`?value`) with `{:#?}`, across
+    /// several indented lines, rather than `{:?}`.
+    pub pretty_debug: bool,
+}
+
+/// How [`CoercionRules`] passes `u64` fields above `i64::MAX`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LargeInts {
+    /// As integers.
+    #[default]
+    Keep,
+    /// As their decimal strings.
+    String,
+    /// As `i64::MAX`.
+    Saturate,
+}
+
+/// A [`Visit`] collecting the values of the fields [`CoercionRules`] change.
+struct Coerced<'a> {
+    rules: &'a CoercionRules,
+    values: Vec<(&'static str, PyFieldValue)>,
+}
+
+impl Visit for Coerced<'_> {
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        if self.rules.bools_as_ints {
+            self.values
+                .push((field.name(), PyFieldValue::I64(i64::from(value))));
+        }
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        if i64::try_from(value).is_ok() {
+            return;
+        }
+        let coerced = match self.rules.large_ints {
+            LargeInts::Keep => return,
+            LargeInts::String => PyFieldValue::Str(value.to_string()),
+            LargeInts::Saturate => PyFieldValue::I64(i64::MAX),
+        };
+        self.values.push((field.name(), coerced));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if self.rules.pretty_debug {
+            self.values
+                .push((field.name(), PyFieldValue::Str(format!("{value:#?}"))));
+        }
+    }
+
+    // The other types fall back to `record_debug` otherwise.
+    fn record_i64(&mut self, _field: &Field, _value: i64) {}
+
+    fn record_f64(&mut self, _field: &Field, _value: f64) {}
+
+    fn record_i128(&mut self, _field: &Field, _value: i128) {}
+
+    fn record_u128(&mut self, _field: &Field, _value: u128) {}
+
+    fn record_str(&mut self, _field: &Field, _value: &str) {}
+
+    fn record_error(&mut self, _field: &Field, _value: &(dyn Error + 'static)) {}
+}
+
+impl CoercionRules {
+    /// Returns the coerced values of those of `fields` the rules change.
+    fn coerced(&self, fields: &impl RecordFields) -> Vec<(&'static str, PyFieldValue)> {
+        if *self == CoercionRules::default() {
+            return Vec::new();
+        }
+        let mut coerced = Coerced {
+            rules: self,
+            values: Vec::new(),
+        };
+        fields.record(&mut coerced);
+        coerced
+            .values
+            .retain(|(name, _)| !LOG_FIELDS.contains(name));
+        coerced.values
+    }
+
+    /// Replaces the values `tracing-serde` serialized `fields` as in
+    /// `payload` with their coerced values.
+    pub(crate) fn coerce_payload(
+        &self,
+        payload: &mut serde_json::Value,
+        fields: &impl RecordFields,
+    ) {
+        for (name, value) in self.coerced(fields) {
+            payload[name] = value.to_json();
+        }
+    }
+
+    /// Replaces the values `collector` collected from `fields` with their
+    /// coerced values.
+    pub(crate) fn coerce_collected(
+        &self,
+        collector: &mut FieldCollector,
+        fields: &impl RecordFields,
+    ) {
+        for (name, value) in self.coerced(fields) {
+            if let Some((_, collected)) = collector
+                .fields
+                .iter_mut()
+                .find(|(field, _)| *field == name)
+            {
+                *collected = value;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::Python;
+    use tracing::{info, info_span};
+
+    use super::*;
+    use crate::tests::initialize_tracing_with;
+
+    #[derive(Debug)]
+    #[allow(dead_code)]
+    struct Row {
+        id: u32,
+    }
+
+    #[test]
+    fn test_coercion_rules() {
+        let (py_layer, _dispatcher) = initialize_tracing_with(|bridge| {
+            bridge.with_coercion_rules(CoercionRules {
+                bools_as_ints: true,
+                large_ints: LargeInts::String,
+                pretty_debug: true,
+            })
+        });
+
+        info_span!("scope", cached = false).in_scope(|| {
+            info!(
+                ok = true,
+                small = 7u64,
+                large = u64::MAX,
+                row = ?Row { id: 1 },
+                name = "alice",
+                "coerced"
+            );
+        });
+
+        Python::with_gil(|py| {
+            let borrowed = py_layer.borrow(py);
+            assert_eq!(0, borrowed.new_spans[0]["cached"]);
+            let fields = &borrowed.event_fields[0];
+            assert_eq!(1, fields["ok"]);
+            assert_eq!(7, fields["small"]);
+            assert_eq!(u64::MAX.to_string(), fields["large"]);
+            assert_eq!("Row {\n    id: 1,\n}", fields["row"]);
+            assert_eq!("alice", fields["name"]);
+            assert_eq!("coerced", borrowed.events[0].0);
+        });
+    }
+}
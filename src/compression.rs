@@ -0,0 +1,46 @@
+//! Gzip compression of oversized event payloads, so giant fields like `Debug`
+//! dumps of data structures cost less GIL time to copy into Python.
+
+use std::io::Write;
+
+use flate2::{write::GzEncoder, Compression};
+
+/// Compresses `payload` with gzip, which Python can decompress with
+/// `gzip.decompress`.
+pub(crate) fn gzip(payload: &str) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+    encoder
+        .write_all(payload.as_bytes())
+        .expect("writing to a Vec can't fail");
+    encoder.finish().expect("writing to a Vec can't fail")
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::Python;
+    use tracing::{info, info_span};
+
+    use crate::tests::initialize_tracing_with;
+
+    #[test]
+    fn test_payload_compression() {
+        let (py_layer, _dispatcher) =
+            initialize_tracing_with(|bridge| bridge.with_payload_compression(1000));
+
+        info_span!("scope").in_scope(|| {
+            info!(dump = "x".repeat(2000), "large");
+            info!("small");
+        });
+
+        Python::with_gil(|py| {
+            let borrowed = py_layer.borrow(py);
+            assert_eq!("large", borrowed.events[0].0);
+            assert_eq!("x".repeat(2000), borrowed.event_fields[0]["dump"]);
+            assert_eq!("small", borrowed.events[1].0);
+            assert_eq!(
+                vec![Some("gzip".to_owned()), None],
+                borrowed.event_encodings
+            );
+        });
+    }
+}
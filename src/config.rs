@@ -0,0 +1,370 @@
+//! Bridge settings loaded from a JSON file or the environment, so operators can
+//! tune telemetry without changing Python or Rust code.
+
+use std::{env, fs, path::PathBuf, time::Duration};
+
+use pyo3::{exceptions::PyValueError, prelude::*, pybacked::PyBackedStr, types::PyDict};
+use serde_json::{Map, Value};
+
+use crate::{ErrorMode, PayloadMode, PythonCallbackLayerBridge};
+
+/// The settings of a [`PythonCallbackLayerBridge`] and its filter, each of
+/// which is optional so that configs can be layered with
+/// [`BridgeConfig::merge`].
+#[derive(Debug, Default)]
+pub(crate) struct BridgeConfig {
+    filter: Option<String>,
+    jsonl_path: Option<PathBuf>,
+    payload_mode: Option<PayloadMode>,
+    error_mode: Option<ErrorMode>,
+    trace_ids: Option<bool>,
+    current_span_state: Option<bool>,
+    deterministic: Option<bool>,
+    overhead_accounting: Option<bool>,
+    internal_tracing: Option<bool>,
+    pretty_json: Option<bool>,
+    sorted_fields: Option<bool>,
+    orphan_events: Option<bool>,
+    follows_from_state: Option<bool>,
+    error_backtraces: Option<u32>,
+    slow_callback_threshold: Option<Duration>,
+    tick_interval: Option<Duration>,
+}
+
+/// The config keys, each of which can also be set with an environment variable
+/// named `PYO3_TRACING_` followed by the upper-cased key.
+const KEYS: [&str; 16] = [
+    "filter",
+    "jsonl_path",
+    "payload_mode",
+    "error_mode",
+    "trace_ids",
+    "current_span_state",
+    "deterministic",
+    "overhead_accounting",
+    "internal_tracing",
+    "pretty_json",
+    "sorted_fields",
+    "orphan_events",
+    "follows_from_state",
+    "error_backtraces",
+    "slow_callback_threshold",
+    "tick_interval",
+];
+
+/// The keys whose values are strings, which environment variables set verbatim
+/// rather than as JSON.
+const STRING_KEYS: [&str; 4] = ["filter", "jsonl_path", "payload_mode", "error_mode"];
+
+fn invalid(key: &str, expected: &str) -> PyErr {
+    PyValueError::new_err(format!("config key {key:?} must be {expected}"))
+}
+
+fn bool_value(key: &str, value: &Value) -> PyResult<bool> {
+    value.as_bool().ok_or_else(|| invalid(key, "a boolean"))
+}
+
+fn str_value<'a>(key: &str, value: &'a Value) -> PyResult<&'a str> {
+    value.as_str().ok_or_else(|| invalid(key, "a string"))
+}
+
+fn seconds_value(key: &str, value: &Value) -> PyResult<Duration> {
+    value
+        .as_f64()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| invalid(key, "a non-negative number of seconds"))
+}
+
+impl BridgeConfig {
+    /// Parses a config from a JSON object.
+    pub(crate) fn from_json(config: &Map<String, Value>) -> PyResult<BridgeConfig> {
+        let mut parsed = BridgeConfig::default();
+        for (key, value) in config {
+            let key = key.as_str();
+            match key {
+                "filter" => parsed.filter = Some(str_value(key, value)?.to_owned()),
+                "jsonl_path" => parsed.jsonl_path = Some(str_value(key, value)?.into()),
+                "payload_mode" => {
+                    parsed.payload_mode = Some(match str_value(key, value)? {
+                        "json" => PayloadMode::Json,
+                        "dict" => PayloadMode::Dict,
+                        #[cfg(not(feature = "abi3"))]
+                        "buffer" => PayloadMode::Buffer,
+                        "structlog_event_dict" => PayloadMode::StructlogEventDict,
+                        _ => {
+                            return Err(invalid(
+                                key,
+                                "\"json\", \"dict\", \"buffer\" or \"structlog_event_dict\"",
+                            ))
+                        }
+                    })
+                }
+                "error_mode" => {
+                    parsed.error_mode = Some(match str_value(key, value)? {
+                        "ignore" => ErrorMode::Ignore,
+                        "log_once" => ErrorMode::LogOnce,
+                        "panic" => ErrorMode::Panic,
+                        "unraisable" => ErrorMode::Unraisable,
+                        _ => {
+                            return Err(invalid(
+                                key,
+                                "\"ignore\", \"log_once\", \"panic\" or \"unraisable\"",
+                            ))
+                        }
+                    })
+                }
+                "trace_ids" => parsed.trace_ids = Some(bool_value(key, value)?),
+                "current_span_state" => parsed.current_span_state = Some(bool_value(key, value)?),
+                "deterministic" => parsed.deterministic = Some(bool_value(key, value)?),
+                "overhead_accounting" => parsed.overhead_accounting = Some(bool_value(key, value)?),
+                "internal_tracing" => parsed.internal_tracing = Some(bool_value(key, value)?),
+                "pretty_json" => parsed.pretty_json = Some(bool_value(key, value)?),
+                "sorted_fields" => parsed.sorted_fields = Some(bool_value(key, value)?),
+                "orphan_events" => parsed.orphan_events = Some(bool_value(key, value)?),
+                "follows_from_state" => parsed.follows_from_state = Some(bool_value(key, value)?),
+                "error_backtraces" => {
+                    parsed.error_backtraces = Some(
+                        value
+                            .as_u64()
+                            .and_then(|max| u32::try_from(max).ok())
+                            .ok_or_else(|| invalid(key, "a number of backtraces per second"))?,
+                    )
+                }
+                "slow_callback_threshold" => {
+                    parsed.slow_callback_threshold = Some(seconds_value(key, value)?)
+                }
+                "tick_interval" => parsed.tick_interval = Some(seconds_value(key, value)?),
+                key => return Err(PyValueError::new_err(format!("unknown config key {key:?}"))),
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Reads a config from a JSON file containing an object.
+    pub(crate) fn from_file(path: &PathBuf) -> PyResult<BridgeConfig> {
+        let contents = fs::read_to_string(path)?;
+        let config = serde_json::from_str::<Map<String, Value>>(&contents).map_err(|err| {
+            PyValueError::new_err(format!("invalid config file {}: {err}", path.display()))
+        })?;
+        BridgeConfig::from_json(&config)
+    }
+
+    /// Reads a config from the `PYO3_TRACING_*` environment variables.
+    pub(crate) fn from_env() -> PyResult<BridgeConfig> {
+        BridgeConfig::from_vars(|name| env::var(name).ok())
+    }
+
+    /// Reads a config from the `PYO3_TRACING_*` variables `var` returns.
+    /// Booleans and numbers are parsed as JSON, e.g. `true` or `0.5`.
+    pub(crate) fn from_vars(var: impl Fn(&str) -> Option<String>) -> PyResult<BridgeConfig> {
+        let mut config = Map::new();
+        for key in KEYS {
+            let Some(raw) = var(&format!("PYO3_TRACING_{}", key.to_uppercase())) else {
+                continue;
+            };
+            let value = if STRING_KEYS.contains(&key) {
+                Value::String(raw)
+            } else {
+                serde_json::from_str(&raw).unwrap_or(Value::String(raw))
+            };
+            config.insert(key.to_owned(), value);
+        }
+        BridgeConfig::from_json(&config)
+    }
+
+    /// Parses a config from a Python `dict` with the keys of a config file.
+    pub(crate) fn from_py_dict(config: &Bound<'_, PyDict>) -> PyResult<BridgeConfig> {
+        let json = config
+            .py()
+            .import_bound("json")?
+            .call_method1("dumps", (config,))?;
+        let config = serde_json::from_str::<Map<String, Value>>(&json.extract::<PyBackedStr>()?)
+            .map_err(|err| PyValueError::new_err(format!("invalid config: {err}")))?;
+        BridgeConfig::from_json(&config)
+    }
+
+    /// Returns this config with the settings `overrides` sets replaced.
+    pub(crate) fn merge(self, overrides: BridgeConfig) -> BridgeConfig {
+        BridgeConfig {
+            filter: overrides.filter.or(self.filter),
+            jsonl_path: overrides.jsonl_path.or(self.jsonl_path),
+            payload_mode: overrides.payload_mode.or(self.payload_mode),
+            error_mode: overrides.error_mode.or(self.error_mode),
+            trace_ids: overrides.trace_ids.or(self.trace_ids),
+            current_span_state: overrides.current_span_state.or(self.current_span_state),
+            deterministic: overrides.deterministic.or(self.deterministic),
+            overhead_accounting: overrides.overhead_accounting.or(self.overhead_accounting),
+            internal_tracing: overrides.internal_tracing.or(self.internal_tracing),
+            pretty_json: overrides.pretty_json.or(self.pretty_json),
+            sorted_fields: overrides.sorted_fields.or(self.sorted_fields),
+            orphan_events: overrides.orphan_events.or(self.orphan_events),
+            follows_from_state: overrides.follows_from_state.or(self.follows_from_state),
+            error_backtraces: overrides.error_backtraces.or(self.error_backtraces),
+            slow_callback_threshold: overrides
+                .slow_callback_threshold
+                .or(self.slow_callback_threshold),
+            tick_interval: overrides.tick_interval.or(self.tick_interval),
+        }
+    }
+
+    /// Returns the config with the `filter` and `jsonl_path` arguments of an
+    /// `initialize_*` function, if given, taking precedence over it.
+    pub(crate) fn with_arguments(
+        self,
+        filter: Option<&str>,
+        jsonl_path: Option<PathBuf>,
+    ) -> BridgeConfig {
+        self.merge(BridgeConfig {
+            filter: filter.map(str::to_owned),
+            jsonl_path,
+            ..BridgeConfig::default()
+        })
+    }
+
+    /// The filter directives, `"info"` unless set.
+    pub(crate) fn filter(&self) -> &str {
+        self.filter.as_deref().unwrap_or("info")
+    }
+
+    /// Applies the settings to `bridge`. Unset settings keep their defaults.
+    pub(crate) fn apply(
+        &self,
+        mut bridge: PythonCallbackLayerBridge,
+    ) -> PyResult<PythonCallbackLayerBridge> {
+        if let Some(jsonl_path) = &self.jsonl_path {
+            let file = fs::File::options()
+                .create(true)
+                .append(true)
+                .open(jsonl_path)?;
+            bridge = bridge.with_jsonl_file(file);
+        }
+        if let Some(payload_mode) = self.payload_mode {
+            bridge = bridge.with_payload_mode(payload_mode);
+        }
+        if let Some(error_mode) = self.error_mode {
+            bridge = bridge.with_error_mode(error_mode);
+        }
+        if let Some(trace_ids) = self.trace_ids {
+            bridge = bridge.with_trace_ids(trace_ids);
+        }
+        if let Some(current_span_state) = self.current_span_state {
+            bridge = bridge.with_current_span_state(current_span_state);
+        }
+        if let Some(deterministic) = self.deterministic {
+            bridge = bridge.with_deterministic_mode(deterministic);
+        }
+        if let Some(overhead_accounting) = self.overhead_accounting {
+            bridge = bridge.with_overhead_accounting(overhead_accounting);
+        }
+        if let Some(internal_tracing) = self.internal_tracing {
+            bridge = bridge.with_internal_tracing(internal_tracing);
+        }
+        if let Some(pretty_json) = self.pretty_json {
+            bridge = bridge.with_pretty_json(pretty_json);
+        }
+        if let Some(sorted_fields) = self.sorted_fields {
+            bridge = bridge.with_sorted_fields(sorted_fields);
+        }
+        if let Some(orphan_events) = self.orphan_events {
+            bridge = bridge.with_orphan_events(orphan_events);
+        }
+        if let Some(follows_from_state) = self.follows_from_state {
+            bridge = bridge.with_follows_from_state(follows_from_state);
+        }
+        if let Some(max_per_second) = self.error_backtraces {
+            bridge = bridge.with_error_backtraces(max_per_second);
+        }
+        if let Some(threshold) = self.slow_callback_threshold {
+            bridge = bridge.with_slow_callback_threshold(threshold);
+        }
+        if let Some(interval) = self.tick_interval {
+            bridge = bridge.with_tick_interval(interval);
+        }
+        Ok(bridge)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, process};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::tests::prepare_python;
+
+    #[test]
+    fn test_bridge_config() {
+        prepare_python();
+        let path = std::env::temp_dir().join(format!("bridge-config-{}.json", process::id()));
+        fs::write(
+            &path,
+            json!({
+                "filter": "debug",
+                "payload_mode": "structlog_event_dict",
+                "trace_ids": true,
+                "slow_callback_threshold": 0.5,
+            })
+            .to_string(),
+        )
+        .unwrap();
+        let from_file = BridgeConfig::from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let config = Python::with_gil(|py| {
+            let overrides = PyDict::new_bound(py);
+            overrides.set_item("trace_ids", false).unwrap();
+            overrides.set_item("error_mode", "log_once").unwrap();
+            from_file
+                .merge(BridgeConfig::from_py_dict(&overrides).unwrap())
+                .with_arguments(None, None)
+        });
+        assert_eq!("debug", config.filter());
+        assert_eq!(Some(PayloadMode::StructlogEventDict), config.payload_mode);
+        assert_eq!(Some(false), config.trace_ids);
+        assert_eq!(Some(ErrorMode::LogOnce), config.error_mode);
+        assert_eq!(
+            Some(Duration::from_millis(500)),
+            config.slow_callback_threshold
+        );
+        assert_eq!("warn", config.with_arguments(Some("warn"), None).filter());
+
+        let invalid = [
+            json!({"filter": 1}),
+            json!({"payload_mode": "xml"}),
+            json!({"batch_size": 10}),
+        ];
+        for config in invalid {
+            assert!(BridgeConfig::from_json(config.as_object().unwrap()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_bridge_config_from_env() {
+        let vars = HashMap::from([
+            ("PYO3_TRACING_FILTER", "my_crate=debug"),
+            ("PYO3_TRACING_TRACE_IDS", "true"),
+            ("PYO3_TRACING_TICK_INTERVAL", "2.5"),
+        ]);
+        let config =
+            BridgeConfig::from_vars(|name| vars.get(name).map(|value| value.to_string())).unwrap();
+        assert_eq!("my_crate=debug", config.filter());
+        assert_eq!(Some(true), config.trace_ids);
+        assert_eq!(Some(Duration::from_millis(2500)), config.tick_interval);
+        assert_eq!(None, config.payload_mode);
+
+        let invalid = HashMap::from([("PYO3_TRACING_TRACE_IDS", "yes")]);
+        assert!(
+            BridgeConfig::from_vars(|name| invalid.get(name).map(|value| value.to_string()))
+                .is_err()
+        );
+
+        prepare_python();
+        let sorted = HashMap::from([("PYO3_TRACING_SORTED_FIELDS", "true")]);
+        let bridge = Python::with_gil(|py| {
+            PythonCallbackLayerBridge::new(py.None().into_bound(py))
+                .with_vars(|name| sorted.get(name).map(|value| value.to_string()))
+        });
+        assert!(bridge.unwrap().sorted_fields);
+    }
+}
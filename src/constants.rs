@@ -0,0 +1,82 @@
+//! Python strings for the keys and values repeated in every payload, like
+//! `metadata` and level names, created once and reused instead of allocating
+//! a new `str` for each callback.
+
+use std::collections::HashMap;
+
+use pyo3::{prelude::*, sync::GILOnceCell, types::PyString};
+
+/// The strings cached by [`py_str`].
+const STRINGS: &[&str] = &[
+    // Payload keys, from `tracing-serde` and the bridge.
+    "metadata",
+    "name",
+    "target",
+    "level",
+    "module_path",
+    "file",
+    "line",
+    "fields",
+    "is_span",
+    "is_event",
+    "message",
+    "parent",
+    "ids",
+    "trace_id",
+    "span_id",
+    "parent_span_id",
+    "baggage",
+    "contextvars",
+    "exception",
+    "backtrace",
+    // structlog `event_dict` keys.
+    "event",
+    "timestamp",
+    "logger",
+    "exc_info",
+    // `tracing` and structlog level names.
+    "TRACE",
+    "DEBUG",
+    "INFO",
+    "WARN",
+    "ERROR",
+    "debug",
+    "info",
+    "warning",
+    "error",
+];
+
+static CACHE: GILOnceCell<HashMap<&'static str, Py<PyString>>> = GILOnceCell::new();
+
+/// Returns `s` as a Python `str`, reusing a cached one for common payload keys
+/// and level names.
+pub(crate) fn py_str<'py>(py: Python<'py>, s: &str) -> Bound<'py, PyString> {
+    let cache = CACHE.get_or_init(py, || {
+        STRINGS
+            .iter()
+            .map(|&s| (s, PyString::intern_bound(py, s).unbind()))
+            .collect()
+    });
+    match cache.get(s) {
+        Some(cached) => cached.bind(py).clone(),
+        None => PyString::new_bound(py, s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::prelude::*;
+
+    use super::py_str;
+    use crate::tests::prepare_python;
+
+    #[test]
+    fn test_cached_strings() {
+        prepare_python();
+        Python::with_gil(|py| {
+            assert!(py_str(py, "metadata").is(&py_str(py, "metadata")));
+            assert!(!py_str(py, "alice").is(&py_str(py, "alice")));
+            assert_eq!("alice", py_str(py, "alice").to_cow().unwrap());
+        });
+    }
+}
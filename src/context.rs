@@ -0,0 +1,418 @@
+//! Python-side context that Rust spans pick up when they are created.
+//!
+//! Context is kept in Python `contextvars.ContextVar`s rather than Rust
+//! thread-locals so that it follows asyncio tasks and threads the same way the
+//! rest of the calling Python code's context does. Rust threads that aren't
+//! running Python code get a fresh Python context each time they acquire the
+//! GIL, so context set there doesn't persist.
+
+use std::{cell::RefCell, collections::BTreeMap};
+
+use pyo3::{
+    exceptions::PyValueError,
+    prelude::*,
+    sync::GILOnceCell,
+    types::{IntoPyDict, PyDict, PyModule, PyString},
+};
+use serde_json::{Map, Value};
+use tracing_core::{dispatcher, span};
+use tracing_subscriber::{registry::LookupSpan, Registry};
+
+use crate::propagation;
+
+static PARENT_CONTEXT: GILOnceCell<Py<PyAny>> = GILOnceCell::new();
+static CURRENT_SPAN_STATE: GILOnceCell<Py<PyAny>> = GILOnceCell::new();
+static BAGGAGE: GILOnceCell<Py<PyAny>> = GILOnceCell::new();
+
+thread_local! {
+    /// Tokens for restoring `CURRENT_SPAN_STATE` when each span entered on
+    /// this thread is exited.
+    static ENTERED_SPAN_TOKENS: RefCell<Vec<(span::Id, Py<PyAny>)>> = const { RefCell::new(Vec::new()) };
+
+    /// The parent context and baggage carried to this thread by the
+    /// [`crate::SpanContext`]s it is in the scope of, innermost last.
+    static CARRIED_CONTEXTS: RefCell<Vec<CarriedContext>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The Python parent context and baggage a [`crate::SpanContext`] captured.
+pub(crate) struct CarriedContext {
+    pub(crate) parent_context: Option<Py<PyAny>>,
+    pub(crate) baggage: Option<Baggage>,
+}
+
+impl CarriedContext {
+    /// Captures the parent context and baggage set in the calling Python
+    /// context, or those carried to this thread.
+    pub(crate) fn current(py: Python<'_>) -> CarriedContext {
+        CarriedContext {
+            parent_context: current_parent_context(py).map(Bound::unbind),
+            baggage: current_python_baggage(py),
+        }
+    }
+
+    pub(crate) fn clone_ref(&self, py: Python<'_>) -> CarriedContext {
+        CarriedContext {
+            parent_context: self
+                .parent_context
+                .as_ref()
+                .map(|context| context.clone_ref(py)),
+            baggage: self.baggage.clone(),
+        }
+    }
+
+    /// Makes this the context new spans on this thread pick up when the
+    /// calling Python context has none set, until the returned guard is
+    /// dropped.
+    pub(crate) fn carry(self) -> CarriedContextGuard {
+        CARRIED_CONTEXTS.with_borrow_mut(|carried| carried.push(self));
+        CarriedContextGuard(())
+    }
+}
+
+/// Stops carrying a [`CarriedContext`] to this thread when dropped.
+pub(crate) struct CarriedContextGuard(());
+
+impl Drop for CarriedContextGuard {
+    fn drop(&mut self) {
+        CARRIED_CONTEXTS.with_borrow_mut(|carried| carried.pop());
+    }
+}
+
+fn carried<T>(f: impl FnOnce(&CarriedContext) -> Option<T>) -> Option<T> {
+    CARRIED_CONTEXTS.with_borrow(|carried| carried.last().and_then(f))
+}
+
+/// The Python parent context that was set with [`set_parent_context`] when a
+/// root span was created. It is stored in the span's
+/// [`tracing_subscriber::registry::Extensions`] so other layers can read it.
+pub struct ParentContext(pub Py<PyAny>);
+
+/// The W3C baggage of a span: the baggage its parent span had, updated with
+/// the baggage set with [`set_baggage`] in the Python context that created it.
+/// It is stored in the span's [`tracing_subscriber::registry::Extensions`] so
+/// other layers can read it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Baggage(pub BTreeMap<String, String>);
+
+fn context_var<'py>(
+    py: Python<'py>,
+    cell: &'py GILOnceCell<Py<PyAny>>,
+    name: &str,
+) -> PyResult<&'py Bound<'py, PyAny>> {
+    cell.get_or_try_init(py, || {
+        let contextvars = PyModule::import_bound(py, "contextvars")?;
+        let var = contextvars
+            .getattr("ContextVar")?
+            .call1((format!("pyo3_python_tracing_subscriber.{name}"),))?;
+        Ok::<_, PyErr>(var.unbind())
+    })
+    .map(|var| var.bind(py))
+}
+
+fn parent_context_var(py: Python<'_>) -> PyResult<&Bound<'_, PyAny>> {
+    context_var(py, &PARENT_CONTEXT, "parent_context")
+}
+
+fn current_span_state_var(py: Python<'_>) -> PyResult<&Bound<'_, PyAny>> {
+    context_var(py, &CURRENT_SPAN_STATE, "current_span_state")
+}
+
+fn baggage_var(py: Python<'_>) -> PyResult<&Bound<'_, PyAny>> {
+    context_var(py, &BAGGAGE, "baggage")
+}
+
+/// Returns the parent context set with [`set_parent_context`] in the calling
+/// Python context, or else the one carried to this thread, if any.
+pub(crate) fn current_parent_context(py: Python<'_>) -> Option<Bound<'_, PyAny>> {
+    // Avoid creating the `ContextVar` if nothing has ever been set.
+    PARENT_CONTEXT
+        .get(py)
+        .and_then(|var| var.bind(py).call_method1("get", (py.None(),)).ok())
+        .filter(|context| !context.is_none())
+        .or_else(|| carried(|carried| carried.parent_context.as_ref().map(|c| c.bind(py).clone())))
+}
+
+/// Sets `context` as the logical parent of Rust root spans subsequently created
+/// in the calling Python context, such as the trace context of an incoming HTTP
+/// request.
+///
+/// The parent context is stored with each new root span and passed to the
+/// Python layer's `on_new_span` as the `parent_context` keyword argument.
+/// Returns a `contextvars.Token` which can be passed to
+/// [`reset_parent_context`] to restore the previous parent context.
+#[pyfunction]
+pub fn set_parent_context<'py>(
+    py: Python<'py>,
+    context: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyAny>> {
+    parent_context_var(py)?.call_method1("set", (context,))
+}
+
+/// Restores the parent context that was active before the
+/// [`set_parent_context`] call which returned `token`.
+#[pyfunction]
+pub fn reset_parent_context(py: Python<'_>, token: &Bound<'_, PyAny>) -> PyResult<()> {
+    parent_context_var(py)?.call_method1("reset", (token,))?;
+    Ok(())
+}
+
+/// Returns the baggage set with [`set_baggage`] in the calling Python context,
+/// or else the baggage carried to this thread, if any.
+pub(crate) fn current_python_baggage(py: Python<'_>) -> Option<Baggage> {
+    // Avoid creating the `ContextVar` if nothing has ever been set.
+    BAGGAGE
+        .get(py)
+        .and_then(|var| var.bind(py).call_method1("get", (py.None(),)).ok())
+        .and_then(|baggage| baggage.extract::<Option<BTreeMap<String, String>>>().ok())
+        .flatten()
+        .map(Baggage)
+        .or_else(|| carried(|carried| carried.baggage.clone()))
+}
+
+/// Sets the W3C baggage of Rust spans subsequently created in the calling
+/// Python context, replacing any baggage set before.
+///
+/// `baggage` is either a `dict` of `str` keys and values or the value of a W3C
+/// `baggage` header, e.g. `"customer.tier=gold,region=eu"`. Each new span
+/// carries its parent span's baggage updated with this baggage, which is passed
+/// to the Python layer's `on_new_span` in a `baggage` object in `span_attrs`.
+/// Returns a `contextvars.Token` which can be passed to [`reset_baggage`].
+/// Raises `ValueError` for an invalid header.
+#[pyfunction]
+pub fn set_baggage<'py>(
+    py: Python<'py>,
+    baggage: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let baggage = match baggage.downcast::<PyString>() {
+        Ok(header) => {
+            let header = header.to_cow()?;
+            propagation::parse_baggage(&header)
+                .ok_or_else(|| PyValueError::new_err(format!("invalid baggage {header:?}")))?
+        }
+        Err(_) => baggage.extract::<BTreeMap<String, String>>()?,
+    };
+    baggage_var(py)?.call_method1("set", (baggage.into_py_dict_bound(py),))
+}
+
+/// Restores the baggage that was set before the [`set_baggage`] call which
+/// returned `token`.
+#[pyfunction]
+pub fn reset_baggage(py: Python<'_>, token: &Bound<'_, PyAny>) -> PyResult<()> {
+    baggage_var(py)?.call_method1("reset", (token,))?;
+    Ok(())
+}
+
+/// Returns the baggage of the current contextual Rust span as a `dict`, or the
+/// baggage set with [`set_baggage`] in the calling Python context if no Rust
+/// span is current.
+#[pyfunction]
+pub fn current_baggage(py: Python<'_>) -> Bound<'_, PyDict> {
+    let span_baggage = dispatcher::get_default(|dispatch| {
+        let span_id = dispatch.current_span().id()?.clone();
+        let span = dispatch.downcast_ref::<Registry>()?.span(&span_id)?;
+        let baggage = span.extensions().get::<Baggage>().cloned();
+        Some(baggage.unwrap_or_default())
+    });
+
+    span_baggage
+        .or_else(|| current_python_baggage(py))
+        .unwrap_or_default()
+        .0
+        .into_py_dict_bound(py)
+}
+
+/// Returns the `str()` of the current values of the contextvars named in
+/// `names` that are set in the calling Python context.
+pub(crate) fn snapshot_context_vars(py: Python<'_>, names: &[String]) -> Map<String, Value> {
+    let mut snapshot = Map::new();
+    if names.is_empty() {
+        return snapshot;
+    }
+
+    let Ok(items) = PyModule::import_bound(py, "contextvars")
+        .and_then(|contextvars| contextvars.call_method0("copy_context"))
+        .and_then(|context| context.call_method0("items"))
+        .and_then(|items| items.iter())
+    else {
+        return snapshot;
+    };
+
+    for item in items.flatten() {
+        let Ok((var, value)) = item.extract::<(Bound<'_, PyAny>, Bound<'_, PyAny>)>() else {
+            continue;
+        };
+        let Ok(name) = var
+            .getattr("name")
+            .and_then(|name| name.extract::<String>())
+        else {
+            continue;
+        };
+        if names.contains(&name) {
+            if let Ok(value) = value.str() {
+                snapshot.insert(name, Value::String(value.to_string()));
+            }
+        }
+    }
+    snapshot
+}
+
+/// Makes `state` the value returned by [`current_span_state`] until the span
+/// `span_id` is exited on this thread.
+pub(crate) fn enter_span_state(py: Python<'_>, span_id: &span::Id, state: Option<Py<PyAny>>) {
+    let Ok(token) = current_span_state_var(py)
+        .and_then(|var| var.call_method1("set", (state.unwrap_or_else(|| py.None()),)))
+    else {
+        return;
+    };
+
+    ENTERED_SPAN_TOKENS.with_borrow_mut(|tokens| tokens.push((span_id.clone(), token.unbind())));
+}
+
+/// Restores the value [`current_span_state`] had before the span `span_id` was
+/// entered on this thread.
+pub(crate) fn exit_span_state(py: Python<'_>, span_id: &span::Id) {
+    let Some(token) = ENTERED_SPAN_TOKENS.with_borrow_mut(|tokens| {
+        let position = tokens.iter().rposition(|(id, _)| id == span_id)?;
+        Some(tokens.remove(position).1)
+    }) else {
+        return;
+    };
+
+    if let Ok(var) = current_span_state_var(py) {
+        let _ = var.call_method1("reset", (token,));
+    }
+}
+
+/// Returns the state the Python layer's `on_new_span` returned for the Rust
+/// span that is currently entered, or `None`.
+///
+/// Only available when the bridge was built with
+/// [`crate::PythonCallbackLayerBridge::with_current_span_state`].
+#[pyfunction]
+pub fn current_span_state(py: Python<'_>) -> PyResult<Bound<'_, PyAny>> {
+    current_span_state_var(py)?.call_method1("get", (py.None(),))
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{info_span, warn_span};
+
+    use super::*;
+    use crate::tests::{initialize_tracing, initialize_tracing_with};
+
+    // A Python context only outlives `with_gil` on threads that are already
+    // running Python code, so these tests hold the GIL throughout like a Python
+    // caller of a native extension would.
+
+    #[test]
+    fn test_parent_context() {
+        let (py_layer, _dispatcher) = initialize_tracing();
+
+        Python::with_gil(|py| {
+            let token =
+                set_parent_context(py, PyString::new_bound(py, "request-1").as_any()).unwrap();
+            info_span!("root").in_scope(|| {
+                let _child = warn_span!("child");
+            });
+            reset_parent_context(py, &token).unwrap();
+            let _unparented = info_span!("after reset");
+
+            let borrowed = py_layer.borrow(py);
+            let expected_parent_contexts = vec![Some("request-1".to_owned()), None, None];
+            assert_eq!(&expected_parent_contexts, &borrowed.parent_contexts);
+        });
+    }
+
+    #[test]
+    fn test_current_span_state() {
+        let (_py_layer, _dispatcher) =
+            initialize_tracing_with(|bridge| bridge.with_current_span_state(true));
+
+        Python::with_gil(|py| {
+            let current_state = || {
+                current_span_state(py)
+                    .unwrap()
+                    .extract::<Option<u16>>()
+                    .unwrap()
+            };
+
+            info_span!("outer").in_scope(|| {
+                assert_eq!(Some(0), current_state());
+                warn_span!("inner").in_scope(|| {
+                    assert_eq!(Some(1), current_state());
+                });
+                assert_eq!(Some(0), current_state());
+            });
+            assert_eq!(None, current_state());
+        });
+    }
+
+    #[test]
+    fn test_context_var_snapshot() {
+        let (py_layer, _dispatcher) = initialize_tracing_with(|bridge| {
+            bridge.with_context_vars(["request_id", "tenant", "unset"])
+        });
+
+        Python::with_gil(|py| {
+            let contextvars = PyModule::import_bound(py, "contextvars").unwrap();
+            let context_var = contextvars.getattr("ContextVar").unwrap();
+            for (name, value) in [("request_id", "abc123"), ("tenant", "acme"), ("other", "x")] {
+                let var = context_var.call1((name,)).unwrap();
+                var.call_method1("set", (value,)).unwrap();
+            }
+            context_var.call1(("unset",)).unwrap();
+
+            let _span = info_span!("handler");
+
+            let borrowed = py_layer.borrow(py);
+            let expected_new_spans = vec![serde_json::json!({
+                "level": "INFO",
+                "name": "handler",
+                "contextvars": {"request_id": "abc123", "tenant": "acme"},
+            })];
+            assert_eq!(&expected_new_spans, &borrowed.new_spans);
+        });
+    }
+
+    #[test]
+    fn test_baggage() {
+        let (py_layer, _dispatcher) = initialize_tracing();
+
+        Python::with_gil(|py| {
+            assert!(
+                set_baggage(py, PyString::new_bound(py, "no equals sign").as_any())
+                    .unwrap_err()
+                    .is_instance_of::<PyValueError>(py)
+            );
+
+            let header = PyString::new_bound(py, "customer.tier=gold, region=eu%2Dwest;ttl=5");
+            let token = set_baggage(py, header.as_any()).unwrap();
+            info_span!("root").in_scope(|| {
+                let child_baggage = [("region", "us")].into_py_dict_bound(py);
+                let child_token = set_baggage(py, child_baggage.as_any()).unwrap();
+                info_span!("child").in_scope(|| {
+                    let baggage = current_baggage(py)
+                        .extract::<BTreeMap<String, String>>()
+                        .unwrap();
+                    assert_eq!(Some("gold"), baggage.get("customer.tier").map(|s| &**s));
+                    assert_eq!(Some("us"), baggage.get("region").map(|s| &**s));
+                });
+                reset_baggage(py, &child_token).unwrap();
+            });
+            reset_baggage(py, &token).unwrap();
+            assert!(current_baggage(py).is_empty());
+
+            let borrowed = py_layer.borrow(py);
+            let expected_baggage = vec![
+                serde_json::json!({"customer.tier": "gold", "region": "eu-west"}),
+                serde_json::json!({"customer.tier": "gold", "region": "us"}),
+            ];
+            let baggage = borrowed
+                .new_spans
+                .iter()
+                .map(|span| span["baggage"].clone())
+                .collect::<Vec<_>>();
+            assert_eq!(expected_baggage, baggage);
+        });
+    }
+}
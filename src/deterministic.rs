@@ -0,0 +1,188 @@
+//! Stable span ids and timestamps, for snapshot tests of Python layers.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, PoisonError,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tracing_core::span;
+
+use crate::ids::SpanIds;
+
+/// The timestamp of every payload in deterministic mode.
+pub(crate) const FIXED_TIME: SystemTime = UNIX_EPOCH;
+
+/// The id a bridge in deterministic mode, or with unique span ids, passes to
+/// Python for a span instead of its registry id, stored in the span's
+/// extensions.
+#[derive(Clone, Copy)]
+pub(crate) struct SequentialId(pub(crate) u64);
+
+/// The counters a bridge in deterministic mode numbers spans and traces with,
+/// from 1 in the order it sees them.
+#[derive(Default)]
+pub(crate) struct Sequences {
+    spans: AtomicU64,
+    traces: AtomicU64,
+    /// The registry ids of the open spans, by their [`SequentialId`], for ids
+    /// Python passes back to resolve to their spans.
+    registry_ids: Mutex<HashMap<u64, span::Id>>,
+}
+
+impl Sequences {
+    /// Numbers the span with the registry id `span_id`.
+    pub(crate) fn next_span_id(&self, span_id: &span::Id) -> SequentialId {
+        let sequential_id = self.spans.fetch_add(1, Ordering::Relaxed) + 1;
+        self.registry_ids
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(sequential_id, span_id.clone());
+        SequentialId(sequential_id)
+    }
+
+    /// Forgets the span numbered `sequential_id`, once it closed.
+    pub(crate) fn closed(&self, sequential_id: SequentialId) {
+        self.registry_ids
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&sequential_id.0);
+    }
+
+    /// Returns the registry id of the open span numbered `sequential_id`.
+    pub(crate) fn registry_id(&self, sequential_id: u64) -> Option<span::Id> {
+        self.registry_ids
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&sequential_id)
+            .cloned()
+    }
+
+    /// [`SpanIds`] for the span with `span_id`, which start a new trace unless
+    /// the span has a `parent`.
+    pub(crate) fn span_ids(&self, span_id: SequentialId, parent: Option<&SpanIds>) -> SpanIds {
+        match parent {
+            Some(parent) => SpanIds {
+                trace_id: parent.trace_id,
+                span_id: span_id.0,
+                parent_span_id: Some(parent.span_id),
+            },
+            None => SpanIds {
+                trace_id: u128::from(self.traces.fetch_add(1, Ordering::Relaxed) + 1),
+                span_id: span_id.0,
+                parent_span_id: None,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::{prelude::*, types::PyModule};
+    use serde_json::json;
+    use tracing::{info, info_span};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use crate::{
+        tests::{initialize_tracing_with, prepare_python},
+        PayloadMode, PythonCallbackLayerBridge,
+    };
+
+    #[test]
+    fn test_deterministic_mode() {
+        for _ in 0..2 {
+            let (py_layer, _dispatcher) = initialize_tracing_with(|bridge| {
+                bridge.with_deterministic_mode(true).with_trace_ids(true)
+            });
+
+            let _unrelated = info_span!("unrelated");
+            info_span!("root").in_scope(|| {
+                info_span!("child").in_scope(|| info!("inside"));
+            });
+
+            Python::with_gil(|py| {
+                let borrowed = py_layer.borrow(py);
+                assert_eq!(vec![1, 2, 3], borrowed.new_span_ids);
+                assert_eq!(
+                    json!({
+                        "trace_id": format!("{:032x}", 2),
+                        "span_id": format!("{:016x}", 3),
+                        "parent_span_id": format!("{:016x}", 2),
+                    }),
+                    borrowed.event_fields[0]["ids"]
+                );
+            });
+        }
+
+        let (py_layer, _dispatcher) = initialize_tracing_with(|bridge| {
+            bridge
+                .with_deterministic_mode(true)
+                .with_payload_mode(PayloadMode::StructlogEventDict)
+        });
+        info_span!("scope").in_scope(|| info!("timed"));
+        Python::with_gil(|py| {
+            let borrowed = py_layer.borrow(py);
+            assert_eq!(
+                "1970-01-01T00:00:00.000000Z",
+                borrowed.event_fields[0]["timestamp"]
+            );
+        });
+    }
+
+    #[test]
+    fn test_unique_span_ids() {
+        prepare_python();
+        for unique_span_ids in [false, true] {
+            let (py_layer, bridge) = Python::with_gil(|py| {
+                let py_layer = PyModule::from_code_bound(
+                    py,
+                    "
+import json
+
+class LifecycleLayer:
+    def __init__(self):
+        self.calls = []
+
+    def on_new_span(self, span_attrs, span_id):
+        self.calls.append(('new', json.loads(span_id)[0]))
+
+    def on_close(self, span_id, state):
+        self.calls.append(('close', json.loads(span_id)[0]))
+",
+                    "lifecycle_layer.py",
+                    "lifecycle_layer",
+                )
+                .unwrap()
+                .getattr("LifecycleLayer")
+                .unwrap()
+                .call0()
+                .unwrap();
+                let bridge = PythonCallbackLayerBridge::new(py_layer.clone())
+                    .with_unique_span_ids(unique_span_ids);
+                (py_layer.unbind(), bridge)
+            });
+            let subscriber = tracing_subscriber::registry().with(bridge);
+            tracing::subscriber::with_default(subscriber, || {
+                drop(info_span!("first"));
+                drop(info_span!("second"));
+            });
+
+            Python::with_gil(|py| {
+                let calls = py_layer
+                    .getattr(py, "calls")
+                    .unwrap()
+                    .extract::<Vec<(String, u64)>>(py)
+                    .unwrap();
+                let kinds: Vec<_> = calls.iter().map(|(kind, _)| kind.as_str()).collect();
+                assert_eq!(vec!["new", "close", "new", "close"], kinds);
+                assert_eq!(calls[0].1, calls[1].1);
+                if unique_span_ids {
+                    assert_eq!((1, 2), (calls[0].1, calls[2].1));
+                }
+            });
+        }
+    }
+}
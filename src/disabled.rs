@@ -0,0 +1,170 @@
+//! A per-bridge cache of the event callsites the bridge will never forward,
+//! so their events are dropped with a single relaxed load, before looking up
+//! spans, serializing or acquiring the GIL.
+
+use std::{
+    ptr,
+    sync::{
+        atomic::{AtomicPtr, Ordering},
+        Arc, Mutex, PoisonError, Weak,
+    },
+};
+
+use tracing_core::Metadata;
+
+use crate::callsite;
+
+/// The number of slots in a [`DisabledCallsites`] table, a power of two.
+pub(crate) const SLOTS: usize = 1024;
+
+/// Returns the slot of `metadata`'s callsite in a table of [`SLOTS`] slots.
+pub(crate) fn slot_index(metadata: &Metadata<'_>) -> usize {
+    // Fibonacci hashing of the metadata's address, which is unique and stable
+    // since callsite metadata is `'static`.
+    let address = ptr::from_ref(metadata) as usize as u64;
+    (address.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> (64 - SLOTS.trailing_zeros())) as usize
+}
+
+/// The tables of every live bridge, cleared when [`rebuild_interest_cache`]
+/// makes the bridges look up their Python layer's methods again.
+///
+/// [`rebuild_interest_cache`]: crate::rebuild_interest_cache
+static TABLES: Mutex<Vec<Weak<DisabledCallsites>>> = Mutex::new(Vec::new());
+
+/// The event callsites a bridge will never forward, because its Python layer
+/// has no method for them or they're the bridge's own internal events.
+///
+/// Each callsite hashes to one slot, which holds a pointer to its metadata if
+/// it's disabled. Two disabled callsites sharing a slot just leave the second
+/// one uncached, so a callsite is never dropped because of another one.
+pub(crate) struct DisabledCallsites {
+    slots: Box<[AtomicPtr<Metadata<'static>>]>,
+}
+
+impl DisabledCallsites {
+    /// Creates an empty table, registered to be cleared by [`clear_all`].
+    pub(crate) fn new() -> Arc<DisabledCallsites> {
+        let table = Arc::new(DisabledCallsites {
+            slots: (0..SLOTS).map(|_| AtomicPtr::default()).collect(),
+        });
+        let mut tables = TABLES.lock().unwrap_or_else(PoisonError::into_inner);
+        tables.retain(|table| table.strong_count() > 0);
+        tables.push(Arc::downgrade(&table));
+        table
+    }
+
+    fn slot(&self, metadata: &Metadata<'_>) -> &AtomicPtr<Metadata<'static>> {
+        &self.slots[slot_index(metadata)]
+    }
+
+    /// Whether the callsite of `metadata` was recorded as disabled.
+    pub(crate) fn is_disabled(&self, metadata: &Metadata<'_>) -> bool {
+        ptr::eq(self.slot(metadata).load(Ordering::Relaxed), metadata)
+    }
+
+    /// Records that the callsite of `metadata` is never forwarded by the
+    /// Python layer methods looked up at `generation`, unless
+    /// [`rebuild_interest_cache`](crate::rebuild_interest_cache) was called
+    /// since.
+    pub(crate) fn disable(&self, metadata: &'static Metadata<'static>, generation: usize) {
+        if generation != callsite::callbacks_generation() {
+            return;
+        }
+        let _ = self.slot(metadata).compare_exchange(
+            ptr::null_mut(),
+            ptr::from_ref(metadata).cast_mut(),
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Forgets every disabled callsite, e.g. after the Python layer changed.
+    pub(crate) fn clear(&self) {
+        for slot in &*self.slots {
+            slot.store(ptr::null_mut(), Ordering::Relaxed);
+        }
+    }
+}
+
+/// Clears the tables of every live bridge.
+pub(crate) fn clear_all() {
+    let tables = TABLES.lock().unwrap_or_else(PoisonError::into_inner);
+    for table in tables.iter().filter_map(Weak::upgrade) {
+        table.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use pyo3::{prelude::*, types::PyModule};
+    use tracing::{info, info_span};
+    use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+    use crate::{
+        rebuild_interest_cache, tests::prepare_python, with_current_bridge,
+        PythonCallbackLayerBridge,
+    };
+
+    fn disabled_count() -> usize {
+        with_current_bridge(|bridge| {
+            bridge
+                .disabled_callsites
+                .slots
+                .iter()
+                .filter(|slot| !slot.load(Ordering::Relaxed).is_null())
+                .count()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_disabled_callsites() {
+        prepare_python();
+        let (py_class, bridge) = Python::with_gil(|py| {
+            let py_class = PyModule::from_code_bound(
+                py,
+                "
+class SpanOnlyLayer:
+    events = []
+
+    def on_new_span(self, span_attrs, span_id):
+        return None
+",
+                "span_only_layer.py",
+                "span_only_layer",
+            )
+            .unwrap()
+            .getattr("SpanOnlyLayer")
+            .unwrap();
+            let bridge = PythonCallbackLayerBridge::new(py_class.call0().unwrap());
+            (py_class.unbind(), bridge)
+        });
+        let _dispatcher = tracing_subscriber::registry().with(bridge).set_default();
+        let emit = || info_span!("scope").in_scope(|| info!("dropped until on_event exists"));
+
+        emit();
+        assert_eq!(1, disabled_count());
+        emit();
+
+        Python::with_gil(|py| {
+            let on_event = py
+                .eval_bound(
+                    "lambda self, event, state: self.events.append(event)",
+                    None,
+                    None,
+                )
+                .unwrap();
+            py_class.setattr(py, "on_event", on_event).unwrap();
+        });
+        rebuild_interest_cache();
+        assert_eq!(0, disabled_count());
+        emit();
+
+        Python::with_gil(|py| {
+            let events = py_class.getattr(py, "events").unwrap();
+            assert_eq!(1, events.bind(py).len().unwrap());
+        });
+    }
+}
@@ -0,0 +1,120 @@
+//! Errors of the bridge, and what it does when the Python layer raises.
+
+use std::{
+    collections::HashSet,
+    error::Error,
+    fmt,
+    sync::{Mutex, PoisonError},
+};
+
+use pyo3::{exceptions::PyRuntimeError, prelude::*};
+use tracing_core::{callsite::Identifier, Metadata};
+
+/// What [`crate::PythonCallbackLayerBridge`] does, besides calling its error
+/// hooks, when a method of the Python layer raises.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorMode {
+    /// Drop the exception.
+    #[default]
+    Ignore,
+    /// Print the first exception each method raises for each callsite, with its
+    /// traceback, to stderr.
+    LogOnce,
+    /// Panic in debug builds, so broken layers fail tests and development runs
+    /// loudly. Release builds behave like [`ErrorMode::LogOnce`].
+    Panic,
+    /// Pass every exception to `sys.unraisablehook`, as CPython does for
+    /// exceptions it can't raise (e.g. in `__del__`), with the method that
+    /// raised as the object, so Python error tooling sees it with its
+    /// traceback.
+    Unraisable,
+}
+
+/// An error returned by the fallible methods of
+/// [`crate::PythonCallbackLayerBridge`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum BridgeError {
+    /// The object passed to [`crate::PythonCallbackLayerBridge::try_new`]
+    /// can't be used as a Python layer.
+    Construction(String),
+    /// Data forwarded by the bridge couldn't be delivered, e.g. because the
+    /// Python layer's `flush` method raised or the JSONL file couldn't be
+    /// written.
+    Delivery(Box<dyn Error + Send + Sync>),
+    /// The Python layer's `shutdown` method raised.
+    Shutdown(Box<dyn Error + Send + Sync>),
+}
+
+impl fmt::Display for BridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BridgeError::Construction(reason) => write!(f, "invalid Python layer: {reason}"),
+            BridgeError::Delivery(err) => write!(f, "failed to deliver tracing data: {err}"),
+            BridgeError::Shutdown(err) => write!(f, "failed to shut down the Python layer: {err}"),
+        }
+    }
+}
+
+impl Error for BridgeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BridgeError::Construction(_) => None,
+            BridgeError::Delivery(err) | BridgeError::Shutdown(err) => Some(err.as_ref()),
+        }
+    }
+}
+
+impl From<BridgeError> for PyErr {
+    /// Converts to the Python exception that caused the error, if any, or a
+    /// `RuntimeError`.
+    fn from(err: BridgeError) -> PyErr {
+        match err {
+            BridgeError::Delivery(source) | BridgeError::Shutdown(source) => {
+                match source.downcast::<PyErr>() {
+                    Ok(err) => *err,
+                    Err(source) => PyRuntimeError::new_err(source.to_string()),
+                }
+            }
+            err => PyRuntimeError::new_err(err.to_string()),
+        }
+    }
+}
+
+/// The methods and callsites [`ErrorMode::LogOnce`] already printed an
+/// exception for.
+#[derive(Default)]
+pub(crate) struct LoggedErrors(Mutex<HashSet<(&'static str, Identifier)>>);
+
+impl LoggedErrors {
+    /// Returns whether this is the first exception `callback` raised for the
+    /// callsite of `metadata`.
+    pub(crate) fn first(&self, callback: &'static str, metadata: &Metadata<'_>) -> bool {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert((callback, metadata.callsite()))
+    }
+}
+
+/// Describes the exception `callback` raised for the callsite of `metadata`,
+/// with its traceback.
+pub(crate) fn describe(
+    py: Python<'_>,
+    callback: &str,
+    metadata: &Metadata<'_>,
+    err: &PyErr,
+) -> String {
+    let traceback = err
+        .traceback_bound(py)
+        .and_then(|traceback| traceback.format().ok())
+        .unwrap_or_default();
+    format!(
+        "pyo3-python-tracing-subscriber: the Python layer's {callback} raised for {}::{} ({}:{}):\n{traceback}{err}",
+        metadata.target(),
+        metadata.name(),
+        metadata.file().unwrap_or("<unknown>"),
+        metadata.line().unwrap_or_default(),
+    )
+}
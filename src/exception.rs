@@ -0,0 +1,222 @@
+//! Structured exception info reconstructed from the fields of error events.
+
+use pyo3::{
+    exceptions::PyException,
+    prelude::*,
+    sync::GILOnceCell,
+    types::{PyDict, PyTuple, PyType},
+};
+use serde_json::{json, Value};
+use tracing_core::{Event, Metadata};
+
+use crate::fields::{FieldCollector, PyFieldValue};
+
+/// Synthesized Python exception classes, by Rust error type name.
+static CLASSES: GILOnceCell<Py<PyDict>> = GILOnceCell::new();
+
+/// Whether `metadata` declares fields that [`ExceptionInfo::from_fields`]
+/// reads, so events without them don't need their fields collected.
+fn has_exception_fields(metadata: &Metadata<'_>) -> bool {
+    metadata
+        .fields()
+        .iter()
+        .any(|field| field.name() == "error" || field.name().starts_with("exception."))
+}
+
+/// Adds the [`ExceptionInfo`] of `event`, if any, to its JSON payload as an
+/// `exception` object with `type`, `message` and `chain` keys.
+pub(crate) fn insert_exception(json_event: &mut Value, event: &Event<'_>) {
+    if !has_exception_fields(event.metadata()) {
+        return;
+    }
+    let mut fields = FieldCollector::default();
+    event.record(&mut fields);
+    if let Some(info) = ExceptionInfo::from_fields(&fields) {
+        json_event["exception"] = info.to_json();
+    }
+}
+
+/// The error an event describes, either with OpenTelemetry's `exception.type`
+/// and `exception.message` fields or with an `error` field, like the one
+/// `#[instrument(err)]` records.
+pub(crate) struct ExceptionInfo {
+    pub(crate) exc_type: String,
+    pub(crate) message: String,
+    /// The messages of the error's `source()` chain, outermost first, if it was
+    /// recorded as a `std::error::Error`.
+    pub(crate) chain: Vec<String>,
+}
+
+fn text(value: &PyFieldValue) -> String {
+    match value {
+        PyFieldValue::Str(s) => s.clone(),
+        value => value.to_json().to_string(),
+    }
+}
+
+impl ExceptionInfo {
+    pub(crate) fn from_fields(fields: &FieldCollector) -> Option<ExceptionInfo> {
+        let get = |name: &str| {
+            fields
+                .fields
+                .iter()
+                .find(|(field, _)| *field == name)
+                .map(|(_, value)| text(value))
+        };
+
+        let exc_type = get("exception.type");
+        let message = get("exception.message");
+        if exc_type.is_some() || message.is_some() {
+            return Some(ExceptionInfo {
+                exc_type: exc_type.unwrap_or_else(|| "Exception".to_owned()),
+                message: message.unwrap_or_default(),
+                chain: fields.error_chain("exception.message").to_vec(),
+            });
+        }
+
+        Some(ExceptionInfo {
+            exc_type: "Error".to_owned(),
+            message: get("error")?,
+            chain: fields.error_chain("error").to_vec(),
+        })
+    }
+
+    pub(crate) fn to_json(&self) -> Value {
+        json!({
+            "type": self.exc_type,
+            "message": self.message,
+            "chain": self.chain,
+        })
+    }
+
+    /// Builds a Python exception for the error, whose `__cause__` chain holds
+    /// the error's sources.
+    ///
+    /// Its class is a subclass of `Exception` synthesized for the error type,
+    /// e.g. `std::io::Error` becomes `Error` in the `std.io` module, so
+    /// tracebacks show the Rust type name.
+    pub(crate) fn to_exception<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let mut cause: Option<Bound<'py, PyAny>> = None;
+        for message in self.chain.iter().rev() {
+            cause = Some(new_exception(py, "Error", message, cause)?);
+        }
+        new_exception(py, &self.exc_type, &self.message, cause)
+    }
+
+    /// Builds an `exc_info` tuple for [`ExceptionInfo::to_exception`], without
+    /// a traceback.
+    pub(crate) fn to_exc_info<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyTuple>> {
+        let exception = self.to_exception(py)?;
+        Ok(PyTuple::new_bound(
+            py,
+            [
+                exception.get_type().into_any(),
+                exception,
+                py.None().into_bound(py),
+            ],
+        ))
+    }
+}
+
+fn new_exception<'py>(
+    py: Python<'py>,
+    exc_type: &str,
+    message: &str,
+    cause: Option<Bound<'py, PyAny>>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let exception = exception_class(py, exc_type)?.call1((message,))?;
+    if let Some(cause) = cause {
+        exception.setattr("__cause__", cause)?;
+    }
+    Ok(exception)
+}
+
+fn exception_class<'py>(py: Python<'py>, exc_type: &str) -> PyResult<Bound<'py, PyAny>> {
+    let classes = CLASSES
+        .get_or_init(py, || PyDict::new_bound(py).unbind())
+        .bind(py);
+    if let Some(class) = classes.get_item(exc_type)? {
+        return Ok(class);
+    }
+
+    let (module, name) = match exc_type.rsplit_once("::") {
+        Some((module, name)) => (module.replace("::", "."), name),
+        None => ("builtins".to_owned(), exc_type),
+    };
+    let namespace = PyDict::new_bound(py);
+    namespace.set_item("__module__", module)?;
+    let class = py.get_type_bound::<PyType>().call1((
+        name,
+        (py.get_type_bound::<PyException>(),),
+        namespace,
+    ))?;
+    classes.set_item(exc_type, &class)?;
+    Ok(class)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use tracing::{error, info_span};
+
+    use super::*;
+    use crate::tests::{initialize_tracing_with, prepare_python, ChainedError};
+
+    #[test]
+    fn test_exception_info() {
+        let (py_layer, _dispatcher) = initialize_tracing_with(|bridge| bridge);
+
+        info_span!("scope").in_scope(|| {
+            error!(
+                exception.type = "std::io::Error",
+                exception.message = "connection reset",
+                "query failed"
+            );
+            error!(error = "timed out", "gave up");
+            let err = ChainedError::new(&["query failed", "connection reset"]);
+            error!(error = &err as &dyn Error, "retrying");
+        });
+
+        Python::with_gil(|py| {
+            let borrowed = py_layer.borrow(py);
+            assert_eq!(
+                json!({"type": "std::io::Error", "message": "connection reset", "chain": []}),
+                borrowed.event_fields[0]["exception"]
+            );
+            assert_eq!(
+                json!({"type": "Error", "message": "timed out", "chain": []}),
+                borrowed.event_fields[1]["exception"]
+            );
+            assert_eq!(
+                json!({"type": "Error", "message": "query failed", "chain": ["connection reset"]}),
+                borrowed.event_fields[2]["exception"]
+            );
+        });
+    }
+
+    #[test]
+    fn test_to_exception() {
+        prepare_python();
+        let info = ExceptionInfo {
+            exc_type: "std::io::Error".to_owned(),
+            message: "request failed".to_owned(),
+            chain: vec!["connection reset".to_owned(), "broken pipe".to_owned()],
+        };
+
+        Python::with_gil(|py| {
+            let exc_info = info.to_exc_info(py).unwrap();
+            let formatted = py
+                .import_bound("traceback")
+                .unwrap()
+                .call_method1("format_exception", exc_info)
+                .unwrap()
+                .extract::<Vec<String>>()
+                .unwrap()
+                .concat();
+            assert!(formatted.contains("Error: broken pipe"));
+            assert!(formatted.contains("Error: connection reset"));
+            assert!(formatted.ends_with("std.io.Error: request failed\n"));
+        });
+    }
+}
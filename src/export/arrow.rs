@@ -0,0 +1,373 @@
+//! Collects spans and events into Apache Arrow record batches, which Python
+//! data frame libraries can import through the Arrow PyCapsule interface.
+
+use std::{
+    ffi::CString,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use arrow_array::{
+    ffi::to_ffi, Array, ArrayRef, DurationMicrosecondArray, RecordBatch, StringArray, StructArray,
+    TimestampMicrosecondArray, UInt64Array,
+};
+use arrow_schema::{ffi::FFI_ArrowSchema, ArrowError, DataType, Field, Schema, TimeUnit};
+use pyo3::{exceptions::PyRuntimeError, prelude::*, types::PyCapsule};
+use serde_json::{Map, Value};
+use tracing_core::{span, Event, Subscriber};
+use tracing_subscriber::{
+    layer::{Context, Layer},
+    registry::LookupSpan,
+};
+
+use crate::{fields::FieldCollector, log_bridge};
+
+/// The rows collected since the last [`take_arrow_batch`], column by column.
+static ROWS: Mutex<Rows> = Mutex::new(Rows::new());
+/// Whether spans and events are being collected into `ROWS`.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+struct Rows {
+    kind: Vec<&'static str>,
+    timestamp: Vec<i64>,
+    duration: Vec<Option<i64>>,
+    span_id: Vec<Option<u64>>,
+    parent_span_id: Vec<Option<u64>>,
+    name: Vec<String>,
+    target: Vec<String>,
+    level: Vec<&'static str>,
+    fields: Vec<String>,
+}
+
+/// One row of [`Rows`].
+struct Row {
+    kind: &'static str,
+    timestamp: SystemTime,
+    duration: Option<i64>,
+    span_id: Option<u64>,
+    parent_span_id: Option<u64>,
+    name: String,
+    target: String,
+    level: &'static str,
+    fields: Map<String, Value>,
+}
+
+impl Rows {
+    const fn new() -> Rows {
+        Rows {
+            kind: Vec::new(),
+            timestamp: Vec::new(),
+            duration: Vec::new(),
+            span_id: Vec::new(),
+            parent_span_id: Vec::new(),
+            name: Vec::new(),
+            target: Vec::new(),
+            level: Vec::new(),
+            fields: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, row: Row) {
+        let timestamp = row
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros();
+        self.kind.push(row.kind);
+        self.timestamp.push(timestamp as i64);
+        self.duration.push(row.duration);
+        self.span_id.push(row.span_id);
+        self.parent_span_id.push(row.parent_span_id);
+        self.name.push(row.name);
+        self.target.push(row.target);
+        self.level.push(row.level);
+        self.fields.push(Value::Object(row.fields).to_string());
+    }
+
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("kind", DataType::Utf8, false),
+            Field::new(
+                "timestamp",
+                DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+                false,
+            ),
+            Field::new("duration", DataType::Duration(TimeUnit::Microsecond), true),
+            Field::new("span_id", DataType::UInt64, true),
+            Field::new("parent_span_id", DataType::UInt64, true),
+            Field::new("name", DataType::Utf8, false),
+            Field::new("target", DataType::Utf8, false),
+            Field::new("level", DataType::Utf8, false),
+            Field::new("fields", DataType::Utf8, false),
+        ])
+    }
+
+    fn into_batch(self) -> Result<RecordBatch, ArrowError> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(self.kind)),
+            Arc::new(TimestampMicrosecondArray::from(self.timestamp).with_timezone("UTC")),
+            Arc::new(DurationMicrosecondArray::from(self.duration)),
+            Arc::new(UInt64Array::from(self.span_id)),
+            Arc::new(UInt64Array::from(self.parent_span_id)),
+            Arc::new(StringArray::from(self.name)),
+            Arc::new(StringArray::from(self.target)),
+            Arc::new(StringArray::from(self.level)),
+            Arc::new(StringArray::from(self.fields)),
+        ];
+        RecordBatch::try_new(Arc::new(Rows::schema()), columns)
+    }
+}
+
+fn push(row: Row) {
+    ROWS.lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(row);
+}
+
+/// A span being collected, stored in the span's extensions until it closes.
+struct SpanRow {
+    timestamp: SystemTime,
+    started: Instant,
+    fields: Map<String, Value>,
+}
+
+/// A [`Layer`] that collects spans and events as rows of Arrow record batches
+/// between [`start_arrow_collection`] and [`stop_arrow_collection`].
+///
+/// Spans are collected when they close, with the time they were created and
+/// how long they were open. Events are collected with the id of the span they
+/// occurred in. Span ids are registry ids, which are reused once spans close.
+#[derive(Default)]
+pub struct ArrowLayer {
+    _private: (),
+}
+
+impl ArrowLayer {
+    pub fn new() -> ArrowLayer {
+        ArrowLayer::default()
+    }
+}
+
+impl<S> Layer<S> for ArrowLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, span_id: &span::Id, ctx: Context<'_, S>) {
+        if !ACTIVE.load(Ordering::Relaxed) {
+            return;
+        }
+        let Some(span) = ctx.span(span_id) else {
+            return;
+        };
+
+        let mut fields = FieldCollector::default();
+        attrs.record(&mut fields);
+        span.extensions_mut().insert(SpanRow {
+            timestamp: SystemTime::now(),
+            started: Instant::now(),
+            fields: fields.to_json_map(),
+        });
+    }
+
+    fn on_record(&self, span_id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(span_id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        let Some(row) = extensions.get_mut::<SpanRow>() else {
+            return;
+        };
+
+        let mut fields = FieldCollector::default();
+        values.record(&mut fields);
+        row.fields.extend(fields.to_json_map());
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if !ACTIVE.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let mut fields = FieldCollector::default();
+        event.record(&mut fields);
+        let normalized_metadata = log_bridge::normalized_metadata(event);
+        let metadata = normalized_metadata
+            .as_ref()
+            .unwrap_or_else(|| event.metadata());
+        let span_id = event
+            .parent()
+            .cloned()
+            .or_else(|| ctx.current_span().id().cloned());
+        push(Row {
+            kind: "event",
+            timestamp: SystemTime::now(),
+            duration: None,
+            span_id: span_id.map(|id| id.into_u64()),
+            parent_span_id: None,
+            name: fields
+                .take_message()
+                .unwrap_or_else(|| metadata.name().to_owned()),
+            target: metadata.target().to_owned(),
+            level: metadata.level().as_str(),
+            fields: fields.to_json_map(),
+        });
+    }
+
+    fn on_close(&self, span_id: span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&span_id) else {
+            return;
+        };
+        let Some(row) = span.extensions_mut().remove::<SpanRow>() else {
+            return;
+        };
+        if !ACTIVE.load(Ordering::Relaxed) {
+            return;
+        }
+
+        push(Row {
+            kind: "span",
+            timestamp: row.timestamp,
+            duration: Some(row.started.elapsed().as_micros() as i64),
+            span_id: Some(span_id.into_u64()),
+            parent_span_id: span.parent().map(|parent| parent.id().into_u64()),
+            name: span.name().to_owned(),
+            target: span.metadata().target().to_owned(),
+            level: span.metadata().level().as_str(),
+            fields: row.fields,
+        });
+    }
+}
+
+fn arrow_error(err: ArrowError) -> PyErr {
+    PyRuntimeError::new_err(err.to_string())
+}
+
+/// An Arrow record batch of spans and events, returned by
+/// [`take_arrow_batch`].
+///
+/// It implements the Arrow PyCapsule interface, so it can be passed to e.g.
+/// `pyarrow.record_batch`, `polars.DataFrame` or `pandas.DataFrame.from_arrow`
+/// (after converting with `pyarrow`). Its columns are `kind` (`"span"` or
+/// `"event"`), `timestamp`, `duration` (spans only), `span_id`,
+/// `parent_span_id` (spans only), `name`, `target`, `level` and `fields` (a
+/// JSON object).
+#[pyclass(frozen, module = "pyo3_python_tracing_subscriber")]
+pub struct ArrowRecordBatch(RecordBatch);
+
+impl ArrowRecordBatch {
+    /// Returns the collected record batch.
+    pub fn record_batch(&self) -> &RecordBatch {
+        &self.0
+    }
+}
+
+#[pymethods]
+impl ArrowRecordBatch {
+    fn __len__(&self) -> usize {
+        self.0.num_rows()
+    }
+
+    fn __arrow_c_schema__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyCapsule>> {
+        let schema =
+            FFI_ArrowSchema::try_from(self.0.schema_ref().as_ref()).map_err(arrow_error)?;
+        PyCapsule::new_bound(py, schema, Some(CString::new("arrow_schema").unwrap()))
+    }
+
+    /// Exports the batch as a struct array. `requested_schema` is ignored, as
+    /// the interface allows.
+    #[pyo3(signature = (requested_schema = None))]
+    fn __arrow_c_array__<'py>(
+        &self,
+        py: Python<'py>,
+        requested_schema: Option<Bound<'py, PyAny>>,
+    ) -> PyResult<(Bound<'py, PyCapsule>, Bound<'py, PyCapsule>)> {
+        let _ = requested_schema;
+        let array = StructArray::from(self.0.clone());
+        let (array, schema) = to_ffi(&array.to_data()).map_err(arrow_error)?;
+        Ok((
+            PyCapsule::new_bound(py, schema, Some(CString::new("arrow_schema").unwrap()))?,
+            PyCapsule::new_bound(py, array, Some(CString::new("arrow_array").unwrap()))?,
+        ))
+    }
+}
+
+/// Starts collecting spans and events for [`take_arrow_batch`].
+///
+/// Requires a subscriber with an [`ArrowLayer`], such as one installed by the
+/// `initialize_*` functions.
+#[pyfunction]
+pub fn start_arrow_collection() {
+    ACTIVE.store(true, Ordering::Relaxed);
+}
+
+/// Stops collecting spans and events. Those collected so far can still be
+/// taken with [`take_arrow_batch`].
+#[pyfunction]
+pub fn stop_arrow_collection() {
+    ACTIVE.store(false, Ordering::Relaxed);
+}
+
+/// Returns the spans and events collected since the last call as an
+/// [`ArrowRecordBatch`], and starts a new batch.
+#[pyfunction]
+pub fn take_arrow_batch() -> PyResult<ArrowRecordBatch> {
+    let rows = std::mem::replace(
+        &mut *ROWS.lock().unwrap_or_else(PoisonError::into_inner),
+        Rows::new(),
+    );
+    rows.into_batch().map(ArrowRecordBatch).map_err(arrow_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{cast::AsArray, ffi::from_ffi, ffi::FFI_ArrowArray};
+    use tracing::{info, info_span};
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::tests::prepare_python;
+
+    #[test]
+    fn test_arrow_batches() {
+        prepare_python();
+        let _dispatcher = tracing_subscriber::registry()
+            .with(ArrowLayer::new())
+            .set_default();
+
+        start_arrow_collection();
+        info_span!("arrow_load", rows = 3).in_scope(|| info!(cached = true, "arrow_loaded"));
+        stop_arrow_collection();
+        info!("arrow_ignored");
+
+        let batch = take_arrow_batch().unwrap();
+        let names = batch.0.column_by_name("name").unwrap().as_string::<i32>();
+        let kinds = batch.0.column_by_name("kind").unwrap().as_string::<i32>();
+        let fields = batch.0.column_by_name("fields").unwrap().as_string::<i32>();
+        let rows = (0..batch.0.num_rows())
+            .filter(|&row| names.value(row).starts_with("arrow_"))
+            .map(|row| (kinds.value(row), names.value(row), fields.value(row)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("event", "arrow_loaded", r#"{"cached":true}"#),
+                ("span", "arrow_load", r#"{"rows":3}"#),
+            ],
+            rows
+        );
+
+        Python::with_gil(|py| {
+            let (schema, array) = batch.__arrow_c_array__(py, None).unwrap();
+            let imported = unsafe {
+                let array = std::ptr::replace(
+                    array.pointer() as *mut FFI_ArrowArray,
+                    FFI_ArrowArray::empty(),
+                );
+                from_ffi(array, &*(schema.pointer() as *const FFI_ArrowSchema)).unwrap()
+            };
+            assert_eq!(StructArray::from(batch.0.clone()).to_data(), imported);
+        });
+    }
+}
@@ -0,0 +1,329 @@
+//! Writes spans and events to a file in the Chrome Trace Event format, which
+//! Perfetto and `chrome://tracing` can open and VizTracer can combine with its
+//! own reports.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, PoisonError,
+    },
+    time::Instant,
+};
+
+use pyo3::{exceptions::PyRuntimeError, prelude::*};
+use serde_json::{json, Map, Value};
+use tracing_core::{span, Event, Subscriber};
+use tracing_subscriber::{
+    layer::{Context, Layer},
+    registry::LookupSpan,
+};
+
+use super::{native_thread_id, thread_id};
+use crate::fields::FieldCollector;
+
+/// The trace being written, if one was started with [`start_chrome_trace`].
+static TRACE: Mutex<Option<ChromeTrace>> = Mutex::new(None);
+/// Whether `TRACE` is `Some`, to skip locking it when no trace is being
+/// written.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+struct ChromeTrace {
+    writer: BufWriter<File>,
+    start: Instant,
+    /// The timestamp of `start`, in microseconds.
+    start_ts: f64,
+    viztracer: bool,
+    first: bool,
+}
+
+impl ChromeTrace {
+    fn write(&mut self, mut trace_event: Value) {
+        let tid = if self.viztracer {
+            native_thread_id()
+        } else {
+            thread_id()
+        };
+        trace_event["ts"] = json!(self.start_ts + self.start.elapsed().as_secs_f64() * 1_000_000.0);
+        trace_event["pid"] = json!(process::id());
+        trace_event["tid"] = json!(tid);
+
+        let separator = if std::mem::take(&mut self.first) {
+            ""
+        } else {
+            ",\n"
+        };
+        let _ = write!(self.writer, "{separator}{trace_event}");
+    }
+}
+
+fn write(trace_event: impl FnOnce() -> Value) {
+    if !ACTIVE.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Some(trace) = TRACE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .as_mut()
+    {
+        trace.write(trace_event());
+    }
+}
+
+/// The fields of a span, kept as the `args` of its trace events.
+struct SpanArgs(Map<String, Value>);
+
+/// A [`Layer`] that writes spans and events to the file passed to
+/// [`start_chrome_trace`] until [`stop_chrome_trace`] is called.
+///
+/// Entering and exiting a span are written as duration (`B`/`E`) events with
+/// the span's fields as `args`, and events as thread-scoped instant (`i`)
+/// events with their fields as `args`. Both are categorized by target.
+#[derive(Default)]
+pub struct ChromeTraceLayer {
+    _private: (),
+}
+
+impl ChromeTraceLayer {
+    pub fn new() -> ChromeTraceLayer {
+        ChromeTraceLayer::default()
+    }
+}
+
+impl<S> Layer<S> for ChromeTraceLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, span_id: &span::Id, ctx: Context<'_, S>) {
+        if !ACTIVE.load(Ordering::Relaxed) {
+            return;
+        }
+        let Some(span) = ctx.span(span_id) else {
+            return;
+        };
+
+        let mut fields = FieldCollector::default();
+        attrs.record(&mut fields);
+        span.extensions_mut().insert(SpanArgs(fields.to_json_map()));
+    }
+
+    fn on_record(&self, span_id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(span_id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        let Some(SpanArgs(args)) = extensions.get_mut::<SpanArgs>() else {
+            return;
+        };
+
+        let mut fields = FieldCollector::default();
+        values.record(&mut fields);
+        args.extend(fields.to_json_map());
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        write(|| {
+            let mut fields = FieldCollector::default();
+            event.record(&mut fields);
+            let metadata = event.metadata();
+            let name = fields
+                .take_message()
+                .unwrap_or_else(|| metadata.name().to_owned());
+            json!({
+                "name": name,
+                "cat": metadata.target(),
+                "ph": "i",
+                "s": "t",
+                "args": fields.to_json_map(),
+            })
+        })
+    }
+
+    fn on_enter(&self, span_id: &span::Id, ctx: Context<'_, S>) {
+        write(|| {
+            let span = ctx.span(span_id);
+            let args = span
+                .as_ref()
+                .and_then(|span| {
+                    let extensions = span.extensions();
+                    extensions.get::<SpanArgs>().map(|args| args.0.clone())
+                })
+                .unwrap_or_default();
+            json!({
+                "name": span.as_ref().map(|span| span.name()),
+                "cat": span.as_ref().map(|span| span.metadata().target()),
+                "ph": "B",
+                "args": args,
+            })
+        })
+    }
+
+    fn on_exit(&self, span_id: &span::Id, ctx: Context<'_, S>) {
+        write(|| {
+            let span = ctx.span(span_id);
+            json!({
+                "name": span.as_ref().map(|span| span.name()),
+                "cat": span.as_ref().map(|span| span.metadata().target()),
+                "ph": "E",
+            })
+        })
+    }
+}
+
+/// Starts writing spans and events to a new file at `path` in the Chrome Trace
+/// Event format, until [`stop_chrome_trace`] is called.
+///
+/// With `viztracer`, the file is written like a VizTracer report, so `viztracer
+/// --combine` can merge it with reports of the Python code: timestamps are
+/// taken from Python's `time.monotonic_ns()` clock, and threads are identified
+/// by `threading.get_native_id()` (on Linux).
+///
+/// Requires a subscriber with a [`ChromeTraceLayer`], such as one installed by
+/// the `initialize_*` functions. Raises `RuntimeError` if a trace is already
+/// being written and `OSError` if the file can't be created.
+#[pyfunction]
+#[pyo3(signature = (path, viztracer = false))]
+pub fn start_chrome_trace(
+    py: Python<'_>,
+    path: std::path::PathBuf,
+    viztracer: bool,
+) -> PyResult<()> {
+    let mut trace = TRACE.lock().unwrap_or_else(PoisonError::into_inner);
+    if trace.is_some() {
+        return Err(PyRuntimeError::new_err(
+            "a Chrome trace is already being written",
+        ));
+    }
+
+    let start_ts = if viztracer {
+        let monotonic_ns = py.import_bound("time")?.call_method0("monotonic_ns")?;
+        monotonic_ns.extract::<u64>()? as f64 / 1_000.0
+    } else {
+        0.0
+    };
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(if viztracer {
+        b"{\"traceEvents\": [\n"
+    } else {
+        b"[\n"
+    })?;
+    *trace = Some(ChromeTrace {
+        writer,
+        start: Instant::now(),
+        start_ts,
+        viztracer,
+        first: true,
+    });
+    ACTIVE.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Finishes the trace started with [`start_chrome_trace`] and closes its file.
+/// Does nothing if no trace is being written.
+#[pyfunction]
+pub fn stop_chrome_trace() -> PyResult<()> {
+    let mut trace = TRACE.lock().unwrap_or_else(PoisonError::into_inner);
+    ACTIVE.store(false, Ordering::Relaxed);
+    let Some(mut trace) = trace.take() else {
+        return Ok(());
+    };
+
+    trace
+        .writer
+        .write_all(if trace.viztracer { b"\n]}\n" } else { b"\n]\n" })?;
+    trace.writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{info, info_span};
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::tests::prepare_python;
+
+    /// Serializes the tests, which share the global trace.
+    static TRACE_TEST: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn test_chrome_trace() {
+        let _guard = TRACE_TEST.lock().unwrap_or_else(PoisonError::into_inner);
+        prepare_python();
+        let _dispatcher = tracing_subscriber::registry()
+            .with(ChromeTraceLayer::new())
+            .set_default();
+        let path = std::env::temp_dir().join(format!("chrome-trace-{}.json", process::id()));
+
+        info!("before start");
+        Python::with_gil(|py| start_chrome_trace(py, path.clone(), false)).unwrap();
+        info_span!("load", rows = 3).in_scope(|| info!(cached = true, "loaded"));
+        stop_chrome_trace().unwrap();
+        info!("after stop");
+
+        let trace_events =
+            serde_json::from_str::<Vec<Value>>(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let phases = trace_events
+            .iter()
+            .map(|trace_event| trace_event["ph"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["B", "i", "E"], phases);
+        assert_eq!("load", trace_events[0]["name"]);
+        assert_eq!(3, trace_events[0]["args"]["rows"]);
+        assert_eq!("loaded", trace_events[1]["name"]);
+        assert_eq!(true, trace_events[1]["args"]["cached"]);
+        assert_eq!(module_path!(), trace_events[1]["cat"]);
+        assert_eq!(trace_events[0]["tid"], trace_events[2]["tid"]);
+    }
+
+    #[test]
+    fn test_viztracer_trace() {
+        let _guard = TRACE_TEST.lock().unwrap_or_else(PoisonError::into_inner);
+        prepare_python();
+        let _dispatcher = tracing_subscriber::registry()
+            .with(ChromeTraceLayer::new())
+            .set_default();
+        let path = std::env::temp_dir().join(format!("viztracer-{}.json", process::id()));
+
+        Python::with_gil(|py| {
+            let monotonic_us = || {
+                let time = py.import_bound("time").unwrap();
+                time.call_method0("monotonic_ns")
+                    .unwrap()
+                    .extract::<u64>()
+                    .unwrap() as f64
+                    / 1_000.0
+            };
+
+            let before = monotonic_us();
+            start_chrome_trace(py, path.clone(), true).unwrap();
+            info!("in viztracer");
+            stop_chrome_trace().unwrap();
+            let after = monotonic_us();
+
+            let report =
+                serde_json::from_str::<Value>(&std::fs::read_to_string(&path).unwrap()).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            let trace_event = &report["traceEvents"][0];
+            assert_eq!("in viztracer", trace_event["name"]);
+            let ts = trace_event["ts"].as_f64().unwrap();
+            assert!(before <= ts && ts <= after);
+
+            #[cfg(target_os = "linux")]
+            {
+                let native_id = py
+                    .import_bound("threading")
+                    .unwrap()
+                    .call_method0("get_native_id")
+                    .unwrap()
+                    .extract::<u64>()
+                    .unwrap();
+                assert_eq!(native_id, trace_event["tid"]);
+            }
+        });
+    }
+}
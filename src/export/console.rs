@@ -0,0 +1,275 @@
+//! Prints spans and events as an indented tree, for reading while developing.
+
+use std::{
+    fmt::Write as _,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, PoisonError,
+    },
+    time::Instant,
+};
+
+use pyo3::{exceptions::PyValueError, prelude::*};
+use tracing_core::{span, Event, Level, Subscriber};
+use tracing_subscriber::{
+    layer::{Context, Layer},
+    registry::LookupSpan,
+};
+
+use crate::fields::FieldCollector;
+
+/// The console output's settings, if it was enabled with
+/// [`enable_console_tracing`].
+static CONSOLE: Mutex<Option<Console>> = Mutex::new(None);
+/// Whether `CONSOLE` is `Some`.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy)]
+struct Console {
+    level: Level,
+    ansi: bool,
+}
+
+impl Console {
+    fn current(level: &Level) -> Option<Console> {
+        if !ACTIVE.load(Ordering::Relaxed) {
+            return None;
+        }
+        let console = (*CONSOLE.lock().unwrap_or_else(PoisonError::into_inner))?;
+        (*level <= console.level).then_some(console)
+    }
+
+    fn paint(&self, line: &mut String, ansi_code: &str, text: &str) {
+        if self.ansi {
+            let _ = write!(line, "\x1b[{ansi_code}m{text}\x1b[0m");
+        } else {
+            line.push_str(text);
+        }
+    }
+
+    fn indent(&self, line: &mut String, depth: usize) {
+        line.push_str(&"│ ".repeat(depth));
+    }
+
+    fn fields(&self, line: &mut String, fields: &FieldCollector) {
+        for (name, value) in &fields.fields {
+            line.push(' ');
+            self.paint(line, "2", &format!("{name}={}", value.to_json()));
+        }
+    }
+}
+
+/// Writes `line` to Python's `sys.stderr`, so it shows up in notebooks too.
+fn print(line: String) {
+    Python::with_gil(|py| {
+        if let Ok(stderr) = py.import_bound("sys").and_then(|sys| sys.getattr("stderr")) {
+            let _ = stderr.call_method1("write", (line + "\n",));
+        }
+    })
+}
+
+fn level_color(level: &Level) -> &'static str {
+    match *level {
+        Level::ERROR => "31",
+        Level::WARN => "33",
+        Level::INFO => "32",
+        Level::DEBUG => "34",
+        Level::TRACE => "35",
+    }
+}
+
+/// When a span printed by the console layer was created.
+struct ConsoleSpan {
+    created: Instant,
+}
+
+/// A [`Layer`] that prints spans and events to Python's `sys.stderr` as an
+/// indented tree between [`enable_console_tracing`] and
+/// [`disable_console_tracing`].
+///
+/// New spans are printed with their fields, events with their level, message
+/// and fields under the span they occurred in, and closed spans with how long
+/// they were open.
+#[derive(Default)]
+pub struct ConsoleLayer {
+    _private: (),
+}
+
+impl ConsoleLayer {
+    pub fn new() -> ConsoleLayer {
+        ConsoleLayer::default()
+    }
+}
+
+impl<S> Layer<S> for ConsoleLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, span_id: &span::Id, ctx: Context<'_, S>) {
+        let (Some(console), Some(span)) = (
+            Console::current(attrs.metadata().level()),
+            ctx.span(span_id),
+        ) else {
+            return;
+        };
+        span.extensions_mut().insert(ConsoleSpan {
+            created: Instant::now(),
+        });
+
+        let mut fields = FieldCollector::default();
+        attrs.record(&mut fields);
+        let mut line = String::new();
+        console.indent(&mut line, span.scope().skip(1).count());
+        line.push_str("┐ ");
+        console.paint(
+            &mut line,
+            "1",
+            &format!("{}::{}", attrs.metadata().target(), attrs.metadata().name()),
+        );
+        console.fields(&mut line, &fields);
+        print(line);
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let Some(console) = Console::current(metadata.level()) else {
+            return;
+        };
+
+        let mut fields = FieldCollector::default();
+        event.record(&mut fields);
+        let message = fields.take_message().unwrap_or_default();
+        let depth = ctx.event_scope(event).map_or(0, |scope| scope.count());
+        let mut line = String::new();
+        console.indent(&mut line, depth.saturating_sub(1));
+        line.push_str(if depth == 0 { "• " } else { "├ " });
+        console.paint(
+            &mut line,
+            level_color(metadata.level()),
+            &format!("{:<5}", metadata.level().as_str()),
+        );
+        line.push(' ');
+        line.push_str(&message);
+        console.fields(&mut line, &fields);
+        print(line);
+    }
+
+    fn on_close(&self, span_id: span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&span_id) else {
+            return;
+        };
+        let Some(console_span) = span.extensions_mut().remove::<ConsoleSpan>() else {
+            return;
+        };
+        let Some(console) = Console::current(span.metadata().level()) else {
+            return;
+        };
+
+        let mut line = String::new();
+        console.indent(&mut line, span.scope().skip(1).count());
+        line.push_str("┘ ");
+        line.push_str(span.name());
+        line.push(' ');
+        console.paint(
+            &mut line,
+            "2",
+            &format!("{:.2?}", console_span.created.elapsed()),
+        );
+        print(line);
+    }
+}
+
+/// Starts printing spans and events at or above `level` (`"trace"`, `"debug"`,
+/// `"info"`, `"warn"` or `"error"`) to `sys.stderr` as an indented tree, with
+/// ANSI colors unless `ansi` is false, until [`disable_console_tracing`] is
+/// called.
+///
+/// Requires a subscriber with a [`ConsoleLayer`], such as one installed by the
+/// `initialize_*` functions, whose filter must also let them through (see
+/// [`crate::set_level`]). Raises `ValueError` for an unknown level.
+#[pyfunction]
+#[pyo3(signature = (level = "debug", ansi = true))]
+pub fn enable_console_tracing(level: &str, ansi: bool) -> PyResult<()> {
+    let level = level
+        .parse::<Level>()
+        .map_err(|_| PyValueError::new_err(format!("unknown level {level:?}")))?;
+    *CONSOLE.lock().unwrap_or_else(PoisonError::into_inner) = Some(Console { level, ansi });
+    ACTIVE.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Stops printing spans and events to `sys.stderr`.
+#[pyfunction]
+pub fn disable_console_tracing() {
+    ACTIVE.store(false, Ordering::Relaxed);
+    CONSOLE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .take();
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{debug, info, info_span, trace};
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::tests::prepare_python;
+
+    #[test]
+    fn test_console_tracing() {
+        prepare_python();
+        let _dispatcher = tracing_subscriber::registry()
+            .with(ConsoleLayer::new())
+            .set_default();
+
+        Python::with_gil(|py| {
+            let sys = py.import_bound("sys").unwrap();
+            let stderr = sys.getattr("stderr").unwrap();
+            let output = py
+                .import_bound("io")
+                .unwrap()
+                .call_method0("StringIO")
+                .unwrap();
+            sys.setattr("stderr", &output).unwrap();
+
+            enable_console_tracing("debug", false).unwrap();
+            info_span!("console_outer", rows = 3).in_scope(|| {
+                info!(cached = true, "looked up");
+                info_span!("console_inner").in_scope(|| debug!("deeper"));
+                trace!("too verbose");
+            });
+            disable_console_tracing();
+            info!("disabled");
+            sys.setattr("stderr", stderr).unwrap();
+
+            let output = output
+                .call_method0("getvalue")
+                .unwrap()
+                .extract::<String>()
+                .unwrap();
+            let lines = output
+                .lines()
+                .map(|line| {
+                    // Replace the timings, which vary.
+                    match line.rsplit_once(' ') {
+                        Some((start, _)) if line.contains('┘') => format!("{start} <elapsed>"),
+                        _ => line.to_owned(),
+                    }
+                })
+                .collect::<Vec<_>>();
+            let target = module_path!();
+            assert_eq!(
+                vec![
+                    format!("┐ {target}::console_outer rows=3"),
+                    "├ INFO  looked up cached=true".to_owned(),
+                    format!("│ ┐ {target}::console_inner"),
+                    "│ ├ DEBUG deeper".to_owned(),
+                    "│ ┘ console_inner <elapsed>".to_owned(),
+                    "┘ console_outer <elapsed>".to_owned(),
+                ],
+                lines
+            );
+        });
+    }
+}
@@ -0,0 +1,199 @@
+//! Accumulates the time spent in spans as folded stacks, the input format of
+//! flamegraph tools like `inferno-flamegraph` and `flamegraph.pl`.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufWriter, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+use pyo3::prelude::*;
+use tracing_core::{span, Subscriber};
+use tracing_subscriber::{
+    layer::{Context, Layer},
+    registry::{LookupSpan, SpanRef},
+};
+
+/// Microseconds spent in each folded stack since [`start_flamegraph`].
+static STACKS: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+/// Whether span timings are being accumulated into `STACKS`.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// When a span was last entered and how long its children have run since,
+/// stored in the span's extensions.
+#[derive(Default)]
+struct Timing {
+    entered_at: Option<Instant>,
+    children: Duration,
+}
+
+fn frame<S>(span: &SpanRef<'_, S>) -> String
+where
+    S: for<'a> LookupSpan<'a>,
+{
+    format!("{}::{}", span.metadata().target(), span.name())
+}
+
+/// A [`Layer`] that accumulates how long each span runs, excluding time spent
+/// in its child spans, into folded stack samples between [`start_flamegraph`]
+/// and [`stop_flamegraph`].
+///
+/// Each frame is a span's target and name, and the sample counts are
+/// microseconds.
+#[derive(Default)]
+pub struct FlamegraphLayer {
+    _private: (),
+}
+
+impl FlamegraphLayer {
+    pub fn new() -> FlamegraphLayer {
+        FlamegraphLayer::default()
+    }
+}
+
+impl<S> Layer<S> for FlamegraphLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_enter(&self, span_id: &span::Id, ctx: Context<'_, S>) {
+        if !ACTIVE.load(Ordering::Relaxed) {
+            return;
+        }
+        let Some(span) = ctx.span(span_id) else {
+            return;
+        };
+
+        span.extensions_mut().replace(Timing {
+            entered_at: Some(Instant::now()),
+            children: Duration::ZERO,
+        });
+    }
+
+    fn on_exit(&self, span_id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(span_id) else {
+            return;
+        };
+        let Some(timing) = span.extensions_mut().remove::<Timing>() else {
+            return;
+        };
+        let Some(entered_at) = timing.entered_at else {
+            return;
+        };
+        let elapsed = entered_at.elapsed();
+
+        if let Some(parent) = span.parent() {
+            let mut extensions = parent.extensions_mut();
+            match extensions.get_mut::<Timing>() {
+                Some(parent_timing) => parent_timing.children += elapsed,
+                None => extensions.insert(Timing {
+                    entered_at: None,
+                    children: elapsed,
+                }),
+            }
+        }
+        if !ACTIVE.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let stack = span
+            .scope()
+            .from_root()
+            .map(|span| frame(&span))
+            .collect::<Vec<_>>()
+            .join(";");
+        let busy = elapsed.saturating_sub(timing.children).as_micros() as u64;
+        *STACKS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(stack)
+            .or_default() += busy;
+    }
+}
+
+/// Starts accumulating span timings for [`dump_flamegraph`], discarding any
+/// accumulated before.
+///
+/// Requires a subscriber with a [`FlamegraphLayer`], such as one installed by
+/// the `initialize_*` functions.
+#[pyfunction]
+pub fn start_flamegraph() {
+    STACKS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clear();
+    ACTIVE.store(true, Ordering::Relaxed);
+}
+
+/// Stops accumulating span timings. Those accumulated so far can still be
+/// written with [`dump_flamegraph`].
+#[pyfunction]
+pub fn stop_flamegraph() {
+    ACTIVE.store(false, Ordering::Relaxed);
+}
+
+/// Writes the span timings accumulated since [`start_flamegraph`] to a new file
+/// at `path` as folded stacks, one `frame;frame;frame count` line per stack.
+///
+/// Raises `OSError` if the file can't be written.
+#[pyfunction]
+pub fn dump_flamegraph(path: std::path::PathBuf) -> PyResult<()> {
+    let stacks = STACKS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    for (stack, busy) in stacks {
+        writeln!(writer, "{stack} {busy}")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{process, thread};
+
+    use tracing::info_span;
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_flamegraph() {
+        let _dispatcher = tracing_subscriber::registry()
+            .with(FlamegraphLayer::new())
+            .set_default();
+        let path = std::env::temp_dir().join(format!("flamegraph-{}.folded", process::id()));
+
+        start_flamegraph();
+        info_span!("outer").in_scope(|| {
+            thread::sleep(Duration::from_millis(5));
+            info_span!("inner").in_scope(|| thread::sleep(Duration::from_millis(20)));
+        });
+        stop_flamegraph();
+        info_span!("ignored").in_scope(|| {});
+        dump_flamegraph(path.clone()).unwrap();
+
+        let folded = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let stacks = folded
+            .lines()
+            .map(|line| {
+                let (stack, busy) = line.rsplit_once(' ').unwrap();
+                (stack.to_owned(), busy.parse::<u64>().unwrap())
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let outer = format!("{}::outer", module_path!());
+        let inner = format!("{outer};{}::inner", module_path!());
+        assert_eq!(2, stacks.len());
+        assert!((5_000..20_000).contains(&stacks[&outer]));
+        assert!(stacks[&inner] >= 20_000);
+    }
+}
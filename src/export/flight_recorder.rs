@@ -0,0 +1,237 @@
+//! Keeps the most recent records in memory, to inspect after something went
+//! wrong.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex, OnceLock, PoisonError,
+    },
+    time::SystemTime,
+};
+
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyList};
+use serde_json::Value;
+use tracing_core::{span, Event, Subscriber};
+use tracing_subscriber::{
+    layer::{Context, Layer},
+    registry::LookupSpan,
+};
+
+use super::stream::records;
+use crate::payload::iso_timestamp;
+
+/// The ring buffer, allocated by the first [`start_flight_recorder`].
+static RING: OnceLock<Ring> = OnceLock::new();
+/// Whether records are being kept in `RING`.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// A slot of a [`Ring`], holding a record and its sequence number.
+type Slot = Mutex<Option<(u64, Value)>>;
+
+/// A fixed number of slots that records are written to in turn, overwriting
+/// the oldest.
+///
+/// Writers claim slots with an atomic counter and only lock the slot they
+/// claimed, so they don't wait for each other unless they wrap around to the
+/// same slot.
+struct Ring {
+    slots: Box<[Slot]>,
+    next: AtomicU64,
+}
+
+impl Ring {
+    fn new(capacity: usize) -> Ring {
+        Ring {
+            slots: (0..capacity).map(|_| Mutex::new(None)).collect(),
+            next: AtomicU64::new(0),
+        }
+    }
+
+    fn push(&self, record: Value) {
+        let sequence = self.next.fetch_add(1, Ordering::Relaxed);
+        let slot = &self.slots[(sequence % self.slots.len() as u64) as usize];
+        *slot.lock().unwrap_or_else(PoisonError::into_inner) = Some((sequence, record));
+    }
+
+    /// Returns the records in the ring, oldest first.
+    fn records(&self) -> Vec<Value> {
+        let mut records = self
+            .slots
+            .iter()
+            .filter_map(|slot| slot.lock().unwrap_or_else(PoisonError::into_inner).clone())
+            .collect::<Vec<_>>();
+        records.sort_unstable_by_key(|(sequence, _)| *sequence);
+        records.into_iter().map(|(_, record)| record).collect()
+    }
+
+    fn clear(&self) {
+        for slot in &*self.slots {
+            slot.lock().unwrap_or_else(PoisonError::into_inner).take();
+        }
+    }
+}
+
+fn push(record: impl FnOnce() -> Value) {
+    if !ACTIVE.load(Ordering::Relaxed) {
+        return;
+    }
+    let Some(ring) = RING.get() else {
+        return;
+    };
+
+    let mut record = record();
+    record["timestamp"] = iso_timestamp(SystemTime::now()).into();
+    ring.push(record);
+}
+
+/// A [`Layer`] that keeps the most recent new spans, recorded values, events
+/// and closed spans in a ring buffer in memory between
+/// [`start_flight_recorder`] and [`stop_flight_recorder`], to be read with
+/// [`dump_recent`].
+///
+/// The records have the shape of those streamed by
+/// [`super::stream::StreamLayer`], plus an ISO 8601 `timestamp`.
+#[derive(Default)]
+pub struct FlightRecorderLayer {
+    _private: (),
+}
+
+impl FlightRecorderLayer {
+    pub fn new() -> FlightRecorderLayer {
+        FlightRecorderLayer::default()
+    }
+}
+
+impl<S> Layer<S> for FlightRecorderLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, span_id: &span::Id, _ctx: Context<'_, S>) {
+        push(|| records::new_span(attrs, span_id))
+    }
+
+    fn on_record(&self, span_id: &span::Id, values: &span::Record<'_>, _ctx: Context<'_, S>) {
+        push(|| records::record(span_id, values))
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        push(|| {
+            let span_id = event
+                .parent()
+                .cloned()
+                .or_else(|| ctx.current_span().id().cloned());
+            records::event(event, span_id.as_ref())
+        })
+    }
+
+    fn on_close(&self, span_id: span::Id, _ctx: Context<'_, S>) {
+        push(|| records::close(&span_id))
+    }
+}
+
+/// Starts keeping the last `capacity` records in memory, for [`dump_recent`].
+///
+/// The memory for them is allocated by the first call, so later calls must pass
+/// the same `capacity` or raise `ValueError`. Requires a subscriber with a
+/// [`FlightRecorderLayer`], such as one installed by the `initialize_*`
+/// functions.
+#[pyfunction]
+#[pyo3(signature = (capacity = 1000))]
+pub fn start_flight_recorder(capacity: usize) -> PyResult<()> {
+    if capacity == 0 {
+        return Err(PyValueError::new_err("capacity must be positive"));
+    }
+    let ring = RING.get_or_init(|| Ring::new(capacity));
+    if ring.slots.len() != capacity {
+        return Err(PyValueError::new_err(format!(
+            "the flight recorder was already started with a capacity of {}",
+            ring.slots.len()
+        )));
+    }
+
+    ACTIVE.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Stops keeping records. Those kept so far can still be read with
+/// [`dump_recent`].
+#[pyfunction]
+pub fn stop_flight_recorder() {
+    ACTIVE.store(false, Ordering::Relaxed);
+}
+
+/// Returns the records kept by the flight recorder as a `list` of `dict`s,
+/// oldest first, e.g. to log from an exception handler what Rust was doing
+/// before the exception. With `clear`, they are also discarded.
+#[pyfunction]
+#[pyo3(signature = (clear = false))]
+pub fn dump_recent(py: Python<'_>, clear: bool) -> PyResult<Bound<'_, PyList>> {
+    let Some(ring) = RING.get() else {
+        return Ok(PyList::empty_bound(py));
+    };
+
+    let records = ring.records();
+    if clear {
+        ring.clear();
+    }
+    let loads = py.import_bound("json")?.getattr("loads")?;
+    let records = records
+        .iter()
+        .map(|record| loads.call1((record.to_string(),)))
+        .collect::<PyResult<Vec<_>>>()?;
+    Ok(PyList::new_bound(py, records))
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::info;
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::tests::prepare_python;
+
+    #[test]
+    fn test_flight_recorder() {
+        prepare_python();
+        let _dispatcher = tracing_subscriber::registry()
+            .with(FlightRecorderLayer::new())
+            .set_default();
+
+        start_flight_recorder(3).unwrap();
+        for i in 0..5 {
+            info!(i, "recorded");
+        }
+        stop_flight_recorder();
+        info!(i = 5, "not recorded");
+
+        Python::with_gil(|py| {
+            assert!(start_flight_recorder(4)
+                .unwrap_err()
+                .is_instance_of::<PyValueError>(py));
+
+            let recent = dump_recent(py, true).unwrap();
+            let numbers = recent
+                .iter()
+                .map(|record| {
+                    record
+                        .get_item("event")
+                        .unwrap()
+                        .get_item("i")
+                        .unwrap()
+                        .extract::<i64>()
+                        .unwrap()
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(vec![2, 3, 4], numbers);
+            assert!(recent
+                .get_item(0)
+                .unwrap()
+                .get_item("timestamp")
+                .unwrap()
+                .extract::<String>()
+                .unwrap()
+                .ends_with('Z'));
+            assert!(dump_recent(py, false).unwrap().is_empty());
+        });
+    }
+}
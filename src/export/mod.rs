@@ -0,0 +1,52 @@
+//! Sinks that record `tracing` data in Rust, without calling into Python, while
+//! they are started from Python.
+//!
+//! Each sink is a [`tracing_subscriber::Layer`] that the `initialize_*`
+//! functions add to the subscriber they install. The layers do nothing until
+//! their sink is started, so subscribers built by hand can include them at
+//! little cost.
+
+use std::{
+    cell::Cell,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod chrome;
+pub mod console;
+pub mod flamegraph;
+pub mod flight_recorder;
+pub mod stats;
+pub mod stream;
+
+/// Returns a small number identifying the calling thread, as the `tid` of
+/// trace formats that need one.
+pub(crate) fn thread_id() -> u64 {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+    thread_local! {
+        static ID: Cell<u64> = const { Cell::new(0) };
+    }
+
+    ID.with(|id| {
+        if id.get() == 0 {
+            id.set(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+        }
+        id.get()
+    })
+}
+
+/// Returns the operating system's id for the calling thread where it's known
+/// (e.g. the id Python's `threading.get_native_id()` returns), and otherwise
+/// [`thread_id`].
+pub(crate) fn native_thread_id() -> u64 {
+    #[cfg(target_os = "linux")]
+    {
+        thread_local! {
+            static ID: u64 = unsafe { libc::gettid() } as u64;
+        }
+        ID.with(|id| *id)
+    }
+    #[cfg(not(target_os = "linux"))]
+    thread_id()
+}
@@ -0,0 +1,135 @@
+//! Counts spans and events per target and level, without calling into Python.
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        PoisonError, RwLock,
+    },
+};
+
+use pyo3::{prelude::*, types::PyDict};
+use tracing_core::{span, Event, Level, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// Whether a count is of spans or events, its target and its level.
+type Key = (bool, &'static str, Level);
+
+/// How many spans and events [`StatsLayer`]s have seen, by [`Key`].
+static COUNTS: RwLock<BTreeMap<Key, AtomicU64>> = RwLock::new(BTreeMap::new());
+
+fn count(metadata: &'static Metadata<'static>) {
+    let key = (metadata.is_span(), metadata.target(), *metadata.level());
+    let counts = COUNTS.read().unwrap_or_else(PoisonError::into_inner);
+    if let Some(count) = counts.get(&key) {
+        count.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    drop(counts);
+    COUNTS
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .entry(key)
+        .or_default()
+        .fetch_add(1, Ordering::Relaxed);
+}
+
+/// A [`Layer`] that counts the spans and events it sees per target and level,
+/// returned by [`get_tracing_stats`].
+///
+/// Unlike the other sinks, it always counts, since that's only an atomic
+/// increment per span or event after the first of each target and level.
+#[derive(Default)]
+pub struct StatsLayer {
+    _private: (),
+}
+
+impl StatsLayer {
+    pub fn new() -> StatsLayer {
+        StatsLayer::default()
+    }
+}
+
+impl<S: Subscriber> Layer<S> for StatsLayer {
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, _id: &span::Id, _ctx: Context<'_, S>) {
+        count(attrs.metadata());
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        count(event.metadata());
+    }
+}
+
+/// Returns how many spans and events the subscriber has recorded since the
+/// process started, as a `dict` with `spans` and `events` keys mapping targets
+/// to `dict`s of counts by level name, e.g.
+/// `{"spans": {}, "events": {"my_crate::db": {"INFO": 3, "WARN": 1}}}`.
+///
+/// Spans and events disabled by the filter aren't counted. Requires a
+/// subscriber with [`StatsLayer`], which the `initialize_*` functions
+/// include, so [`crate::initialize_stats`] gets these counts without any
+/// Python layer.
+#[pyfunction]
+pub fn get_tracing_stats(py: Python<'_>) -> PyResult<Bound<'_, PyDict>> {
+    let stats = PyDict::new_bound(py);
+    let spans = PyDict::new_bound(py);
+    let events = PyDict::new_bound(py);
+    stats.set_item("spans", &spans)?;
+    stats.set_item("events", &events)?;
+    let counts = COUNTS.read().unwrap_or_else(PoisonError::into_inner);
+    for ((is_span, target, level), count) in counts.iter() {
+        let kind = if *is_span { &spans } else { &events };
+        let by_level = match kind.get_item(target)? {
+            Some(by_level) => by_level.downcast_into::<PyDict>()?,
+            None => {
+                let by_level = PyDict::new_bound(py);
+                kind.set_item(target, &by_level)?;
+                by_level
+            }
+        };
+        by_level.set_item(level.as_str(), count.load(Ordering::Relaxed))?;
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{info, info_span, warn};
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::tests::prepare_python;
+
+    #[test]
+    fn test_tracing_stats() {
+        prepare_python();
+        let _dispatcher = tracing_subscriber::registry()
+            .with(StatsLayer::new())
+            .set_default();
+
+        info_span!(target: "stats_test", "request").in_scope(|| {
+            info!(target: "stats_test", "first");
+            info!(target: "stats_test", "second");
+            warn!(target: "stats_test::db", "slow");
+        });
+
+        Python::with_gil(|py| {
+            let stats = get_tracing_stats(py).unwrap();
+            let count = |kind: &str, target: &str, level: &str| {
+                stats
+                    .get_item(kind)
+                    .unwrap()
+                    .unwrap()
+                    .get_item(target)
+                    .unwrap()
+                    .get_item(level)
+                    .unwrap()
+                    .extract::<u64>()
+                    .unwrap()
+            };
+            assert_eq!(1, count("spans", "stats_test", "INFO"));
+            assert_eq!(2, count("events", "stats_test", "INFO"));
+            assert_eq!(1, count("events", "stats_test::db", "WARN"));
+        });
+    }
+}
@@ -0,0 +1,456 @@
+//! Streams the data the bridge would pass to Python as serialized records to
+//! another process, and reads them back there.
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+use pyo3::{
+    exceptions::{PyRuntimeError, PyValueError},
+    prelude::*,
+    sync::GILOnceCell,
+};
+use serde_json::{json, Value};
+use tracing_core::{span, Event, Subscriber};
+use tracing_serde::AsSerde;
+use tracing_subscriber::{
+    layer::{Context, Layer},
+    registry::LookupSpan,
+};
+
+use crate::log_bridge;
+
+/// How records are delimited in a stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Framing {
+    /// Each record is a line of JSON.
+    Lines,
+    /// Each record is JSON preceded by its length in bytes as a big-endian
+    /// `u32`.
+    LengthPrefixed,
+}
+
+impl Framing {
+    /// Parses the `framing` argument of the stream functions.
+    fn parse(framing: &str) -> PyResult<Framing> {
+        match framing {
+            "lines" => Ok(Framing::Lines),
+            "length_prefixed" => Ok(Framing::LengthPrefixed),
+            _ => Err(PyValueError::new_err(format!(
+                "unknown framing {framing:?}, expected \"lines\" or \"length_prefixed\""
+            ))),
+        }
+    }
+
+    /// Returns `record` as a frame.
+    pub(crate) fn encode(self, record: &Value) -> Vec<u8> {
+        let json = record.to_string();
+        let mut frame = Vec::with_capacity(json.len() + 4);
+        match self {
+            Framing::Lines => {
+                frame.extend_from_slice(json.as_bytes());
+                frame.push(b'\n');
+            }
+            Framing::LengthPrefixed => {
+                frame.extend_from_slice(&(json.len() as u32).to_be_bytes());
+                frame.extend_from_slice(json.as_bytes());
+            }
+        }
+        frame
+    }
+
+    /// Reads the next frame's JSON from `reader`, or `None` at the end of the
+    /// stream.
+    fn decode(self, reader: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+        match self {
+            Framing::Lines => {
+                let mut line = Vec::new();
+                if reader.read_until(b'\n', &mut line)? == 0 {
+                    return Ok(None);
+                }
+                if line.last() == Some(&b'\n') {
+                    line.pop();
+                }
+                Ok(Some(line))
+            }
+            Framing::LengthPrefixed => {
+                let mut len = [0; 4];
+                match reader.read_exact(&mut len) {
+                    Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                    result => result?,
+                }
+                let mut json = vec![0; u32::from_be_bytes(len) as usize];
+                reader.read_exact(&mut json)?;
+                Ok(Some(json))
+            }
+        }
+    }
+}
+
+/// The records sent for each `Layer` method. Span ids are plain integers.
+pub(crate) mod records {
+    use super::*;
+
+    pub(crate) fn new_span(attrs: &span::Attributes<'_>, span_id: &span::Id) -> Value {
+        json!({
+            "type": "new_span",
+            "span_id": span_id.into_u64(),
+            "attrs": attrs.as_serde(),
+        })
+    }
+
+    pub(crate) fn record(span_id: &span::Id, values: &span::Record<'_>) -> Value {
+        json!({
+            "type": "record",
+            "span_id": span_id.into_u64(),
+            "values": values.as_serde(),
+        })
+    }
+
+    pub(crate) fn event(event: &Event<'_>, span_id: Option<&span::Id>) -> Value {
+        let mut json_event = json!(event.as_serde());
+        log_bridge::normalize_payload(&mut json_event, event);
+        json!({
+            "type": "event",
+            "span_id": span_id.map(span::Id::into_u64),
+            "event": json_event,
+        })
+    }
+
+    pub(crate) fn close(span_id: &span::Id) -> Value {
+        json!({
+            "type": "close",
+            "span_id": span_id.into_u64(),
+        })
+    }
+}
+
+/// Where records are being streamed, if a stream was started.
+static STREAM: Mutex<Option<(Box<dyn Write + Send>, Framing)>> = Mutex::new(None);
+/// Whether `STREAM` is `Some`, to skip serializing records otherwise.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+fn write(record: impl FnOnce() -> Value) {
+    if !ACTIVE.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut stream = STREAM.lock().unwrap_or_else(PoisonError::into_inner);
+    let Some((writer, framing)) = stream.as_mut() else {
+        return;
+    };
+
+    if writer.write_all(&framing.encode(&record())).is_err() {
+        // The reader went away; stop streaming rather than failing every write.
+        *stream = None;
+        ACTIVE.store(false, Ordering::Relaxed);
+    }
+}
+
+fn start(writer: Box<dyn Write + Send>, framing: Framing) -> PyResult<()> {
+    let mut stream = STREAM.lock().unwrap_or_else(PoisonError::into_inner);
+    if stream.is_some() {
+        return Err(PyRuntimeError::new_err(
+            "records are already being streamed",
+        ));
+    }
+    *stream = Some((writer, framing));
+    ACTIVE.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// A [`Layer`] that streams new spans, recorded values, events and closed spans
+/// as JSON records to the destination of a stream started with e.g.
+/// [`start_socket_stream`], until [`stop_stream`] is called.
+///
+/// Each record has a `type` (`"new_span"`, `"record"`, `"event"` or `"close"`)
+/// and a `span_id`, which for events is the span they occurred in, along with
+/// the same `attrs`, `values` or `event` JSON the bridge passes to Python.
+/// Streaming stops if the destination can't be written to.
+#[derive(Default)]
+pub struct StreamLayer {
+    _private: (),
+}
+
+impl StreamLayer {
+    pub fn new() -> StreamLayer {
+        StreamLayer::default()
+    }
+}
+
+impl<S> Layer<S> for StreamLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, span_id: &span::Id, _ctx: Context<'_, S>) {
+        write(|| records::new_span(attrs, span_id))
+    }
+
+    fn on_record(&self, span_id: &span::Id, values: &span::Record<'_>, _ctx: Context<'_, S>) {
+        write(|| records::record(span_id, values))
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        write(|| {
+            let span_id = event
+                .parent()
+                .cloned()
+                .or_else(|| ctx.current_span().id().cloned());
+            records::event(event, span_id.as_ref())
+        })
+    }
+
+    fn on_close(&self, span_id: span::Id, _ctx: Context<'_, S>) {
+        write(|| records::close(&span_id))
+    }
+}
+
+/// Starts streaming records to the Unix domain socket at `path`, where a
+/// consumer (e.g. a [`RecordReader`] from [`listen_socket_stream`]) is
+/// listening, until [`stop_stream`] is called.
+///
+/// `framing` is `"lines"` or `"length_prefixed"`. Requires a subscriber with a
+/// [`StreamLayer`], such as one installed by the `initialize_*` functions.
+/// Raises `RuntimeError` if records are already being streamed and `OSError`
+/// if the socket can't be connected to.
+#[cfg(unix)]
+#[pyfunction]
+#[pyo3(signature = (path, framing = "lines"))]
+pub fn start_socket_stream(path: std::path::PathBuf, framing: &str) -> PyResult<()> {
+    let framing = Framing::parse(framing)?;
+    let socket = std::os::unix::net::UnixStream::connect(path)?;
+    start(Box::new(socket), framing)
+}
+
+/// Stops streaming records and closes the stream's destination. Does nothing if
+/// records aren't being streamed.
+#[pyfunction]
+pub fn stop_stream() {
+    ACTIVE.store(false, Ordering::Relaxed);
+    STREAM.lock().unwrap_or_else(PoisonError::into_inner).take();
+}
+
+/// Where a [`RecordReader`] gets its next input once its current one ends.
+enum Source {
+    #[cfg(unix)]
+    Listener(std::os::unix::net::UnixListener),
+}
+
+/// Iterates over the records of a stream, as `dict`s.
+///
+/// Iteration blocks (without holding the GIL) until the next record arrives,
+/// and ends when the writer stops streaming. Iterating again waits for the next
+/// writer to connect, so a long-running consumer can loop over the reader
+/// forever.
+#[pyclass(module = "pyo3_python_tracing_subscriber")]
+pub struct RecordReader {
+    source: Option<Source>,
+    reader: Option<BufReader<Box<dyn Read + Send>>>,
+    framing: Framing,
+}
+
+impl RecordReader {
+    fn next_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        if self.reader.is_none() {
+            let reader: Box<dyn Read + Send> = match &self.source {
+                #[cfg(unix)]
+                Some(Source::Listener(listener)) => Box::new(listener.accept()?.0),
+                None => return Ok(None),
+            };
+            self.reader = Some(BufReader::new(reader));
+        }
+
+        let frame = self
+            .framing
+            .decode(self.reader.as_mut().expect("reader was just set"))?;
+        if frame.is_none() {
+            self.reader = None;
+        }
+        Ok(frame)
+    }
+}
+
+#[pymethods]
+impl RecordReader {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        static LOADS: GILOnceCell<PyObject> = GILOnceCell::new();
+
+        let Some(frame) = py.allow_threads(|| self.next_frame())? else {
+            return Ok(None);
+        };
+        let loads = LOADS.get_or_try_init(py, || {
+            Ok::<_, PyErr>(py.import_bound("json")?.getattr("loads")?.unbind())
+        })?;
+        Ok(Some(loads.call1(py, (frame.as_slice(),))?))
+    }
+}
+
+/// Listens on a new Unix domain socket at `path` for a process streaming records
+/// with [`start_socket_stream`], and returns a [`RecordReader`] over them.
+///
+/// `framing` must match the writer's. Raises `OSError` if the socket can't be
+/// created.
+#[cfg(unix)]
+#[pyfunction]
+#[pyo3(signature = (path, framing = "lines"))]
+pub fn listen_socket_stream(path: std::path::PathBuf, framing: &str) -> PyResult<RecordReader> {
+    Ok(RecordReader {
+        source: Some(Source::Listener(std::os::unix::net::UnixListener::bind(
+            path,
+        )?)),
+        reader: None,
+        framing: Framing::parse(framing)?,
+    })
+}
+
+/// Starts streaming records to the file descriptor `fd`, typically the write
+/// end of a pipe from [`create_stream_pipe`] inherited by a worker process,
+/// until [`stop_stream`] is called.
+///
+/// Takes ownership of `fd`, closing it when streaming stops. `framing` must
+/// match the reader's. Raises `RuntimeError` if records are already being
+/// streamed.
+#[cfg(unix)]
+#[pyfunction]
+#[pyo3(signature = (fd, framing = "length_prefixed"))]
+pub fn start_fd_stream(fd: std::os::fd::RawFd, framing: &str) -> PyResult<()> {
+    use std::os::fd::{FromRawFd, OwnedFd};
+
+    let framing = Framing::parse(framing)?;
+    // SAFETY: the caller hands over `fd`, as documented.
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    start(Box::new(std::fs::File::from(fd)), framing)
+}
+
+/// Creates a pipe for a worker process to stream records to its parent, and
+/// returns a [`RecordReader`] over its read end and the file descriptor of its
+/// write end.
+///
+/// Workers started with the `fork` start method of `multiprocessing` inherit the
+/// write end and pass it to [`start_fd_stream`]. The parent should close it
+/// with `os.close` once its workers have started, so iterating over the reader
+/// ends when they exit. Records longer than `PIPE_BUF` can interleave if
+/// several workers write to the same pipe, so create one pipe per worker.
+#[cfg(unix)]
+#[pyfunction]
+#[pyo3(signature = (framing = "length_prefixed"))]
+pub fn create_stream_pipe(framing: &str) -> PyResult<(RecordReader, std::os::fd::RawFd)> {
+    use std::os::fd::{IntoRawFd, OwnedFd};
+
+    let framing = Framing::parse(framing)?;
+    let (reader, writer) = io::pipe()?;
+    let reader: Box<dyn Read + Send> = Box::new(std::fs::File::from(OwnedFd::from(reader)));
+    Ok((
+        RecordReader {
+            source: None,
+            reader: Some(BufReader::new(reader)),
+            framing,
+        },
+        OwnedFd::from(writer).into_raw_fd(),
+    ))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use pyo3::types::PyDict;
+    use tracing::{info, info_span};
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::tests::prepare_python;
+
+    /// Serializes the tests, which share the global stream.
+    static STREAM_TEST: Mutex<()> = Mutex::new(());
+
+    fn item<'py>(record: &Bound<'py, PyDict>, key: &str) -> Bound<'py, PyAny> {
+        record.get_item(key).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_socket_stream() {
+        let _guard = STREAM_TEST.lock().unwrap_or_else(PoisonError::into_inner);
+        prepare_python();
+        let _dispatcher = tracing_subscriber::registry()
+            .with(StreamLayer::new())
+            .set_default();
+        let path = std::env::temp_dir().join(format!("socket-stream-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        Python::with_gil(|py| {
+            let reader =
+                Bound::new(py, listen_socket_stream(path.clone(), "lines").unwrap()).unwrap();
+            start_socket_stream(path.clone(), "lines").unwrap();
+            info_span!("stream_span", rows = 3).in_scope(|| info!("streamed"));
+            stop_stream();
+            std::fs::remove_file(&path).unwrap();
+
+            let records = reader
+                .iter()
+                .unwrap()
+                .map(|record| record.unwrap().downcast_into::<PyDict>().unwrap())
+                .collect::<Vec<_>>();
+            let types = records
+                .iter()
+                .map(|record| item(record, "type").extract::<String>().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(vec!["new_span", "event", "close"], types);
+
+            let span_id = item(&records[0], "span_id").extract::<u64>().unwrap();
+            assert_eq!(
+                span_id,
+                item(&records[1], "span_id").extract::<u64>().unwrap()
+            );
+            assert_eq!(
+                span_id,
+                item(&records[2], "span_id").extract::<u64>().unwrap()
+            );
+            let message = item(&records[1], "event").get_item("message").unwrap();
+            assert_eq!("streamed", message.extract::<String>().unwrap());
+        });
+    }
+
+    #[test]
+    fn test_pipe_stream() {
+        let _guard = STREAM_TEST.lock().unwrap_or_else(PoisonError::into_inner);
+        prepare_python();
+        let _dispatcher = tracing_subscriber::registry()
+            .with(StreamLayer::new())
+            .set_default();
+
+        Python::with_gil(|py| {
+            let (reader, write_fd) = create_stream_pipe("length_prefixed").unwrap();
+            let reader = Bound::new(py, reader).unwrap();
+            start_fd_stream(write_fd, "length_prefixed").unwrap();
+            info!(worker = 1, "from worker");
+            stop_stream();
+
+            let records = reader
+                .iter()
+                .unwrap()
+                .map(|record| record.unwrap().downcast_into::<PyDict>().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(1, records.len());
+            let event = item(&records[0], "event");
+            assert!(item(&records[0], "span_id").is_none());
+            assert_eq!(
+                "from worker",
+                event
+                    .get_item("message")
+                    .unwrap()
+                    .extract::<String>()
+                    .unwrap()
+            );
+            assert_eq!(
+                1,
+                event.get_item("worker").unwrap().extract::<i64>().unwrap()
+            );
+        });
+    }
+}
@@ -0,0 +1,334 @@
+//! Fan-out of a bridge's callbacks to several Python layers, so they share one
+//! serialization of each payload and one slot in the span extensions.
+
+use std::sync::{Mutex, PoisonError};
+
+use pyo3::{
+    prelude::*,
+    types::{PyDict, PyTuple},
+};
+
+use crate::Backpressure;
+
+/// The methods a [`FanoutLayer`] forwards, with whether their last positional
+/// argument is the state the Python layer returned from `on_new_span`.
+const METHODS: [(&str, bool); 11] = [
+    ("on_event", true),
+    ("on_new_span", false),
+    ("on_close", true),
+    ("on_record", true),
+    ("on_follows_from", true),
+    ("on_bridge_error", false),
+    ("on_tick", false),
+    ("flush", false),
+    ("shutdown", false),
+    ("on_metrics", false),
+    ("on_subtree", false),
+];
+
+/// A Python layer forwarding every callback to several Python layers, built
+/// with [`crate::PythonCallbackLayerBridge::fanout`] or `FanoutLayer(layers)`
+/// from Python.
+///
+/// Each layer only receives the callbacks it has a method for, with its own
+/// state: `on_new_span` returns a `tuple` of the layers' states, and the
+/// other callbacks pass each layer its own. An exception raised by one layer
+/// doesn't keep the others from receiving the callback, and the bridge
+/// handles it like any other exception of a Python layer. `on_event` and
+/// `on_tick` return `Backpressure.SLOW_DOWN` if any layer does, or else
+/// `Backpressure.CLEAR` if any layer does.
+#[pyclass(frozen, module = "pyo3_python_tracing_subscriber")]
+pub struct FanoutLayer {
+    layers: Vec<Py<PyAny>>,
+    /// The layers' methods, by index in [`METHODS`], then in `layers`.
+    methods: Vec<Vec<Option<Py<PyAny>>>>,
+    /// Exceptions raised by the layers' callbacks, for the bridge to handle.
+    errors: Mutex<Vec<PyErr>>,
+}
+
+impl FanoutLayer {
+    /// Takes the exceptions the layers raised since the last call.
+    pub(crate) fn take_errors(&self) -> Vec<PyErr> {
+        std::mem::take(&mut *self.errors.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Calls the method `METHODS[index]` of every layer having it with `args`
+    /// and `kwargs`, and returns their return values, or `None` for layers
+    /// without it or that raised.
+    ///
+    /// The exceptions of span and event callbacks are kept for
+    /// [`Self::take_errors`]; of the others, the first is raised once every
+    /// layer was called, except for `on_bridge_error`'s, which are dropped like
+    /// the bridge drops them.
+    fn forward<'py>(
+        &self,
+        py: Python<'py>,
+        index: usize,
+        args: &Bound<'py, PyTuple>,
+        kwargs: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Vec<PyObject>> {
+        let (name, with_state) = METHODS[index];
+        let states = with_state
+            .then(|| args.len().checked_sub(1))
+            .flatten()
+            .map(|last| args.get_item(last))
+            .transpose()?
+            .and_then(|states| states.downcast_into::<PyTuple>().ok())
+            .filter(|states| states.len() == self.layers.len());
+
+        let mut results = Vec::with_capacity(self.layers.len());
+        let mut errors = Vec::new();
+        for (layer, method) in self.methods[index].iter().enumerate() {
+            let Some(method) = method else {
+                results.push(py.None());
+                continue;
+            };
+            let result = if with_state {
+                let mut layer_args = args.iter().collect::<Vec<_>>();
+                if let Some(state) = layer_args.last_mut() {
+                    *state = match &states {
+                        Some(states) => states.get_item(layer)?,
+                        None => py.None().into_bound(py),
+                    };
+                }
+                method
+                    .bind(py)
+                    .call(PyTuple::new_bound(py, layer_args), kwargs)
+            } else {
+                method.bind(py).call(args, kwargs)
+            };
+            match result {
+                Ok(result) => results.push(result.unbind()),
+                Err(err) => {
+                    results.push(py.None());
+                    errors.push(err);
+                }
+            }
+        }
+
+        match name {
+            "on_bridge_error" => {}
+            "on_tick" | "flush" | "shutdown" | "on_metrics" => {
+                if let Some(err) = errors.into_iter().next() {
+                    return Err(err);
+                }
+            }
+            _ => self
+                .errors
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .extend(errors),
+        }
+        Ok(results)
+    }
+}
+
+#[pymethods]
+impl FanoutLayer {
+    #[new]
+    pub fn new(layers: Vec<Bound<'_, PyAny>>) -> FanoutLayer {
+        let methods = METHODS
+            .iter()
+            .map(|(name, _)| {
+                layers
+                    .iter()
+                    .map(|layer| layer.getattr(*name).ok().map(Bound::unbind))
+                    .collect()
+            })
+            .collect();
+        FanoutLayer {
+            layers: layers.into_iter().map(Bound::unbind).collect(),
+            methods,
+            errors: Mutex::default(),
+        }
+    }
+
+    /// The layers callbacks are forwarded to.
+    #[getter]
+    fn layers(&self, py: Python<'_>) -> Vec<PyObject> {
+        self.layers
+            .iter()
+            .map(|layer| layer.clone_ref(py))
+            .collect()
+    }
+
+    #[pyo3(signature = (*args, **kwargs))]
+    fn on_event(
+        &self,
+        args: &Bound<'_, PyTuple>,
+        kwargs: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Option<Backpressure>> {
+        let returned = self.forward(args.py(), 0, args, kwargs)?;
+        Ok(Backpressure::combine(args.py(), &returned))
+    }
+
+    #[pyo3(signature = (*args, **kwargs))]
+    fn on_new_span<'py>(
+        &self,
+        args: &Bound<'py, PyTuple>,
+        kwargs: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let states = self.forward(args.py(), 1, args, kwargs)?;
+        Ok(PyTuple::new_bound(args.py(), states))
+    }
+
+    #[pyo3(signature = (*args, **kwargs))]
+    fn on_close(
+        &self,
+        args: &Bound<'_, PyTuple>,
+        kwargs: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<()> {
+        self.forward(args.py(), 2, args, kwargs).map(drop)
+    }
+
+    #[pyo3(signature = (*args, **kwargs))]
+    fn on_record(
+        &self,
+        args: &Bound<'_, PyTuple>,
+        kwargs: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<()> {
+        self.forward(args.py(), 3, args, kwargs).map(drop)
+    }
+
+    #[pyo3(signature = (*args, **kwargs))]
+    fn on_follows_from(
+        &self,
+        args: &Bound<'_, PyTuple>,
+        kwargs: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<()> {
+        self.forward(args.py(), 4, args, kwargs).map(drop)
+    }
+
+    #[pyo3(signature = (*args, **kwargs))]
+    fn on_bridge_error(
+        &self,
+        args: &Bound<'_, PyTuple>,
+        kwargs: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<()> {
+        self.forward(args.py(), 5, args, kwargs).map(drop)
+    }
+
+    #[pyo3(signature = (*args, **kwargs))]
+    fn on_tick(
+        &self,
+        args: &Bound<'_, PyTuple>,
+        kwargs: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Option<Backpressure>> {
+        let returned = self.forward(args.py(), 6, args, kwargs)?;
+        Ok(Backpressure::combine(args.py(), &returned))
+    }
+
+    #[pyo3(signature = (*args, **kwargs))]
+    fn flush(&self, args: &Bound<'_, PyTuple>, kwargs: Option<&Bound<'_, PyDict>>) -> PyResult<()> {
+        self.forward(args.py(), 7, args, kwargs).map(drop)
+    }
+
+    #[pyo3(signature = (*args, **kwargs))]
+    fn shutdown(
+        &self,
+        args: &Bound<'_, PyTuple>,
+        kwargs: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<()> {
+        self.forward(args.py(), 8, args, kwargs).map(drop)
+    }
+
+    #[pyo3(signature = (*args, **kwargs))]
+    fn on_metrics(
+        &self,
+        args: &Bound<'_, PyTuple>,
+        kwargs: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<()> {
+        self.forward(args.py(), 9, args, kwargs).map(drop)
+    }
+
+    #[pyo3(signature = (*args, **kwargs))]
+    fn on_subtree(
+        &self,
+        args: &Bound<'_, PyTuple>,
+        kwargs: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<()> {
+        self.forward(args.py(), 10, args, kwargs).map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tracing::{info, info_span};
+    use tracing_subscriber::{prelude::*, Registry};
+
+    use crate::{
+        tests::{prepare_python, PythonLayer},
+        PythonCallbackLayerBridge,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_fanout() {
+        prepare_python();
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let (bridge, layers, events) = Python::with_gil(|py| {
+            let module = PyModule::from_code_bound(
+                py,
+                "
+class EventsOnly:
+    def __init__(self):
+        self.events = []
+
+    def on_event(self, event, state):
+        self.events.append(state)
+
+class Raising:
+    def on_new_span(self, span_attrs, span_id):
+        return 'raising state'
+
+    def on_event(self, event, state):
+        raise ValueError(state)
+",
+                "fanout_layers.py",
+                "fanout_layers",
+            )
+            .unwrap();
+            let layers = [
+                Bound::new(py, PythonLayer::new()).unwrap().into_any(),
+                module.getattr("Raising").unwrap().call0().unwrap(),
+                module.getattr("EventsOnly").unwrap().call0().unwrap(),
+            ];
+            let events = layers[2].getattr("events").unwrap().unbind();
+            let bridge = PythonCallbackLayerBridge::fanout(layers.to_vec()).with_error_hook({
+                let errors = errors.clone();
+                move |py, callback, err| {
+                    errors
+                        .lock()
+                        .unwrap()
+                        .push((callback.to_owned(), err.value_bound(py).to_string()));
+                }
+            });
+            let layers = layers.map(Bound::unbind);
+            (bridge, layers, events)
+        });
+        let _guard = tracing::subscriber::set_default(Registry::default().with(bridge));
+
+        info_span!("scope").in_scope(|| info!("fanned out"));
+
+        Python::with_gil(|py| {
+            let py_layer = layers[0]
+                .bind(py)
+                .downcast::<PythonLayer>()
+                .unwrap()
+                .borrow();
+            assert_eq!(
+                ("fanned out".to_owned(), "INFO".to_owned(), 0),
+                py_layer.events[0]
+            );
+            assert_eq!(vec![0], py_layer.closed_spans);
+            assert!(events.bind(py).get_item(0).unwrap().is_none());
+        });
+        assert_eq!(
+            vec![("on_event".to_owned(), "raising state".to_owned())],
+            *errors.lock().unwrap()
+        );
+    }
+}
@@ -0,0 +1,350 @@
+//! Conversions between `tracing` field values and Python objects.
+
+use std::{error::Error, fmt};
+
+use pyo3::{
+    prelude::*,
+    types::{PyBool, PyDict, PyFloat, PyInt, PyString},
+};
+use serde_json::json;
+use tracing_core::field::{Field, Value, Visit};
+use tracing_subscriber::field::RecordFields;
+
+use crate::log_bridge::LOG_FIELDS;
+
+/// A field value that can be converted between a `tracing` field value and a
+/// Python object.
+pub(crate) enum PyFieldValue {
+    Bool(bool),
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    Str(String),
+}
+
+impl PyFieldValue {
+    /// Converts `value` to the closest `tracing` primitive. Values that aren't
+    /// bools, ints, floats or strings are recorded as their `str()`.
+    pub(crate) fn extract(value: &Bound<'_, PyAny>) -> PyResult<PyFieldValue> {
+        if value.is_instance_of::<PyBool>() {
+            Ok(PyFieldValue::Bool(value.extract()?))
+        } else if value.is_instance_of::<PyInt>() {
+            match value.extract::<i64>() {
+                Ok(i) => Ok(PyFieldValue::I64(i)),
+                Err(_) => match value.extract::<u64>() {
+                    Ok(u) => Ok(PyFieldValue::U64(u)),
+                    Err(_) => Ok(PyFieldValue::Str(value.str()?.to_string())),
+                },
+            }
+        } else if value.is_instance_of::<PyFloat>() {
+            Ok(PyFieldValue::F64(value.extract()?))
+        } else if value.is_instance_of::<PyString>() {
+            Ok(PyFieldValue::Str(value.extract()?))
+        } else {
+            Ok(PyFieldValue::Str(value.str()?.to_string()))
+        }
+    }
+
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        match self {
+            PyFieldValue::Bool(b) => (*b).into(),
+            PyFieldValue::I64(i) => (*i).into(),
+            PyFieldValue::U64(u) => (*u).into(),
+            PyFieldValue::F64(f) => (*f).into(),
+            PyFieldValue::Str(s) => s.as_str().into(),
+        }
+    }
+
+    pub(crate) fn as_value(&self) -> &dyn Value {
+        match self {
+            PyFieldValue::Bool(b) => b,
+            PyFieldValue::I64(i) => i,
+            PyFieldValue::U64(u) => u,
+            PyFieldValue::F64(f) => f,
+            PyFieldValue::Str(s) => s,
+        }
+    }
+}
+
+impl ToPyObject for PyFieldValue {
+    fn to_object(&self, py: Python<'_>) -> PyObject {
+        match self {
+            PyFieldValue::Bool(b) => b.to_object(py),
+            PyFieldValue::I64(i) => i.to_object(py),
+            PyFieldValue::U64(u) => u.to_object(py),
+            PyFieldValue::F64(f) => f.to_object(py),
+            PyFieldValue::Str(s) => s.to_object(py),
+        }
+    }
+}
+
+/// A [`Visit`] that collects the fields of an event or span. Values recorded
+/// with `Debug` are collected as their `Debug` output, and errors as their
+/// `Display` output.
+#[derive(Default)]
+pub(crate) struct FieldCollector {
+    pub(crate) fields: Vec<(&'static str, PyFieldValue)>,
+    /// The messages of the `source()` chains of fields recorded as errors.
+    error_chains: Vec<(&'static str, Vec<String>)>,
+}
+
+impl FieldCollector {
+    /// Removes and returns the `message` field, if it was recorded.
+    pub(crate) fn take_message(&mut self) -> Option<String> {
+        let position = self
+            .fields
+            .iter()
+            .position(|(name, _)| *name == "message")?;
+        match self.fields.remove(position).1 {
+            PyFieldValue::Str(message) => Some(message),
+            _ => None,
+        }
+    }
+
+    /// Returns the messages of the `source()` chain of the field `name`,
+    /// outermost first, or nothing if it wasn't recorded as an error.
+    pub(crate) fn error_chain(&self, name: &str) -> &[String] {
+        self.error_chains
+            .iter()
+            .find(|(field, _)| *field == name)
+            .map_or(&[], |(_, chain)| chain)
+    }
+
+    /// Returns the fields recorded as errors with their messages followed by
+    /// those of their `source()` chain, outermost first.
+    pub(crate) fn error_lists(&self) -> impl Iterator<Item = (&'static str, Vec<String>)> + '_ {
+        self.error_chains.iter().filter_map(|(name, chain)| {
+            let (_, value) = self.fields.iter().find(|(field, _)| field == name)?;
+            let PyFieldValue::Str(message) = value else {
+                return None;
+            };
+            Some((
+                *name,
+                [message.clone()].into_iter().chain(chain.clone()).collect(),
+            ))
+        })
+    }
+
+    /// Returns the collected fields as a JSON object.
+    pub(crate) fn to_json_map(&self) -> serde_json::Map<String, serde_json::Value> {
+        self.fields
+            .iter()
+            .map(|(name, value)| ((*name).to_owned(), value.to_json()))
+            .collect()
+    }
+
+    /// Returns the collected fields as a `dict`.
+    pub(crate) fn to_py_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new_bound(py);
+        for (name, value) in &self.fields {
+            dict.set_item(name, value)?;
+        }
+        Ok(dict)
+    }
+}
+
+impl FieldCollector {
+    fn push(&mut self, field: &Field, value: PyFieldValue) {
+        // Metadata of `log` records rather than fields.
+        if LOG_FIELDS.contains(&field.name()) {
+            return;
+        }
+        self.fields.push((field.name(), value));
+    }
+}
+
+impl Visit for FieldCollector {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.push(field, PyFieldValue::F64(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.push(field, PyFieldValue::I64(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.push(field, PyFieldValue::U64(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.push(field, PyFieldValue::Bool(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push(field, PyFieldValue::Str(value.to_owned()));
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn Error + 'static)) {
+        self.error_chains.push((field.name(), source_chain(value)));
+        self.push(field, PyFieldValue::Str(value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.push(field, PyFieldValue::Str(format!("{value:?}")));
+    }
+}
+
+/// Returns the messages of the `source()` chain of `error`, outermost first.
+fn source_chain(error: &(dyn Error + 'static)) -> Vec<String> {
+    let mut chain = Vec::new();
+    let mut source = error.source();
+    while let Some(error) = source {
+        chain.push(error.to_string());
+        source = error.source();
+    }
+    chain
+}
+
+/// A [`Visit`] that only collects the fields recorded as errors, as lists of
+/// their messages followed by those of their `source()` chain.
+#[derive(Default)]
+struct ErrorLists(Vec<(&'static str, Vec<String>)>);
+
+impl Visit for ErrorLists {
+    fn record_error(&mut self, field: &Field, value: &(dyn Error + 'static)) {
+        let mut messages = vec![value.to_string()];
+        messages.extend(source_chain(value));
+        self.0.push((field.name(), messages));
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn fmt::Debug) {}
+}
+
+/// A [`Visit`] that collects how each field was recorded, e.g. `"i64"` or
+/// `"debug"`.
+///
+/// `tracing` records values passed with `%` through `record_debug` too, so
+/// they're `"debug"` as well.
+#[derive(Default)]
+struct FieldKinds(serde_json::Map<String, serde_json::Value>);
+
+impl FieldKinds {
+    fn push(&mut self, field: &Field, kind: &str) {
+        if !LOG_FIELDS.contains(&field.name()) {
+            self.0.insert(field.name().to_owned(), kind.into());
+        }
+    }
+}
+
+impl Visit for FieldKinds {
+    fn record_f64(&mut self, field: &Field, _value: f64) {
+        self.push(field, "f64");
+    }
+
+    fn record_i64(&mut self, field: &Field, _value: i64) {
+        self.push(field, "i64");
+    }
+
+    fn record_u64(&mut self, field: &Field, _value: u64) {
+        self.push(field, "u64");
+    }
+
+    fn record_i128(&mut self, field: &Field, _value: i128) {
+        self.push(field, "i128");
+    }
+
+    fn record_u128(&mut self, field: &Field, _value: u128) {
+        self.push(field, "u128");
+    }
+
+    fn record_bool(&mut self, field: &Field, _value: bool) {
+        self.push(field, "bool");
+    }
+
+    fn record_str(&mut self, field: &Field, _value: &str) {
+        self.push(field, "str");
+    }
+
+    fn record_bytes(&mut self, field: &Field, _value: &[u8]) {
+        self.push(field, "bytes");
+    }
+
+    fn record_error(&mut self, field: &Field, _value: &(dyn Error + 'static)) {
+        self.push(field, "error");
+    }
+
+    #[cfg(all(tracing_unstable, feature = "valuable"))]
+    fn record_value(&mut self, field: &Field, _value: valuable::Value<'_>) {
+        self.push(field, "valuable");
+    }
+
+    fn record_debug(&mut self, field: &Field, _value: &dyn fmt::Debug) {
+        self.push(field, "debug");
+    }
+}
+
+/// Returns how each of `fields` was recorded, by field name, as an object
+/// for payloads' `field_kinds`.
+pub(crate) fn field_kinds(fields: &impl RecordFields) -> serde_json::Value {
+    let mut kinds = FieldKinds::default();
+    fields.record(&mut kinds);
+    kinds.0.into()
+}
+
+/// Replaces the strings that `tracing-serde` serializes some fields as in
+/// `payload` with their full values: errors with their `source()` chains, and,
+/// with the features enabling them, `valuable` structures and exact 128-bit
+/// integers.
+pub(crate) fn insert_recorded_values(payload: &mut serde_json::Value, fields: &impl RecordFields) {
+    insert_error_chains(payload, fields);
+    #[cfg(all(tracing_unstable, feature = "valuable"))]
+    crate::structured::insert_structured_fields(payload, fields);
+    #[cfg(feature = "arbitrary-precision")]
+    crate::wide_ints::insert_wide_ints(payload, fields);
+}
+
+/// Replaces the `Display` strings that `tracing-serde` serializes fields
+/// recorded as errors as in `payload` with lists of their messages followed
+/// by those of their `source()` chain, so root causes aren't lost.
+fn insert_error_chains(payload: &mut serde_json::Value, fields: &impl RecordFields) {
+    let mut errors = ErrorLists::default();
+    fields.record(&mut errors);
+    for (name, messages) in errors.0 {
+        if LOG_FIELDS.contains(&name) {
+            continue;
+        }
+        payload[name] = json!(messages);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::Python;
+    use serde_json::json;
+    use tracing::{info, info_span};
+
+    use crate::{tests::initialize_tracing_with, PayloadMode};
+
+    #[test]
+    fn test_field_kinds() {
+        for payload_mode in [PayloadMode::Json, PayloadMode::StructlogEventDict] {
+            let (py_layer, _dispatcher) = initialize_tracing_with(|bridge| {
+                bridge
+                    .with_field_kinds(true)
+                    .with_payload_mode(payload_mode)
+            });
+
+            info_span!("scope").in_scope(|| {
+                info!(
+                    count = 1337,
+                    ratio = 0.5,
+                    shown = %1337,
+                    debugged = ?"1337",
+                    "kinds"
+                );
+            });
+
+            Python::with_gil(|py| {
+                let borrowed = py_layer.borrow(py);
+                let kinds = json!({
+                    "message": "debug",
+                    "count": "i64",
+                    "ratio": "f64",
+                    "shown": "debug",
+                    "debugged": "debug",
+                });
+                assert_eq!(kinds, borrowed.event_fields[0]["field_kinds"]);
+            });
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Falling back to the state of the spans a span follows from, for pipelines
+//! that model causality with `follows_from` links rather than parents.
+
+use pyo3::prelude::*;
+use tracing_subscriber::registry::{Extensions, LookupSpan, SpanRef};
+
+use crate::PythonLayerState;
+
+/// The states of the spans a span follows from, in the order the links were
+/// added, stored in its extensions by bridges built with
+/// [`crate::PythonCallbackLayerBridge::with_follows_from_state`].
+pub(crate) struct FollowsFromStates(Vec<Py<PyAny>>);
+
+/// Returns the state of the span with `extensions`, or, if it has none, the
+/// state of the first span it follows from that has one.
+fn state(py: Python<'_>, extensions: &Extensions<'_>) -> Option<Py<PyAny>> {
+    extensions
+        .get::<PythonLayerState>()
+        .filter(|state| !state.bind(py).is_none())
+        .map(|state| state.clone_ref(py))
+        .or_else(|| fallback_state(py, extensions))
+}
+
+/// Returns the state of the first span the span with `extensions` follows
+/// from that has one.
+pub(crate) fn fallback_state(py: Python<'_>, extensions: &Extensions<'_>) -> Option<Py<PyAny>> {
+    let states = extensions.get::<FollowsFromStates>()?;
+    states.0.first().map(|state| state.clone_ref(py))
+}
+
+/// Records the state of `follows`, or the one it falls back to, in the
+/// extensions of `span`.
+///
+/// Like the bridge's other callbacks, this takes a span's extensions before
+/// the GIL, and the GIL isn't held while `span`'s are written, so a thread
+/// holding the GIL and waiting for them can't deadlock with one holding them.
+pub(crate) fn record<S>(span: &SpanRef<'_, S>, follows: &SpanRef<'_, S>)
+where
+    S: for<'a> LookupSpan<'a>,
+{
+    let follows_extensions = follows.extensions();
+    let state = Python::with_gil(|py| state(py, &follows_extensions));
+    drop(follows_extensions);
+    let Some(state) = state else {
+        return;
+    };
+    let mut extensions = span.extensions_mut();
+    match extensions.get_mut::<FollowsFromStates>() {
+        Some(states) => states.0.push(state),
+        None => extensions.insert(FollowsFromStates(vec![state])),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{info, info_span};
+
+    use super::*;
+    use crate::{pause, resume, tests::initialize_tracing_with};
+
+    #[test]
+    fn test_follows_from_state() {
+        let (py_layer, _dispatcher) =
+            initialize_tracing_with(|bridge| bridge.with_follows_from_state(true));
+
+        let producer = info_span!("producer");
+        // The Python layer never sees spans created while the bridge is paused,
+        // so the consumer has no state.
+        pause();
+        let consumer = info_span!(parent: None, "consumer");
+        resume();
+        consumer.follows_from(&producer);
+        drop(producer);
+        consumer.in_scope(|| info!("consumed"));
+
+        Python::with_gil(|py| {
+            let borrowed = py_layer.borrow(py);
+            assert_eq!(
+                ("consumed".to_owned(), "INFO".to_owned(), 0),
+                borrowed.events[0]
+            );
+        });
+    }
+}
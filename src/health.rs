@@ -0,0 +1,96 @@
+//! A cheap probe of the bridge's health, for services' health endpoints.
+
+use std::sync::atomic::Ordering;
+
+use pyo3::{prelude::*, types::PyDict};
+use tracing_core::dispatcher;
+
+use crate::{tick, PythonCallbackLayerBridge};
+
+/// Returns the health of the [`PythonCallbackLayerBridge`] in the current
+/// subscriber as a `dict`, cheap enough to call from a service's health
+/// endpoint:
+///
+/// - `installed`: whether the current subscriber has a bridge.
+/// - `shut_down`: whether the bridge was shut down.
+/// - `worker_alive`: whether the worker thread calling `on_tick` is running,
+///   or `None` without a
+///   [`PythonCallbackLayerBridge::with_tick_interval`]. Other callbacks are
+///   called on the threads that emit spans and events.
+/// - `queue_saturated`: whether data waiting for Python is being dropped.
+///   Data isn't queued, so this is `False`.
+/// - `last_error`: the `callback`, exception `type` name and `message` of the
+///   last exception the Python layer raised, or `None`.
+/// - `healthy`: whether the bridge is installed, not shut down, and its
+///   delivery isn't stalled.
+#[pyfunction]
+pub fn health_check(py: Python<'_>) -> PyResult<Bound<'_, PyDict>> {
+    let health = PyDict::new_bound(py);
+    dispatcher::get_default(|dispatch| {
+        let bridge = dispatch.downcast_ref::<PythonCallbackLayerBridge>();
+        let shut_down = bridge.is_some_and(|bridge| bridge.shut_down.load(Ordering::Acquire));
+        let last_error = bridge
+            .map(|bridge| bridge.stats.last_error(py))
+            .transpose()?
+            .flatten();
+        health.set_item("installed", bridge.is_some())?;
+        health.set_item("shut_down", shut_down)?;
+        let worker_alive = bridge
+            .and_then(|bridge| bridge.ticker.as_ref())
+            .map(tick::Ticker::is_alive);
+        health.set_item("worker_alive", worker_alive)?;
+        health.set_item("queue_saturated", false)?;
+        health.set_item("last_error", last_error)?;
+        health.set_item(
+            "healthy",
+            bridge.is_some() && !shut_down && worker_alive != Some(false),
+        )
+    })?;
+    Ok(health)
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{info, info_span};
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::tests::{initialize_tracing_with, prepare_python};
+
+    fn get<'py>(health: &Bound<'py, PyDict>, key: &str) -> Bound<'py, PyAny> {
+        health.get_item(key).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_health_check() {
+        prepare_python();
+        {
+            let _dispatcher = tracing_subscriber::registry().set_default();
+            Python::with_gil(|py| {
+                let health = health_check(py).unwrap();
+                assert!(!get(&health, "installed").is_truthy().unwrap());
+                assert!(!get(&health, "healthy").is_truthy().unwrap());
+            });
+        }
+
+        let (_py_layer, _dispatcher) = initialize_tracing_with(|bridge| bridge);
+        // The test layer's `on_event` requires a span state, so this raises.
+        info!("outside any span");
+        info_span!("scope").in_scope(|| info!("inside"));
+
+        Python::with_gil(|py| {
+            let health = health_check(py).unwrap();
+            assert!(get(&health, "installed").is_truthy().unwrap());
+            assert!(get(&health, "healthy").is_truthy().unwrap());
+            assert!(get(&health, "worker_alive").is_none());
+            assert_eq!(
+                "on_event",
+                get(&health, "last_error")
+                    .get_item("callback")
+                    .unwrap()
+                    .extract::<String>()
+                    .unwrap()
+            );
+        });
+    }
+}
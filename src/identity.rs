@@ -0,0 +1,130 @@
+//! Identifiers telling bridges apart, for Python code managing several of them.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use pyo3::prelude::*;
+
+use crate::{ids, with_current_bridge};
+
+/// Returns a process-wide unique id for a new bridge: `bridge-1`, `bridge-2`…
+pub(crate) fn next_id() -> String {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+    format!("bridge-{}", NEXT_ID.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Returns a random version 4 UUID identifying a bridge's session, e.g.
+/// `0f8fad5b-d9cb-469f-a165-70867728950e`.
+pub(crate) fn new_session_id() -> String {
+    let random = (u128::from(ids::random_u64()) << 64) | u128::from(ids::random_u64());
+    let uuid = (random & !(0xf << 76) & !(0x3 << 62)) | (0x4 << 76) | (0x2 << 62);
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        uuid >> 96,
+        (uuid >> 80) & 0xffff,
+        (uuid >> 64) & 0xffff,
+        (uuid >> 48) & 0xffff,
+        uuid & 0xffff_ffff_ffff,
+    )
+}
+
+/// Calls the `on_attach(bridge_id)` method of `py_impl`, if it has one, to tell
+/// it which bridge it was attached to.
+pub(crate) fn attach(py_impl: &Bound<'_, PyAny>, bridge_id: &str) -> PyResult<()> {
+    if py_impl.hasattr("on_attach")? {
+        py_impl.call_method1("on_attach", (bridge_id,))?;
+    }
+    Ok(())
+}
+
+/// Returns the id of the bridge of the current subscriber, as set with
+/// [`crate::PythonCallbackLayerBridge::with_id`] or generated when it was
+/// created, or `None` if the subscriber has no bridge.
+#[pyfunction]
+pub fn current_bridge_id() -> Option<String> {
+    with_current_bridge(|bridge| bridge.id().to_owned())
+}
+
+/// Returns the session id of the bridge of the current subscriber, a UUID
+/// generated when it was created, or `None` if the subscriber has no bridge.
+///
+/// Bridges built with [`crate::PythonCallbackLayerBridge::with_session_id`]
+/// include it in every payload, so telemetry aggregated from several processes,
+/// or from one across restarts, can be told apart by run.
+#[pyfunction]
+pub fn current_session_id() -> Option<String> {
+    with_current_bridge(|bridge| bridge.session_id().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::{
+        tests::{initialize_tracing, initialize_tracing_with, prepare_python},
+        PythonCallbackLayerBridge,
+    };
+
+    #[test]
+    fn test_bridge_ids() {
+        prepare_python();
+        let (attached, bridges) = Python::with_gil(|py| {
+            let module = PyModule::from_code_bound(
+                py,
+                "
+attached = []
+
+class Layer:
+    def on_attach(self, bridge_id):
+        attached.append(bridge_id)
+",
+                "attached_layer.py",
+                "attached_layer",
+            )
+            .unwrap();
+            let layer = module.getattr("Layer").unwrap();
+            let bridges = [
+                PythonCallbackLayerBridge::new(layer.call0().unwrap()),
+                PythonCallbackLayerBridge::new(layer.call0().unwrap()).with_id("audit"),
+            ];
+            (module.getattr("attached").unwrap().unbind(), bridges)
+        });
+        let [generated, named] = bridges;
+        let generated_id = generated.id().to_owned();
+        assert!(generated_id.starts_with("bridge-"));
+
+        {
+            let _dispatcher = tracing_subscriber::registry().with(generated).set_default();
+            assert_eq!(Some(generated_id.clone()), current_bridge_id());
+        }
+        let _dispatcher = tracing_subscriber::registry().with(named).set_default();
+        assert_eq!(Some("audit".to_owned()), current_bridge_id());
+
+        Python::with_gil(|py| {
+            let attached = attached.extract::<Vec<String>>(py).unwrap();
+            assert_eq!(vec![generated_id, "audit".to_owned()], attached);
+        });
+    }
+
+    #[test]
+    fn test_session_id() {
+        let other_session_id = {
+            let (_py_layer, _dispatcher) = initialize_tracing();
+            current_session_id().unwrap()
+        };
+        let (py_layer, _dispatcher) =
+            initialize_tracing_with(|bridge| bridge.with_session_id(true));
+        let session_id = current_session_id().unwrap();
+        assert_ne!(other_session_id, session_id);
+        assert_eq!(36, session_id.len());
+        assert_eq!(Some('4'), session_id.chars().nth(14));
+
+        tracing::info_span!("run").in_scope(|| tracing::info!("stamped"));
+
+        Python::with_gil(|py| {
+            let borrowed = py_layer.borrow(py);
+            assert_eq!(session_id, borrowed.new_spans[0]["session_id"]);
+            assert_eq!(session_id, borrowed.event_fields[0]["session_id"]);
+        });
+    }
+}
@@ -0,0 +1,213 @@
+//! Trace and span ids that, unlike registry span ids, are never reused and can
+//! be sent to external tracing systems.
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::SystemTime,
+};
+
+use pyo3::{prelude::*, types::PyDict};
+use serde_json::{json, Value};
+
+use crate::constants;
+
+/// The W3C/OpenTelemetry-compatible ids of a span, stored in its
+/// [`tracing_subscriber::registry::Extensions`] by a bridge built with
+/// [`crate::PythonCallbackLayerBridge::with_trace_ids`].
+///
+/// Root spans start a new trace; other spans share their parent's trace id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpanIds {
+    pub trace_id: u128,
+    pub span_id: u64,
+    pub parent_span_id: Option<u64>,
+}
+
+/// Returns a random non-zero `u64`.
+pub(crate) fn random_u64() -> u64 {
+    static STATE: OnceLock<RandomState> = OnceLock::new();
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    loop {
+        let mut hasher = STATE.get_or_init(RandomState::new).build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        if let Ok(since_epoch) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+            hasher.write_u128(since_epoch.as_nanos());
+        }
+        let random = hasher.finish();
+        if random != 0 {
+            return random;
+        }
+    }
+}
+
+impl SpanIds {
+    /// Ids for a span that starts a new trace.
+    pub(crate) fn new_root() -> SpanIds {
+        SpanIds {
+            trace_id: (u128::from(random_u64()) << 64) | u128::from(random_u64()),
+            span_id: random_u64(),
+            parent_span_id: None,
+        }
+    }
+
+    /// Ids for a child of the span with ids `self`.
+    pub(crate) fn new_child(&self) -> SpanIds {
+        SpanIds {
+            trace_id: self.trace_id,
+            span_id: random_u64(),
+            parent_span_id: Some(self.span_id),
+        }
+    }
+
+    /// The ids as lowercase hex strings, as in W3C `traceparent` headers.
+    pub(crate) fn to_json(self) -> Value {
+        let mut ids = json!({
+            "trace_id": format!("{:032x}", self.trace_id),
+            "span_id": format!("{:016x}", self.span_id),
+        });
+        if let Some(parent_span_id) = self.parent_span_id {
+            ids["parent_span_id"] = format!("{parent_span_id:016x}").into();
+        }
+        ids
+    }
+}
+
+/// Sets the `trace_id` and `span_id` of a structlog `event_dict` to `ids`, as
+/// hex strings like structlog's OpenTelemetry processors add them.
+pub(crate) fn set_event_dict_ids(event_dict: &Bound<'_, PyDict>, ids: &SpanIds) {
+    let py = event_dict.py();
+    let _ = event_dict.set_item(
+        constants::py_str(py, "trace_id"),
+        format!("{:032x}", ids.trace_id),
+    );
+    let _ = event_dict.set_item(
+        constants::py_str(py, "span_id"),
+        format!("{:016x}", ids.span_id),
+    );
+}
+
+/// Adds an `ids` object with `ids` to `payload`.
+pub(crate) fn insert_ids(payload: &mut Value, ids: Option<&SpanIds>) {
+    if let (Some(ids), Some(payload)) = (ids, payload.as_object_mut()) {
+        payload.insert("ids".to_owned(), ids.to_json());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::{prelude::*, types::PyModule};
+    use tracing::{info, info_span};
+    use tracing_subscriber::{prelude::*, Registry};
+
+    use crate::{tests::initialize_tracing_with, PayloadMode, PythonCallbackLayerBridge};
+
+    #[test]
+    fn test_trace_ids() {
+        let (py_layer, _dispatcher) = initialize_tracing_with(|bridge| bridge.with_trace_ids(true));
+
+        info_span!("root", n = tracing::field::Empty).in_scope(|| {
+            tracing::Span::current().record("n", 1);
+            info_span!("child").in_scope(|| info!(x = 1, "inside"));
+        });
+        let _other_root = info_span!("other root");
+
+        Python::with_gil(|py| {
+            let borrowed = py_layer.borrow(py);
+            let ids = borrowed
+                .new_spans
+                .iter()
+                .map(|span| span["ids"].clone())
+                .collect::<Vec<_>>();
+            let (root, child, other_root) = (&ids[0], &ids[1], &ids[2]);
+
+            assert_eq!(32, root["trace_id"].as_str().unwrap().len());
+            assert_eq!(16, root["span_id"].as_str().unwrap().len());
+            assert!(root.get("parent_span_id").is_none());
+            assert_eq!(root["trace_id"], child["trace_id"]);
+            assert_eq!(root["span_id"], child["parent_span_id"]);
+            assert_ne!(root["span_id"], child["span_id"]);
+            assert_ne!(root["trace_id"], other_root["trace_id"]);
+
+            assert_eq!(child, &borrowed.event_fields[0]["ids"]);
+            assert_eq!(root, &borrowed.span_records[0].0["ids"]);
+        });
+    }
+
+    #[test]
+    fn test_trace_ids_without_forwarded_spans() {
+        let (py_layer, _dispatcher) = initialize_tracing_with(|bridge| {
+            bridge
+                .with_trace_ids(true)
+                .with_payload_mode(PayloadMode::StructlogEventDict)
+        });
+        let events_layer = Python::with_gil(|py| {
+            let module = PyModule::from_code_bound(
+                py,
+                "
+class EventsOnly:
+    def __init__(self):
+        self.events = []
+
+    def on_event(self, event, state):
+        self.events.append(event)
+",
+                "events_only.py",
+                "events_only",
+            )
+            .unwrap();
+            module
+                .getattr("EventsOnly")
+                .unwrap()
+                .call0()
+                .unwrap()
+                .unbind()
+        });
+        let events_only =
+            Python::with_gil(|py| PythonCallbackLayerBridge::new(events_layer.bind(py).clone()));
+        let _events_only = Registry::default()
+            .with(
+                events_only
+                    .with_trace_ids(true)
+                    .with_payload_mode(PayloadMode::Dict),
+            )
+            .set_default();
+
+        info_span!("root").in_scope(|| {
+            info_span!("child").in_scope(|| info!("first"));
+            info!("second");
+        });
+
+        Python::with_gil(|py| {
+            let events = events_layer.getattr(py, "events").unwrap();
+            let trace_ids: Vec<String> = (0..2)
+                .map(|index| {
+                    let event = events.bind(py).get_item(index).unwrap();
+                    event
+                        .get_item("ids")
+                        .unwrap()
+                        .get_item("trace_id")
+                        .unwrap()
+                        .extract()
+                        .unwrap()
+                })
+                .collect();
+            assert_eq!(32, trace_ids[0].len());
+            assert_eq!(trace_ids[0], trace_ids[1]);
+        });
+        drop(_events_only);
+
+        info_span!("structlog").in_scope(|| info!("in structlog"));
+        Python::with_gil(|py| {
+            let borrowed = py_layer.borrow(py);
+            let ids = &borrowed.new_spans[0]["ids"];
+            assert_eq!(ids["trace_id"], borrowed.event_fields[0]["trace_id"]);
+            assert_eq!(ids["span_id"], borrowed.event_fields[0]["span_id"]);
+        });
+    }
+}
@@ -0,0 +1,497 @@
+//! Helpers for installing a global subscriber that forwards to Python.
+
+use std::{
+    collections::HashMap,
+    io::{self, IsTerminal},
+    path::PathBuf,
+    sync::OnceLock,
+};
+
+use pyo3::{
+    exceptions::{PyRuntimeError, PyValueError},
+    prelude::*,
+    pybacked::PyBackedStr,
+    types::PyDict,
+};
+use tracing_core::Level;
+use tracing_subscriber::{
+    fmt::{self, MakeWriter},
+    layer::{Identity, Layered},
+    prelude::*,
+    reload, EnvFilter, Layer, Registry,
+};
+
+use crate::{
+    adapters::{
+        datadog::PythonDatadogLayer, logging::PythonLoggingLayer, loguru::PythonLoguruLayer,
+        opentelemetry::PythonOpenTelemetryLayer, sentry::PythonSentryLayer,
+        structlog::PythonStructlogLayer,
+    },
+    config::BridgeConfig,
+    export::{
+        chrome::ChromeTraceLayer, console::ConsoleLayer, flamegraph::FlamegraphLayer,
+        flight_recorder::FlightRecorderLayer, stats::StatsLayer, stream::StreamLayer,
+    },
+    PythonCallbackLayerBridge,
+};
+
+type FilterHandle = reload::Handle<EnvFilter, Registry>;
+/// The subscriber the layers installed by [`try_init`] are added to.
+type Filtered = Layered<reload::Layer<EnvFilter, Registry>, Registry>;
+
+/// Handle to the filter installed by the `initialize_*` functions.
+static FILTER: OnceLock<FilterHandle> = OnceLock::new();
+
+pub(crate) fn parse_filter(filter: &str) -> PyResult<EnvFilter> {
+    EnvFilter::builder()
+        .parse(filter)
+        .map_err(|err| PyValueError::new_err(format!("invalid filter {filter:?}: {err}")))
+}
+
+fn reload_filter(handle: &FilterHandle, filter: &str) -> PyResult<()> {
+    handle
+        .reload(parse_filter(filter)?)
+        .map_err(|err| PyRuntimeError::new_err(err.to_string()))
+}
+
+/// Keeps an output installed by an `initialize_*` function running, like the
+/// background thread writing log files or the exporter sending spans, returned
+/// by those functions.
+///
+/// Closing it flushes data that hasn't been written or sent yet and stops that
+/// output, either explicitly with `close()`, at the end of a `with` block or
+/// when it's garbage collected, so keep it alive for as long as the
+/// application runs.
+#[cfg(any(feature = "appender", feature = "otlp"))]
+#[pyclass(module = "pyo3_python_tracing_subscriber")]
+pub struct TracingGuard(Option<Box<dyn Send>>);
+
+#[cfg(any(feature = "appender", feature = "otlp"))]
+impl TracingGuard {
+    /// Creates a guard that drops `guard` when closed.
+    pub(crate) fn new(guard: impl Send + 'static) -> TracingGuard {
+        TracingGuard(Some(Box::new(guard)))
+    }
+}
+
+#[cfg(any(feature = "appender", feature = "otlp"))]
+#[pymethods]
+impl TracingGuard {
+    /// Flushes pending data and stops the output.
+    pub fn close(&mut self) {
+        self.0.take();
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        _exc_type: &Bound<'_, PyAny>,
+        _exc_value: &Bound<'_, PyAny>,
+        _traceback: &Bound<'_, PyAny>,
+    ) {
+        self.close();
+    }
+}
+
+/// Installs `layer` and the [`crate::export`] layers as a global subscriber,
+/// behind a reloadable filter, and with the `log` feature forwards `log`
+/// records to it.
+pub(crate) fn try_init<L>(layer: L, filter: &str) -> PyResult<()>
+where
+    L: Layer<Filtered> + Send + Sync + 'static,
+{
+    let (filter, handle) = reload::Layer::new(parse_filter(filter)?);
+
+    let subscriber = tracing_subscriber::registry()
+        .with(filter)
+        .with(layer)
+        .with(ChromeTraceLayer::new())
+        .with(ConsoleLayer::new())
+        .with(FlamegraphLayer::new())
+        .with(StreamLayer::new())
+        .with(FlightRecorderLayer::new())
+        .with(StatsLayer::new());
+    #[cfg(feature = "arrow")]
+    let subscriber = subscriber.with(crate::export::arrow::ArrowLayer::new());
+    #[cfg(feature = "span-trace")]
+    let subscriber = subscriber.with(tracing_error::ErrorLayer::default());
+    subscriber
+        .try_init()
+        .map_err(|err| PyRuntimeError::new_err(err.to_string()))?;
+
+    let _ = FILTER.set(handle);
+    #[cfg(feature = "log")]
+    crate::log_bridge::init_log_tracer();
+    Ok(())
+}
+
+/// Returns the config of the `PYO3_TRACING_*` environment variables, with the
+/// `filter` argument of an `initialize_*` function, if given, taking
+/// precedence over `PYO3_TRACING_FILTER`.
+///
+/// The helpers installing a bridge apply the whole config to it; the others
+/// only use its filter.
+pub(crate) fn env_config(filter: Option<&str>) -> PyResult<BridgeConfig> {
+    Ok(BridgeConfig::from_env()?.with_arguments(filter, None))
+}
+
+/// Installs a global subscriber that forwards `tracing` data to `py_impl`
+/// through a [`PythonCallbackLayerBridge`].
+///
+/// `filter` uses the [`EnvFilter`] directive syntax (e.g.
+/// `"my_crate::db=trace,info"`) and can be changed later with [`set_level`].
+/// `jsonl_path` optionally names a file to append everything forwarded to
+/// `py_impl` to, as with [`PythonCallbackLayerBridge::with_jsonl_file`].
+///
+/// The bridge can also be configured with a JSON file at `config_path`, the
+/// `PYO3_TRACING_*` environment variables, and a `config` `dict` with the same
+/// keys as the file, each overriding the settings before it. `filter` and
+/// `jsonl_path` override all of them; `filter` defaults to `"info"`.
+///
+/// `routes` maps target prefixes to other Python layers receiving their spans
+/// and events instead of `py_impl`, as with
+/// [`PythonCallbackLayerBridge::with_route`].
+///
+/// Raises `RuntimeError` if a global subscriber is already installed,
+/// `ValueError` if the config is invalid and `OSError` if a file can't be
+/// opened.
+#[pyfunction]
+#[pyo3(signature = (py_impl, filter = None, jsonl_path = None, config_path = None, config = None, routes = None))]
+pub fn initialize_tracing(
+    py_impl: Bound<'_, PyAny>,
+    filter: Option<&str>,
+    jsonl_path: Option<PathBuf>,
+    config_path: Option<PathBuf>,
+    config: Option<&Bound<'_, PyDict>>,
+    routes: Option<&Bound<'_, PyDict>>,
+) -> PyResult<()> {
+    let mut merged = match &config_path {
+        Some(config_path) => BridgeConfig::from_file(config_path)?,
+        None => BridgeConfig::default(),
+    };
+    merged = merged.merge(BridgeConfig::from_env()?);
+    if let Some(config) = config {
+        merged = merged.merge(BridgeConfig::from_py_dict(config)?);
+    }
+    let merged = merged.with_arguments(filter, jsonl_path);
+    let mut bridge = PythonCallbackLayerBridge::new(py_impl);
+    for (prefix, route) in routes.into_iter().flatten() {
+        bridge = bridge.with_route(prefix.extract::<String>()?, route);
+    }
+    let bridge = merged.apply(bridge)?;
+    try_init(bridge, merged.filter())
+}
+
+/// How [`init_with_fmt`] formats logs, parsed from its `fmt_options` `dict`.
+#[derive(Debug)]
+struct FmtOptions {
+    /// `"full"`, `"compact"`, `"pretty"` or `"json"`.
+    format: String,
+    /// Whether to color the output; defaults to whether stderr is a terminal.
+    ansi: bool,
+    /// Whether to include the event's target.
+    target: bool,
+}
+
+impl FmtOptions {
+    fn extract(fmt_options: Option<&Bound<'_, PyDict>>) -> PyResult<FmtOptions> {
+        let mut options = FmtOptions {
+            format: "full".to_owned(),
+            ansi: io::stderr().is_terminal(),
+            target: true,
+        };
+        for (key, value) in fmt_options.into_iter().flatten() {
+            match &*key.extract::<PyBackedStr>()? {
+                "format" => options.format = value.extract()?,
+                "ansi" => options.ansi = value.extract()?,
+                "target" => options.target = value.extract()?,
+                key => return Err(PyValueError::new_err(format!("unknown fmt option {key:?}"))),
+            }
+        }
+        Ok(options)
+    }
+
+    fn layer<W>(&self, writer: W) -> PyResult<Box<dyn Layer<Filtered> + Send + Sync>>
+    where
+        W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+    {
+        let layer = fmt::layer()
+            .with_ansi(self.ansi)
+            .with_target(self.target)
+            .with_writer(writer);
+        Ok(match self.format.as_str() {
+            "full" => layer.boxed(),
+            "compact" => layer.compact().boxed(),
+            "pretty" => layer.pretty().boxed(),
+            "json" => layer.json().boxed(),
+            format => {
+                return Err(PyValueError::new_err(format!(
+                "unknown format {format:?}, expected \"full\", \"compact\", \"pretty\" or \"json\""
+            )))
+            }
+        })
+    }
+}
+
+/// Installs a global subscriber that forwards `tracing` data to `py_impl`, like
+/// [`initialize_tracing`], and also logs it to stderr with a
+/// [`tracing_subscriber::fmt`] layer, both behind the same reloadable filter.
+///
+/// `fmt_options` optionally configures the logs with the keys `format`
+/// (`"full"`, the default, `"compact"`, `"pretty"` or `"json"`), `ansi`
+/// (whether to use colors, by default only when stderr is a terminal) and
+/// `target` (whether to include event targets, `True` by default). `filter`
+/// and the `PYO3_TRACING_*` environment variables work as they do for
+/// [`initialize_tracing`].
+#[pyfunction]
+#[pyo3(signature = (py_impl, fmt_options = None, filter = None))]
+pub fn init_with_fmt(
+    py_impl: Bound<'_, PyAny>,
+    fmt_options: Option<&Bound<'_, PyDict>>,
+    filter: Option<&str>,
+) -> PyResult<()> {
+    let fmt_layer = FmtOptions::extract(fmt_options)?.layer(io::stderr)?;
+    let config = env_config(filter)?;
+    let bridge = config.apply(PythonCallbackLayerBridge::new(py_impl))?;
+    try_init(bridge.and_then(fmt_layer), config.filter())
+}
+
+/// Installs a global subscriber that forwards `tracing` events to Python's
+/// `logging` module through a [`PythonLoggingLayer`].
+///
+/// `filter` works as it does for [`initialize_tracing`]. `levels` optionally
+/// maps `tracing` level names to the `logging` levels to use for them, e.g.
+/// `{"TRACE": 5, "DEBUG": 10}`; unmapped levels keep their defaults.
+/// `logger_names` optionally maps target prefixes to logger names, e.g.
+/// `{"my_crate::db": "myapp.rustdb"}`.
+#[pyfunction]
+#[pyo3(signature = (filter = None, levels = None, logger_names = None))]
+pub fn initialize_logging(
+    filter: Option<&str>,
+    levels: Option<HashMap<String, i32>>,
+    logger_names: Option<HashMap<String, String>>,
+) -> PyResult<()> {
+    let mut layer = PythonLoggingLayer::new();
+    for (target_prefix, logger_name) in logger_names.unwrap_or_default() {
+        layer = layer.with_logger_name(target_prefix, logger_name);
+    }
+    for (level, logging_level) in levels.unwrap_or_default() {
+        let level = level
+            .parse::<Level>()
+            .map_err(|_| PyValueError::new_err(format!("unknown level {level:?}")))?;
+        layer = layer.with_level(level, logging_level);
+    }
+
+    try_init(layer, env_config(filter)?.filter())
+}
+
+/// Installs a global subscriber that mirrors `tracing` spans and events as
+/// OpenTelemetry spans through a [`PythonOpenTelemetryLayer`], using the
+/// tracer named `instrumentation_name` from the `TracerProvider` the Python
+/// application installed.
+///
+/// `filter` works as it does for [`initialize_tracing`].
+#[pyfunction]
+#[pyo3(signature = (instrumentation_name, filter = None))]
+pub fn initialize_opentelemetry(instrumentation_name: &str, filter: Option<&str>) -> PyResult<()> {
+    try_init(
+        PythonOpenTelemetryLayer::new(instrumentation_name),
+        env_config(filter)?.filter(),
+    )
+}
+
+/// Installs a global subscriber that reports `tracing` data to Sentry through a
+/// [`PythonSentryLayer`], using the client the Python application initialized
+/// with `sentry_sdk.init`.
+///
+/// `filter` works as it does for [`initialize_tracing`].
+#[pyfunction]
+#[pyo3(signature = (filter = None))]
+pub fn initialize_sentry(filter: Option<&str>) -> PyResult<()> {
+    try_init(PythonSentryLayer::new(), env_config(filter)?.filter())
+}
+
+/// Installs a global subscriber without any Python layer, for processes that
+/// only need the counts of [`crate::get_tracing_stats`] or the other
+/// [`crate::export`] sinks.
+///
+/// `filter` works as it does for [`initialize_tracing`].
+#[pyfunction]
+#[pyo3(signature = (filter = None))]
+pub fn initialize_stats(filter: Option<&str>) -> PyResult<()> {
+    try_init(Identity::new(), env_config(filter)?.filter())
+}
+
+/// Installs a global subscriber that logs `tracing` events with structlog
+/// through a [`PythonStructlogLayer`].
+///
+/// `filter` works as it does for [`initialize_tracing`].
+#[pyfunction]
+#[pyo3(signature = (filter = None))]
+pub fn initialize_structlog(filter: Option<&str>) -> PyResult<()> {
+    try_init(PythonStructlogLayer::new(), env_config(filter)?.filter())
+}
+
+/// Installs a global subscriber that logs `tracing` events with loguru's
+/// `logger` through a [`PythonLoguruLayer`].
+///
+/// `filter` works as it does for [`initialize_tracing`]. `levels` optionally
+/// maps `tracing` level names to the loguru level names to use for them, e.g.
+/// `{"TRACE": "DEBUG"}`; unmapped levels keep their defaults.
+#[pyfunction]
+#[pyo3(signature = (filter = None, levels = None))]
+pub fn initialize_loguru(
+    filter: Option<&str>,
+    levels: Option<HashMap<String, String>>,
+) -> PyResult<()> {
+    let mut layer = PythonLoguruLayer::new();
+    for (level, loguru_level) in levels.unwrap_or_default() {
+        let level = level
+            .parse::<Level>()
+            .map_err(|_| PyValueError::new_err(format!("unknown level {level:?}")))?;
+        layer = layer.with_level(level, loguru_level);
+    }
+    try_init(layer, env_config(filter)?.filter())
+}
+
+/// Installs a global subscriber that mirrors `tracing` spans as Datadog spans
+/// through a [`PythonDatadogLayer`], using the global `ddtrace.tracer`.
+///
+/// `service` optionally sets the Datadog service of the Rust spans; otherwise
+/// they inherit their parent's. `filter` works as it does for
+/// [`initialize_tracing`].
+#[pyfunction]
+#[pyo3(signature = (service = None, filter = None))]
+pub fn initialize_datadog(service: Option<&str>, filter: Option<&str>) -> PyResult<()> {
+    let mut layer = PythonDatadogLayer::new();
+    if let Some(service) = service {
+        layer = layer.with_service(service);
+    }
+    try_init(layer, env_config(filter)?.filter())
+}
+
+/// Replaces the filter installed by [`initialize_tracing`] or one of the other
+/// `initialize_*` functions with `filter`, e.g.
+/// `set_level("my_crate::db=trace,info")`.
+///
+/// The filter applies to every layer those functions installed. Raises
+/// `ValueError` for an invalid filter and `RuntimeError` if tracing wasn't
+/// initialized with one of them.
+#[pyfunction]
+pub fn set_level(filter: &str) -> PyResult<()> {
+    let handle = FILTER
+        .get()
+        .ok_or_else(|| PyRuntimeError::new_err("tracing wasn't initialized by this extension"))?;
+    reload_filter(handle, filter)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use pyo3::types::IntoPyDict;
+    use serde_json::Value;
+    use tracing::{debug, info, info_span, warn, warn_span};
+
+    use super::*;
+    use crate::tests::{prepare_python, PythonLayer};
+
+    #[test]
+    fn test_reload_filter() {
+        prepare_python();
+        let (py_layer, bridge) = Python::with_gil(|py| {
+            let py_layer = Bound::new(py, PythonLayer::new()).unwrap();
+            let bridge = PythonCallbackLayerBridge::new(py_layer.clone().into_any());
+            (py_layer.unbind(), bridge)
+        });
+        let (filter, handle) = reload::Layer::new(parse_filter("warn").unwrap());
+        let _dispatcher = tracing_subscriber::registry()
+            .with(filter)
+            .with(bridge)
+            .set_default();
+
+        warn_span!("scope").in_scope(|| {
+            info!("dropped");
+            warn!("kept");
+            reload_filter(&handle, &format!("{}=debug,warn", module_path!())).unwrap();
+            debug!("now kept");
+        });
+        Python::with_gil(|py| {
+            assert!(reload_filter(&handle, "=nonsense=")
+                .unwrap_err()
+                .is_instance_of::<PyValueError>(py));
+        });
+
+        Python::with_gil(|py| {
+            let messages = py_layer
+                .borrow(py)
+                .events
+                .iter()
+                .map(|(message, _, _)| message.clone())
+                .collect::<Vec<_>>();
+            assert_eq!(vec!["kept", "now kept"], messages);
+        });
+    }
+
+    #[test]
+    fn test_fmt_options() {
+        #[derive(Clone, Default)]
+        struct Output(Arc<Mutex<Vec<u8>>>);
+
+        impl io::Write for Output {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        prepare_python();
+        let output = Output::default();
+        let (py_layer, fmt_layer) = Python::with_gil(|py| {
+            let fmt_options = [("format", "json".into_py(py)), ("ansi", false.into_py(py))]
+                .into_py_dict_bound(py);
+            let fmt_layer = FmtOptions::extract(Some(&fmt_options))
+                .unwrap()
+                .layer({
+                    let output = output.clone();
+                    move || output.clone()
+                })
+                .unwrap();
+
+            let unknown = [("colour", true)].into_py_dict_bound(py);
+            assert!(FmtOptions::extract(Some(&unknown))
+                .unwrap_err()
+                .is_instance_of::<PyValueError>(py));
+
+            let py_layer = Bound::new(py, PythonLayer::new()).unwrap();
+            (py_layer.unbind(), fmt_layer)
+        });
+        let bridge = Python::with_gil(|py| {
+            PythonCallbackLayerBridge::new(py_layer.bind(py).clone().into_any())
+        });
+        let (filter, _handle) = reload::Layer::new(parse_filter("info").unwrap());
+        let _dispatcher = tracing_subscriber::registry()
+            .with(filter)
+            .with(bridge.and_then(fmt_layer))
+            .set_default();
+
+        info_span!("fmt").in_scope(|| {
+            info!(rows = 3, "formatted");
+            debug!("filtered out");
+        });
+
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(1, lines.len());
+        let line = serde_json::from_str::<Value>(lines[0]).unwrap();
+        assert_eq!("formatted", line["fields"]["message"]);
+        assert_eq!(3, line["fields"]["rows"]);
+        Python::with_gil(|py| assert_eq!(1, py_layer.borrow(py).events.len()));
+    }
+}
@@ -0,0 +1,58 @@
+//! The bridge's own diagnostics, which it doesn't forward to Python layers.
+//!
+//! The bridge emits events about itself, like exceptions raised by `on_tick`,
+//! with the [`TARGET`] target, so they reach other layers such as a `fmt` layer.
+//! Forwarding them to the Python layer could feed its own failures back into
+//! it, so bridges skip them unless built with
+//! [`crate::PythonCallbackLayerBridge::with_internal_tracing`]. Events emitted
+//! while the bridge handles spans and events are dropped by `tracing` anyway.
+
+use tracing_core::Metadata;
+
+/// The target of the spans and events the bridge emits about itself.
+pub(crate) const TARGET: &str = "pyo3_python_tracing_subscriber::internal";
+
+/// Whether `metadata` belongs to a span or event the bridge emitted about
+/// itself.
+pub(crate) fn is_internal(metadata: &Metadata<'_>) -> bool {
+    metadata.target() == TARGET
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::Python;
+    use tracing::{info, info_span, warn};
+
+    use super::*;
+    use crate::tests::initialize_tracing_with;
+
+    #[test]
+    fn test_internal_tracing() {
+        for include_internal in [false, true] {
+            let (py_layer, _dispatcher) =
+                initialize_tracing_with(|bridge| bridge.with_internal_tracing(include_internal));
+
+            info_span!(target: TARGET, "internal").in_scope(|| {
+                warn!(target: TARGET, "about the bridge");
+            });
+            info_span!("application").in_scope(|| info!("about the application"));
+
+            Python::with_gil(|py| {
+                let borrowed = py_layer.borrow(py);
+                let messages = borrowed
+                    .events
+                    .iter()
+                    .map(|(message, _, _)| message.as_str())
+                    .collect::<Vec<_>>();
+                if include_internal {
+                    assert_eq!(vec!["about the bridge", "about the application"], messages);
+                    assert_eq!(2, borrowed.new_spans.len());
+                } else {
+                    assert_eq!(vec!["about the application"], messages);
+                    assert_eq!(1, borrowed.new_spans.len());
+                    assert_eq!(1, borrowed.closed_spans.len());
+                }
+            });
+        }
+    }
+}
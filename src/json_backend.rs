@@ -0,0 +1,70 @@
+//! The serializers the bridge can write JSON payloads with, selected at build
+//! time.
+
+use serde_json::Value;
+
+/// Writes compact JSON payloads.
+pub(crate) trait JsonBackend {
+    /// Appends `payload` to `out`, as `serde_json` would serialize it.
+    ///
+    /// What's appended must be UTF-8, since [`JsonBuffer`] reads it as a
+    /// `str` without validating it.
+    ///
+    /// [`JsonBuffer`]: crate::json_buffer::JsonBuffer
+    fn write(payload: &Value, out: &mut Vec<u8>);
+}
+
+/// Writes payloads with `serde_json`.
+#[cfg(any(not(feature = "simd-json"), feature = "arbitrary-precision", test))]
+pub(crate) struct SerdeJson;
+
+#[cfg(any(not(feature = "simd-json"), feature = "arbitrary-precision", test))]
+impl JsonBackend for SerdeJson {
+    fn write(payload: &Value, out: &mut Vec<u8>) {
+        serde_json::to_writer(out, payload).expect("a JSON value can always be serialized");
+    }
+}
+
+/// Writes payloads with `simd-json`, whose string escaping uses SIMD
+/// instructions, which speeds up payloads with long messages and fields.
+#[cfg(all(feature = "simd-json", not(feature = "arbitrary-precision")))]
+pub(crate) struct SimdJson;
+
+#[cfg(all(feature = "simd-json", not(feature = "arbitrary-precision")))]
+impl JsonBackend for SimdJson {
+    fn write(payload: &Value, out: &mut Vec<u8>) {
+        simd_json::serde::to_writer(out, payload).expect("a JSON value can always be serialized");
+    }
+}
+
+/// The backend compact payloads are written with.
+///
+/// `simd-json` writes `serde_json`'s arbitrary precision numbers as a private
+/// struct, so `serde_json` writes them when both features are enabled.
+#[cfg(any(not(feature = "simd-json"), feature = "arbitrary-precision"))]
+pub(crate) type Backend = SerdeJson;
+#[cfg(all(feature = "simd-json", not(feature = "arbitrary-precision")))]
+pub(crate) type Backend = SimdJson;
+
+#[cfg(all(test, feature = "simd-json", not(feature = "arbitrary-precision")))]
+mod tests {
+    use serde_json::json;
+
+    use super::{JsonBackend, SerdeJson, SimdJson};
+
+    #[test]
+    fn test_simd_json_matches_serde_json() {
+        let payload = json!({
+            "message": "quotes \" and \\ backslashes,\ttabs\nand ünïcödé 🦀 ".repeat(8),
+            "fields": {"count": -3, "ratio": 0.1, "big": u64::MAX, "flag": true, "none": null},
+            "spans": [{"name": "outer"}, {"name": "control \u{1} characters"}],
+        });
+        let (mut serde_json, mut simd_json) = (Vec::new(), Vec::new());
+        SerdeJson::write(&payload, &mut serde_json);
+        SimdJson::write(&payload, &mut simd_json);
+        assert_eq!(
+            String::from_utf8(serde_json).unwrap(),
+            String::from_utf8(simd_json).unwrap()
+        );
+    }
+}
@@ -0,0 +1,96 @@
+//! Serializing payloads into a buffer reused by each thread, instead of
+//! allocating a `String` for every callback.
+
+use std::{cell::Cell, mem};
+
+use pyo3::{prelude::*, types::PyString};
+use serde_json::Value;
+
+use crate::json_backend::{Backend, JsonBackend};
+
+/// The largest buffer kept for reuse, so one huge payload doesn't pin its
+/// memory to the thread.
+const MAX_RETAINED_CAPACITY: usize = 1 << 20;
+
+thread_local! {
+    static BUFFER: Cell<Vec<u8>> = const { Cell::new(Vec::new()) };
+}
+
+/// A serialized payload, in a buffer taken from the current thread and given
+/// back when it's dropped.
+///
+/// Payloads serialized while another one is alive on the same thread, e.g. by
+/// a Python layer that emits events from its callbacks, get a fresh buffer.
+pub(crate) struct JsonBuffer(Vec<u8>);
+
+impl JsonBuffer {
+    /// Serializes `payload` with the [`Backend`] selected by the crate's
+    /// features, or pretty-printed with `serde_json` if `pretty` is set.
+    pub(crate) fn serialize(payload: &Value, pretty: bool) -> JsonBuffer {
+        let mut buffer = BUFFER.try_with(Cell::take).unwrap_or_default();
+        buffer.clear();
+        if pretty {
+            serde_json::to_writer_pretty(&mut buffer, payload)
+                .expect("a JSON value can always be serialized");
+        } else {
+            Backend::write(payload, &mut buffer);
+        }
+        JsonBuffer(buffer)
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        // SAFETY: the buffer only holds what a `JsonBackend` or `serde_json`
+        // wrote into it, which is UTF-8.
+        unsafe { std::str::from_utf8_unchecked(&self.0) }
+    }
+
+    /// Takes the payload out, without giving its buffer back to the thread.
+    #[cfg(not(feature = "abi3"))]
+    pub(crate) fn into_vec(self) -> Vec<u8> {
+        mem::take(&mut mem::ManuallyDrop::new(self).0)
+    }
+
+    /// Copies the payload into a Python `str`.
+    pub(crate) fn to_py(&self, py: Python<'_>) -> PyObject {
+        PyString::new_bound(py, self.as_str()).into_any().unbind()
+    }
+}
+
+impl Drop for JsonBuffer {
+    fn drop(&mut self) {
+        if self.0.capacity() <= MAX_RETAINED_CAPACITY {
+            let buffer = mem::take(&mut self.0);
+            let _ = BUFFER.try_with(|cell| cell.set(buffer));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::JsonBuffer;
+
+    #[test]
+    fn test_buffer_reuse() {
+        let first = JsonBuffer::serialize(&json!({"message": "first"}), false);
+        let nested = JsonBuffer::serialize(&json!({"message": "nested"}), true);
+        assert_eq!(r#"{"message":"first"}"#, first.as_str());
+        assert_eq!("{\n  \"message\": \"nested\"\n}", nested.as_str());
+        let address = first.as_str().as_ptr();
+        drop(nested);
+        drop(first);
+
+        let reused = JsonBuffer::serialize(&json!("short"), false);
+        assert_eq!(address, reused.as_str().as_ptr());
+        assert_eq!(r#""short""#, reused.as_str());
+
+        #[cfg(not(feature = "abi3"))]
+        {
+            let taken = reused.into_vec();
+            assert_eq!(address, taken.as_ptr());
+            let fresh = JsonBuffer::serialize(&json!("fresh"), false);
+            assert_ne!(address, fresh.as_str().as_ptr());
+        }
+    }
+}
@@ -1,11 +1,256 @@
-use pyo3::prelude::*;
-use serde_json::json;
-use tracing_core::{span, Event, Subscriber};
+use std::{
+    fs::File,
+    io::Write,
+    iter,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock, PoisonError, RwLock,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use pyo3::{
+    exceptions::{PyAttributeError, PyRuntimeWarning},
+    prelude::*,
+    types::{IntoPyDict, PyDict, PyList, PyTuple},
+};
+use serde_json::{json, Value};
+use tracing_core::{
+    dispatcher::WeakDispatch, span, subscriber::Interest, Dispatch, Event, Level, Metadata,
+    Subscriber,
+};
 use tracing_serde::AsSerde;
 use tracing_subscriber::{
+    field::RecordFields,
     layer::{Context, Layer},
-    registry::LookupSpan,
+    registry::{LookupSpan, SpanRef},
+};
+
+mod active_spans;
+pub mod adapters;
+#[cfg(feature = "appender")]
+mod appender;
+mod backpressure;
+mod backtraces;
+mod benchmark;
+#[cfg(not(feature = "abi3"))]
+mod buffer;
+mod callback_dispatch;
+mod callsite;
+mod callsite_registry;
+mod capture;
+mod clock;
+mod coercion;
+#[cfg(feature = "compression")]
+mod compression;
+mod config;
+mod constants;
+mod context;
+mod deterministic;
+mod disabled;
+mod errors;
+mod exception;
+pub mod export;
+mod fanout;
+mod fields;
+mod follows;
+mod health;
+mod identity;
+mod ids;
+mod init;
+mod internal;
+mod json_backend;
+mod json_buffer;
+mod lifecycle_audit;
+mod log_bridge;
+mod lookup;
+mod metrics;
+mod named_bridges;
+#[cfg(feature = "opentelemetry")]
+mod otel;
+#[cfg(feature = "otlp")]
+mod otlp;
+mod overhead;
+mod pausing;
+mod payload;
+mod prometheus;
+mod propagation;
+mod python_stack;
+mod routing;
+mod span_api;
+mod span_context;
+#[cfg(feature = "span-trace")]
+mod span_trace;
+mod state;
+mod stats;
+#[cfg(all(tracing_unstable, feature = "valuable"))]
+mod structured;
+mod stubs;
+mod subtree;
+mod synthetic;
+mod tick;
+#[cfg(feature = "arbitrary-precision")]
+mod wide_ints;
+
+pub use active_spans::dump_active_spans;
+#[cfg(feature = "appender")]
+pub use appender::initialize_tracing_with_file;
+pub use backpressure::Backpressure;
+pub use benchmark::benchmark_bridge;
+#[cfg(not(feature = "abi3"))]
+pub use buffer::PayloadBuffer;
+pub use callsite::rebuild_interest_cache;
+pub use callsite_registry::{list_callsites, set_callsite_enabled};
+pub use capture::{captured_rust_tracing, CapturedRustTracing, CapturingLayer};
+pub use clock::{Clock, MockClock, SystemClock};
+pub use coercion::{CoercionRules, LargeInts};
+pub use context::{
+    current_baggage, current_span_state, reset_baggage, reset_parent_context, set_baggage,
+    set_parent_context, Baggage, ParentContext,
+};
+use deterministic::SequentialId;
+pub use errors::{BridgeError, ErrorMode};
+#[cfg(feature = "arrow")]
+pub use export::arrow::{
+    start_arrow_collection, stop_arrow_collection, take_arrow_batch, ArrowRecordBatch,
+};
+#[cfg(unix)]
+pub use export::stream::{
+    create_stream_pipe, listen_socket_stream, start_fd_stream, start_socket_stream,
 };
+pub use export::{
+    chrome::{start_chrome_trace, stop_chrome_trace},
+    console::{disable_console_tracing, enable_console_tracing},
+    flamegraph::{dump_flamegraph, start_flamegraph, stop_flamegraph},
+    flight_recorder::{dump_recent, start_flight_recorder, stop_flight_recorder},
+    stats::get_tracing_stats,
+    stream::{stop_stream, RecordReader},
+};
+pub use fanout::FanoutLayer;
+pub use health::health_check;
+pub use identity::{current_bridge_id, current_session_id};
+use ids::SpanIds;
+#[cfg(any(feature = "appender", feature = "otlp"))]
+pub use init::TracingGuard;
+pub use init::{
+    init_with_fmt, initialize_datadog, initialize_logging, initialize_loguru,
+    initialize_opentelemetry, initialize_sentry, initialize_stats, initialize_structlog,
+    initialize_tracing, set_level,
+};
+use json_buffer::JsonBuffer;
+pub use lifecycle_audit::span_lifecycle_audit;
+pub use metrics::get_metrics;
+pub use named_bridges::{bridge_names, flush, set_filter};
+#[cfg(feature = "otlp")]
+pub use otlp::initialize_otlp;
+pub use overhead::BridgeOverhead;
+pub use pausing::{mark_thread_untraced, pause, resume};
+pub use payload::PayloadMode;
+pub use prometheus::prometheus_metrics;
+pub use propagation::{current_traceparent, set_traceparent};
+pub use span_api::{add_link, current_span_info, event_in_span, record, record_current};
+pub use span_context::SpanContext;
+#[cfg(feature = "span-trace")]
+pub use span_trace::{capture_span_trace, RustSpanTrace};
+pub use state::PythonLayerState;
+pub use stats::get_bridge_stats;
+pub use stubs::write_type_stubs;
+pub use synthetic::generate_synthetic_trace;
+
+/// Adds this crate's `#[pyfunction]`s to `m`, the Python module of a native
+/// extension.
+pub fn add_functions(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(initialize_tracing, m)?)?;
+    m.add_function(wrap_pyfunction!(initialize_logging, m)?)?;
+    m.add_function(wrap_pyfunction!(initialize_opentelemetry, m)?)?;
+    m.add_function(wrap_pyfunction!(initialize_sentry, m)?)?;
+    m.add_function(wrap_pyfunction!(initialize_structlog, m)?)?;
+    m.add_function(wrap_pyfunction!(initialize_loguru, m)?)?;
+    m.add_function(wrap_pyfunction!(initialize_datadog, m)?)?;
+    m.add_function(wrap_pyfunction!(init_with_fmt, m)?)?;
+    m.add_function(wrap_pyfunction!(initialize_stats, m)?)?;
+    #[cfg(any(feature = "appender", feature = "otlp"))]
+    m.add_class::<TracingGuard>()?;
+    #[cfg(feature = "appender")]
+    m.add_function(wrap_pyfunction!(initialize_tracing_with_file, m)?)?;
+    #[cfg(feature = "otlp")]
+    m.add_function(wrap_pyfunction!(initialize_otlp, m)?)?;
+    m.add_function(wrap_pyfunction!(set_level, m)?)?;
+    m.add_function(wrap_pyfunction!(pause, m)?)?;
+    m.add_function(wrap_pyfunction!(resume, m)?)?;
+    m.add_function(wrap_pyfunction!(mark_thread_untraced, m)?)?;
+    m.add_function(wrap_pyfunction!(current_bridge_id, m)?)?;
+    m.add_function(wrap_pyfunction!(current_session_id, m)?)?;
+    m.add_function(wrap_pyfunction!(bridge_names, m)?)?;
+    m.add_function(wrap_pyfunction!(flush, m)?)?;
+    m.add_function(wrap_pyfunction!(set_filter, m)?)?;
+    m.add_function(wrap_pyfunction!(span_lifecycle_audit, m)?)?;
+    m.add_function(wrap_pyfunction!(rebuild_interest_cache, m)?)?;
+    m.add_function(wrap_pyfunction!(start_chrome_trace, m)?)?;
+    m.add_function(wrap_pyfunction!(stop_chrome_trace, m)?)?;
+    m.add_function(wrap_pyfunction!(enable_console_tracing, m)?)?;
+    m.add_function(wrap_pyfunction!(disable_console_tracing, m)?)?;
+    m.add_function(wrap_pyfunction!(start_flamegraph, m)?)?;
+    m.add_function(wrap_pyfunction!(stop_flamegraph, m)?)?;
+    m.add_function(wrap_pyfunction!(dump_flamegraph, m)?)?;
+    m.add_function(wrap_pyfunction!(start_flight_recorder, m)?)?;
+    m.add_function(wrap_pyfunction!(stop_flight_recorder, m)?)?;
+    m.add_function(wrap_pyfunction!(dump_recent, m)?)?;
+    m.add_function(wrap_pyfunction!(get_tracing_stats, m)?)?;
+    m.add_class::<RecordReader>()?;
+    m.add_class::<FanoutLayer>()?;
+    m.add_class::<Backpressure>()?;
+    m.add_function(wrap_pyfunction!(stop_stream, m)?)?;
+    #[cfg(unix)]
+    {
+        m.add_function(wrap_pyfunction!(start_socket_stream, m)?)?;
+        m.add_function(wrap_pyfunction!(listen_socket_stream, m)?)?;
+        m.add_function(wrap_pyfunction!(start_fd_stream, m)?)?;
+        m.add_function(wrap_pyfunction!(create_stream_pipe, m)?)?;
+    }
+    #[cfg(feature = "arrow")]
+    {
+        m.add_class::<ArrowRecordBatch>()?;
+        m.add_function(wrap_pyfunction!(start_arrow_collection, m)?)?;
+        m.add_function(wrap_pyfunction!(stop_arrow_collection, m)?)?;
+        m.add_function(wrap_pyfunction!(take_arrow_batch, m)?)?;
+    }
+    m.add_function(wrap_pyfunction!(record_current, m)?)?;
+    m.add_function(wrap_pyfunction!(record, m)?)?;
+    m.add_function(wrap_pyfunction!(event_in_span, m)?)?;
+    m.add_function(wrap_pyfunction!(add_link, m)?)?;
+    m.add_function(wrap_pyfunction!(set_parent_context, m)?)?;
+    m.add_function(wrap_pyfunction!(reset_parent_context, m)?)?;
+    m.add_function(wrap_pyfunction!(set_traceparent, m)?)?;
+    m.add_function(wrap_pyfunction!(current_traceparent, m)?)?;
+    m.add_function(wrap_pyfunction!(set_baggage, m)?)?;
+    m.add_function(wrap_pyfunction!(reset_baggage, m)?)?;
+    m.add_function(wrap_pyfunction!(current_baggage, m)?)?;
+    m.add_function(wrap_pyfunction!(current_span_state, m)?)?;
+    m.add_function(wrap_pyfunction!(current_span_info, m)?)?;
+    m.add_function(wrap_pyfunction!(get_bridge_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(get_metrics, m)?)?;
+    m.add_function(wrap_pyfunction!(prometheus_metrics, m)?)?;
+    m.add_function(wrap_pyfunction!(list_callsites, m)?)?;
+    m.add_function(wrap_pyfunction!(set_callsite_enabled, m)?)?;
+    m.add_function(wrap_pyfunction!(health_check, m)?)?;
+    m.add_function(wrap_pyfunction!(dump_active_spans, m)?)?;
+    m.add_function(wrap_pyfunction!(generate_synthetic_trace, m)?)?;
+    m.add_function(wrap_pyfunction!(benchmark_bridge, m)?)?;
+    m.add_function(wrap_pyfunction!(write_type_stubs, m)?)?;
+    #[cfg(not(feature = "abi3"))]
+    m.add_class::<PayloadBuffer>()?;
+    m.add_class::<CapturingLayer>()?;
+    m.add_class::<CapturedRustTracing>()?;
+    m.add_function(wrap_pyfunction!(captured_rust_tracing, m)?)?;
+    #[cfg(feature = "span-trace")]
+    {
+        m.add_class::<RustSpanTrace>()?;
+        m.add_function(wrap_pyfunction!(capture_span_trace, m)?)?;
+    }
+    Ok(())
+}
 
 /// `PythonCallbackLayerBridge` is an adapter allowing the
 /// [`tracing_subscriber::layer::Layer`] trait to be implemented by a Python
@@ -15,7 +260,8 @@ use tracing_subscriber::{
 /// The interface `PythonCallbackLayerBridge` expects Python objects to
 /// implement differs slightly from the `Layer` trait in Rust:
 /// - The Python implementation of `on_new_span` may return some state that will
-///   be stored in the new span's [`tracing_subscriber::registry::Extensions`].
+///   be stored in the new span's [`tracing_subscriber::registry::Extensions`]
+///   as a [`PythonLayerState`], which other layers can read.
 /// - When calling other trait methods, `PythonCallbackLayerBridge` will get
 ///   that state from the current span and pass it back to Python as an
 ///   additional positional argument.
@@ -28,107 +274,1920 @@ use tracing_subscriber::{
 /// - [`tracing_subscriber::layer::Layer::on_event`], with corresponding Python
 ///   signature ```python def on_event(self, event: str, state: Any): ... ```
 /// - [`tracing_subscriber::layer::Layer::on_new_span`] ```python def
-///   on_new_span(self, span_attrs: str, span_id: str): ... ```
+///   on_new_span(self, span_attrs: str, span_id: str): ... ```. Root spans
+///   created while a parent context is set with [`set_parent_context`] also
+///   receive it as the `parent_context` keyword argument, and spans with
+///   baggage set with [`set_baggage`] receive it in a `baggage` object in
+///   `span_attrs`.
 /// - [`tracing_subscriber::layer::Layer::on_close`] ```python def
 ///   on_close(self, span_id: str, state: Any): ... ```
 /// - [`tracing_subscriber::layer::Layer::on_record`] ```python def
 ///   on_record(self, span_id: str, values: str, state: Any): ... ```
+/// - [`tracing_subscriber::layer::Layer::on_follows_from`] ```python def
+///   on_follows_from(self, span_id: str, follows_id: str, state: Any): ... ```,
+///   called when a link from `span_id` to `follows_id` is added with
+///   `Span::follows_from` or [`add_link`].
+///
+/// Exceptions raised by these methods are passed to the Python object's
+/// `on_bridge_error(exc, context)` method if it has one, where `context` is the
+/// name of the method that raised, and to the hook set with
+/// [`PythonCallbackLayerBridge::with_error_hook`].
+///
+/// Fields recorded as a `std::error::Error` are serialized as a list of the
+/// error's message followed by those of its `source()` chain.
+///
+/// Events with an `error` field, or OpenTelemetry's `exception.type` and
+/// `exception.message` fields, also include an `exception` object with the
+/// error's `type`, `message` and `chain`, the messages of its `source()`s if it
+/// was recorded as a `std::error::Error`.
+///
+/// With the `opentelemetry` feature enabled, and a `tracing-opentelemetry`
+/// layer added to the subscriber before the bridge, `span_attrs` and events in
+/// a span also include an `otel` object with the span's W3C `trace_id` and
+/// `span_id` as hex strings.
+///
+/// The methods are looked up when the bridge is created and again after
+/// [`rebuild_interest_cache`] is called, so a Python layer that adds or removes
+/// methods later should call it afterwards.
+///
+/// Once installed, other Rust code can reach the bridge of the current
+/// subscriber with [`with_current_bridge`], e.g. to flush it, read its stats or
+/// swap its Python layer with [`PythonCallbackLayerBridge::set_py_impl`].
 pub struct PythonCallbackLayerBridge {
+    id: String,
+    session_id: String,
+    stamps_session_id: bool,
+    callbacks: Arc<RwLock<Arc<Callbacks>>>,
+    current_span_state: bool,
+    context_vars: Vec<String>,
+    payload_mode: PayloadMode,
+    trace_ids: bool,
+    jsonl_file: Option<Arc<Mutex<File>>>,
+    error_hook: Option<Box<ErrorHook>>,
+    error_mode: ErrorMode,
+    logged_errors: errors::LoggedErrors,
+    stats: Arc<stats::BridgeStats>,
+    metrics: Arc<metrics::Metrics>,
+    aggregates_metrics: bool,
+    active_spans: active_spans::ActiveSpans,
+    backtraces: Option<backtraces::BacktraceSampler>,
+    shut_down: AtomicBool,
+    paused: AtomicBool,
+    orphan_events: bool,
+    follows_from_state: bool,
+    lookup_diagnostic: lookup::LookupDiagnostic,
+    slow_callback_threshold: Option<Duration>,
+    sequences: Option<deterministic::Sequences>,
+    unique_span_ids: Option<deterministic::Sequences>,
+    disabled_callsites: Arc<disabled::DisabledCallsites>,
+    callsite_registry: callsite_registry::CallsiteRegistry,
+    filter: Arc<named_bridges::BridgeFilter>,
+    named: Option<Arc<named_bridges::NamedBridge>>,
+    clock: Box<dyn Clock>,
+    overhead_accounting: bool,
+    ticker: Option<tick::Ticker>,
+    internal_tracing: bool,
+    pretty_json: bool,
+    sorted_fields: bool,
+    datetime_timezone: Option<Py<PyAny>>,
+    coercion: CoercionRules,
+    field_kinds: bool,
+    python_stack_depth: usize,
+    backpressure_level: Level,
+    lifecycle_audit: Option<lifecycle_audit::LifecycleAudit>,
+    renders_subtrees: bool,
+    routes: Vec<routing::Route>,
+    /// The subscriber the bridge is part of, which its Python layer's
+    /// callbacks reach through [`callback_dispatch::current`].
+    dispatch: OnceLock<WeakDispatch>,
+    #[cfg(feature = "compression")]
+    compression_threshold: Option<usize>,
+}
+
+/// A function called with the name of the Python layer method that raised and
+/// the exception.
+type ErrorHook = dyn Fn(Python<'_>, &str, &PyErr) + Send + Sync;
+
+/// The names of the Python layer methods the bridge calls.
+const CALLBACK_NAMES: [&str; 12] = [
+    "on_event",
+    "on_orphan_event",
+    "on_new_span",
+    "on_close",
+    "on_record",
+    "on_follows_from",
+    "on_subtree",
+    LEVEL_EVENT_NAMES[0],
+    LEVEL_EVENT_NAMES[1],
+    LEVEL_EVENT_NAMES[2],
+    LEVEL_EVENT_NAMES[3],
+    LEVEL_EVENT_NAMES[4],
+];
+
+/// The names of the Python layer methods receiving the events of one level
+/// instead of `on_event`, from `TRACE` to `ERROR`.
+const LEVEL_EVENT_NAMES: [&str; 5] = [
+    "on_trace_event",
+    "on_debug_event",
+    "on_info_event",
+    "on_warn_event",
+    "on_error_event",
+];
+
+/// Returns the index of `level` in [`LEVEL_EVENT_NAMES`].
+fn level_index(level: &Level) -> usize {
+    match *level {
+        Level::TRACE => 0,
+        Level::DEBUG => 1,
+        Level::INFO => 2,
+        Level::WARN => 3,
+        Level::ERROR => 4,
+    }
+}
+
+/// The methods of the Python layer object, as of `generation`.
+struct Callbacks {
+    py_impl: Py<PyAny>,
     on_event: Option<Py<PyAny>>,
     on_new_span: Option<Py<PyAny>>,
     on_close: Option<Py<PyAny>>,
     on_record: Option<Py<PyAny>>,
+    on_follows_from: Option<Py<PyAny>>,
+    on_bridge_error: Option<Py<PyAny>>,
+    on_orphan_event: Option<Py<PyAny>>,
+    on_subtree: Option<Py<PyAny>>,
+    /// The methods named in [`LEVEL_EVENT_NAMES`].
+    level_events: [Option<Py<PyAny>>; 5],
+    /// The Python layer, if it's a [`FanoutLayer`].
+    fanout: Option<Py<FanoutLayer>>,
+    /// The exceptions other than `AttributeError` raised while looking up the
+    /// methods, e.g. by a broken `__getattr__`, by method name.
+    lookup_errors: Vec<(&'static str, PyErr)>,
+    generation: usize,
+}
+
+impl Callbacks {
+    /// Returns the Python layer's method named `name`.
+    fn method(&self, name: &str) -> Option<&Py<PyAny>> {
+        match name {
+            "on_event" => self.on_event.as_ref(),
+            "on_new_span" => self.on_new_span.as_ref(),
+            "on_close" => self.on_close.as_ref(),
+            "on_record" => self.on_record.as_ref(),
+            "on_follows_from" => self.on_follows_from.as_ref(),
+            "on_orphan_event" => self.on_orphan_event.as_ref(),
+            "on_subtree" => self.on_subtree.as_ref(),
+            _ => LEVEL_EVENT_NAMES
+                .iter()
+                .position(|level_event| *level_event == name)
+                .and_then(|index| self.level_events[index].as_ref()),
+        }
+    }
+
+    /// Returns the name of the Python layer's method receiving the events of
+    /// `level`, which is the one for `level` in [`LEVEL_EVENT_NAMES`] if it
+    /// has it and `on_event` otherwise, and the method.
+    fn event_method(&self, level: &Level) -> Option<(&'static str, &Py<PyAny>)> {
+        let index = level_index(level);
+        match &self.level_events[index] {
+            Some(level_event) => Some((LEVEL_EVENT_NAMES[index], level_event)),
+            None => self
+                .on_event
+                .as_ref()
+                .map(|on_event| ("on_event", on_event)),
+        }
+    }
+
+    fn resolve(py_impl: &Bound<'_, PyAny>) -> Callbacks {
+        let generation = callsite::callbacks_generation();
+        let mut lookup_errors = Vec::new();
+        let mut lookup = |name: &'static str| match py_impl.getattr(name) {
+            Ok(method) => Some(method.unbind()),
+            Err(err) if err.is_instance_of::<PyAttributeError>(py_impl.py()) => None,
+            Err(err) => {
+                lookup_errors.push((name, err));
+                None
+            }
+        };
+        let on_event = lookup("on_event");
+        let on_close = lookup("on_close");
+        let on_new_span = lookup("on_new_span");
+        let on_record = lookup("on_record");
+        let on_follows_from = lookup("on_follows_from");
+        let on_bridge_error = lookup("on_bridge_error");
+        let on_orphan_event = lookup("on_orphan_event");
+        let on_subtree = lookup("on_subtree");
+        let level_events = LEVEL_EVENT_NAMES.map(&mut lookup);
+        let fanout = py_impl
+            .downcast::<FanoutLayer>()
+            .ok()
+            .map(|fanout| fanout.clone().unbind());
+
+        Callbacks {
+            py_impl: py_impl.clone().unbind(),
+            on_event,
+            on_new_span,
+            on_close,
+            on_record,
+            on_follows_from,
+            on_bridge_error,
+            on_orphan_event,
+            on_subtree,
+            level_events,
+            fanout,
+            lookup_errors,
+            generation,
+        }
+    }
+
+    /// Returns the methods cached in `cache`, looking them up again if
+    /// [`rebuild_interest_cache`] was called since they were last resolved.
+    fn cached(cache: &RwLock<Arc<Callbacks>>) -> Arc<Callbacks> {
+        let callbacks = cache.read().unwrap_or_else(PoisonError::into_inner).clone();
+        if callbacks.generation == callsite::callbacks_generation() {
+            return callbacks;
+        }
+
+        let callbacks = Arc::new(Python::with_gil(|py| {
+            Callbacks::resolve(callbacks.py_impl.bind(py))
+        }));
+        *cache.write().unwrap_or_else(PoisonError::into_inner) = callbacks.clone();
+        callbacks
+    }
 }
 
-impl PythonCallbackLayerBridge {
-    pub fn new(py_impl: Bound<'_, PyAny>) -> PythonCallbackLayerBridge {
-        let on_event = py_impl.getattr("on_event").ok().map(Bound::unbind);
-        let on_close = py_impl.getattr("on_close").ok().map(Bound::unbind);
-        let on_new_span = py_impl.getattr("on_new_span").ok().map(Bound::unbind);
-        let on_record = py_impl.getattr("on_record").ok().map(Bound::unbind);
+impl PythonCallbackLayerBridge {
+    pub fn new(py_impl: Bound<'_, PyAny>) -> PythonCallbackLayerBridge {
+        let callbacks = Callbacks::resolve(&py_impl);
+
+        PythonCallbackLayerBridge {
+            id: identity::next_id(),
+            session_id: identity::new_session_id(),
+            stamps_session_id: false,
+            callbacks: Arc::new(RwLock::new(Arc::new(callbacks))),
+            current_span_state: false,
+            context_vars: Vec::new(),
+            payload_mode: PayloadMode::Json,
+            trace_ids: false,
+            jsonl_file: None,
+            error_hook: None,
+            error_mode: ErrorMode::Ignore,
+            logged_errors: errors::LoggedErrors::default(),
+            stats: Arc::default(),
+            metrics: Arc::default(),
+            aggregates_metrics: false,
+            active_spans: active_spans::ActiveSpans::default(),
+            backtraces: None,
+            shut_down: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            orphan_events: true,
+            follows_from_state: false,
+            lookup_diagnostic: lookup::LookupDiagnostic::default(),
+            slow_callback_threshold: None,
+            sequences: None,
+            unique_span_ids: None,
+            disabled_callsites: disabled::DisabledCallsites::new(),
+            callsite_registry: callsite_registry::CallsiteRegistry::default(),
+            filter: Arc::default(),
+            named: None,
+            clock: Box::new(SystemClock),
+            overhead_accounting: false,
+            ticker: None,
+            internal_tracing: false,
+            pretty_json: false,
+            sorted_fields: false,
+            datetime_timezone: None,
+            coercion: CoercionRules::default(),
+            field_kinds: false,
+            python_stack_depth: 0,
+            backpressure_level: Level::WARN,
+            lifecycle_audit: None,
+            renders_subtrees: false,
+            routes: Vec::new(),
+            dispatch: OnceLock::new(),
+            #[cfg(feature = "compression")]
+            compression_threshold: None,
+        }
+    }
+
+    /// Creates a bridge delivering every callback to each of `py_impls`, with a
+    /// [`FanoutLayer`]. An exception raised by one of them is handled like with
+    /// a single Python layer, without keeping the others from receiving the
+    /// callback.
+    pub fn fanout(py_impls: Vec<Bound<'_, PyAny>>) -> PythonCallbackLayerBridge {
+        Python::with_gil(|py| {
+            let fanout = Bound::new(py, FanoutLayer::new(py_impls))
+                .expect("failed to create the fan-out layer");
+            PythonCallbackLayerBridge::new(fanout.into_any())
+        })
+    }
+
+    /// Creates a bridge like [`Self::new`], but checks that `py_impl` can be
+    /// used as a Python layer: it must have at least one of the methods the
+    /// bridge calls, and they must be callable.
+    pub fn try_new(py_impl: Bound<'_, PyAny>) -> Result<PythonCallbackLayerBridge, BridgeError> {
+        let callbacks = Callbacks::resolve(&py_impl);
+        if let Some((name, err)) = callbacks.lookup_errors.first() {
+            return Err(BridgeError::Construction(format!(
+                "looking up {name} raised {err}"
+            )));
+        }
+        let mut found = false;
+        for name in CALLBACK_NAMES {
+            let Ok(method) = py_impl.getattr(name) else {
+                continue;
+            };
+            if !method.is_callable() {
+                return Err(BridgeError::Construction(format!("{name} isn't callable")));
+            }
+            found = true;
+        }
+        if !found {
+            return Err(BridgeError::Construction(format!(
+                "it has none of the methods {}",
+                CALLBACK_NAMES.join(", ")
+            )));
+        }
+        Ok(PythonCallbackLayerBridge::new(py_impl))
+    }
+
+    /// Flushes the JSONL file set with [`Self::with_jsonl_file`] and calls the
+    /// Python layer's `flush()` method, if it has one.
+    pub fn flush(&self) -> Result<(), BridgeError> {
+        flush_layers(self.jsonl_file.as_deref(), self.layer_callbacks())
+    }
+
+    /// Stops forwarding events and span records to the Python layer until
+    /// [`Self::resume`] is called, and returns whether the bridge was
+    /// forwarding them.
+    ///
+    /// Spans created in the meantime are never forwarded, even after resuming,
+    /// while spans the Python layer already saw are still closed, so its state
+    /// stays consistent.
+    pub fn pause(&self) -> bool {
+        !self.paused.swap(true, Ordering::AcqRel)
+    }
+
+    /// Resumes forwarding after [`Self::pause`], and returns whether the bridge
+    /// was paused.
+    pub fn resume(&self) -> bool {
+        self.paused.swap(false, Ordering::AcqRel)
+    }
+
+    /// Stops forwarding data to the Python layer, flushes the bridge and calls
+    /// the Python layer's `shutdown()` method, if it has one.
+    ///
+    /// Only the first call has any effect.
+    pub fn shutdown(&self) -> Result<(), BridgeError> {
+        if self.shut_down.swap(true, Ordering::AcqRel) {
+            return Ok(());
+        }
+        if let Some(ticker) = &self.ticker {
+            ticker.stop();
+        }
+        self.flush()?;
+        Python::with_gil(|py| self.call_optional(py, "shutdown"))
+            .map_err(|err| BridgeError::Shutdown(err.into()))
+    }
+
+    /// Replaces the Python layer of the bridge, e.g. to reconfigure telemetry
+    /// without reinstalling the subscriber. Routes set with [`Self::with_route`]
+    /// keep theirs.
+    ///
+    /// Spans created before the swap keep the state the previous Python layer
+    /// returned for them, which is passed to the new one. The new one's
+    /// `on_attach` method is called like when the bridge is installed.
+    pub fn set_py_impl(&self, py_impl: Bound<'_, PyAny>) {
+        let callbacks = Arc::new(Callbacks::resolve(&py_impl));
+        self.attach(py_impl.py(), &callbacks);
+        *self
+            .callbacks
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = callbacks;
+        self.disabled_callsites.clear();
+    }
+
+    /// Returns the bridge's id, set with [`Self::with_id`] or generated when it
+    /// was created.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns the bridge's session id, a UUID generated when it was created,
+    /// like [`current_session_id`].
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// Returns the bridge's Python layer.
+    pub fn py_impl<'py>(&self, py: Python<'py>) -> Bound<'py, PyAny> {
+        self.callbacks().py_impl.bind(py).clone()
+    }
+
+    /// Returns the bridge's counters, like [`get_bridge_stats`].
+    pub fn stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        self.stats.to_py_dict(py)
+    }
+
+    /// Returns the callsites the bridge has seen, like [`list_callsites`].
+    pub fn callsites<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        self.callsite_registry
+            .to_py_list(py, |metadata| self.forwards_callsite(metadata))
+    }
+
+    /// Whether the bridge forwards spans or events from the callsite of
+    /// `metadata` to its Python layer.
+    fn forwards_callsite(&self, metadata: &Metadata<'_>) -> bool {
+        if !self.forwards(metadata) {
+            return false;
+        }
+        let (_, callbacks) = self.route(metadata);
+        if metadata.is_span() {
+            callbacks.on_new_span.is_some()
+        } else {
+            callbacks.event_method(metadata.level()).is_some()
+                || (callbacks.on_orphan_event.is_some() && self.orphan_events)
+        }
+    }
+
+    /// Calls the method `name` of the Python layer and those of the routes set
+    /// with [`Self::with_route`], if they have one.
+    fn call_optional(&self, py: Python<'_>, name: &str) -> PyResult<()> {
+        call_optional(py, self.layer_callbacks(), name)
+    }
+
+    /// Returns the methods of the Python layer and of those of the routes set
+    /// with [`Self::with_route`].
+    fn layer_callbacks(&self) -> impl Iterator<Item = Arc<Callbacks>> + '_ {
+        let routed = self
+            .routes
+            .iter()
+            .map(|route| Callbacks::cached(&route.callbacks));
+        iter::once(self.callbacks()).chain(routed)
+    }
+
+    /// Returns the Python layer's methods, looking them up again if
+    /// [`rebuild_interest_cache`] was called since they were last resolved.
+    fn callbacks(&self) -> Arc<Callbacks> {
+        Callbacks::cached(&self.callbacks)
+    }
+
+    /// Returns the route of the spans and events with `metadata`, and the
+    /// methods of the Python layer it leads to.
+    fn route(&self, metadata: &Metadata<'_>) -> (routing::RouteIndex, Arc<Callbacks>) {
+        match routing::find(&self.routes, metadata.target()) {
+            Some(index) => {
+                let route = &self.routes[index];
+                (
+                    routing::RouteIndex(Some(index)),
+                    Callbacks::cached(&route.callbacks),
+                )
+            }
+            None => (routing::RouteIndex::default(), self.callbacks()),
+        }
+    }
+
+    /// Sets whether entering and exiting spans updates the Python contextvar
+    /// read by [`current_span_state`] with the entered span's state.
+    ///
+    /// This lets Python code find the state of the Rust span it is running
+    /// under, but requires acquiring the GIL on every enter and exit, so it is
+    /// disabled by default.
+    pub fn with_current_span_state(mut self, enabled: bool) -> PythonCallbackLayerBridge {
+        self.current_span_state = enabled;
+        self
+    }
+
+    /// Sets the names of Python contextvars whose values are captured when a
+    /// span is created.
+    ///
+    /// The `str()` of each contextvar that is set in the Python context creating
+    /// the span is passed to `on_new_span` in a `contextvars` object in
+    /// `span_attrs`. This carries values like request ids from Python
+    /// frameworks into Rust spans without any extra instrumentation.
+    pub fn with_context_vars<I>(mut self, names: I) -> PythonCallbackLayerBridge
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.context_vars = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the form in which events are passed to the Python layer's
+    /// `on_event`.
+    ///
+    /// With [`PayloadMode::Dict`], `on_event` receives the JSON payload as
+    /// the `dict` `json.loads` would return, built without serializing it.
+    /// With `PayloadMode::Buffer`, it receives the JSON payload's UTF-8 bytes
+    /// as a [`PayloadBuffer`]-like bytes-like object, without copying them.
+    /// With [`PayloadMode::StructlogEventDict`], it receives a flat `dict`
+    /// with `event`, `level`, `timestamp`, `logger` and the event's fields,
+    /// ready to feed into a structlog processor chain, instead of a JSON
+    /// string.
+    pub fn with_payload_mode(mut self, payload_mode: PayloadMode) -> PythonCallbackLayerBridge {
+        self.payload_mode = payload_mode;
+        self
+    }
+
+    /// Sets whether the bridge assigns each span a 128-bit trace id and a
+    /// 64-bit span id, stored as [`SpanIds`].
+    ///
+    /// Registry span ids are reused once spans close, so they can't identify
+    /// spans to external tracing systems. With this enabled, `span_attrs`, the
+    /// values passed to `on_record` and events in spans include an `ids` object
+    /// with the span's hex `trace_id`, `span_id` and, for child spans,
+    /// `parent_span_id`. Structlog `event_dict`s include the `trace_id` and
+    /// `span_id` as keys.
+    ///
+    /// Spans that aren't forwarded to the Python layer, because it has no
+    /// `on_new_span` method or the bridge is paused, still get ids, so their
+    /// descendants and events stay in their trace.
+    pub fn with_trace_ids(mut self, enabled: bool) -> PythonCallbackLayerBridge {
+        self.trace_ids = enabled;
+        self
+    }
+
+    /// Sets a file to which the bridge also appends everything it forwards to
+    /// the Python layer, one JSON record per line, before calling Python.
+    ///
+    /// The records have the shape of those streamed by
+    /// [`export::stream::StreamLayer`], plus `follows_from` records, with the
+    /// same payloads the Python layer receives. When the Python layer drops or
+    /// mangles data, the file shows what it was given.
+    pub fn with_jsonl_file(mut self, file: File) -> PythonCallbackLayerBridge {
+        self.jsonl_file = Some(Arc::new(Mutex::new(file)));
+        self
+    }
+
+    /// Sets a function to call when a method of the Python layer raises, with
+    /// the method's name and the exception.
+    ///
+    /// It's called in addition to the Python layer's `on_bridge_error` method.
+    pub fn with_error_hook<F>(mut self, error_hook: F) -> PythonCallbackLayerBridge
+    where
+        F: Fn(Python<'_>, &str, &PyErr) + Send + Sync + 'static,
+    {
+        self.error_hook = Some(Box::new(error_hook));
+        self
+    }
+
+    /// Sets what the bridge does, besides calling the error hooks, when a
+    /// method of the Python layer raises. Exceptions are dropped by default.
+    pub fn with_error_mode(mut self, error_mode: ErrorMode) -> PythonCallbackLayerBridge {
+        self.error_mode = error_mode;
+        self
+    }
+
+    /// Sets how many Rust backtraces per second the bridge captures for
+    /// `ERROR` events, passed to the Python layer as a `backtrace` string in
+    /// the event. `0`, the default, captures none.
+    ///
+    /// Capturing a backtrace is expensive, so events over the limit are
+    /// forwarded without one.
+    pub fn with_error_backtraces(mut self, max_per_second: u32) -> PythonCallbackLayerBridge {
+        self.backtraces =
+            (max_per_second > 0).then(|| backtraces::BacktraceSampler::new(max_per_second));
+        self
+    }
+
+    /// Sets how long a call to a Python layer method may take before the bridge
+    /// reports it to the error hooks as a `RuntimeWarning`, and counts it in
+    /// [`get_bridge_stats`].
+    ///
+    /// The instrumented Rust code waits for the Python layer, so e.g. a slow
+    /// exporter call in `on_event` stalls the application. Calls aren't timed
+    /// against a threshold by default.
+    pub fn with_slow_callback_threshold(
+        mut self,
+        threshold: Duration,
+    ) -> PythonCallbackLayerBridge {
+        self.slow_callback_threshold = Some(threshold);
+        self
+    }
+
+    /// Sets whether the bridge replaces the ids and timestamps in payloads with
+    /// ones that are the same on every run, for snapshot tests of Python
+    /// layers.
+    ///
+    /// Spans are numbered from 1 in the order the bridge sees them, instead of
+    /// passing their registry ids. Trace ids from [`Self::with_trace_ids`]
+    /// are numbered the same way, and timestamps are the Unix epoch. Ids from
+    /// other layers, like `tracing-opentelemetry`, aren't replaced.
+    pub fn with_deterministic_mode(mut self, enabled: bool) -> PythonCallbackLayerBridge {
+        self.sequences = enabled.then(deterministic::Sequences::default);
+        self
+    }
+
+    /// Sets whether the bridge passes Python a span id that's never reused,
+    /// instead of the span's registry id.
+    ///
+    /// The registry reuses a span's id once it closes, so Python layers that
+    /// keep data keyed by span id past `on_close` can mix up unrelated spans.
+    /// With this enabled, spans are numbered from 1 in the order the bridge
+    /// sees them, like in [deterministic mode](Self::with_deterministic_mode),
+    /// but timestamps and trace ids are unchanged. Either way, `on_close` for
+    /// a span is always called before `on_new_span` for a span reusing its id.
+    pub fn with_unique_span_ids(mut self, enabled: bool) -> PythonCallbackLayerBridge {
+        self.unique_span_ids = enabled.then(deterministic::Sequences::default);
+        self
+    }
+
+    /// Sets the [`Clock`] the bridge reads timestamps and measures durations
+    /// with, e.g. a [`MockClock`] to test time-dependent behavior. Defaults to
+    /// the [`SystemClock`].
+    pub fn with_clock(mut self, clock: impl Clock) -> PythonCallbackLayerBridge {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Sets whether the bridge tracks the time it spends handling each span and
+    /// its events, in the span's [`BridgeOverhead`] extension.
+    ///
+    /// Heavy tracing makes the traced code look slower than it is. With this
+    /// enabled, `on_close` is also passed the span's overhead in seconds as
+    /// the `overhead` keyword argument, to correct its busy time with.
+    pub fn with_overhead_accounting(mut self, enabled: bool) -> PythonCallbackLayerBridge {
+        self.overhead_accounting = enabled;
+        self
+    }
+
+    /// Starts a worker thread that calls the Python layer's `on_tick(stats)`
+    /// method every `interval`, with the bridge's counters like
+    /// [`get_bridge_stats`] returns them, until the bridge is shut down or
+    /// dropped. If the Python layer has an `on_metrics(metrics)` method, it's
+    /// called after `on_tick`, with the metrics [`get_metrics`] returns.
+    ///
+    /// This gives Python layers that buffer data a place to flush it or export
+    /// aggregates when no spans or events arrive. Exceptions raised by
+    /// `on_tick` and `on_metrics` are counted in the stats and emitted as
+    /// `WARN` events with the bridge's internal target, which the bridge
+    /// doesn't forward.
+    pub fn with_tick_interval(mut self, interval: Duration) -> PythonCallbackLayerBridge {
+        self.ticker = Some(tick::Ticker::start(
+            self.callbacks.clone(),
+            self.stats.clone(),
+            self.metrics.clone(),
+            interval,
+        ));
+        self
+    }
+
+    /// Sets how many frames of the Python call stack that created each span
+    /// the bridge passes to `on_new_span`, as a `python_stack` list in
+    /// `span_attrs` of the frames' `file`, `line` and `function`, innermost
+    /// first, or `0` (the default) to pass none.
+    ///
+    /// This shows which Python code path led to Rust work when profiling mixed
+    /// stacks. Spans created on threads that aren't running Python code have
+    /// no `python_stack`.
+    pub fn with_python_stack(mut self, depth: usize) -> PythonCallbackLayerBridge {
+        self.python_stack_depth = depth;
+        self
+    }
+
+    /// Sets the least severe level of the events the bridge still forwards
+    /// while the Python layer asked it to slow down, `WARN` by default.
+    ///
+    /// The Python layer asks it to by returning [`Backpressure::SlowDown`]
+    /// (`Backpressure.SLOW_DOWN`) from an event callback or `on_tick`, e.g.
+    /// when the exporter it feeds is falling behind, and the bridge then drops
+    /// less severe events until one returns `Backpressure.CLEAR`.
+    pub fn with_backpressure_level(mut self, level: Level) -> PythonCallbackLayerBridge {
+        self.backpressure_level = level;
+        self
+    }
+
+    /// Sets whether the bridge accounts for the spans it calls the Python
+    /// layer's `on_new_span` for, checking that each gets exactly one
+    /// `on_close`, as [`span_lifecycle_audit`] reports.
+    ///
+    /// This is a diagnostics mode for layers whose span state keeps growing: it
+    /// finds the spans that closed without `on_close` being called and the
+    /// calls that raised, at the cost of a lock on every span's creation and
+    /// close.
+    pub fn with_lifecycle_audit(mut self, enabled: bool) -> PythonCallbackLayerBridge {
+        self.lifecycle_audit = enabled.then(lifecycle_audit::LifecycleAudit::default);
+        self
+    }
+
+    /// Applies the settings of the `PYO3_TRACING_*` environment variables, as
+    /// the `initialize_*` functions do, to a bridge installed from Rust.
+    ///
+    /// `PYO3_TRACING_FILTER` is left to the subscriber the bridge is added to.
+    /// Returns `ValueError` if a variable has an invalid value and `OSError` if
+    /// the file of `PYO3_TRACING_JSONL_PATH` can't be opened.
+    pub fn with_env_config(self) -> PyResult<PythonCallbackLayerBridge> {
+        self.with_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Like [`Self::with_env_config`], with the variables `var` returns.
+    pub(crate) fn with_vars(
+        self,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> PyResult<PythonCallbackLayerBridge> {
+        config::BridgeConfig::from_vars(var)?.apply(self)
+    }
+
+    /// Sets whether the bridge keeps the events and child spans of each span
+    /// while it's open and, when a root span closes, calls the Python layer's
+    /// `on_subtree(span_id, tree, rendered)` with the whole subtree: `tree` as
+    /// nested `dict`s and `rendered` as text indented like `tracing-tree`.
+    ///
+    /// A span is a root here if its parent isn't kept, e.g. because it was
+    /// created while the bridge was paused. The events and fields are kept as
+    /// formatted text until the root closes, so long-lived root spans hold on
+    /// to everything emitted in them.
+    pub fn with_rendered_subtrees(mut self, enabled: bool) -> PythonCallbackLayerBridge {
+        self.renders_subtrees = enabled;
+        self
+    }
+
+    /// Sets whether the bridge aggregates metrics from event fields named like
+    /// `tracing-opentelemetry`'s `MetricsLayer` expects them:
+    /// `monotonic_counter.<name>` and `counter.<name>` fields add to counters
+    /// and `histogram.<name>` fields record values in histograms, with the
+    /// event's other fields as the metric's attributes.
+    ///
+    /// Python reads the current values with [`get_metrics`], or receives them
+    /// in an `on_metrics(metrics)` method with [`Self::with_tick_interval`].
+    /// The events are still forwarded as usual.
+    pub fn with_metrics(mut self, enabled: bool) -> PythonCallbackLayerBridge {
+        self.aggregates_metrics = enabled;
+        self
+    }
+
+    /// Sets whether the bridge also forwards the spans and events it emits about
+    /// itself, with the `pyo3_python_tracing_subscriber::internal` target, to
+    /// the Python layer.
+    ///
+    /// They're skipped by default, so the Python layer's failures don't feed
+    /// back into it, but can be included to troubleshoot the bridge.
+    pub fn with_internal_tracing(mut self, enabled: bool) -> PythonCallbackLayerBridge {
+        self.internal_tracing = enabled;
+        self
+    }
+
+    /// Sets whether the JSON strings passed to the Python layer are
+    /// pretty-printed with newlines and indentation, to read them more easily
+    /// while prototyping a layer that prints them, rather than compact.
+    ///
+    /// Span ids stay compact, and so do the records of
+    /// [`Self::with_jsonl_file`], which must be one per line.
+    pub fn with_pretty_json(mut self, enabled: bool) -> PythonCallbackLayerBridge {
+        self.pretty_json = enabled;
+        self
+    }
+
+    /// Sets whether payloads list their fields, and the keys of every other
+    /// object, sorted by name, so they're identical across runs and builds for
+    /// snapshot tests and diffs.
+    ///
+    /// JSON payloads are already sorted unless another crate in the build
+    /// enables `serde_json`'s `preserve_order` feature; this sorts them
+    /// regardless. Structlog `event_dict`s otherwise list fields in
+    /// declaration order after the standard keys.
+    pub fn with_sorted_fields(mut self, enabled: bool) -> PythonCallbackLayerBridge {
+        self.sorted_fields = enabled;
+        self
+    }
+
+    /// Makes structlog `event_dict`s (see [`PayloadMode::StructlogEventDict`])
+    /// carry their `timestamp` as an aware `datetime` in `timezone`, a
+    /// `tzinfo` like `datetime.timezone.utc` or a `zoneinfo.ZoneInfo`, instead
+    /// of an ISO 8601 string.
+    pub fn with_datetime_timestamps(
+        mut self,
+        timezone: Bound<'_, PyAny>,
+    ) -> PythonCallbackLayerBridge {
+        self.datetime_timezone = Some(timezone.unbind());
+        self
+    }
+
+    /// Sets how the bridge coerces field values that downstream systems type
+    /// differently, like `bool`s and `u64`s too large for signed 64-bit
+    /// integers, in every payload mode.
+    pub fn with_coercion_rules(mut self, rules: CoercionRules) -> PythonCallbackLayerBridge {
+        self.coercion = rules;
+        self
+    }
+
+    /// Sets whether payloads include a `field_kinds` object with how `tracing`
+    /// recorded each field, before any coercion: `"bool"`, `"i64"`, `"u64"`,
+    /// `"i128"`, `"u128"`, `"f64"`, `"str"`, `"bytes"`, `"error"`, `"debug"`
+    /// or, with the `valuable` feature, `"valuable"`.
+    ///
+    /// This tells Python consumers whether `"1337"` was a string or a number
+    /// formatted with `Debug`, and which values are `Debug` dumps rather than
+    /// data. Values passed with `%` are `"debug"` too, since `tracing` records
+    /// them the same way.
+    pub fn with_field_kinds(mut self, enabled: bool) -> PythonCallbackLayerBridge {
+        self.field_kinds = enabled;
+        self
+    }
+
+    /// Sets whether the bridge forwards events emitted outside of any span,
+    /// which it does by default.
+    ///
+    /// Python layers with an `on_orphan_event(event)` method receive them there
+    /// instead of in `on_event`, to treat records without context differently.
+    pub fn with_orphan_events(mut self, enabled: bool) -> PythonCallbackLayerBridge {
+        self.orphan_events = enabled;
+        self
+    }
+
+    /// Sets whether events in a span without state receive the state of the
+    /// first span it follows from that has one instead of `None`, for
+    /// pipelines that model causality with `follows_from` links.
+    ///
+    /// A span without state is one the Python layer returned `None` for or
+    /// didn't see, e.g. because it was created while the bridge was paused. The
+    /// states are recorded when the links are added, so they outlive the spans
+    /// followed.
+    pub fn with_follows_from_state(mut self, enabled: bool) -> PythonCallbackLayerBridge {
+        self.follows_from_state = enabled;
+        self
+    }
+
+    /// Sets the bridge's id, which is otherwise generated as `bridge-1`,
+    /// `bridge-2`… It's passed to the Python layer's `on_attach(bridge_id)`
+    /// method when the bridge is added to a subscriber, and returned by
+    /// [`current_bridge_id`], so Python code managing several bridges can tell
+    /// which one is calling it.
+    pub fn with_id(mut self, id: impl Into<String>) -> PythonCallbackLayerBridge {
+        self.id = id.into();
+        self
+    }
+
+    /// Sets whether the bridge includes its session id, a UUID generated when
+    /// it was created, as `session_id` in every payload: `span_attrs`, the
+    /// values passed to `on_record`, events and structlog `event_dict`s.
+    ///
+    /// Python consumers aggregating telemetry from several processes, or from
+    /// one across restarts, can tell the runs apart by it.
+    pub fn with_session_id(mut self, enabled: bool) -> PythonCallbackLayerBridge {
+        self.stamps_session_id = enabled;
+        self
+    }
+
+    /// Sends the spans and events whose target is `prefix`, or a module under
+    /// it, to `py_impl` instead of the bridge's Python layer, e.g. to feed a
+    /// database profiler with `my_crate::db` without a Python layer branching
+    /// on targets.
+    ///
+    /// The longest matching prefix wins, and the route is chosen before any
+    /// payload is built. Events only receive the state of their span if it was
+    /// sent to the same Python layer.
+    pub fn with_route(
+        mut self,
+        prefix: impl Into<String>,
+        py_impl: Bound<'_, PyAny>,
+    ) -> PythonCallbackLayerBridge {
+        self.routes
+            .push(routing::Route::new(prefix.into(), py_impl));
+        self
+    }
+
+    /// The options of the structlog `event_dict`s the bridge builds.
+    fn event_dict_options<'a, 'py>(
+        &'a self,
+        py: Python<'py>,
+    ) -> payload::EventDictOptions<'a, 'py> {
+        payload::EventDictOptions {
+            now: self.now(),
+            sorted_fields: self.sorted_fields,
+            timezone: self
+                .datetime_timezone
+                .as_ref()
+                .map(|timezone| timezone.bind(py)),
+            coercion: self.coercion,
+            field_kinds: self.field_kinds,
+        }
+    }
+
+    /// Adds the `field_kinds` of `fields` to `payload` if the bridge was built
+    /// with [`Self::with_field_kinds`].
+    fn insert_field_kinds(&self, payload: &mut Value, fields: &impl RecordFields) {
+        if let (true, Some(payload)) = (self.field_kinds, payload.as_object_mut()) {
+            payload.insert("field_kinds".to_owned(), fields::field_kinds(fields));
+        }
+    }
+
+    /// Adds the bridge's session id to `payload` if it was built with
+    /// [`Self::with_session_id`].
+    fn insert_session_id(&self, payload: &mut Value) {
+        if let (true, Some(payload)) = (self.stamps_session_id, payload.as_object_mut()) {
+            payload.insert("session_id".to_owned(), self.session_id.clone().into());
+        }
+    }
+
+    /// Sorts the keys of `payload`'s objects if the bridge was built with
+    /// [`Self::with_sorted_fields`].
+    fn order_fields(&self, payload: &mut Value) {
+        if self.sorted_fields {
+            payload.sort_all_objects();
+        }
+    }
+
+    /// Serializes a payload for the Python layer, pretty-printed if the bridge
+    /// was built with [`Self::with_pretty_json`].
+    fn json_string(&self, payload: &Value) -> JsonBuffer {
+        JsonBuffer::serialize(payload, self.pretty_json)
+    }
+
+    /// Gzips JSON event payloads longer than `threshold` bytes before handing
+    /// them to Python, outside the GIL.
+    ///
+    /// `on_event` then receives the compressed payload as `bytes`, with
+    /// `encoding="gzip"` as a keyword argument, to decompress with
+    /// `gzip.decompress`. Smaller payloads are passed as usual, without the
+    /// keyword argument. Only applies with [`PayloadMode::Json`].
+    #[cfg(feature = "compression")]
+    pub fn with_payload_compression(mut self, threshold: usize) -> PythonCallbackLayerBridge {
+        self.compression_threshold = Some(threshold);
+        self
+    }
+
+    /// Converts a JSON event payload to the object passed to `on_event` in the
+    /// bridge's [`PayloadMode`], and the `encoding` keyword argument to pass
+    /// with it, if any.
+    fn json_event_payload(
+        &self,
+        py: Python<'_>,
+        json_event: &Value,
+    ) -> (PyObject, Option<&'static str>) {
+        match self.payload_mode {
+            PayloadMode::Dict => return (payload::json_to_py(py, json_event), None),
+            #[cfg(not(feature = "abi3"))]
+            PayloadMode::Buffer => {
+                let payload = PayloadBuffer::new(self.json_string(json_event).into_vec());
+                return (payload.into_py(py), None);
+            }
+            _ => {}
+        }
+        let json_event = self.json_string(json_event);
+        #[cfg(feature = "compression")]
+        if self
+            .compression_threshold
+            .is_some_and(|threshold| json_event.as_str().len() > threshold)
+        {
+            let compressed = py.allow_threads(|| compression::gzip(json_event.as_str()));
+            return (
+                pyo3::types::PyBytes::new_bound(py, &compressed)
+                    .into_any()
+                    .unbind(),
+                Some("gzip"),
+            );
+        }
+        (json_event.to_py(py), None)
+    }
+
+    /// Whether the bridge is paused or the current thread was marked with
+    /// [`mark_thread_untraced`], so new spans, events and span records aren't
+    /// forwarded.
+    fn suspended(&self) -> bool {
+        self.paused.load(Ordering::Acquire) || pausing::is_thread_untraced()
+    }
+
+    /// Whether the bridge forwards the span or event of `metadata` to Python.
+    fn forwards(&self, metadata: &Metadata<'_>) -> bool {
+        self.internal_tracing || !internal::is_internal(metadata)
+    }
+
+    /// Returns when the bridge started handling a callback, if it accounts for
+    /// its overhead.
+    fn overhead_started(&self) -> Option<Instant> {
+        self.overhead_accounting.then(|| self.clock.instant())
+    }
+
+    /// Gives `span` [`SpanIds`] in its parent's trace, or a new trace if it's
+    /// a root, and returns them, if the bridge was built with
+    /// [`Self::with_trace_ids`].
+    ///
+    /// This is done even for spans the Python layer isn't told about, so the
+    /// spans and events in them are still in the same trace.
+    fn assign_span_ids<S>(
+        &self,
+        span: &SpanRef<'_, S>,
+        sequential_id: Option<SequentialId>,
+    ) -> Option<SpanIds>
+    where
+        S: for<'a> LookupSpan<'a>,
+    {
+        if !self.trace_ids {
+            return None;
+        }
+        let parent_ids = span
+            .parent()
+            .and_then(|parent| parent.extensions().get::<SpanIds>().copied());
+        let span_ids = match (&self.sequences, sequential_id) {
+            (Some(sequences), Some(sequential_id)) => {
+                sequences.span_ids(sequential_id, parent_ids.as_ref())
+            }
+            _ => parent_ids.map_or_else(SpanIds::new_root, |parent| parent.new_child()),
+        };
+        span.extensions_mut().insert(span_ids);
+        Some(span_ids)
+    }
+
+    /// Adds the time since `started` to the [`BridgeOverhead`] of `span`.
+    fn add_overhead<S>(&self, started: Option<Instant>, span: Option<SpanRef<'_, S>>)
+    where
+        S: for<'a> LookupSpan<'a>,
+    {
+        let (Some(started), Some(span)) = (started, span) else {
+            return;
+        };
+        let extensions = span.extensions();
+        if let Some(overhead) = extensions.get::<BridgeOverhead>() {
+            overhead.add(self.clock.instant().saturating_duration_since(started));
+        }
+    }
+
+    /// Returns the counters the bridge numbers spans with instead of passing
+    /// their registry ids, in deterministic mode or with unique span ids.
+    fn renumbered_spans(&self) -> Option<&deterministic::Sequences> {
+        self.sequences.as_ref().or(self.unique_span_ids.as_ref())
+    }
+
+    /// Returns the registry id of the span passed to Python as `payload_id`,
+    /// or `None` if the bridge numbers spans itself and none that's open has
+    /// that number.
+    pub(crate) fn registry_span_id(&self, payload_id: u64) -> Option<span::Id> {
+        match self.renumbered_spans() {
+            Some(sequences) => sequences.registry_id(payload_id),
+            None => Some(span::Id::from_u64(payload_id)),
+        }
+    }
+
+    /// Returns the id passed to Python for `span`.
+    fn payload_id<S>(&self, span: &SpanRef<'_, S>) -> u64
+    where
+        S: for<'a> LookupSpan<'a>,
+    {
+        if self.sequences.is_some() || self.unique_span_ids.is_some() {
+            if let Some(SequentialId(id)) = span.extensions().get::<SequentialId>() {
+                return *id;
+            }
+        }
+        span.id().into_u64()
+    }
+
+    /// Returns the current time, or a fixed one in deterministic mode.
+    fn now(&self) -> SystemTime {
+        match self.sequences {
+            Some(_) => deterministic::FIXED_TIME,
+            None => self.clock.now(),
+        }
+    }
+
+    /// Calls the Python layer's `callback` method for the callsite of
+    /// `metadata` with `call` and returns its value, or reports its exception
+    /// to the error hooks and handles it according to the [`ErrorMode`].
+    ///
+    /// Calls slower than the threshold set with
+    /// [`Self::with_slow_callback_threshold`] are reported to the error hooks
+    /// too.
+    fn call<T>(
+        &self,
+        py: Python<'_>,
+        callbacks: &Callbacks,
+        callback: &'static str,
+        metadata: &Metadata<'_>,
+        call: impl FnOnce() -> PyResult<T>,
+    ) -> Option<T> {
+        let started = self.clock.instant();
+        let result = call();
+        let latency = self.clock.instant().saturating_duration_since(started);
+        self.check_latency(py, callbacks, callback, metadata, latency);
+
+        if let Some(fanout) = &callbacks.fanout {
+            for err in fanout.get().take_errors() {
+                self.handle_error(py, callbacks, callback, metadata, err);
+            }
+        }
+        match result {
+            Ok(value) => Some(value),
+            Err(err) => {
+                self.handle_error(py, callbacks, callback, metadata, err);
+                None
+            }
+        }
+    }
+
+    /// Counts and reports `err`, raised by the Python layer's `callback`
+    /// method, according to the bridge's [`ErrorMode`].
+    fn handle_error(
+        &self,
+        py: Python<'_>,
+        callbacks: &Callbacks,
+        callback: &'static str,
+        metadata: &Metadata<'_>,
+        err: PyErr,
+    ) {
+        self.stats.record_error(py, callback, &err);
+        match self.error_mode {
+            ErrorMode::Panic if cfg!(debug_assertions) => {
+                panic!("{}", errors::describe(py, callback, metadata, &err))
+            }
+            ErrorMode::LogOnce | ErrorMode::Panic
+                if self.logged_errors.first(callback, metadata) =>
+            {
+                eprintln!("{}", errors::describe(py, callback, metadata, &err));
+            }
+            ErrorMode::Unraisable => {
+                let method = callbacks.method(callback).map(|method| method.bind(py));
+                err.clone_ref(py).write_unraisable_bound(py, method);
+            }
+            _ => {}
+        }
+
+        self.notify(py, callbacks, callback, &err);
+    }
+
+    /// Records that a call to the Python layer's `callback` method took
+    /// `elapsed`, and reports it to the error hooks as a `RuntimeWarning` if
+    /// that's over the slow callback threshold.
+    fn check_latency(
+        &self,
+        py: Python<'_>,
+        callbacks: &Callbacks,
+        callback: &'static str,
+        metadata: &Metadata<'_>,
+        elapsed: Duration,
+    ) {
+        self.stats.record_latency(callback, elapsed);
+        let Some(threshold) = self.slow_callback_threshold else {
+            return;
+        };
+        if elapsed <= threshold {
+            return;
+        }
+
+        self.stats.record_slow_call(callback);
+        let warning = PyRuntimeWarning::new_err(format!(
+            "the Python layer's {callback} took {elapsed:?} for {}::{}, over the {threshold:?} threshold",
+            metadata.target(),
+            metadata.name(),
+        ));
+        self.notify(py, callbacks, callback, &warning);
+    }
+
+    /// Closes the subtree of the span `span_id`, adding it to its parent's or,
+    /// if it's a root, passing it to the Python layer's `on_subtree`.
+    fn close_subtree<S>(&self, span_id: &span::Id, ctx: &Context<'_, S>)
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let Some(span) = ctx.span(span_id) else {
+            return;
+        };
+        let Some(mut node) = span.extensions_mut().remove::<subtree::Node>() else {
+            return;
+        };
+        node.close(self.clock.instant());
+        if let Some(parent) = span.parent() {
+            if let Some(parent_node) = parent.extensions_mut().get_mut::<subtree::Node>() {
+                parent_node.push_child(node);
+                return;
+            }
+        }
+
+        let (_, callbacks) = self.route(span.metadata());
+        let Some(py_on_subtree) = &callbacks.on_subtree else {
+            return;
+        };
+        let json_id = json!([self.payload_id(&span)]).to_string();
+        Python::with_gil(|py| {
+            let tree = node.to_py(py);
+            self.call(py, &callbacks, "on_subtree", span.metadata(), || {
+                py_on_subtree.bind(py).call1((json_id, tree, node.render()))
+            });
+        });
+    }
+
+    /// Forwards `callback` for a span the subscriber couldn't look up, with the
+    /// Python arguments returned by `args`, counting the failure and printing a
+    /// diagnostic the first time.
+    fn forward_unknown_span(
+        &self,
+        callback: &'static str,
+        metadata: &Metadata<'_>,
+        args: impl FnOnce(Python<'_>) -> Vec<PyObject>,
+    ) {
+        self.stats.record_lookup_failure(callback);
+        self.lookup_diagnostic.report(callback);
+        if self.suspended() || !self.forwards(metadata) {
+            return;
+        }
+        let (_, callbacks) = self.route(metadata);
+        let Some(method) = callbacks.method(callback) else {
+            return;
+        };
+        Python::with_gil(|py| {
+            let args = PyTuple::new_bound(py, args(py));
+            self.call(py, &callbacks, callback, metadata, || {
+                method.bind(py).call1(args)
+            });
+        });
+    }
+
+    /// Calls the `on_attach` method of the Python layer of `callbacks` with the
+    /// bridge's id, counting and reporting any exception it raises.
+    ///
+    /// The exceptions raised while looking up the Python layer's methods are
+    /// reported the same way, since the error hook isn't set yet when they're
+    /// looked up.
+    fn attach(&self, py: Python<'_>, callbacks: &Callbacks) {
+        for (name, err) in &callbacks.lookup_errors {
+            self.stats.record_error(py, name, err);
+            self.notify(py, callbacks, name, err);
+        }
+        if let Err(err) = identity::attach(callbacks.py_impl.bind(py), &self.id) {
+            self.stats.record_error(py, "on_attach", &err);
+            self.notify(py, callbacks, "on_attach", &err);
+        }
+    }
+
+    /// Passes `err`, about the Python layer's `callback` method, to the error
+    /// hook and the Python layer's `on_bridge_error`.
+    fn notify(&self, py: Python<'_>, callbacks: &Callbacks, callback: &str, err: &PyErr) {
+        if let Some(error_hook) = &self.error_hook {
+            error_hook(py, callback, err);
+        }
+        if let Some(on_bridge_error) = &callbacks.on_bridge_error {
+            // An error hook that raises has nothing left to report to.
+            let _ = on_bridge_error.call1(py, (err.value_bound(py), callback));
+        }
+    }
+
+    /// Runs `f`, the body of the `Layer` method `callback`, unless the bridge
+    /// was shut down, counting and dropping any panic instead of unwinding into
+    /// the instrumented code, which may be called from Python through FFI.
+    /// Panics still propagate with [`ErrorMode::Panic`] in debug builds.
+    fn guard(&self, callback: &'static str, f: impl FnOnce()) {
+        if self.shut_down.load(Ordering::Acquire) {
+            return;
+        }
+        let entered = callback_dispatch::enter(self.dispatch.get());
+        let result = panic::catch_unwind(AssertUnwindSafe(f));
+        drop(entered);
+        if let Err(panic) = result {
+            if self.error_mode == ErrorMode::Panic && cfg!(debug_assertions) {
+                panic::resume_unwind(panic);
+            }
+            self.stats.record_panic(callback);
+        }
+    }
+
+    /// Appends `record` to the file set with [`Self::with_jsonl_file`], if any.
+    fn tee(&self, record: impl FnOnce() -> Value) {
+        let Some(jsonl_file) = &self.jsonl_file else {
+            return;
+        };
+
+        let line = export::stream::Framing::Lines.encode(&record());
+        let _ = jsonl_file
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .write_all(&line);
+    }
+}
+
+/// Flushes `jsonl_file` and calls the `flush()` method of each Python layer in
+/// `callbacks`, if it has one.
+fn flush_layers(
+    jsonl_file: Option<&Mutex<File>>,
+    callbacks: impl Iterator<Item = Arc<Callbacks>>,
+) -> Result<(), BridgeError> {
+    if let Some(jsonl_file) = jsonl_file {
+        jsonl_file
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .flush()
+            .map_err(|err| BridgeError::Delivery(err.into()))?;
+    }
+    Python::with_gil(|py| call_optional(py, callbacks, "flush"))
+        .map_err(|err| BridgeError::Delivery(err.into()))
+}
+
+/// Calls the method `name` of each Python layer in `callbacks`, if it has one.
+fn call_optional(
+    py: Python<'_>,
+    callbacks: impl Iterator<Item = Arc<Callbacks>>,
+    name: &str,
+) -> PyResult<()> {
+    for callbacks in callbacks {
+        let py_impl = callbacks.py_impl.bind(py);
+        if py_impl.hasattr(name)? {
+            py_impl.call_method0(name)?;
+        }
+    }
+    Ok(())
+}
+
+/// Calls `f` with the bridge of the current subscriber and returns its result,
+/// or returns `None` if the subscriber has no bridge.
+///
+/// The bridge is found through the dispatcher's `downcast_ref`, so it can be
+/// nested in `Layered` and `Filtered` layers. Inside the Python layer's
+/// callbacks, it's the bridge calling them.
+pub fn with_current_bridge<T>(f: impl FnOnce(&PythonCallbackLayerBridge) -> T) -> Option<T> {
+    let dispatch = callback_dispatch::current();
+    dispatch.downcast_ref::<PythonCallbackLayerBridge>().map(f)
+}
+
+impl<S> Layer<S> for PythonCallbackLayerBridge
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_register_dispatch(&self, subscriber: &Dispatch) {
+        let _ = self.dispatch.set(subscriber.downgrade());
+    }
+
+    fn on_layer(&mut self, _subscriber: &mut S) {
+        Python::with_gil(|py| {
+            for callbacks in self.layer_callbacks() {
+                self.attach(py, &callbacks);
+            }
+        });
+        let routed = self.routes.iter().map(|route| route.callbacks.clone());
+        self.named = Some(named_bridges::NamedBridge::register(
+            &self.id,
+            named_bridges::NamedBridge {
+                callbacks: iter::once(self.callbacks.clone()).chain(routed).collect(),
+                jsonl_file: self.jsonl_file.clone(),
+                filter: self.filter.clone(),
+            },
+        ));
+    }
+
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        self.callsite_registry.register(metadata);
+        Interest::always()
+    }
+
+    fn on_event(&self, event: &Event, ctx: Context<'_, S>) {
+        if self.aggregates_metrics {
+            self.metrics.record(event);
+        }
+        if self.disabled_callsites.is_disabled(event.metadata())
+            || self.callsite_registry.is_disabled(event.metadata())
+            || !self.filter.enabled(event.metadata())
+        {
+            return;
+        }
+        let started = self.overhead_started();
+        self.guard("on_event", || {
+            if self.suspended() {
+                return;
+            }
+            if !self.forwards(event.metadata()) {
+                let generation = callsite::callbacks_generation();
+                self.disabled_callsites
+                    .disable(event.metadata(), generation);
+                return;
+            }
+            let current_span = event
+                .parent()
+                .and_then(|id| ctx.span(id))
+                .or_else(|| ctx.lookup_current());
+            if let (true, Some(span)) = (self.renders_subtrees, &current_span) {
+                if let Some(node) = span.extensions_mut().get_mut::<subtree::Node>() {
+                    node.push_event(event, self.clock.instant());
+                }
+            }
+            if current_span.is_none() && !self.orphan_events {
+                return;
+            }
+            let (route, callbacks) = self.route(event.metadata());
+            let level = event.metadata().level();
+            if callbacks.event_method(level).is_none()
+                && (callbacks.on_orphan_event.is_none() || !self.orphan_events)
+            {
+                // The callsite's events are still kept for the subtrees.
+                if !self.renders_subtrees {
+                    self.disabled_callsites
+                        .disable(event.metadata(), callbacks.generation);
+                }
+                return;
+            }
+            let method = match (&current_span, &callbacks.on_orphan_event) {
+                (None, Some(on_orphan_event)) => Some(("on_orphan_event", on_orphan_event)),
+                _ => callbacks.event_method(event.metadata().level()),
+            };
+            let Some((callback, py_on_event)) = method else {
+                return;
+            };
+            if self
+                .stats
+                .pressure()
+                .sheds(event.metadata().level(), self.backpressure_level)
+            {
+                return;
+            }
+            self.callsite_registry.forwarded(event.metadata());
+            let extensions = current_span.as_ref().map(|span| span.extensions());
+            let backtrace = self
+                .backtraces
+                .as_ref()
+                .filter(|_| *event.metadata().level() == Level::ERROR)
+                .and_then(|backtraces| backtraces.capture(self.clock.instant()));
+            let json_event = (self.payload_mode != PayloadMode::StructlogEventDict
+                || self.jsonl_file.is_some())
+            .then(|| {
+                let mut json_event = json!(event.as_serde());
+                log_bridge::normalize_payload(&mut json_event, event);
+                fields::insert_recorded_values(&mut json_event, event);
+                self.coercion.coerce_payload(&mut json_event, event);
+                self.insert_field_kinds(&mut json_event, event);
+                exception::insert_exception(&mut json_event, event);
+                if let Some(extensions) = &extensions {
+                    ids::insert_ids(&mut json_event, extensions.get::<SpanIds>());
+                }
+                #[cfg(feature = "opentelemetry")]
+                if let Some(extensions) = &extensions {
+                    otel::insert_otel_ids(&mut json_event, extensions);
+                }
+                if let Some(backtrace) = &backtrace {
+                    json_event["backtrace"] = backtrace.as_str().into();
+                }
+                self.insert_session_id(&mut json_event);
+                self.order_fields(&mut json_event);
+                json_event
+            });
+            if let Some(json_event) = &json_event {
+                self.tee(|| {
+                    json!({
+                        "type": "event",
+                        "span_id": current_span.as_ref().map(|span| self.payload_id(span)),
+                        "event": json_event,
+                    })
+                });
+            }
+            let json_event =
+                json_event.filter(|_| self.payload_mode != PayloadMode::StructlogEventDict);
+
+            Python::with_gil(|py| {
+                let mut py_state = extensions.as_ref().and_then(|ext| {
+                    ext.get::<PythonLayerState>()
+                        .filter(|_| {
+                            ext.get::<routing::RouteIndex>()
+                                .copied()
+                                .unwrap_or_default()
+                                == route
+                        })
+                        .map(|state| state.clone_ref(py))
+                });
+                if let (true, Some(ext)) = (self.follows_from_state, &extensions) {
+                    if py_state.as_ref().is_none_or(|state| state.is_none(py)) {
+                        py_state = follows::fallback_state(py, ext).or(py_state);
+                    }
+                }
+                let mut encoding = None;
+                let py_event = match json_event {
+                    Some(json_event) => {
+                        let py_event;
+                        (py_event, encoding) = self.json_event_payload(py, &json_event);
+                        py_event
+                    }
+                    None => {
+                        match payload::structlog_event_dict(py, event, &self.event_dict_options(py))
+                        {
+                            Ok(event_dict) => {
+                                if let Some(span_ids) =
+                                    extensions.as_ref().and_then(|ext| ext.get::<SpanIds>())
+                                {
+                                    ids::set_event_dict_ids(&event_dict, span_ids);
+                                }
+                                if let Some(backtrace) = backtrace {
+                                    let _ = event_dict.set_item("backtrace", backtrace);
+                                }
+                                if self.stamps_session_id {
+                                    let _ = event_dict.set_item("session_id", &self.session_id);
+                                }
+                                event_dict.into_any().unbind()
+                            }
+                            Err(_) => return,
+                        }
+                    }
+                };
+                let kwargs =
+                    encoding.map(|encoding| [("encoding", encoding)].into_py_dict_bound(py));
+                // The callback may record to the span or emit events in it
+                // through the span API.
+                drop(extensions);
+                let args = match callback {
+                    "on_orphan_event" => PyTuple::new_bound(py, [py_event]),
+                    _ => PyTuple::new_bound(py, [py_event, py_state.into_py(py)]),
+                };
+                let returned = self.call(py, &callbacks, callback, event.metadata(), || {
+                    py_on_event.bind(py).call(args, kwargs.as_ref())
+                });
+                if let Some(returned) = returned {
+                    self.stats.pressure().signal(&returned);
+                }
+            })
+        });
+        self.add_overhead(
+            started,
+            started.and_then(|_| {
+                event
+                    .parent()
+                    .and_then(|id| ctx.span(id))
+                    .or_else(|| ctx.lookup_current())
+            }),
+        );
+    }
+
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, span_id: &span::Id, ctx: Context<'_, S>) {
+        let started = self.overhead_started();
+        if let (Some(_), Some(span)) = (started, ctx.span(span_id)) {
+            span.extensions_mut().insert(BridgeOverhead::default());
+        }
+        self.active_spans.opened(span_id, self.clock.instant());
+        if let (true, Some(span)) = (
+            self.renders_subtrees && !self.suspended(),
+            ctx.span(span_id),
+        ) {
+            span.extensions_mut()
+                .insert(subtree::Node::new(attrs, self.clock.instant()));
+        }
+        self.guard("on_new_span", || {
+            let (route, callbacks) = self.route(attrs.metadata());
+            let Some(py_on_new_span) = &callbacks.on_new_span else {
+                // The span isn't forwarded, but the events in it still are,
+                // with its trace's ids.
+                if let Some(current_span) = ctx.span(span_id) {
+                    self.assign_span_ids(&current_span, None);
+                }
+                return;
+            };
+            let Some(current_span) = ctx.span(span_id) else {
+                self.callsite_registry.forwarded(attrs.metadata());
+                self.forward_unknown_span("on_new_span", attrs.metadata(), |py| {
+                    let mut json_attrs = json!(attrs.as_serde());
+                    fields::insert_recorded_values(&mut json_attrs, attrs);
+                    self.coercion.coerce_payload(&mut json_attrs, attrs);
+                    self.insert_field_kinds(&mut json_attrs, attrs);
+                    self.insert_session_id(&mut json_attrs);
+                    self.order_fields(&mut json_attrs);
+                    let json_id = json!([span_id.into_u64()]).to_string();
+                    vec![self.json_string(&json_attrs).to_py(py), json_id.into_py(py)]
+                });
+                return;
+            };
+            if !self.forwards(current_span.metadata()) {
+                return;
+            }
+            if self.suspended()
+                || self.callsite_registry.is_disabled(attrs.metadata())
+                || !self.filter.enabled(attrs.metadata())
+            {
+                self.assign_span_ids(&current_span, None);
+                current_span.extensions_mut().insert(pausing::Unforwarded);
+                return;
+            }
+            self.callsite_registry.forwarded(attrs.metadata());
+
+            let sequential_id = self.renumbered_spans().map(|sequences| {
+                let sequential_id = sequences.next_span_id(span_id);
+                current_span.extensions_mut().insert(sequential_id);
+                sequential_id
+            });
+            let mut json_attrs = json!(attrs.as_serde());
+            fields::insert_recorded_values(&mut json_attrs, attrs);
+            self.coercion.coerce_payload(&mut json_attrs, attrs);
+            self.insert_field_kinds(&mut json_attrs, attrs);
+            let payload_id = self.payload_id(&current_span);
+            let json_id = json!([payload_id]).to_string();
+            let parent = current_span.parent();
+            let is_root = parent.is_none();
+            if let (Some(parent), false) = (&parent, json_attrs["parent"].is_null()) {
+                json_attrs["parent"] = json!([self.payload_id(parent)]);
+            }
+            let parent_baggage = parent
+                .as_ref()
+                .and_then(|parent| parent.extensions().get::<Baggage>().cloned());
+            let span_ids = self.assign_span_ids(&current_span, sequential_id);
+            ids::insert_ids(&mut json_attrs, span_ids.as_ref());
+            let mut extensions = current_span.extensions_mut();
+
+            let py_state = Python::with_gil(|py| {
+                let snapshot = context::snapshot_context_vars(py, &self.context_vars);
+                if let (false, Some(attrs)) = (snapshot.is_empty(), json_attrs.as_object_mut()) {
+                    attrs.insert("contextvars".to_owned(), snapshot.into());
+                }
+
+                let mut baggage = parent_baggage.unwrap_or_default();
+                if let Some(python_baggage) = context::current_python_baggage(py) {
+                    baggage.0.extend(python_baggage.0);
+                }
+                if let (false, Some(attrs)) = (baggage.0.is_empty(), json_attrs.as_object_mut()) {
+                    attrs.insert("baggage".to_owned(), json!(baggage.0));
+                    extensions.insert(baggage);
+                }
+
+                if let (Some(stack), Some(attrs)) = (
+                    python_stack::capture(py, self.python_stack_depth),
+                    json_attrs.as_object_mut(),
+                ) {
+                    attrs.insert("python_stack".to_owned(), stack);
+                }
 
-        PythonCallbackLayerBridge {
-            on_event,
-            on_close,
-            on_new_span,
-            on_record,
-        }
+                let parent_context = is_root
+                    .then(|| context::current_parent_context(py))
+                    .flatten();
+                #[cfg(feature = "opentelemetry")]
+                otel::init_new_span(&mut json_attrs, &mut extensions, parent_context.as_ref());
+                self.insert_session_id(&mut json_attrs);
+                self.order_fields(&mut json_attrs);
+                self.tee(|| {
+                    json!({
+                        "type": "new_span",
+                        "span_id": payload_id,
+                        "attrs": json_attrs,
+                    })
+                });
+                let json_attrs = self.json_string(&json_attrs);
+                let kwargs = parent_context.as_ref().map(|parent_context| {
+                    [("parent_context", parent_context)].into_py_dict_bound(py)
+                });
+                if let Some(parent_context) = parent_context {
+                    extensions.insert(ParentContext(parent_context.unbind()));
+                }
+                // The callback may record to the span or emit events in it
+                // through the span API, and its state is stored once it
+                // returns, without the GIL.
+                drop(extensions);
+
+                let py_state = self.call(py, &callbacks, "on_new_span", attrs.metadata(), || {
+                    py_on_new_span
+                        .bind(py)
+                        .call((json_attrs.to_py(py), json_id), kwargs.as_ref())
+                });
+                if let Some(audit) = &self.lifecycle_audit {
+                    audit.new_span(span_id, attrs.metadata().name(), py_state.is_some());
+                }
+                py_state.map(Bound::unbind)
+            });
+            let Some(py_state) = py_state else {
+                return;
+            };
+
+            let mut extensions = current_span.extensions_mut();
+            extensions.insert(PythonLayerState::new(py_state));
+            if route.0.is_some() {
+                extensions.insert(route);
+            }
+        });
+        self.add_overhead(started, started.and_then(|_| ctx.span(span_id)));
     }
-}
 
-impl<S> Layer<S> for PythonCallbackLayerBridge
-where
-    S: Subscriber + for<'a> LookupSpan<'a>,
-{
-    fn on_event(&self, event: &Event, ctx: Context<'_, S>) {
-        let Some(py_on_event) = &self.on_event else {
-            return;
-        };
+    fn on_enter(&self, span_id: &span::Id, ctx: Context<'_, S>) {
+        let started = self.overhead_started();
+        self.guard("on_enter", || {
+            let (true, Some(current_span)) = (self.current_span_state, ctx.span(span_id)) else {
+                return;
+            };
 
-        let current_span = event
-            .parent()
-            .and_then(|id| ctx.span(id))
-            .or_else(|| ctx.lookup_current());
-        let extensions = current_span.as_ref().map(|span| span.extensions());
-        let json_event = json!(event.as_serde()).to_string();
+            let extensions = current_span.extensions();
 
-        Python::with_gil(|py| {
-            let py_state =
-                extensions.map(|ext| ext.get::<Py<PyAny>>().map(|state| state.clone_ref(py)));
-            let _ = py_on_event.bind(py).call((json_event, py_state), None);
-        })
+            Python::with_gil(|py| {
+                let py_state = extensions
+                    .get::<PythonLayerState>()
+                    .map(|state| state.clone_ref(py));
+                context::enter_span_state(py, span_id, py_state);
+            })
+        });
+        self.add_overhead(started, started.and_then(|_| ctx.span(span_id)));
     }
 
-    fn on_new_span(&self, attrs: &span::Attributes<'_>, span_id: &span::Id, ctx: Context<'_, S>) {
-        let (Some(py_on_new_span), Some(current_span)) = (&self.on_new_span, ctx.span(span_id))
-        else {
-            return;
-        };
+    fn on_exit(&self, span_id: &span::Id, ctx: Context<'_, S>) {
+        let started = self.overhead_started();
+        self.guard("on_exit", || {
+            if !self.current_span_state {
+                return;
+            }
 
-        let json_attrs = json!(attrs.as_serde()).to_string();
-        let json_id = json!(span_id.as_serde()).to_string();
-        let mut extensions = current_span.extensions_mut();
+            Python::with_gil(|py| context::exit_span_state(py, span_id))
+        });
+        self.add_overhead(started, started.and_then(|_| ctx.span(span_id)));
+    }
 
-        Python::with_gil(|py| {
-            let Ok(py_state) = py_on_new_span.bind(py).call((json_attrs, json_id), None) else {
+    fn on_close(&self, span_id: span::Id, ctx: Context<'_, S>) {
+        let started = self.overhead_started();
+        self.active_spans.closed(&span_id);
+        let mut delivered_close = None;
+        self.guard("on_close", || {
+            let Some(current_span) = ctx.span(&span_id) else {
+                self.forward_unknown_span("on_close", &lookup::UNKNOWN_SPAN, |py| {
+                    let json_id = json!([span_id.into_u64()]).to_string();
+                    vec![json_id.into_py(py), py.None()]
+                });
+                return;
+            };
+            let (_, callbacks) = self.route(current_span.metadata());
+            let Some(py_on_close) = &callbacks.on_close else {
                 return;
             };
+            if !self.forwards(current_span.metadata())
+                || current_span
+                    .extensions()
+                    .get::<pausing::Unforwarded>()
+                    .is_some()
+            {
+                return;
+            }
 
-            extensions.insert::<Py<PyAny>>(py_state.unbind());
-        })
+            let payload_id = self.payload_id(&current_span);
+            let json_id = json!([payload_id]).to_string();
+            self.tee(|| json!({"type": "close", "span_id": payload_id}));
+            self.add_overhead(started, ctx.span(&span_id));
+            let overhead = current_span
+                .extensions()
+                .get::<BridgeOverhead>()
+                .map(BridgeOverhead::get);
+
+            Python::with_gil(|py| {
+                // The state stays in the span's extensions until the registry
+                // frees the span, after every layer's `on_close`, so events
+                // emitted while the span closes and later layers still see it.
+                let py_state = current_span
+                    .extensions()
+                    .get::<PythonLayerState>()
+                    .map(|state| state.clone_ref(py));
+                let kwargs = overhead
+                    .map(|overhead| [("overhead", overhead.as_secs_f64())].into_py_dict_bound(py));
+                let returned =
+                    self.call(py, &callbacks, "on_close", current_span.metadata(), || {
+                        py_on_close
+                            .bind(py)
+                            .call((json_id, py_state), kwargs.as_ref())
+                    });
+                delivered_close = Some(returned.is_some());
+            })
+        });
+        if let Some(audit) = &self.lifecycle_audit {
+            audit.closed(&span_id, delivered_close);
+        }
+        if let (Some(sequences), Some(span)) = (self.renumbered_spans(), ctx.span(&span_id)) {
+            if let Some(sequential_id) = span.extensions().get::<SequentialId>() {
+                sequences.closed(*sequential_id);
+            }
+        }
+        if self.renders_subtrees {
+            self.guard("on_subtree", || self.close_subtree(&span_id, &ctx));
+        }
+        if let Some(span) = started.and_then(|_| ctx.span(&span_id)) {
+            let overhead = span
+                .extensions()
+                .get::<BridgeOverhead>()
+                .map(BridgeOverhead::get);
+            if let (Some(overhead), Some(parent)) = (overhead, span.parent()) {
+                if let Some(parent_overhead) = parent.extensions().get::<BridgeOverhead>() {
+                    parent_overhead.add(overhead);
+                }
+            }
+        }
     }
 
-    fn on_close(&self, span_id: span::Id, ctx: Context<'_, S>) {
-        let (Some(py_on_close), Some(current_span)) = (&self.on_close, ctx.span(&span_id)) else {
-            return;
-        };
+    fn on_record(&self, span_id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let started = self.overhead_started();
+        if let (true, Some(span)) = (self.renders_subtrees, ctx.span(span_id)) {
+            if let Some(node) = span.extensions_mut().get_mut::<subtree::Node>() {
+                node.record(values);
+            }
+        }
+        self.guard("on_record", || {
+            let Some(current_span) = ctx.span(span_id) else {
+                self.forward_unknown_span("on_record", &lookup::UNKNOWN_SPAN, |py| {
+                    let mut json_values = json!(values.as_serde());
+                    fields::insert_recorded_values(&mut json_values, values);
+                    self.coercion.coerce_payload(&mut json_values, values);
+                    self.insert_field_kinds(&mut json_values, values);
+                    self.insert_session_id(&mut json_values);
+                    self.order_fields(&mut json_values);
+                    let json_id = json!([span_id.into_u64()]).to_string();
+                    vec![
+                        json_id.into_py(py),
+                        self.json_string(&json_values).to_py(py),
+                        py.None(),
+                    ]
+                });
+                return;
+            };
+            let (_, callbacks) = self.route(current_span.metadata());
+            let Some(py_on_record) = &callbacks.on_record else {
+                return;
+            };
+            if !self.forwards(current_span.metadata())
+                || self.suspended()
+                || current_span
+                    .extensions()
+                    .get::<pausing::Unforwarded>()
+                    .is_some()
+            {
+                return;
+            }
 
-        let json_id = json!(span_id.as_serde()).to_string();
-        let py_state = current_span.extensions_mut().remove::<Py<PyAny>>();
+            let payload_id = self.payload_id(&current_span);
+            let json_id = json!([payload_id]).to_string();
+            let extensions = current_span.extensions();
+            let mut json_values = json!(values.as_serde());
+            fields::insert_recorded_values(&mut json_values, values);
+            self.coercion.coerce_payload(&mut json_values, values);
+            self.insert_field_kinds(&mut json_values, values);
+            ids::insert_ids(&mut json_values, extensions.get::<SpanIds>());
+            self.insert_session_id(&mut json_values);
+            self.order_fields(&mut json_values);
+            self.tee(|| {
+                json!({
+                    "type": "record",
+                    "span_id": payload_id,
+                    "values": json_values,
+                })
+            });
+            let json_values = self.json_string(&json_values);
 
-        Python::with_gil(|py| {
-            let _ = py_on_close.bind(py).call((json_id, py_state), None);
-        })
+            Python::with_gil(|py| {
+                let py_state = extensions
+                    .get::<PythonLayerState>()
+                    .map(|state| state.clone_ref(py));
+                drop(extensions);
+
+                self.call(py, &callbacks, "on_record", current_span.metadata(), || {
+                    py_on_record
+                        .bind(py)
+                        .call((json_id, json_values.to_py(py), py_state), None)
+                });
+            })
+        });
+        self.add_overhead(started, started.and_then(|_| ctx.span(span_id)));
     }
 
-    fn on_record(&self, span_id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
-        let (Some(py_on_record), Some(current_span)) = (&self.on_record, ctx.span(span_id)) else {
-            return;
-        };
+    fn on_follows_from(&self, span_id: &span::Id, follows: &span::Id, ctx: Context<'_, S>) {
+        let started = self.overhead_started();
+        self.guard("on_follows_from", || {
+            let Some(current_span) = ctx.span(span_id) else {
+                self.forward_unknown_span("on_follows_from", &lookup::UNKNOWN_SPAN, |py| {
+                    let json_id = json!([span_id.into_u64()]).to_string();
+                    let json_follows_id = json!([follows.into_u64()]).to_string();
+                    vec![json_id.into_py(py), json_follows_id.into_py(py), py.None()]
+                });
+                return;
+            };
+            if let (true, Some(follows_span)) = (self.follows_from_state, ctx.span(follows)) {
+                follows::record(&current_span, &follows_span);
+            }
+            let (_, callbacks) = self.route(current_span.metadata());
+            let Some(py_on_follows_from) = &callbacks.on_follows_from else {
+                return;
+            };
+            if !self.forwards(current_span.metadata())
+                || self.suspended()
+                || current_span
+                    .extensions()
+                    .get::<pausing::Unforwarded>()
+                    .is_some()
+            {
+                return;
+            }
 
-        let json_id = json!(span_id.as_serde()).to_string();
-        let json_values = json!(values.as_serde()).to_string();
-        let extensions = current_span.extensions();
+            let payload_id = self.payload_id(&current_span);
+            let follows_id = ctx
+                .span(follows)
+                .map_or_else(|| follows.into_u64(), |span| self.payload_id(&span));
+            let json_id = json!([payload_id]).to_string();
+            let json_follows_id = json!([follows_id]).to_string();
+            let extensions = current_span.extensions();
+            self.tee(|| {
+                json!({
+                    "type": "follows_from",
+                    "span_id": payload_id,
+                    "follows_id": follows_id,
+                })
+            });
 
-        Python::with_gil(|py| {
-            let py_state = extensions
-                .get::<Py<PyAny>>()
-                .map(|state| state.clone_ref(py));
+            Python::with_gil(|py| {
+                let py_state = extensions
+                    .get::<PythonLayerState>()
+                    .map(|state| state.clone_ref(py));
 
-            let _ = py_on_record
-                .bind(py)
-                .call((json_id, json_values, py_state), None);
-        })
+                self.call(
+                    py,
+                    &callbacks,
+                    "on_follows_from",
+                    current_span.metadata(),
+                    || {
+                        py_on_follows_from
+                            .bind(py)
+                            .call((json_id, json_follows_id, py_state), None)
+                    },
+                );
+            })
+        });
+        self.add_overhead(started, started.and_then(|_| ctx.span(span_id)));
     }
 }
 
@@ -136,8 +2195,12 @@ where
 mod tests {
     use std::{ops::RangeFrom, sync::Once};
 
+    use pyo3::{
+        exceptions::PyValueError,
+        types::{PyDict, PyList},
+    };
     use serde_json::{Map, Value};
-    use tracing::{info, instrument, warn_span};
+    use tracing::{error, info, info_span, instrument, warn, warn_span};
     use tracing_subscriber::prelude::*;
 
     use super::*;
@@ -145,12 +2208,18 @@ mod tests {
     static INIT: Once = Once::new();
 
     #[pyclass]
-    struct PythonLayer {
+    pub(crate) struct PythonLayer {
         span_ids: RangeFrom<u16>,
         pub events: Vec<(String, String, u16)>,
+        pub event_fields: Vec<Value>,
+        pub event_encodings: Vec<Option<String>>,
         pub new_spans: Vec<Value>,
+        pub new_span_ids: Vec<u64>,
+        pub parent_contexts: Vec<Option<String>>,
         pub closed_spans: Vec<u16>,
+        pub close_overheads: Vec<Option<f64>>,
         pub span_records: Vec<(Value, u16)>,
+        pub links: Vec<(u64, u64, u16)>,
     }
 
     #[pymethods]
@@ -160,14 +2229,68 @@ mod tests {
             PythonLayer {
                 span_ids: 0..,
                 events: Vec::new(),
+                event_fields: Vec::new(),
+                event_encodings: Vec::new(),
                 new_spans: Vec::new(),
+                new_span_ids: Vec::new(),
+                parent_contexts: Vec::new(),
                 closed_spans: Vec::new(),
+                close_overheads: Vec::new(),
                 span_records: Vec::new(),
+                links: Vec::new(),
             }
         }
 
-        pub fn on_event(&mut self, event: String, state: u16) {
-            let event = serde_json::from_str::<Map<String, Value>>(&event).unwrap();
+        #[pyo3(signature = (event, state, encoding=None))]
+        pub fn on_event(&mut self, event: &Bound<'_, PyAny>, state: u16, encoding: Option<String>) {
+            let decompressed;
+            let event = match &encoding {
+                Some(encoding) => {
+                    assert_eq!("gzip", encoding);
+                    decompressed = PyModule::import_bound(event.py(), "gzip")
+                        .unwrap()
+                        .call_method1("decompress", (event,))
+                        .unwrap()
+                        .call_method0("decode")
+                        .unwrap();
+                    &decompressed
+                }
+                None => event,
+            };
+            self.event_encodings.push(encoding);
+            let event = match event.downcast::<PyDict>() {
+                Ok(event_dict) => PyModule::import_bound(event.py(), "json")
+                    .unwrap()
+                    .call_method1("dumps", (event_dict,))
+                    .unwrap()
+                    .extract::<String>()
+                    .unwrap(),
+                Err(_) => match event.extract::<String>() {
+                    Ok(event) => event,
+                    Err(_) => String::from_utf8(
+                        event
+                            .py()
+                            .import_bound("builtins")
+                            .unwrap()
+                            .call_method1("memoryview", (event,))
+                            .unwrap()
+                            .call_method0("tobytes")
+                            .unwrap()
+                            .extract()
+                            .unwrap(),
+                    )
+                    .unwrap(),
+                },
+            };
+            let mut event = serde_json::from_str::<Map<String, Value>>(&event).unwrap();
+            if !event.contains_key("metadata") {
+                let message = event["event"].as_str().unwrap().to_owned();
+                let level = event["level"].as_str().unwrap().to_owned();
+                self.events.push((message, level, state));
+                self.event_fields.push(event.into());
+                return;
+            }
+
             let message = event.get("message").unwrap().as_str().unwrap();
             let level = event
                 .get("metadata")
@@ -179,9 +2302,23 @@ mod tests {
 
             self.events
                 .push((message.to_owned(), level.to_owned(), state));
+
+            event.remove("message");
+            event.remove("metadata");
+            if !event.is_empty() {
+                self.event_fields.push(event.into());
+            }
         }
 
-        pub fn on_new_span(&mut self, span_attrs: String, _span_id: String) -> u16 {
+        #[pyo3(signature = (span_attrs, span_id, parent_context=None))]
+        pub fn on_new_span(
+            &mut self,
+            span_attrs: String,
+            span_id: String,
+            parent_context: Option<String>,
+        ) -> u16 {
+            let (span_id,) = serde_json::from_str::<(u64,)>(&span_id).unwrap();
+            self.new_span_ids.push(span_id);
             let span_attrs = serde_json::from_str::<Map<String, Value>>(&span_attrs).unwrap();
             let metadata = span_attrs.get("metadata").unwrap().as_object().unwrap();
 
@@ -190,6 +2327,19 @@ mod tests {
             stripped_attrs.insert("level".to_string(), metadata.get("level").unwrap().clone());
             stripped_attrs.insert("name".to_string(), metadata.get("name").unwrap().clone());
 
+            for key in [
+                "contextvars",
+                "baggage",
+                "ids",
+                "otel",
+                "python_stack",
+                "session_id",
+            ] {
+                if let Some(value) = span_attrs.get(key) {
+                    stripped_attrs.insert(key.to_owned(), value.clone());
+                }
+            }
+
             let fields = metadata.get("fields").unwrap().as_array().unwrap();
             for field in fields {
                 let field = field.as_str().unwrap();
@@ -199,27 +2349,49 @@ mod tests {
             }
 
             self.new_spans.push(stripped_attrs.into());
+            self.parent_contexts.push(parent_context);
             self.span_ids.next().unwrap()
         }
 
-        pub fn on_close(&mut self, _span_id: String, state: u16) {
+        #[pyo3(signature = (_span_id, state, overhead=None))]
+        pub fn on_close(&mut self, _span_id: String, state: u16, overhead: Option<f64>) {
             self.closed_spans.push(state);
+            self.close_overheads.push(overhead);
         }
 
         pub fn on_record(&mut self, _span_id: String, values: String, state: u16) {
             let values = serde_json::from_str(&values).unwrap();
             self.span_records.push((values, state));
         }
+
+        pub fn on_follows_from(&mut self, span_id: String, follows_id: String, state: u16) {
+            let parse_id = |id: &str| serde_json::from_str::<(u64,)>(id).unwrap().0;
+            self.links
+                .push((parse_id(&span_id), parse_id(&follows_id), state));
+        }
     }
 
-    fn initialize_tracing() -> (Py<PythonLayer>, tracing::dispatcher::DefaultGuard) {
+    pub(crate) fn prepare_python() {
         INIT.call_once(|| {
             pyo3::prepare_freethreaded_python();
         });
+    }
+
+    pub(crate) fn initialize_tracing() -> (Py<PythonLayer>, tracing::dispatcher::DefaultGuard) {
+        initialize_tracing_with(|bridge| bridge)
+    }
+
+    pub(crate) fn initialize_tracing_with(
+        configure: impl FnOnce(PythonCallbackLayerBridge) -> PythonCallbackLayerBridge,
+    ) -> (Py<PythonLayer>, tracing::dispatcher::DefaultGuard) {
+        prepare_python();
         let (py_layer, rs_layer) = Python::with_gil(|py| {
             let py_layer = Bound::new(py, PythonLayer::new()).unwrap();
             let (py_layer, py_layer_unbound) = (py_layer.clone().into_any(), py_layer.unbind());
-            (py_layer_unbound, PythonCallbackLayerBridge::new(py_layer))
+            (
+                py_layer_unbound,
+                configure(PythonCallbackLayerBridge::new(py_layer)),
+            )
         });
         (
             py_layer,
@@ -227,6 +2399,31 @@ mod tests {
         )
     }
 
+    /// An error with a chain of sources.
+    #[derive(Debug)]
+    pub(crate) struct ChainedError(&'static str, Option<Box<ChainedError>>);
+
+    impl ChainedError {
+        /// Creates an error with `messages[0]`, caused by errors with the
+        /// following messages.
+        pub(crate) fn new(messages: &[&'static str]) -> ChainedError {
+            let source = (messages.len() > 1).then(|| Box::new(ChainedError::new(&messages[1..])));
+            ChainedError(messages[0], source)
+        }
+    }
+
+    impl std::fmt::Display for ChainedError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str(self.0)
+        }
+    }
+
+    impl std::error::Error for ChainedError {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            self.1.as_deref().map(|source| source as _)
+        }
+    }
+
     #[instrument(fields(data))]
     fn func(arg1: u16, arg2: String) {
         info!("About to record something");
@@ -241,7 +2438,7 @@ mod tests {
 
         let expected_events = vec![("About to record something".to_owned(), "INFO".to_owned(), 0)];
         let expected_new_spans =
-            vec![json!({"arg1": 1337, "arg2": "\"foo\"", "level": "INFO", "name": "func"})];
+            vec![json!({"arg1": 1337, "arg2": "foo", "level": "INFO", "name": "func"})];
         let expected_closed_spans = vec![0];
         let expected_records = vec![(json!({"data": "some data"}), 0)];
 
@@ -268,7 +2465,7 @@ mod tests {
         let expected_events = vec![("About to record something".to_owned(), "INFO".to_owned(), 1)];
         let expected_new_spans = vec![
             json!({"level": "WARN", "name": "outer"}),
-            json!({"arg1": 1337, "arg2": "\"bar\"", "level": "INFO", "name": "func"}),
+            json!({"arg1": 1337, "arg2": "bar", "level": "INFO", "name": "func"}),
         ];
         let expected_closed_spans = vec![1, 0];
         let expected_records = vec![(json!({"data": "some data"}), 1)];
@@ -281,4 +2478,542 @@ mod tests {
             assert_eq!(&expected_records, &borrowed.span_records);
         });
     }
+
+    #[test]
+    fn test_error_chains() {
+        let (py_layer, _dispatcher) = initialize_tracing();
+
+        let err = ChainedError::new(&["request failed", "query failed", "connection reset"]);
+        let span = warn_span!("request", cause = tracing::field::Empty);
+        span.in_scope(|| {
+            info!(error = &err as &dyn std::error::Error, "retrying");
+        });
+        span.record("cause", &err as &dyn std::error::Error);
+
+        let chain = json!(["request failed", "query failed", "connection reset"]);
+        Python::with_gil(|py| {
+            let borrowed = py_layer.borrow(py);
+            assert_eq!(chain, borrowed.event_fields[0]["error"]);
+            assert_eq!(chain, borrowed.span_records[0].0["cause"]);
+        });
+    }
+
+    #[test]
+    fn test_jsonl_file() {
+        let path = std::env::temp_dir().join(format!("bridge-{}.jsonl", std::process::id()));
+        let file = File::options()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .unwrap();
+        let (py_layer, _dispatcher) =
+            initialize_tracing_with(|bridge| bridge.with_jsonl_file(file));
+
+        func(1337, "baz".to_string());
+
+        let lines = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let records = lines
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        let types = records
+            .iter()
+            .map(|record| record["type"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["new_span", "event", "record", "close"], types);
+        assert_eq!(1337, records[0]["attrs"]["arg1"]);
+        assert_eq!("About to record something", records[1]["event"]["message"]);
+        assert_eq!(records[0]["span_id"], records[1]["span_id"]);
+        assert_eq!("some data", records[2]["values"]["data"]);
+
+        Python::with_gil(|py| assert_eq!(1, py_layer.borrow(py).events.len()));
+    }
+
+    #[test]
+    fn test_pretty_json() {
+        let (py_layer, _dispatcher) =
+            initialize_tracing_with(|bridge| bridge.with_pretty_json(true));
+
+        func(1337, "qux".to_string());
+
+        Python::with_gil(|py| {
+            let borrowed = py_layer.borrow(py);
+            assert_eq!(1, borrowed.events.len());
+            assert_eq!(1337, borrowed.new_spans[0]["arg1"]);
+
+            let bridge = PythonCallbackLayerBridge::new(py.None().into_bound(py));
+            let payload = json!({"message": "hi"});
+            assert_eq!(r#"{"message":"hi"}"#, bridge.json_string(&payload).as_str());
+            let bridge = bridge.with_pretty_json(true);
+            assert_eq!(
+                "{\n  \"message\": \"hi\"\n}",
+                bridge.json_string(&payload).as_str()
+            );
+        });
+    }
+
+    #[test]
+    fn test_try_new_flush_shutdown() {
+        prepare_python();
+        let (module, bridge) = Python::with_gil(|py| {
+            let module = PyModule::from_code_bound(
+                py,
+                "
+calls = []
+
+class Layer:
+    def on_new_span(self, span_attrs, span_id):
+        calls.append('on_new_span')
+
+    def flush(self):
+        calls.append('flush')
+
+    def shutdown(self):
+        raise RuntimeError('already closed')
+
+class NotALayer:
+    on_event = 3
+",
+                "lifecycle_layer.py",
+                "lifecycle_layer",
+            )
+            .unwrap();
+
+            let err = PythonCallbackLayerBridge::try_new(py.None().into_bound(py))
+                .err()
+                .unwrap();
+            assert!(matches!(err, BridgeError::Construction(_)));
+            let not_a_layer = module.getattr("NotALayer").unwrap().call0().unwrap();
+            let err = PythonCallbackLayerBridge::try_new(not_a_layer)
+                .err()
+                .unwrap();
+            assert_eq!(
+                "invalid Python layer: on_event isn't callable",
+                err.to_string()
+            );
+
+            let layer = module.getattr("Layer").unwrap().call0().unwrap();
+            let bridge = PythonCallbackLayerBridge::try_new(layer).unwrap();
+            (module.unbind(), bridge)
+        });
+        let _dispatcher = tracing_subscriber::registry().with(bridge).set_default();
+        let with_bridge = |f: fn(&PythonCallbackLayerBridge) -> Result<(), BridgeError>| {
+            tracing::dispatcher::get_default(|dispatch| {
+                f(dispatch
+                    .downcast_ref::<PythonCallbackLayerBridge>()
+                    .unwrap())
+            })
+        };
+
+        warn_span!("before").in_scope(|| {});
+        with_bridge(PythonCallbackLayerBridge::flush).unwrap();
+        let err = with_bridge(PythonCallbackLayerBridge::shutdown).unwrap_err();
+        assert!(matches!(err, BridgeError::Shutdown(_)));
+        with_bridge(PythonCallbackLayerBridge::shutdown).unwrap();
+        warn_span!("after").in_scope(|| {});
+
+        Python::with_gil(|py| {
+            let calls = module
+                .getattr(py, "calls")
+                .unwrap()
+                .extract::<Vec<String>>(py)
+                .unwrap();
+            assert_eq!(vec!["on_new_span", "flush", "flush"], calls);
+            let err = PyErr::from(err);
+            assert_eq!("RuntimeError: already closed", err.to_string());
+        });
+    }
+
+    #[test]
+    fn test_catch_panics() {
+        let (_py_layer, _dispatcher) = initialize_tracing_with(|bridge| {
+            bridge.with_error_hook(|_, _, _| panic!("broken error hook"))
+        });
+
+        // The test layer's `on_event` raises without a span's state, calling
+        // the hook.
+        info!("outside any span");
+
+        Python::with_gil(|py| {
+            let stats = get_bridge_stats(py).unwrap().unwrap();
+            let panics = stats
+                .get_item("panics")
+                .unwrap()
+                .unwrap()
+                .extract::<std::collections::BTreeMap<String, u64>>()
+                .unwrap();
+            assert_eq!(Some(&1), panics.get("on_event"));
+        });
+    }
+
+    #[test]
+    fn test_error_hooks() {
+        prepare_python();
+        let hook_calls = Arc::new(Mutex::new(Vec::new()));
+        let (py_layer, bridge) = Python::with_gil(|py| {
+            let module = PyModule::from_code_bound(
+                py,
+                "
+class FailingLayer:
+    def __init__(self):
+        self.errors = []
+
+    def on_new_span(self, span_attrs, span_id):
+        return 1
+
+    def on_event(self, event, state):
+        raise ValueError('boom')
+
+    def on_bridge_error(self, exc, context):
+        self.errors.append((type(exc).__name__, str(exc), context))
+",
+                "failing_layer.py",
+                "failing_layer",
+            )
+            .unwrap();
+            let py_layer = module.getattr("FailingLayer").unwrap().call0().unwrap();
+            let hook_calls = hook_calls.clone();
+            let bridge = PythonCallbackLayerBridge::new(py_layer.clone()).with_error_hook(
+                move |py, callback, err| {
+                    hook_calls.lock().unwrap().push((
+                        callback.to_owned(),
+                        err.to_string(),
+                        err.is_instance_of::<PyValueError>(py),
+                    ));
+                },
+            );
+            (py_layer.unbind(), bridge)
+        });
+        let _dispatcher = tracing_subscriber::registry().with(bridge).set_default();
+
+        warn_span!("failing").in_scope(|| info!("raises"));
+
+        assert_eq!(
+            vec![("on_event".to_owned(), "ValueError: boom".to_owned(), true)],
+            *hook_calls.lock().unwrap()
+        );
+        Python::with_gil(|py| {
+            let errors = py_layer
+                .getattr(py, "errors")
+                .unwrap()
+                .extract::<Vec<(String, String, String)>>(py)
+                .unwrap();
+            assert_eq!(
+                vec![(
+                    "ValueError".to_owned(),
+                    "boom".to_owned(),
+                    "on_event".to_owned()
+                )],
+                errors
+            );
+        });
+    }
+
+    #[test]
+    fn test_lookup_errors() {
+        prepare_python();
+        let hook_calls = Arc::new(Mutex::new(Vec::new()));
+        let bridge = Python::with_gil(|py| {
+            let module = PyModule::from_code_bound(
+                py,
+                "
+class BrokenLayer:
+    def on_event(self, event, state):
+        pass
+
+    def __getattr__(self, name):
+        if name == 'on_record':
+            raise RuntimeError('broken lookup')
+        raise AttributeError(name)
+",
+                "broken_layer.py",
+                "broken_layer",
+            )
+            .unwrap();
+            let py_layer = module.getattr("BrokenLayer").unwrap().call0().unwrap();
+            let Err(err) = PythonCallbackLayerBridge::try_new(py_layer.clone()) else {
+                panic!("try_new accepted a layer whose lookups raise");
+            };
+            assert!(err.to_string().contains("looking up on_record raised"));
+            let hook_calls = hook_calls.clone();
+            PythonCallbackLayerBridge::new(py_layer).with_error_hook(move |_, callback, err| {
+                hook_calls
+                    .lock()
+                    .unwrap()
+                    .push((callback.to_owned(), err.to_string()));
+            })
+        });
+        let _dispatcher = tracing_subscriber::registry().with(bridge).set_default();
+
+        assert_eq!(
+            vec![(
+                "on_record".to_owned(),
+                "RuntimeError: broken lookup".to_owned()
+            )],
+            *hook_calls.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_error_mode() {
+        prepare_python();
+        let _dispatcher = tracing_subscriber::registry().set_default();
+        Python::with_gil(|py| {
+            let py_layer = Bound::new(py, PythonLayer::new()).unwrap().into_any();
+            let callbacks = Callbacks::resolve(&py_layer);
+            let (metadata, other_metadata) = (
+                warn_span!("first").metadata().unwrap(),
+                warn_span!("second").metadata().unwrap(),
+            );
+            let err = || PyValueError::new_err("boom");
+
+            let bridge = PythonCallbackLayerBridge::new(py_layer.clone())
+                .with_error_mode(ErrorMode::LogOnce);
+            assert_eq!(
+                None,
+                bridge.call::<()>(py, &callbacks, "on_event", metadata, || Err(err()))
+            );
+            assert!(!bridge.logged_errors.first("on_event", metadata));
+            assert!(bridge.logged_errors.first("on_close", metadata));
+            assert!(bridge.logged_errors.first("on_event", other_metadata));
+
+            let sys = py.import_bound("sys").unwrap();
+            let unraisablehook = sys.getattr("unraisablehook").unwrap();
+            let unraisables = pyo3::types::PyList::empty_bound(py);
+            sys.setattr("unraisablehook", unraisables.getattr("append").unwrap())
+                .unwrap();
+            let bridge = PythonCallbackLayerBridge::new(py_layer.clone())
+                .with_error_mode(ErrorMode::Unraisable);
+            bridge.call::<()>(py, &callbacks, "on_event", metadata, || Err(err()));
+            sys.setattr("unraisablehook", unraisablehook).unwrap();
+            let unraisable = unraisables.get_item(0).unwrap();
+            assert!(unraisable
+                .getattr("exc_value")
+                .unwrap()
+                .is_instance_of::<PyValueError>());
+            assert!(unraisable
+                .getattr("object")
+                .unwrap()
+                .eq(py_layer.getattr("on_event").unwrap())
+                .unwrap());
+
+            let bridge = PythonCallbackLayerBridge::new(py_layer).with_error_mode(ErrorMode::Panic);
+            let panic = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                bridge.call::<()>(py, &callbacks, "on_event", metadata, || Err(err()))
+            }))
+            .unwrap_err();
+            assert!(panic
+                .downcast_ref::<String>()
+                .unwrap()
+                .contains("on_event raised for"));
+        });
+    }
+
+    #[test]
+    fn test_unraisable_error_mode() {
+        prepare_python();
+        let (module, bridge) = Python::with_gil(|py| {
+            let module = PyModule::from_code_bound(
+                py,
+                "
+unraisables = []
+
+def unraisablehook(unraisable):
+    unraisables.append((unraisable.exc_value, unraisable.object.__name__))
+
+class RaisingLayer:
+    def on_new_span(self, span_attrs, span_id):
+        pass
+
+    def on_close(self, span_id, state):
+        raise ValueError('close failed')
+",
+                "raising_layer.py",
+                "raising_layer",
+            )
+            .unwrap();
+            let layer = module.getattr("RaisingLayer").unwrap().call0().unwrap();
+            (
+                module.unbind(),
+                PythonCallbackLayerBridge::new(layer).with_error_mode(ErrorMode::Unraisable),
+            )
+        });
+        let _dispatcher = tracing_subscriber::registry().with(bridge).set_default();
+
+        Python::with_gil(|py| {
+            let module = module.bind(py);
+            let sys = py.import_bound("sys").unwrap();
+            let unraisablehook = sys.getattr("unraisablehook").unwrap();
+            sys.setattr("unraisablehook", module.getattr("unraisablehook").unwrap())
+                .unwrap();
+            drop(info_span!("failing"));
+            sys.setattr("unraisablehook", unraisablehook).unwrap();
+
+            let (exc_value, callback): (Bound<'_, PyAny>, String) = module
+                .getattr("unraisables")
+                .unwrap()
+                .get_item(0)
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert!(exc_value.is_instance_of::<PyValueError>());
+            assert_eq!("close failed", exc_value.str().unwrap().to_string());
+            assert_eq!("on_close", callback);
+        });
+    }
+
+    #[test]
+    fn test_level_event_methods() {
+        prepare_python();
+        let module = Python::with_gil(|py| {
+            PyModule::from_code_bound(
+                py,
+                "
+import json
+
+calls = []
+
+class ErrorsOnly:
+    def on_error_event(self, event, state):
+        calls.append(('on_error_event', json.loads(event)['message']))
+
+class ErrorsFirst(ErrorsOnly):
+    def on_event(self, event, state):
+        calls.append(('on_event', json.loads(event)['message']))
+",
+                "level_layer.py",
+                "level_layer",
+            )
+            .unwrap()
+            .unbind()
+        });
+
+        for (class, expected) in [
+            ("ErrorsOnly", vec![("on_error_event", "failed")]),
+            (
+                "ErrorsFirst",
+                vec![("on_event", "retrying"), ("on_error_event", "failed")],
+            ),
+        ] {
+            let bridge = Python::with_gil(|py| {
+                let module = module.bind(py);
+                module.setattr("calls", PyList::empty_bound(py)).unwrap();
+                PythonCallbackLayerBridge::new(module.getattr(class).unwrap().call0().unwrap())
+            });
+            let _dispatcher = tracing_subscriber::registry().with(bridge).set_default();
+
+            warn!("retrying");
+            error!("failed");
+
+            Python::with_gil(|py| {
+                let calls = module
+                    .getattr(py, "calls")
+                    .unwrap()
+                    .extract::<Vec<(String, String)>>(py)
+                    .unwrap();
+                let expected = expected
+                    .iter()
+                    .map(|(callback, message)| ((*callback).to_owned(), (*message).to_owned()))
+                    .collect::<Vec<_>>();
+                assert_eq!(expected, calls);
+            });
+        }
+    }
+
+    #[test]
+    fn test_with_current_bridge() {
+        prepare_python();
+        assert!(with_current_bridge(|_| ()).is_none());
+        let (first, second, bridge) = Python::with_gil(|py| {
+            let first = Py::new(py, PythonLayer::new()).unwrap();
+            let second = Py::new(py, PythonLayer::new()).unwrap();
+            let bridge = PythonCallbackLayerBridge::new(first.bind(py).clone().into_any());
+            (first, second, bridge)
+        });
+        let _dispatcher = tracing_subscriber::registry()
+            .with(bridge.with_filter(tracing_subscriber::filter::LevelFilter::INFO))
+            .set_default();
+
+        info_span!("before").in_scope(|| info!("to the first layer"));
+        Python::with_gil(|py| {
+            with_current_bridge(|bridge| {
+                assert!(bridge.py_impl(py).is(&first));
+                bridge.set_py_impl(second.bind(py).clone().into_any());
+                assert!(bridge.stats(py).unwrap().contains("errors").unwrap());
+            })
+            .unwrap();
+        });
+        info_span!("after").in_scope(|| info!("to the second layer"));
+
+        Python::with_gil(|py| {
+            for (py_layer, message) in [
+                (first, "to the first layer"),
+                (second, "to the second layer"),
+            ] {
+                let borrowed = py_layer.borrow(py);
+                assert_eq!(message, borrowed.events[0].0);
+                assert_eq!(1, borrowed.events.len());
+            }
+        });
+    }
+
+    #[test]
+    fn test_orphan_events() {
+        prepare_python();
+        let module = Python::with_gil(|py| {
+            PyModule::from_code_bound(
+                py,
+                "
+import json
+
+calls = []
+
+class OrphanLayer:
+    def on_new_span(self, span_attrs, span_id):
+        return 'span state'
+
+    def on_event(self, event, state):
+        calls.append(('on_event', json.loads(event)['message'], state))
+
+    def on_orphan_event(self, event):
+        calls.append(('on_orphan_event', json.loads(event)['message'], None))
+",
+                "orphan_layer.py",
+                "orphan_layer",
+            )
+            .unwrap()
+            .unbind()
+        });
+
+        for orphan_events in [true, false] {
+            let bridge = Python::with_gil(|py| {
+                let module = module.bind(py);
+                module.setattr("calls", PyList::empty_bound(py)).unwrap();
+                let py_layer = module.getattr("OrphanLayer").unwrap().call0().unwrap();
+                PythonCallbackLayerBridge::new(py_layer).with_orphan_events(orphan_events)
+            });
+            let _dispatcher = tracing_subscriber::registry().with(bridge).set_default();
+
+            info!("orphan");
+            info_span!("parent").in_scope(|| info!("in span"));
+
+            Python::with_gil(|py| {
+                let calls = module
+                    .getattr(py, "calls")
+                    .unwrap()
+                    .extract::<Vec<(String, String, Option<String>)>>(py)
+                    .unwrap();
+                let mut expected = vec![(
+                    "on_event".to_owned(),
+                    "in span".to_owned(),
+                    Some("span state".to_owned()),
+                )];
+                if orphan_events {
+                    expected.insert(0, ("on_orphan_event".to_owned(), "orphan".to_owned(), None));
+                }
+                assert_eq!(expected, calls);
+            });
+        }
+    }
 }
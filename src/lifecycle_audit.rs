@@ -0,0 +1,204 @@
+//! Accounting of the spans the Python layer was told about, checking that each
+//! `on_new_span` is matched by exactly one `on_close`, for layer authors
+//! tracking down state that keeps growing.
+
+use std::{
+    collections::HashMap,
+    sync::{Mutex, PoisonError},
+};
+
+use pyo3::{prelude::*, types::PyDict};
+use tracing_core::span;
+
+use crate::with_current_bridge;
+
+/// A span whose `on_new_span` the bridge called.
+struct OpenSpan {
+    name: &'static str,
+    /// Whether `on_new_span` returned, rather than raised.
+    delivered: bool,
+}
+
+#[derive(Default)]
+struct Counts {
+    opened: u64,
+    closed: u64,
+    failed_new_spans: u64,
+    failed_closes: u64,
+    missed_closes: u64,
+    unmatched_closes: u64,
+    reused_ids: u64,
+}
+
+/// The spans of a bridge built with
+/// [`crate::PythonCallbackLayerBridge::with_lifecycle_audit`] whose
+/// `on_new_span` was called, and the imbalances found between those calls and
+/// the calls to `on_close`.
+#[derive(Default)]
+pub(crate) struct LifecycleAudit {
+    open: Mutex<HashMap<u64, OpenSpan>>,
+    counts: Mutex<Counts>,
+}
+
+impl LifecycleAudit {
+    /// Records a call to `on_new_span` for the span `span_id`, and whether it
+    /// returned.
+    pub(crate) fn new_span(&self, span_id: &span::Id, name: &'static str, delivered: bool) {
+        let previous = self
+            .open
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(span_id.into_u64(), OpenSpan { name, delivered });
+        let mut counts = self.counts.lock().unwrap_or_else(PoisonError::into_inner);
+        if delivered {
+            counts.opened += 1;
+        } else {
+            counts.failed_new_spans += 1;
+        }
+        if previous.is_some_and(|previous| previous.delivered) {
+            // The registry only reuses the id of a span once it closed, so the
+            // Python layer wasn't told that the previous span closed.
+            counts.reused_ids += 1;
+            counts.missed_closes += 1;
+        }
+    }
+
+    /// Records that the span `span_id` closed, and whether `on_close` was
+    /// called for it and returned, `None` if it wasn't called.
+    pub(crate) fn closed(&self, span_id: &span::Id, delivered: Option<bool>) {
+        let open = self
+            .open
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&span_id.into_u64());
+        let mut counts = self.counts.lock().unwrap_or_else(PoisonError::into_inner);
+        match (open, delivered) {
+            // A span whose `on_new_span` raised is already counted.
+            (Some(open), _) if !open.delivered => {}
+            (Some(_), Some(true)) => counts.closed += 1,
+            (Some(_), Some(false)) => counts.failed_closes += 1,
+            (Some(_), None) => counts.missed_closes += 1,
+            (None, Some(_)) => counts.unmatched_closes += 1,
+            (None, None) => {}
+        }
+    }
+
+    fn to_py_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let audit = PyDict::new_bound(py);
+        let open = self.open.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut open_spans: Vec<_> = open
+            .iter()
+            .filter(|(_, open)| open.delivered)
+            .map(|(id, open)| (*id, open.name))
+            .collect();
+        drop(open);
+        open_spans.sort_unstable();
+        let counts = self.counts.lock().unwrap_or_else(PoisonError::into_inner);
+        audit.set_item("opened", counts.opened)?;
+        audit.set_item("closed", counts.closed)?;
+        audit.set_item("open", open_spans.len())?;
+        audit.set_item("failed_new_spans", counts.failed_new_spans)?;
+        audit.set_item("failed_closes", counts.failed_closes)?;
+        audit.set_item("missed_closes", counts.missed_closes)?;
+        audit.set_item("unmatched_closes", counts.unmatched_closes)?;
+        audit.set_item("reused_ids", counts.reused_ids)?;
+        let imbalances = counts.failed_new_spans
+            + counts.failed_closes
+            + counts.missed_closes
+            + counts.unmatched_closes;
+        audit.set_item("balanced", imbalances == 0)?;
+        audit.set_item("open_spans", open_spans)?;
+        Ok(audit)
+    }
+}
+
+/// Returns the span lifecycle accounting of the bridge of the current
+/// subscriber as a `dict`, or `None` if it has no bridge or the bridge wasn't
+/// built with [`crate::PythonCallbackLayerBridge::with_lifecycle_audit`].
+///
+/// - `opened` and `closed` count the `on_new_span` and `on_close` calls that
+///   returned, and `open` the spans whose `on_close` is still due, which are
+///   listed as `(id, name)` pairs in `open_spans`.
+/// - `failed_new_spans` and `failed_closes` count the calls that raised.
+/// - `missed_closes` counts the spans that closed without their `on_close`
+///   being called, e.g. because the Python layer was swapped for one without
+///   it or the bridge was shut down, and `unmatched_closes` the `on_close`
+///   calls for spans whose `on_new_span` wasn't called.
+/// - `reused_ids` counts the span ids passed to `on_new_span` again before
+///   `on_close` was called for them.
+/// - `balanced` is whether none of these imbalances were found, so every span
+///   the Python layer was told about was closed exactly once or is still open.
+///
+/// A Python layer that keeps its spans in a `dict` by id should hold as many
+/// as `open`; more means it misses closes, and `missed_closes` or
+/// `failed_closes` tells why.
+#[pyfunction]
+pub fn span_lifecycle_audit(py: Python<'_>) -> PyResult<Option<Bound<'_, PyDict>>> {
+    with_current_bridge(|bridge| {
+        bridge
+            .lifecycle_audit
+            .as_ref()
+            .map(|audit| audit.to_py_dict(py))
+    })
+    .flatten()
+    .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::prelude::*;
+    use tracing::info_span;
+    use tracing_subscriber::prelude::*;
+
+    use super::span_lifecycle_audit;
+    use crate::{tests::prepare_python, PythonCallbackLayerBridge};
+
+    #[test]
+    fn test_span_lifecycle_audit() {
+        prepare_python();
+        let bridge = Python::with_gil(|py| {
+            let module = PyModule::from_code_bound(
+                py,
+                "
+import json
+
+class LeakyLayer:
+    def on_new_span(self, span_attrs, span_id):
+        name = json.loads(span_attrs)['metadata']['name']
+        if name == 'broken':
+            raise ValueError(name)
+        return name
+
+    def on_close(self, span_id, state):
+        if state == 'fails to close':
+            raise ValueError(state)
+",
+                "leaky_layer.py",
+                "leaky_layer",
+            )
+            .unwrap();
+            let layer = module.getattr("LeakyLayer").unwrap().call0().unwrap();
+            PythonCallbackLayerBridge::new(layer).with_lifecycle_audit(true)
+        });
+        let _dispatcher = tracing_subscriber::registry().with(bridge).set_default();
+
+        let _open = info_span!("still open");
+        info_span!("ok").in_scope(|| {
+            info_span!("broken").in_scope(|| {});
+            info_span!("fails to close").in_scope(|| {});
+        });
+
+        Python::with_gil(|py| {
+            let audit = span_lifecycle_audit(py).unwrap().unwrap();
+            let item = |key| audit.get_item(key).unwrap().unwrap().to_string();
+            assert_eq!("3", item("opened"));
+            assert_eq!("1", item("closed"));
+            assert_eq!("1", item("open"));
+            assert_eq!("1", item("failed_new_spans"));
+            assert_eq!("1", item("failed_closes"));
+            assert_eq!("0", item("missed_closes"));
+            assert_eq!("False", item("balanced"));
+            assert!(item("open_spans").contains("'still open'"));
+        });
+    }
+}
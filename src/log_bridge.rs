@@ -0,0 +1,180 @@
+//! Support for `log` records, which are converted into `tracing` events with
+//! the `log` feature enabled.
+//!
+//! Records without structured key/values are converted by `tracing-log`, whose
+//! events come from a generic callsite per level and carry the record's
+//! metadata in `log.*` fields, so the record's own metadata has to be recovered
+//! before it's passed on. Records with key/values get a callsite of their own
+//! from [`crate::callsite`] so the key/values can be fields.
+
+use serde_json::{json, Value};
+use tracing_core::{Event, Metadata};
+use tracing_serde::AsSerde;
+#[cfg(feature = "log")]
+use {
+    crate::{callsite, fields::PyFieldValue},
+    log::kv::{self, VisitSource},
+    std::iter,
+    tracing_core::{dispatcher, field},
+};
+
+/// The fields `tracing-log` uses to carry a record's metadata.
+pub(crate) const LOG_FIELDS: &[&str] = &["log.target", "log.module_path", "log.file", "log.line"];
+
+/// A `log` logger that converts records into `tracing` events, keeping their
+/// structured key/values as fields.
+#[cfg(feature = "log")]
+struct LogBridge {
+    log_tracer: tracing_log::LogTracer,
+}
+
+/// Collects a record's key/values with the value types `tracing` supports,
+/// other values as their `Display` output.
+#[cfg(feature = "log")]
+#[derive(Default)]
+struct KeyValues {
+    names: Vec<String>,
+    values: Vec<PyFieldValue>,
+}
+
+#[cfg(feature = "log")]
+impl<'kvs> VisitSource<'kvs> for KeyValues {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        let value = if let Some(b) = value.to_bool() {
+            PyFieldValue::Bool(b)
+        } else if let Some(i) = value.to_i64() {
+            PyFieldValue::I64(i)
+        } else if let Some(u) = value.to_u64() {
+            PyFieldValue::U64(u)
+        } else if let Some(f) = value.to_f64() {
+            PyFieldValue::F64(f)
+        } else {
+            PyFieldValue::Str(value.to_string())
+        };
+        self.names.push(key.as_str().to_owned());
+        self.values.push(value);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "log")]
+impl log::Log for LogBridge {
+    fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+        self.log_tracer.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        if record.key_values().count() == 0 {
+            return self.log_tracer.log(record);
+        }
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let mut key_values = KeyValues::default();
+        let _ = record.key_values().visit(&mut key_values);
+        // Registering a new callsite consults the default dispatcher, so this
+        // can't happen inside `get_default`.
+        let Some(metadata) = callsite::log_record_metadata(record, &key_values.names) else {
+            return;
+        };
+
+        dispatcher::get_default(|dispatch| {
+            if !dispatch.enabled(metadata) {
+                return;
+            }
+            let values = iter::once(Some(record.args() as &dyn field::Value))
+                .chain(key_values.values.iter().map(|value| Some(value.as_value())))
+                .collect::<Vec<_>>();
+            let value_set = metadata.fields().value_set_all(&values);
+            dispatch.event(&Event::new(metadata, &value_set));
+        })
+    }
+
+    fn flush(&self) {}
+}
+
+/// Forwards records of the `log` crate to the global `tracing` subscriber.
+///
+/// Another `log` logger, such as one from `pyo3-log`, may already be installed,
+/// in which case records keep going there.
+#[cfg(feature = "log")]
+pub(crate) fn init_log_tracer() {
+    let bridge = LogBridge {
+        log_tracer: tracing_log::LogTracer::new(),
+    };
+    if log::set_boxed_logger(Box::new(bridge)).is_ok() {
+        log::set_max_level(log::LevelFilter::Trace);
+    }
+}
+
+/// Returns the metadata of the `log` record `event` was converted from, or
+/// `None` if it wasn't.
+#[cfg(feature = "log")]
+pub(crate) fn normalized_metadata<'a>(event: &'a Event<'a>) -> Option<Metadata<'a>> {
+    tracing_log::NormalizeEvent::normalized_metadata(event)
+}
+
+#[cfg(not(feature = "log"))]
+pub(crate) fn normalized_metadata<'a>(_event: &'a Event<'a>) -> Option<Metadata<'a>> {
+    None
+}
+
+/// If `event` was converted from a `log` record, replaces the `metadata` in its
+/// JSON `payload` with the record's and removes the `log.*` fields.
+pub(crate) fn normalize_payload(payload: &mut Value, event: &Event<'_>) {
+    let (Some(metadata), Some(payload)) = (normalized_metadata(event), payload.as_object_mut())
+    else {
+        return;
+    };
+
+    payload.insert("metadata".to_owned(), json!(metadata.as_serde()));
+    for field in LOG_FIELDS {
+        payload.remove(*field);
+    }
+}
+
+#[cfg(all(test, feature = "log"))]
+mod tests {
+    use pyo3::prelude::*;
+    use serde_json::json;
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::{
+        tests::{prepare_python, PythonLayer},
+        PythonCallbackLayerBridge,
+    };
+
+    #[test]
+    fn test_forwards_log_records() {
+        prepare_python();
+        let (py_layer, bridge) = Python::with_gil(|py| {
+            let py_layer = Bound::new(py, PythonLayer::new()).unwrap();
+            let bridge = PythonCallbackLayerBridge::new(py_layer.clone().into_any());
+            (py_layer.unbind(), bridge)
+        });
+        let _dispatcher = tracing_subscriber::registry().with(bridge).set_default();
+        init_log_tracer();
+
+        tracing::info_span!("scope").in_scope(|| {
+            log::warn!(target: "legacy::db", "slow query");
+            log::info!(rows = 3, table = "users", cached = false; "loaded");
+        });
+
+        Python::with_gil(|py| {
+            let borrowed = py_layer.borrow(py);
+            assert_eq!(
+                vec![
+                    ("slow query".to_owned(), "WARN".to_owned(), 0),
+                    ("loaded".to_owned(), "INFO".to_owned(), 0)
+                ],
+                borrowed.events
+            );
+            assert_eq!(
+                vec![json!({"rows": 3, "table": "users", "cached": false})],
+                borrowed.event_fields
+            );
+        });
+    }
+}
@@ -0,0 +1,164 @@
+//! Forwarding spans the subscriber can't look up, e.g. when the bridge is
+//! composed onto a subscriber whose `LookupSpan` implementation doesn't store
+//! spans, instead of quietly dropping them.
+//!
+//! Their lifecycle is still forwarded, without the state the Python layer
+//! returned from `on_new_span`, since there's nowhere to store it.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tracing_core::{
+    callsite::Callsite, field::FieldSet, identify_callsite, Interest, Kind, Level, Metadata,
+};
+
+struct UnknownSpanCallsite;
+
+static UNKNOWN_SPAN_CALLSITE: UnknownSpanCallsite = UnknownSpanCallsite;
+
+/// Stands in for the metadata of a span the subscriber couldn't look up, when
+/// the bridge reports exceptions its Python layer raised for it.
+pub(crate) static UNKNOWN_SPAN: Metadata<'static> = Metadata::new(
+    "unknown span",
+    module_path!(),
+    Level::TRACE,
+    None,
+    None,
+    None,
+    FieldSet::new(&[], identify_callsite!(&UNKNOWN_SPAN_CALLSITE)),
+    Kind::SPAN,
+);
+
+impl Callsite for UnknownSpanCallsite {
+    fn set_interest(&self, _interest: Interest) {}
+
+    fn metadata(&self) -> &Metadata<'_> {
+        &UNKNOWN_SPAN
+    }
+}
+
+/// Whether a bridge already printed its diagnostic about spans the subscriber
+/// couldn't look up.
+#[derive(Default)]
+pub(crate) struct LookupDiagnostic(AtomicBool);
+
+impl LookupDiagnostic {
+    /// Prints a diagnostic to stderr the first time the subscriber couldn't
+    /// look up a span the bridge forwards `callback` for.
+    pub(crate) fn report(&self, callback: &str) {
+        if self.0.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        eprintln!(
+            "pyo3-python-tracing-subscriber: the subscriber couldn't look up a span in {callback}, \
+             so spans are forwarded without state; add the bridge to a \
+             tracing_subscriber::Registry to store it"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicU64;
+
+    use pyo3::prelude::*;
+    use tracing::{info, info_span};
+    use tracing_core::{span, Event, Subscriber};
+    use tracing_subscriber::{
+        prelude::*,
+        registry::{Data, LookupSpan},
+    };
+
+    use super::*;
+    use crate::{tests::prepare_python, PythonCallbackLayerBridge};
+
+    /// A subscriber that never finds its spans.
+    #[derive(Default)]
+    struct Forgetful(AtomicU64);
+
+    impl Subscriber for Forgetful {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, _span: &span::Attributes<'_>) -> span::Id {
+            span::Id::from_u64(self.0.fetch_add(1, Ordering::Relaxed) + 1)
+        }
+
+        fn record(&self, _span: &span::Id, _values: &span::Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+        fn event(&self, _event: &Event<'_>) {}
+
+        fn enter(&self, _span: &span::Id) {}
+
+        fn exit(&self, _span: &span::Id) {}
+
+        fn try_close(&self, _id: span::Id) -> bool {
+            true
+        }
+    }
+
+    impl<'a> LookupSpan<'a> for Forgetful {
+        type Data = Data<'a>;
+
+        fn span_data(&'a self, _id: &span::Id) -> Option<Data<'a>> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_forgetful_subscriber() {
+        prepare_python();
+        let (calls, bridge) = Python::with_gil(|py| {
+            let module = PyModule::from_code_bound(
+                py,
+                "
+calls = []
+
+class Layer:
+    def on_new_span(self, span_attrs, span_id):
+        calls.append(('on_new_span', span_id))
+        return 'dropped'
+
+    def on_event(self, event, state):
+        calls.append(('on_event', state))
+
+    def on_close(self, span_id, state):
+        calls.append(('on_close', span_id))
+",
+                "forgetful_layer.py",
+                "forgetful_layer",
+            )
+            .unwrap();
+            let bridge =
+                PythonCallbackLayerBridge::new(module.getattr("Layer").unwrap().call0().unwrap());
+            (module.getattr("calls").unwrap().unbind(), bridge)
+        });
+        let _dispatcher = Forgetful::default().with(bridge).set_default();
+
+        info_span!("forgotten").in_scope(|| info!("still forwarded"));
+
+        Python::with_gil(|py| {
+            let calls = calls.extract::<Vec<(String, Option<String>)>>(py).unwrap();
+            assert_eq!(
+                vec![
+                    ("on_new_span".to_owned(), Some("[1]".to_owned())),
+                    ("on_event".to_owned(), None),
+                    ("on_close".to_owned(), Some("[1]".to_owned())),
+                ],
+                calls
+            );
+            let stats = crate::get_bridge_stats(py).unwrap().unwrap();
+            let lookup_failures = stats.get_item("lookup_failures").unwrap().unwrap();
+            assert_eq!(
+                1,
+                lookup_failures
+                    .get_item("on_new_span")
+                    .unwrap()
+                    .extract::<u64>()
+                    .unwrap()
+            );
+        });
+    }
+}
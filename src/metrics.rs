@@ -0,0 +1,353 @@
+//! Metrics emitted as event fields, following the conventions of
+//! `tracing-opentelemetry`'s `MetricsLayer`, aggregated in Rust:
+//!
+//! - `monotonic_counter.<name>` fields add to a counter that only goes up.
+//! - `counter.<name>` fields add to a counter that may go down.
+//! - `histogram.<name>` fields record a value in a histogram.
+//!
+//! The event's other fields, except its message, are the attributes of the
+//! metric, and each combination of name and attributes is aggregated
+//! separately.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write},
+    sync::{Mutex, PoisonError},
+};
+
+use pyo3::{
+    prelude::*,
+    types::{IntoPyDict, PyDict, PyList},
+};
+use tracing_core::{
+    field::{Field, Visit},
+    Event,
+};
+
+use crate::{prometheus, with_current_bridge};
+
+/// The kinds of metrics, by the field name prefix they're recognized by.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    MonotonicCounter,
+    Counter,
+    Histogram,
+}
+
+impl Kind {
+    const PREFIXES: [(&'static str, Kind); 3] = [
+        ("monotonic_counter.", Kind::MonotonicCounter),
+        ("counter.", Kind::Counter),
+        ("histogram.", Kind::Histogram),
+    ];
+
+    /// Returns the kind and name of the metric a field named `field` records,
+    /// if it's one.
+    fn parse(field: &str) -> Option<(Kind, &str)> {
+        Kind::PREFIXES.iter().find_map(|(prefix, kind)| {
+            let name = field.strip_prefix(prefix)?;
+            Some((*kind, name))
+        })
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Kind::MonotonicCounter => "monotonic_counter",
+            Kind::Counter => "counter",
+            Kind::Histogram => "histogram",
+        }
+    }
+}
+
+/// A value recorded for a metric, kept as an integer while it's one so large
+/// counts stay exact.
+#[derive(Clone, Copy)]
+enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl Number {
+    fn add(self, other: Number) -> Number {
+        match (self, other) {
+            (Number::Int(a), Number::Int(b)) => a
+                .checked_add(b)
+                .map_or_else(|| Number::Float(a as f64 + b as f64), Number::Int),
+            (a, b) => Number::Float(a.as_f64() + b.as_f64()),
+        }
+    }
+
+    fn as_f64(self) -> f64 {
+        match self {
+            Number::Int(value) => value as f64,
+            Number::Float(value) => value,
+        }
+    }
+
+    fn to_prometheus(self) -> String {
+        match self {
+            Number::Int(value) => value.to_string(),
+            Number::Float(value) => prometheus::format_float(value),
+        }
+    }
+
+    fn to_py(self, py: Python<'_>) -> PyObject {
+        match self {
+            Number::Int(value) => value.into_py(py),
+            Number::Float(value) => value.into_py(py),
+        }
+    }
+}
+
+/// The aggregate of one metric series.
+enum Aggregate {
+    Sum(Number),
+    Histogram {
+        count: u64,
+        sum: Number,
+        min: f64,
+        max: f64,
+    },
+}
+
+impl Aggregate {
+    fn new(kind: Kind, value: Number) -> Aggregate {
+        match kind {
+            Kind::MonotonicCounter | Kind::Counter => Aggregate::Sum(value),
+            Kind::Histogram => Aggregate::Histogram {
+                count: 1,
+                sum: value,
+                min: value.as_f64(),
+                max: value.as_f64(),
+            },
+        }
+    }
+
+    fn record(&mut self, value: Number) {
+        match self {
+            Aggregate::Sum(total) => *total = total.add(value),
+            Aggregate::Histogram {
+                count,
+                sum,
+                min,
+                max,
+            } => {
+                *count += 1;
+                *sum = sum.add(value);
+                *min = min.min(value.as_f64());
+                *max = max.max(value.as_f64());
+            }
+        }
+    }
+}
+
+/// Identifies a metric series: its kind, name and attributes.
+type SeriesKey = (Kind, String, Vec<(&'static str, String)>);
+
+/// A [`Visit`] collecting an event's metric fields and its other fields as
+/// attributes.
+#[derive(Default)]
+struct MetricFields {
+    metrics: Vec<(Kind, &'static str, Number)>,
+    attributes: Vec<(&'static str, String)>,
+}
+
+impl MetricFields {
+    fn record_number(&mut self, field: &Field, value: Number) {
+        match Kind::parse(field.name()) {
+            // Monotonic counters can't go down, like `tracing-opentelemetry`
+            // only accepts unsigned and float values for them.
+            Some((Kind::MonotonicCounter, _)) if value.as_f64() < 0.0 => {}
+            Some((kind, name)) => self.metrics.push((kind, name, value)),
+            None => self.attributes.push((
+                field.name(),
+                match value {
+                    Number::Int(value) => value.to_string(),
+                    Number::Float(value) => value.to_string(),
+                },
+            )),
+        }
+    }
+}
+
+impl Visit for MetricFields {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.record_number(field, Number::Float(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.record_number(field, Number::Int(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        let value = i64::try_from(value).map_or(Number::Float(value as f64), Number::Int);
+        self.record_number(field, value);
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if Kind::parse(field.name()).is_none() && field.name() != "message" {
+            self.attributes.push((field.name(), value.to_owned()));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if Kind::parse(field.name()).is_none() && field.name() != "message" {
+            self.attributes.push((field.name(), format!("{value:?}")));
+        }
+    }
+}
+
+/// The metrics aggregated by a [`crate::PythonCallbackLayerBridge`] built
+/// with [`crate::PythonCallbackLayerBridge::with_metrics`].
+#[derive(Default)]
+pub(crate) struct Metrics {
+    series: Mutex<BTreeMap<SeriesKey, Aggregate>>,
+}
+
+impl Metrics {
+    /// Aggregates the metric fields of `event`, if it has any.
+    pub(crate) fn record(&self, event: &Event<'_>) {
+        if !event
+            .fields()
+            .any(|field| Kind::parse(field.name()).is_some())
+        {
+            return;
+        }
+        let mut fields = MetricFields::default();
+        event.record(&mut fields);
+        fields.attributes.sort_unstable();
+        let mut series = self.series.lock().unwrap_or_else(PoisonError::into_inner);
+        for (kind, name, value) in fields.metrics {
+            let key = (kind, name.to_owned(), fields.attributes.clone());
+            series
+                .entry(key)
+                .and_modify(|aggregate| aggregate.record(value))
+                .or_insert_with(|| Aggregate::new(kind, value));
+        }
+    }
+
+    /// Returns a `dict` for each metric series, with its `name`, `kind`,
+    /// `attributes` and either the `value` of a counter or the `count`, `sum`,
+    /// `min` and `max` of a histogram.
+    pub(crate) fn to_py_list<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        let series = self.series.lock().unwrap_or_else(PoisonError::into_inner);
+        let list = PyList::empty_bound(py);
+        for ((kind, name, attributes), aggregate) in series.iter() {
+            let metric = PyDict::new_bound(py);
+            metric.set_item("name", name)?;
+            metric.set_item("kind", kind.as_str())?;
+            metric.set_item(
+                "attributes",
+                attributes.iter().cloned().into_py_dict_bound(py),
+            )?;
+            match aggregate {
+                Aggregate::Sum(total) => metric.set_item("value", total.to_py(py))?,
+                Aggregate::Histogram {
+                    count,
+                    sum,
+                    min,
+                    max,
+                } => {
+                    metric.set_item("count", count)?;
+                    metric.set_item("sum", sum.to_py(py))?;
+                    metric.set_item("min", min)?;
+                    metric.set_item("max", max)?;
+                }
+            }
+            list.append(metric)?;
+        }
+        Ok(list)
+    }
+
+    /// Returns the metrics in the Prometheus text exposition format, as
+    /// described on [`prometheus::prometheus_metrics`].
+    pub(crate) fn to_prometheus(&self) -> String {
+        let series = self.series.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut out = String::new();
+        let mut family = None;
+        for ((kind, name, attributes), aggregate) in series.iter() {
+            let mut name = prometheus::sanitize_name(name, true);
+            if *kind == Kind::MonotonicCounter && !name.ends_with("_total") {
+                name.push_str("_total");
+            }
+            if family.as_ref() != Some(&(*kind, name.clone())) {
+                let prometheus_type = match kind {
+                    Kind::MonotonicCounter => "counter",
+                    Kind::Counter => "gauge",
+                    Kind::Histogram => "summary",
+                };
+                let _ = writeln!(out, "# TYPE {name} {prometheus_type}");
+                family = Some((*kind, name.clone()));
+            }
+            let mut labels = String::new();
+            prometheus::write_labels(&mut labels, attributes);
+            match aggregate {
+                Aggregate::Sum(total) => {
+                    let _ = writeln!(out, "{name}{labels} {}", total.to_prometheus());
+                }
+                Aggregate::Histogram { count, sum, .. } => {
+                    let _ = writeln!(out, "{name}_sum{labels} {}", sum.to_prometheus());
+                    let _ = writeln!(out, "{name}_count{labels} {count}");
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Returns the current values of the metrics the bridge in the current
+/// subscriber aggregated from `monotonic_counter.*`, `counter.*` and
+/// `histogram.*` event fields, or `None` if the subscriber has no bridge.
+///
+/// Each metric series is a `dict` with its `name`, `kind`, `attributes` and
+/// either the `value` of a counter or the `count`, `sum`, `min` and `max` of a
+/// histogram. The bridge only aggregates them when built with
+/// [`crate::PythonCallbackLayerBridge::with_metrics`].
+#[pyfunction]
+pub fn get_metrics(py: Python<'_>) -> PyResult<Option<Bound<'_, PyList>>> {
+    with_current_bridge(|bridge| bridge.metrics.to_py_list(py)).transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::prelude::*;
+    use tracing::info;
+
+    use super::get_metrics;
+    use crate::tests::initialize_tracing_with;
+
+    #[test]
+    fn test_metrics() {
+        let (_py_layer, _dispatcher) = initialize_tracing_with(|bridge| bridge.with_metrics(true));
+
+        for latency in [3, 1, 8] {
+            info!(monotonic_counter.requests = 1_u64, route = "/", "request");
+            info!(histogram.latency_ms = latency, route = "/");
+        }
+        info!(monotonic_counter.requests = 2_u64, route = "/admin");
+        info!(counter.connections = 5, counter.queued = 1.5);
+        info!(counter.connections = -2);
+        info!(monotonic_counter.requests = -1, route = "/");
+
+        Python::with_gil(|py| {
+            let metrics = get_metrics(py).unwrap().unwrap();
+            let metrics: Vec<String> = metrics
+                .iter()
+                .map(|metric| metric.str().unwrap().to_string())
+                .collect();
+            assert_eq!(
+                vec![
+                    "{'name': 'requests', 'kind': 'monotonic_counter', \
+                     'attributes': {'route': '/'}, 'value': 3}",
+                    "{'name': 'requests', 'kind': 'monotonic_counter', \
+                     'attributes': {'route': '/admin'}, 'value': 2}",
+                    "{'name': 'connections', 'kind': 'counter', 'attributes': {}, 'value': 3}",
+                    "{'name': 'queued', 'kind': 'counter', 'attributes': {}, 'value': 1.5}",
+                    "{'name': 'latency_ms', 'kind': 'histogram', \
+                     'attributes': {'route': '/'}, 'count': 3, 'sum': 12, 'min': 1.0, 'max': 8.0}",
+                ],
+                metrics
+            );
+        });
+    }
+}
@@ -0,0 +1,229 @@
+//! A process-wide table of the bridges in use by their ids, for Python code to
+//! manage each bridge of a subscriber with several, e.g. a `"db"` and an
+//! `"audit"` one, after installing it.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, PoisonError, RwLock, Weak,
+    },
+};
+
+use pyo3::{exceptions::PyValueError, prelude::*};
+use tracing_core::Metadata;
+use tracing_subscriber::filter::Targets;
+
+use crate::Callbacks;
+
+/// The bridges added to a subscriber, by id. A bridge's entry lives as long
+/// as the bridge.
+static NAMED_BRIDGES: Mutex<BTreeMap<String, Weak<NamedBridge>>> = Mutex::new(BTreeMap::new());
+
+/// A filter set with [`set_filter`] on top of the subscriber's, deciding
+/// which spans and events a bridge forwards.
+#[derive(Default)]
+pub(crate) struct BridgeFilter {
+    targets: RwLock<Option<Targets>>,
+    /// Whether a filter is set, so it isn't looked up while none is.
+    set: AtomicBool,
+}
+
+impl BridgeFilter {
+    /// Whether the filter, if one is set, enables spans or events with
+    /// `metadata`.
+    pub(crate) fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        if !self.set.load(Ordering::Relaxed) {
+            return true;
+        }
+        let targets = self.targets.read().unwrap_or_else(PoisonError::into_inner);
+        targets
+            .as_ref()
+            .is_none_or(|targets| targets.would_enable(metadata.target(), metadata.level()))
+    }
+
+    fn set(&self, targets: Option<Targets>) {
+        let mut current = self.targets.write().unwrap_or_else(PoisonError::into_inner);
+        self.set.store(targets.is_some(), Ordering::Relaxed);
+        *current = targets;
+    }
+}
+
+/// The parts of a bridge that can be reached by its id once it's in a
+/// subscriber.
+pub(crate) struct NamedBridge {
+    pub(crate) callbacks: Vec<Arc<RwLock<Arc<Callbacks>>>>,
+    pub(crate) jsonl_file: Option<Arc<Mutex<File>>>,
+    pub(crate) filter: Arc<BridgeFilter>,
+}
+
+impl NamedBridge {
+    /// Adds `bridge` to the table as `id`, replacing any bridge with the same
+    /// id, and returns it for the bridge to keep its entry alive.
+    pub(crate) fn register(id: &str, bridge: NamedBridge) -> Arc<NamedBridge> {
+        let bridge = Arc::new(bridge);
+        let mut bridges = NAMED_BRIDGES.lock().unwrap_or_else(PoisonError::into_inner);
+        bridges.retain(|_, bridge| bridge.strong_count() > 0);
+        bridges.insert(id.to_owned(), Arc::downgrade(&bridge));
+        bridge
+    }
+}
+
+fn named_bridge(name: &str) -> PyResult<Arc<NamedBridge>> {
+    let bridges = NAMED_BRIDGES.lock().unwrap_or_else(PoisonError::into_inner);
+    bridges
+        .get(name)
+        .and_then(Weak::upgrade)
+        .ok_or_else(|| PyValueError::new_err(format!("no bridge named {name:?}")))
+}
+
+/// Returns the ids of the bridges in use, which the other functions of this
+/// module take as `name`, in sorted order.
+///
+/// A bridge is in use from when it's added to a subscriber until it's dropped,
+/// and its id is set with [`crate::PythonCallbackLayerBridge::with_id`] or
+/// generated as `bridge-1`, `bridge-2`…
+#[pyfunction]
+pub fn bridge_names() -> Vec<String> {
+    let bridges = NAMED_BRIDGES.lock().unwrap_or_else(PoisonError::into_inner);
+    bridges
+        .iter()
+        .filter(|(_, bridge)| bridge.strong_count() > 0)
+        .map(|(name, _)| name.clone())
+        .collect()
+}
+
+/// Flushes the bridge `name`, like
+/// [`crate::PythonCallbackLayerBridge::flush`]: its JSONL file and its Python
+/// layers' `flush()` methods, if they have one.
+///
+/// Raises `ValueError` if no bridge named `name` is in use.
+#[pyfunction]
+pub fn flush(py: Python<'_>, name: &str) -> PyResult<()> {
+    let bridge = named_bridge(name)?;
+    py.allow_threads(|| {
+        crate::flush_layers(
+            bridge.jsonl_file.as_deref(),
+            bridge
+                .callbacks
+                .iter()
+                .map(|callbacks| Callbacks::cached(callbacks)),
+        )
+    })
+    .map_err(PyErr::from)
+}
+
+/// Sets which spans and events the bridge `name` forwards to its Python layers,
+/// on top of the subscriber's filter, e.g.
+/// `set_filter("named-db", "my_crate::db=debug")`, or removes its filter with
+/// `None`.
+///
+/// `filter` is a comma-separated list of `target=level` directives and a
+/// default `level`, like the [`tracing_subscriber::EnvFilter`] syntax without
+/// span and field filters. Spans and events it doesn't enable still reach the
+/// subscriber's other layers. Raises `ValueError` for an invalid filter or if
+/// no bridge named `name` is in use.
+#[pyfunction]
+#[pyo3(signature = (name, filter))]
+pub fn set_filter(name: &str, filter: Option<&str>) -> PyResult<()> {
+    let targets = filter
+        .map(|filter| {
+            Targets::from_str(filter)
+                .map_err(|err| PyValueError::new_err(format!("invalid filter {filter:?}: {err}")))
+        })
+        .transpose()?;
+    named_bridge(name)?.filter.set(targets);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::{exceptions::PyValueError, prelude::*};
+    use tracing::{debug, info, info_span, warn_span};
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::{
+        tests::{prepare_python, PythonLayer},
+        PythonCallbackLayerBridge,
+    };
+
+    #[test]
+    fn test_named_bridges() {
+        prepare_python();
+        let (db_layer, flushed, db, audit) = Python::with_gil(|py| {
+            let module = PyModule::from_code_bound(
+                py,
+                "
+flushed = []
+
+class FlushedLayer:
+    def on_event(self, event, state):
+        pass
+
+    def flush(self):
+        flushed.append('audit')
+",
+                "flushed_layer.py",
+                "flushed_layer",
+            )
+            .unwrap();
+            let db_layer = Bound::new(py, PythonLayer::new()).unwrap();
+            let audit_layer = module.getattr("FlushedLayer").unwrap().call0().unwrap();
+            let db =
+                PythonCallbackLayerBridge::new(db_layer.clone().into_any()).with_id("named-db");
+            let audit = PythonCallbackLayerBridge::new(audit_layer.clone()).with_id("named-audit");
+            (
+                db_layer.unbind(),
+                module.getattr("flushed").unwrap().unbind(),
+                db,
+                audit,
+            )
+        });
+
+        {
+            let _dispatcher = tracing_subscriber::registry()
+                .with(db)
+                .with(audit)
+                .set_default();
+            let names = bridge_names();
+            assert!(names.contains(&"named-db".to_owned()));
+            assert!(names.contains(&"named-audit".to_owned()));
+
+            set_filter("named-db", Some("my_crate::db=debug,warn")).unwrap();
+            warn_span!("scope").in_scope(|| {
+                debug!(target: "my_crate::db", "query");
+                info!("dropped");
+            });
+            info_span!("dropped span").in_scope(|| {});
+            set_filter("named-db", None).unwrap();
+            info_span!("kept span").in_scope(|| info!("kept"));
+
+            Python::with_gil(|py| {
+                flush(py, "named-audit").unwrap();
+                for err in [
+                    flush(py, "missing").unwrap_err(),
+                    set_filter("named-db", Some("=nonsense=")).unwrap_err(),
+                ] {
+                    assert!(err.is_instance_of::<PyValueError>(py));
+                }
+                assert_eq!(vec!["audit"], flushed.extract::<Vec<String>>(py).unwrap());
+            });
+        }
+        assert!(!bridge_names().contains(&"named-db".to_owned()));
+
+        Python::with_gil(|py| {
+            let borrowed = db_layer.borrow(py);
+            let messages: Vec<_> = borrowed.events.iter().map(|event| &*event.0).collect();
+            assert_eq!(vec!["query", "kept"], messages);
+            let spans: Vec<_> = borrowed
+                .new_spans
+                .iter()
+                .map(|span| &span["name"])
+                .collect();
+            assert_eq!(vec!["scope", "kept span"], spans);
+        });
+    }
+}
@@ -0,0 +1,195 @@
+//! Interop with `tracing-opentelemetry`, when it is installed in the same
+//! subscriber as the bridge.
+
+use opentelemetry::{
+    trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState},
+    Context,
+};
+use pyo3::{prelude::*, pybacked::PyBackedStr};
+use serde_json::{json, Value};
+use tracing_core::dispatcher;
+use tracing_opentelemetry::OtelData;
+use tracing_subscriber::{
+    registry::{Extensions, ExtensionsMut, LookupSpan},
+    Registry,
+};
+
+use crate::propagation::TraceParent;
+
+/// Returns the W3C trace id and span id `tracing-opentelemetry` assigned to a
+/// span, if its `OpenTelemetryLayer` has seen the span.
+fn otel_ids(otel_data: &OtelData) -> Option<(TraceId, SpanId)> {
+    let span_id = otel_data.builder.span_id?;
+    let trace_id = otel_data
+        .builder
+        .trace_id
+        .unwrap_or_else(|| otel_data.parent_cx.span().span_context().trace_id());
+    Some((trace_id, span_id))
+}
+
+fn insert_ids(payload: &mut Value, otel_data: Option<&OtelData>) {
+    let (Some((trace_id, span_id)), Some(payload)) =
+        (otel_data.and_then(otel_ids), payload.as_object_mut())
+    else {
+        return;
+    };
+
+    payload.insert(
+        "otel".to_owned(),
+        json!({"trace_id": trace_id.to_string(), "span_id": span_id.to_string()}),
+    );
+}
+
+/// Adds an `otel` object with the span's hex-encoded `trace_id` and `span_id`
+/// to `payload`, if they are known.
+pub(crate) fn insert_otel_ids(payload: &mut Value, extensions: &Extensions<'_>) {
+    insert_ids(payload, extensions.get::<OtelData>());
+}
+
+/// Makes a new root span a child of the remote span in `parent_context`, if it
+/// is a `traceparent` header, then adds the span's ids to `payload` like
+/// [`insert_otel_ids`].
+pub(crate) fn init_new_span(
+    payload: &mut Value,
+    extensions: &mut ExtensionsMut<'_>,
+    parent_context: Option<&Bound<'_, PyAny>>,
+) {
+    let Some(otel_data) = extensions.get_mut::<OtelData>() else {
+        return;
+    };
+
+    let traceparent = parent_context
+        .and_then(|parent_context| parent_context.extract::<PyBackedStr>().ok())
+        .and_then(|traceparent| TraceParent::parse(&traceparent));
+    if let Some(traceparent) = traceparent {
+        let trace_id = TraceId::from_bytes(traceparent.trace_id.to_be_bytes());
+        let remote_parent = SpanContext::new(
+            trace_id,
+            SpanId::from_bytes(traceparent.span_id.to_be_bytes()),
+            TraceFlags::new(traceparent.flags),
+            true,
+            TraceState::default(),
+        );
+        otel_data.parent_cx = Context::new().with_remote_span_context(remote_parent);
+        otel_data.builder.trace_id = Some(trace_id);
+    }
+
+    insert_ids(payload, Some(otel_data));
+}
+
+/// Returns the `traceparent` of the current contextual span, if
+/// `tracing-opentelemetry` assigned it valid ids.
+pub(crate) fn current_traceparent() -> Option<TraceParent> {
+    dispatcher::get_default(|dispatch| {
+        let span_id = dispatch.current_span().id()?.clone();
+        let span = dispatch.downcast_ref::<Registry>()?.span(&span_id)?;
+        let extensions = span.extensions();
+        let otel_data = extensions.get::<OtelData>()?;
+
+        let (trace_id, span_id) = otel_ids(otel_data)?;
+        if trace_id == TraceId::INVALID || span_id == SpanId::INVALID {
+            return None;
+        }
+        let parent = otel_data.parent_cx.span();
+        let flags = if parent.span_context().is_valid() {
+            parent.span_context().trace_flags()
+        } else {
+            TraceFlags::SAMPLED
+        };
+
+        Some(TraceParent {
+            trace_id: u128::from_be_bytes(trace_id.to_bytes()),
+            span_id: u64::from_be_bytes(span_id.to_bytes()),
+            flags: flags.to_u8(),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::trace::{noop::NoopTracer, TracerProvider};
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use pyo3::prelude::*;
+    use serde_json::json;
+    use tracing::{info, info_span};
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::{
+        current_traceparent, reset_parent_context, set_traceparent,
+        tests::{prepare_python, PythonLayer},
+        PythonCallbackLayerBridge,
+    };
+
+    fn python_layer() -> (Py<PythonLayer>, PythonCallbackLayerBridge) {
+        prepare_python();
+        Python::with_gil(|py| {
+            let py_layer = Bound::new(py, PythonLayer::new()).unwrap();
+            let bridge = PythonCallbackLayerBridge::new(py_layer.clone().into_any());
+            (py_layer.unbind(), bridge)
+        })
+    }
+
+    #[test]
+    fn test_otel_ids_in_payloads() {
+        let (py_layer, bridge) = python_layer();
+        let _dispatcher = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(NoopTracer::new()))
+            .with(bridge)
+            .set_default();
+
+        let trace_id = TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap();
+        let remote_parent = SpanContext::new(
+            trace_id,
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        );
+        let _context = Context::new()
+            .with_remote_span_context(remote_parent)
+            .attach();
+
+        info_span!("with_otel").in_scope(|| info!("inside"));
+
+        // The no-op tracer assigns invalid (all-zero) span ids.
+        let expected = json!({
+            "trace_id": "4bf92f3577b34da6a3ce929d0e0e4736",
+            "span_id": "0000000000000000",
+        });
+        Python::with_gil(|py| {
+            let borrowed = py_layer.borrow(py);
+            assert_eq!(expected, borrowed.new_spans[0]["otel"]);
+            assert_eq!(expected, borrowed.event_fields[0]["otel"]);
+        });
+    }
+
+    #[test]
+    fn test_traceparent_propagation() {
+        let (py_layer, bridge) = python_layer();
+        let provider = SdkTracerProvider::builder().build();
+        let _dispatcher = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")))
+            .with(bridge)
+            .set_default();
+
+        Python::with_gil(|py| {
+            let incoming = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+            let token = set_traceparent(py, &incoming.to_object(py).into_bound(py)).unwrap();
+            let outgoing = info_span!("incoming")
+                .in_scope(current_traceparent)
+                .unwrap();
+            reset_parent_context(py, &token).unwrap();
+
+            let ids = &py_layer.borrow(py).new_spans[0]["otel"];
+            assert_eq!("4bf92f3577b34da6a3ce929d0e0e4736", ids["trace_id"]);
+            assert_eq!(
+                format!(
+                    "00-4bf92f3577b34da6a3ce929d0e0e4736-{}-01",
+                    ids["span_id"].as_str().unwrap()
+                ),
+                outgoing
+            );
+        });
+    }
+}
@@ -0,0 +1,122 @@
+//! Exports spans to an OpenTelemetry collector over OTLP, without going through
+//! Python.
+
+use std::collections::HashMap;
+
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig, WithHttpConfig};
+use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+use pyo3::{exceptions::PyValueError, prelude::*};
+use tracing_core::Subscriber;
+use tracing_subscriber::{registry::LookupSpan, Layer};
+
+use crate::{
+    init::{env_config, try_init, TracingGuard},
+    PythonCallbackLayerBridge,
+};
+
+/// Shuts down the tracer provider, exporting pending spans, when dropped.
+struct ProviderGuard(SdkTracerProvider);
+
+impl Drop for ProviderGuard {
+    fn drop(&mut self) {
+        let _ = self.0.shutdown();
+    }
+}
+
+/// Returns a `tracing-opentelemetry` layer exporting spans of `service_name` to
+/// `endpoint` in batches, and the guard exporting the last batch.
+fn otlp_layer<S>(
+    service_name: &str,
+    endpoint: Option<String>,
+    headers: HashMap<String, String>,
+) -> PyResult<(impl Layer<S>, TracingGuard)>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let mut exporter = SpanExporter::builder().with_http().with_headers(headers);
+    if let Some(endpoint) = endpoint {
+        exporter = exporter.with_endpoint(endpoint);
+    }
+    let exporter = exporter
+        .build()
+        .map_err(|err| PyValueError::new_err(err.to_string()))?;
+
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(
+            Resource::builder()
+                .with_service_name(service_name.to_owned())
+                .build(),
+        )
+        .build();
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+
+    let layer = tracing_opentelemetry::layer().with_tracer(tracer);
+    Ok((layer, TracingGuard::new(ProviderGuard(provider))))
+}
+
+/// Installs a global subscriber that exports `tracing` spans, with their events,
+/// to an OpenTelemetry collector over OTLP/HTTP (protobuf), and optionally also
+/// forwards `tracing` data to `py_impl` like [`crate::initialize_tracing`].
+///
+/// `endpoint` is the collector's full traces URL, e.g.
+/// `"http://localhost:4318/v1/traces"`, and defaults to the
+/// `OTEL_EXPORTER_OTLP_*` environment variables. `headers` are sent with each
+/// export request, e.g. for authentication. Spans are exported in batches from
+/// a background thread until the returned [`TracingGuard`] is closed, which
+/// exports the last batch. `filter` and the `PYO3_TRACING_*` environment
+/// variables work as they do for [`crate::initialize_tracing`]. Raises
+/// `ValueError` for an invalid endpoint.
+#[pyfunction]
+#[pyo3(signature = (service_name, endpoint = None, headers = None, py_impl = None, filter = None))]
+pub fn initialize_otlp(
+    service_name: &str,
+    endpoint: Option<String>,
+    headers: Option<HashMap<String, String>>,
+    py_impl: Option<Bound<'_, PyAny>>,
+    filter: Option<&str>,
+) -> PyResult<TracingGuard> {
+    let (layer, guard) = otlp_layer(service_name, endpoint, headers.unwrap_or_default())?;
+    let config = env_config(filter)?;
+    let bridge = py_impl
+        .map(|py_impl| config.apply(PythonCallbackLayerBridge::new(py_impl)))
+        .transpose()?;
+    try_init(layer.and_then(bridge), config.filter())?;
+    Ok(guard)
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::info_span;
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::{current_traceparent, tests::prepare_python};
+
+    #[test]
+    fn test_otlp_layer() {
+        prepare_python();
+        let (layer, mut guard) = otlp_layer(
+            "test",
+            Some("http://127.0.0.1:9/v1/traces".to_owned()),
+            HashMap::from([("authorization".to_owned(), "Bearer token".to_owned())]),
+        )
+        .unwrap();
+        let dispatcher = tracing_subscriber::registry().with(layer).set_default();
+
+        let traceparent = info_span!("exported").in_scope(current_traceparent);
+        assert!(traceparent.is_some_and(|traceparent| traceparent.starts_with("00-")));
+
+        drop(dispatcher);
+        // Fails to export to the discard port without panicking.
+        guard.close();
+
+        assert!(otlp_layer::<tracing_subscriber::Registry>(
+            "test",
+            Some("not a url".to_owned()),
+            HashMap::new()
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,63 @@
+//! Accounting of the time a bridge spends handling each span, so observers can
+//! tell it apart from the time the traced code spends.
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// The time a [`crate::PythonCallbackLayerBridge`] built with
+/// [`crate::PythonCallbackLayerBridge::with_overhead_accounting`] has spent
+/// handling a span, stored in the span's extensions.
+///
+/// It covers serializing payloads, waiting for the GIL and calling the Python
+/// layer for the span and its events while the span is open, and, once they
+/// close, the same for its child spans. Subtracting it from the span's busy
+/// time gives the time the traced code spent.
+#[derive(Debug, Default)]
+pub struct BridgeOverhead(AtomicU64);
+
+impl BridgeOverhead {
+    pub fn get(&self) -> Duration {
+        Duration::from_nanos(self.0.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn add(&self, overhead: Duration) {
+        self.0.fetch_add(
+            u64::try_from(overhead.as_nanos()).unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::Python;
+    use tracing::{info, info_span};
+
+    use crate::tests::initialize_tracing_with;
+
+    #[test]
+    fn test_overhead_accounting() {
+        let (py_layer, _dispatcher) =
+            initialize_tracing_with(|bridge| bridge.with_overhead_accounting(true));
+
+        info_span!("parent").in_scope(|| {
+            info_span!("child").in_scope(|| {
+                for rows in 0..10 {
+                    info!(rows, "queried");
+                }
+            });
+        });
+
+        Python::with_gil(|py| {
+            let borrowed = py_layer.borrow(py);
+            let [child, parent] = borrowed.close_overheads[..] else {
+                panic!("expected two closed spans");
+            };
+            let (child, parent) = (child.unwrap(), parent.unwrap());
+            assert!(child > 0.0);
+            assert!(parent > child);
+        });
+    }
+}
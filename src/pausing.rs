@@ -0,0 +1,111 @@
+//! Pausing a bridge's forwarding, e.g. around bulk operations known to be
+//! noisy, or on threads that shouldn't be traced, without the Python layer
+//! losing track of its spans.
+
+use std::cell::Cell;
+
+use crate::{with_current_bridge, PythonCallbackLayerBridge};
+use pyo3::prelude::*;
+
+thread_local! {
+    static THREAD_UNTRACED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Marks a span created while its bridge was paused, on an untraced thread or
+/// from a disabled callsite, stored in its extensions.
+///
+/// The Python layer never saw the span, so it isn't told when the span is
+/// recorded to, followed from or closed, even after the bridge resumes.
+pub(crate) struct Unforwarded;
+
+/// Pauses the bridge of the current subscriber, as with
+/// [`PythonCallbackLayerBridge::pause`], and returns whether it was
+/// forwarding, so nested pauses can leave resuming to the outermost one.
+///
+/// Returns `False` if the current subscriber has no bridge.
+#[pyfunction]
+pub fn pause() -> bool {
+    with_current_bridge(PythonCallbackLayerBridge::pause).unwrap_or(false)
+}
+
+/// Resumes the bridge of the current subscriber, as with
+/// [`PythonCallbackLayerBridge::resume`], and returns whether it was paused.
+///
+/// Returns `False` if the current subscriber has no bridge.
+#[pyfunction]
+pub fn resume() -> bool {
+    with_current_bridge(PythonCallbackLayerBridge::resume).unwrap_or(false)
+}
+
+/// Marks the current thread as untraced, or as traced again with
+/// `untraced=False`: bridges don't forward the spans, events and span records
+/// it emits, like while they're paused.
+///
+/// This keeps threads like a telemetry exporter's worker, which may call
+/// instrumented Rust code itself, from feeding back into the Python layer.
+#[pyfunction]
+#[pyo3(signature = (untraced = true))]
+pub fn mark_thread_untraced(untraced: bool) {
+    THREAD_UNTRACED.set(untraced);
+}
+
+/// Whether the current thread was marked with [`mark_thread_untraced`].
+pub(crate) fn is_thread_untraced() -> bool {
+    THREAD_UNTRACED.get()
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{info, info_span};
+
+    use super::*;
+    use crate::tests::initialize_tracing;
+
+    #[test]
+    fn test_pause_resume() {
+        let (py_layer, _dispatcher) = initialize_tracing();
+
+        let before = info_span!("before");
+        assert!(pause());
+        assert!(!pause());
+        let during = info_span!("during");
+        before.in_scope(|| info!("dropped"));
+        drop(before);
+        assert!(resume());
+        assert!(!resume());
+        during.in_scope(|| info_span!("after").in_scope(|| info!("forwarded")));
+        drop(during);
+
+        Python::with_gil(|py| {
+            let borrowed = py_layer.borrow(py);
+            assert_eq!(2, borrowed.new_spans.len());
+            assert_eq!(vec![0, 1], borrowed.closed_spans);
+            let messages = borrowed
+                .events
+                .iter()
+                .map(|(message, _, state)| (message.as_str(), *state))
+                .collect::<Vec<_>>();
+            assert_eq!(vec![("forwarded", 1)], messages);
+        });
+    }
+
+    #[test]
+    fn test_mark_thread_untraced() {
+        let (py_layer, _dispatcher) = initialize_tracing();
+
+        let traced = info_span!("traced");
+        mark_thread_untraced(true);
+        let untraced = info_span!("untraced");
+        traced.in_scope(|| info!("dropped"));
+        drop(traced);
+        mark_thread_untraced(false);
+        untraced.in_scope(|| info_span!("traced again").in_scope(|| info!("forwarded")));
+
+        Python::with_gil(|py| {
+            let borrowed = py_layer.borrow(py);
+            assert_eq!(2, borrowed.new_spans.len());
+            assert_eq!(vec![0, 1], borrowed.closed_spans);
+            assert_eq!(1, borrowed.events.len());
+        });
+    }
+}
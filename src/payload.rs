@@ -0,0 +1,438 @@
+//! Alternative shapes for the payloads the bridge passes to Python.
+
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use pyo3::{
+    prelude::*,
+    sync::GILOnceCell,
+    types::{IntoPyDict, PyDict, PyList},
+};
+use serde_json::Value;
+use tracing_core::{Event, Level};
+
+use crate::{
+    coercion::CoercionRules,
+    constants,
+    exception::ExceptionInfo,
+    fields::{self, FieldCollector},
+    log_bridge,
+};
+
+/// The form in which [`crate::PythonCallbackLayerBridge`] passes events to the
+/// Python layer's `on_event`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PayloadMode {
+    /// A JSON string serialized from the event, as described on
+    /// [`crate::PythonCallbackLayerBridge`].
+    #[default]
+    Json,
+    /// The same payload as [`PayloadMode::Json`], converted straight to the
+    /// `dict` `json.loads` would return, which saves serializing it to a
+    /// string and parsing it again in Python.
+    Dict,
+    /// The same payload as [`PayloadMode::Json`], as UTF-8 bytes in a
+    /// [`crate::PayloadBuffer`] that Python reads through the buffer
+    /// protocol, without copying it into a `str`. Not available with the
+    /// `abi3` feature, since the limited API before Python 3.11 lacks the
+    /// buffer protocol.
+    #[cfg(not(feature = "abi3"))]
+    Buffer,
+    /// A flat `dict` shaped like a structlog `event_dict`, with the event's
+    /// message as `event`, its `level` name, an ISO 8601 UTC `timestamp`, its
+    /// target as the `logger` name and its fields.
+    StructlogEventDict,
+}
+
+/// The structlog level name for a `tracing` level. structlog has no level
+/// below `debug`, so `TRACE` maps to it too.
+pub(crate) fn structlog_level(level: &Level) -> &'static str {
+    match *level {
+        Level::ERROR => "error",
+        Level::WARN => "warning",
+        Level::INFO => "info",
+        _ => "debug",
+    }
+}
+
+/// How [`structlog_event_dict`] builds an `event_dict`.
+pub(crate) struct EventDictOptions<'a, 'py> {
+    /// The time of the event.
+    pub(crate) now: SystemTime,
+    /// Whether the fields are sorted by name rather than in declaration order.
+    pub(crate) sorted_fields: bool,
+    /// The timezone of the `timestamp` as an aware `datetime`, if it isn't an
+    /// ISO 8601 string.
+    pub(crate) timezone: Option<&'a Bound<'py, PyAny>>,
+    pub(crate) coercion: CoercionRules,
+    /// Whether the `event_dict` includes the `field_kinds` of its fields.
+    pub(crate) field_kinds: bool,
+}
+
+impl EventDictOptions<'_, '_> {
+    pub(crate) fn new(now: SystemTime) -> Self {
+        EventDictOptions {
+            now,
+            sorted_fields: false,
+            timezone: None,
+            coercion: CoercionRules::default(),
+            field_kinds: false,
+        }
+    }
+}
+
+/// Builds a structlog-style `event_dict` for `event`: its message as `event`,
+/// its `level` name, `now` as an ISO 8601 UTC `timestamp`, its target as the `logger`
+/// name (with `::` replaced by `.`) and its fields. Fields whose names clash
+/// with those keys are prefixed with `field_`, and fields recorded as errors
+/// are lists of their messages followed by those of their `source()` chain.
+/// Events describing an error (see [`ExceptionInfo`]) also get a synthesized
+/// exception as `exc_info`, for structlog's exception processors.
+pub(crate) fn structlog_event_dict<'py>(
+    py: Python<'py>,
+    event: &Event<'_>,
+    options: &EventDictOptions<'_, 'py>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let mut fields = FieldCollector::default();
+    event.record(&mut fields);
+    options.coercion.coerce_collected(&mut fields, event);
+    if options.sorted_fields {
+        fields.fields.sort_by_key(|(name, _)| *name);
+    }
+    let normalized_metadata = log_bridge::normalized_metadata(event);
+    let metadata = normalized_metadata
+        .as_ref()
+        .unwrap_or_else(|| event.metadata());
+
+    let event_dict = PyDict::new_bound(py);
+    let key = |key| constants::py_str(py, key);
+    event_dict.set_item(
+        key("event"),
+        fields
+            .take_message()
+            .unwrap_or_else(|| metadata.name().to_owned()),
+    )?;
+    event_dict.set_item(
+        key("level"),
+        constants::py_str(py, structlog_level(metadata.level())),
+    )?;
+    match options.timezone {
+        Some(timezone) => {
+            event_dict.set_item(key("timestamp"), datetime_timestamp(options.now, timezone)?)?
+        }
+        None => event_dict.set_item(key("timestamp"), iso_timestamp(options.now))?,
+    }
+    event_dict.set_item(key("logger"), metadata.target().replace("::", "."))?;
+    if let Some(info) = ExceptionInfo::from_fields(&fields) {
+        event_dict.set_item(key("exc_info"), info.to_exception(py)?)?;
+    }
+
+    let error_lists = fields.error_lists().collect::<HashMap<_, _>>();
+    for (name, value) in &fields.fields {
+        let key = if event_dict.contains(*name)? {
+            format!("field_{name}")
+        } else {
+            (*name).to_owned()
+        };
+        match error_lists.get(name) {
+            Some(messages) => event_dict.set_item(key, messages)?,
+            None => event_dict.set_item(key, value)?,
+        }
+    }
+    #[cfg(feature = "arbitrary-precision")]
+    for (name, number) in crate::wide_ints::wide_ints(event) {
+        let value = json_to_py(py, &number.into());
+        event_dict.set_item(field_key(&event_dict, name)?, value)?;
+    }
+    #[cfg(all(tracing_unstable, feature = "valuable"))]
+    for (name, value) in crate::structured::structured_fields(event) {
+        event_dict.set_item(field_key(&event_dict, name)?, json_to_py(py, &value))?;
+    }
+    if options.field_kinds {
+        let kinds = fields::field_kinds(event);
+        event_dict.set_item("field_kinds", json_to_py(py, &kinds))?;
+    }
+    Ok(event_dict)
+}
+
+/// Returns the key [`structlog_event_dict`] set the field `name` as, to replace
+/// its value.
+#[cfg(any(
+    feature = "arbitrary-precision",
+    all(tracing_unstable, feature = "valuable")
+))]
+fn field_key(event_dict: &Bound<'_, PyDict>, name: &str) -> PyResult<String> {
+    let prefixed = format!("field_{name}");
+    Ok(match event_dict.contains(&prefixed)? {
+        true => prefixed,
+        false => name.to_owned(),
+    })
+}
+
+/// Converts `value` to the Python object `json.loads` would parse it as.
+pub(crate) fn json_to_py(py: Python<'_>, value: &Value) -> PyObject {
+    match value {
+        Value::Null => py.None(),
+        Value::Bool(value) => value.into_py(py),
+        Value::Number(number) => match (number.as_u64(), number.as_i64()) {
+            (Some(value), _) => value.into_py(py),
+            (None, Some(value)) => value.into_py(py),
+            (None, None) => {
+                #[cfg(feature = "arbitrary-precision")]
+                if let Some(int) = crate::wide_ints::wide_int_to_py(py, number) {
+                    return int;
+                }
+                number.as_f64().unwrap_or(f64::NAN).into_py(py)
+            }
+        },
+        Value::String(value) => constants::py_str(py, value).into_any().unbind(),
+        Value::Array(values) => {
+            PyList::new_bound(py, values.iter().map(|value| json_to_py(py, value)))
+                .into_any()
+                .unbind()
+        }
+        Value::Object(map) => {
+            let dict = PyDict::new_bound(py);
+            for (key, value) in map {
+                dict.set_item(constants::py_str(py, key), json_to_py(py, value))
+                    .expect("setting a str key of a new dict can't fail");
+            }
+            dict.into_any().unbind()
+        }
+    }
+}
+
+/// `datetime.datetime(1970, 1, 1, tzinfo=datetime.timezone.utc)` and
+/// `datetime.timedelta`.
+static DATETIME: GILOnceCell<(Py<PyAny>, Py<PyAny>)> = GILOnceCell::new();
+
+/// Converts `time` to an aware `datetime` in `timezone`, with microsecond
+/// precision.
+pub(crate) fn datetime_timestamp<'py>(
+    time: SystemTime,
+    timezone: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let py = timezone.py();
+    let (epoch, timedelta) = DATETIME.get_or_try_init(py, || {
+        let datetime = py.import_bound("datetime")?;
+        let utc = datetime.getattr("timezone")?.getattr("utc")?;
+        let epoch = datetime.getattr("datetime")?.call(
+            (1970, 1, 1),
+            Some(&[("tzinfo", utc)].into_py_dict_bound(py)),
+        )?;
+        Ok::<_, PyErr>((epoch.unbind(), datetime.getattr("timedelta")?.unbind()))
+    })?;
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let delta =
+        timedelta
+            .bind(py)
+            .call1((0, since_epoch.as_secs(), since_epoch.subsec_micros()))?;
+    epoch
+        .bind(py)
+        .add(delta)?
+        .call_method1("astimezone", (timezone,))
+}
+
+/// Formats `time` like `2024-05-01T12:30:00.123456Z`, the format of structlog's
+/// `TimeStamper(fmt="iso")`.
+pub(crate) fn iso_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let secs_of_day = secs % 86_400;
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:06}Z",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_micros(),
+    )
+}
+
+/// Converts days since the Unix epoch to a proleptic Gregorian
+/// `(year, month, day)`, using Howard Hinnant's `civil_from_days` algorithm.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+    use tracing::{info_span, subscriber::DefaultGuard, warn};
+    use tracing_subscriber::{prelude::*, Registry};
+
+    use super::*;
+    use crate::{
+        tests::{initialize_tracing_with, prepare_python},
+        PythonCallbackLayerBridge,
+    };
+
+    #[test]
+    fn test_iso_timestamp() {
+        let time = UNIX_EPOCH + Duration::from_micros(1_709_210_096_123_456);
+        assert_eq!("2024-02-29T12:34:56.123456Z", iso_timestamp(time));
+        assert_eq!("1970-01-01T00:00:00.000000Z", iso_timestamp(UNIX_EPOCH));
+    }
+
+    #[test]
+    fn test_dict_payloads() {
+        let (py_layer, _dispatcher) =
+            initialize_tracing_with(|bridge| bridge.with_payload_mode(PayloadMode::Dict));
+
+        info_span!("scope").in_scope(|| warn!(user = "alice", rows = 3, "slow query"));
+
+        Python::with_gil(|py| {
+            let borrowed = py_layer.borrow(py);
+            assert_eq!(
+                ("slow query".to_owned(), "WARN".to_owned(), 0),
+                borrowed.events[0]
+            );
+            assert_eq!("alice", borrowed.event_fields[0]["user"]);
+            assert_eq!(3, borrowed.event_fields[0]["rows"]);
+
+            let value = json!({"a": [1, -2, 0.5, true, null, "s"]});
+            let converted = json_to_py(py, &value);
+            let dumped = py
+                .import_bound("json")
+                .unwrap()
+                .call_method1("dumps", (converted,))
+                .unwrap()
+                .extract::<String>()
+                .unwrap();
+            assert_eq!(value, serde_json::from_str::<Value>(&dumped).unwrap());
+        });
+    }
+
+    #[test]
+    fn test_structlog_event_dict() {
+        let (py_layer, _dispatcher) = initialize_tracing_with(|bridge| {
+            bridge.with_payload_mode(PayloadMode::StructlogEventDict)
+        });
+
+        info_span!("scope").in_scope(|| warn!(user = "alice", level = 3, "slow query"));
+
+        Python::with_gil(|py| {
+            let borrowed = py_layer.borrow(py);
+            let event_dict = borrowed.event_fields[0].as_object().unwrap();
+            assert_eq!("slow query", event_dict["event"]);
+            assert_eq!("warning", event_dict["level"]);
+            assert_eq!(module_path!().replace("::", "."), event_dict["logger"]);
+            assert_eq!("alice", event_dict["user"]);
+            assert_eq!(3, event_dict["field_level"]);
+            assert!(event_dict["timestamp"].as_str().unwrap().ends_with('Z'));
+        });
+    }
+
+    /// Installs a bridge passing structlog `event_dict`s to a Python layer
+    /// that keeps them as they are, and returns the `list` they're added to.
+    fn record_event_dicts(
+        configure: impl FnOnce(Python<'_>, PythonCallbackLayerBridge) -> PythonCallbackLayerBridge,
+    ) -> (Py<PyAny>, DefaultGuard) {
+        prepare_python();
+        let (bridge, event_dicts) = Python::with_gil(|py| {
+            let module = PyModule::from_code_bound(
+                py,
+                "
+class EventDictLayer:
+    def __init__(self):
+        self.event_dicts = []
+
+    def on_event(self, event_dict, state):
+        self.event_dicts.append(event_dict)
+",
+                "event_dict_layer.py",
+                "event_dict_layer",
+            )
+            .unwrap();
+            let py_layer = module.getattr("EventDictLayer").unwrap().call0().unwrap();
+            let event_dicts = py_layer.getattr("event_dicts").unwrap().unbind();
+            let bridge = PythonCallbackLayerBridge::new(py_layer)
+                .with_payload_mode(PayloadMode::StructlogEventDict);
+            (configure(py, bridge), event_dicts)
+        });
+        let guard = tracing::subscriber::set_default(Registry::default().with(bridge));
+        (event_dicts, guard)
+    }
+
+    #[test]
+    fn test_sorted_fields() {
+        let (event_dicts, _guard) = record_event_dicts(|_, bridge| bridge.with_sorted_fields(true));
+
+        warn!(zebra = 1, apple = 2, mango = 3, "sorted");
+
+        Python::with_gil(|py| {
+            let keys = event_dicts
+                .bind(py)
+                .get_item(0)
+                .unwrap()
+                .iter()
+                .unwrap()
+                .map(|key| key.unwrap().extract::<String>().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(
+                vec![
+                    "event",
+                    "level",
+                    "timestamp",
+                    "logger",
+                    "apple",
+                    "mango",
+                    "zebra"
+                ],
+                keys
+            );
+        });
+    }
+
+    #[test]
+    fn test_datetime_timestamps() {
+        let (event_dicts, _guard) = record_event_dicts(|py, bridge| {
+            let datetime = py.import_bound("datetime").unwrap();
+            let two_hours = datetime.getattr("timedelta").unwrap().call1((0, 7200));
+            let timezone = datetime
+                .getattr("timezone")
+                .unwrap()
+                .call1((two_hours.unwrap(),));
+            bridge
+                .with_deterministic_mode(true)
+                .with_datetime_timestamps(timezone.unwrap())
+        });
+
+        warn!("dated");
+
+        Python::with_gil(|py| {
+            let timestamp = event_dicts
+                .bind(py)
+                .get_item(0)
+                .unwrap()
+                .get_item("timestamp")
+                .unwrap();
+            let iso = timestamp
+                .call_method0("isoformat")
+                .unwrap()
+                .extract::<String>()
+                .unwrap();
+            assert_eq!("1970-01-01T02:00:00+02:00", iso);
+        });
+    }
+}
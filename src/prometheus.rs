@@ -0,0 +1,107 @@
+//! The Prometheus text exposition format for the metrics a bridge aggregates,
+//! for Python web frameworks to serve on `/metrics`.
+
+use std::fmt::Write;
+
+use pyo3::prelude::*;
+
+use crate::with_current_bridge;
+
+/// Returns `name` as a valid Prometheus metric or label name, with the
+/// characters other than ASCII letters, digits and underscores (and colons,
+/// for metric names) replaced by underscores, e.g. `http.requests` as
+/// `http_requests`.
+pub(crate) fn sanitize_name(name: &str, allow_colons: bool) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => c,
+            ':' if allow_colons => c,
+            _ => '_',
+        })
+        .collect();
+    if !sanitized.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == ':') {
+        sanitized.insert(0, '_');
+    }
+    sanitized
+}
+
+/// Writes `labels` as `{name="value",...}`, or nothing if there are none.
+pub(crate) fn write_labels(out: &mut String, labels: &[(&str, String)]) {
+    if labels.is_empty() {
+        return;
+    }
+    out.push('{');
+    for (index, (name, value)) in labels.iter().enumerate() {
+        if index > 0 {
+            out.push(',');
+        }
+        let value = value
+            .replace('\\', r"\\")
+            .replace('"', r#"\""#)
+            .replace('\n', r"\n");
+        let _ = write!(out, "{}=\"{value}\"", sanitize_name(name, false));
+    }
+    out.push('}');
+}
+
+/// Formats a float sample value, with Prometheus' spelling of infinities.
+pub(crate) fn format_float(value: f64) -> String {
+    match value {
+        f64::INFINITY => "+Inf".to_owned(),
+        f64::NEG_INFINITY => "-Inf".to_owned(),
+        value => value.to_string(),
+    }
+}
+
+/// Returns the metrics the bridge in the current subscriber aggregates, like
+/// [`crate::get_metrics`], in the Prometheus text exposition format, or
+/// `None` if the subscriber has no bridge.
+///
+/// Monotonic counters are exposed as `counter`s with a `_total` suffix,
+/// counters that may go down as `gauge`s, and histograms as `summary`s with
+/// their `_sum` and `_count`. Metric and attribute names are sanitized, e.g.
+/// `http.requests` becomes `http_requests`. Serve the result with the
+/// `text/plain; version=0.0.4` content type, after the Python application's
+/// own metrics if it has any.
+#[pyfunction]
+pub fn prometheus_metrics() -> Option<String> {
+    with_current_bridge(|bridge| bridge.metrics.to_prometheus())
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::info;
+
+    use super::{prometheus_metrics, sanitize_name};
+    use crate::tests::initialize_tracing_with;
+
+    #[test]
+    fn test_prometheus_metrics() {
+        let (_py_layer, _dispatcher) = initialize_tracing_with(|bridge| bridge.with_metrics(true));
+
+        info!(
+            monotonic_counter.http.requests = 2_u64,
+            route = "/\"quoted\""
+        );
+        info!(monotonic_counter.http.requests = 1_u64, route = "/");
+        info!(counter.connections = 4, pool.name = "main");
+        info!(histogram.latency = 1.5);
+        info!(histogram.latency = 2.5);
+
+        assert_eq!(
+            "\
+# TYPE http_requests_total counter
+http_requests_total{route=\"/\"} 1
+http_requests_total{route=\"/\\\"quoted\\\"\"} 2
+# TYPE connections gauge
+connections{pool_name=\"main\"} 4
+# TYPE latency summary
+latency_sum 4
+latency_count 2
+",
+            prometheus_metrics().unwrap()
+        );
+        assert_eq!("_2xx:rate", sanitize_name("2xx:rate", true));
+    }
+}
@@ -0,0 +1,191 @@
+//! W3C Trace Context propagation between Python and Rust spans.
+
+use std::{collections::BTreeMap, fmt};
+
+use pyo3::{exceptions::PyValueError, prelude::*, pybacked::PyBackedStr};
+
+use crate::context;
+
+/// A parsed W3C `traceparent` header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct TraceParent {
+    pub(crate) trace_id: u128,
+    pub(crate) span_id: u64,
+    pub(crate) flags: u8,
+}
+
+impl TraceParent {
+    /// Parses a `traceparent` header, returning `None` if it isn't valid.
+    ///
+    /// Future versions may append fields, which are ignored.
+    pub(crate) fn parse(header: &str) -> Option<TraceParent> {
+        let mut parts = header.trim().split('-');
+        let version = parts.next().filter(|version| is_hex(version, 2))?;
+        let trace_id = parts.next().filter(|trace_id| is_hex(trace_id, 32))?;
+        let span_id = parts.next().filter(|span_id| is_hex(span_id, 16))?;
+        let flags = parts.next().filter(|flags| is_hex(flags, 2))?;
+        if version == "ff" || (version == "00" && parts.next().is_some()) {
+            return None;
+        }
+
+        let traceparent = TraceParent {
+            trace_id: u128::from_str_radix(trace_id, 16).ok()?,
+            span_id: u64::from_str_radix(span_id, 16).ok()?,
+            flags: u8::from_str_radix(flags, 16).ok()?,
+        };
+        (traceparent.trace_id != 0 && traceparent.span_id != 0).then_some(traceparent)
+    }
+}
+
+fn is_hex(s: &str, len: usize) -> bool {
+    s.len() == len && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+impl fmt::Display for TraceParent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id, self.span_id, self.flags
+        )
+    }
+}
+
+/// Parses the value of a W3C `baggage` header into its keys and
+/// percent-decoded values, returning `None` if it isn't valid.
+///
+/// Metadata properties after a member's value are ignored.
+pub(crate) fn parse_baggage(header: &str) -> Option<BTreeMap<String, String>> {
+    let mut baggage = BTreeMap::new();
+    for member in header.split(',') {
+        let member = member.split(';').next().unwrap_or_default().trim();
+        if member.is_empty() {
+            continue;
+        }
+        let (key, value) = member.split_once('=')?;
+        let key = key.trim();
+        if key.is_empty() {
+            return None;
+        }
+        baggage.insert(key.to_owned(), percent_decode(value.trim())?);
+    }
+    Some(baggage)
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Sets an incoming W3C `traceparent` header as the parent context of Rust
+/// root spans created in the current Python context, and returns a token for
+/// [`crate::reset_parent_context`].
+///
+/// This is [`crate::set_parent_context`] with the header as the context, so the
+/// Python layer receives it as `parent_context`. With the `opentelemetry`
+/// feature, root spans tracked by `tracing-opentelemetry` also join the
+/// incoming trace. Raises `ValueError` if the header is invalid.
+#[pyfunction]
+pub fn set_traceparent<'py>(
+    py: Python<'py>,
+    traceparent: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let header = traceparent.extract::<PyBackedStr>()?;
+    if TraceParent::parse(&header).is_none() {
+        return Err(PyValueError::new_err(format!(
+            "invalid traceparent {header:?}"
+        )));
+    }
+    context::set_parent_context(py, traceparent)
+}
+
+/// Returns the W3C `traceparent` header for the current contextual Rust span,
+/// for propagating it to outgoing requests made from Python.
+///
+/// Rust spans only have W3C ids when the `opentelemetry` feature is enabled and
+/// a `tracing-opentelemetry` layer tracks them, so this returns `None`
+/// otherwise, and when there is no current span.
+#[pyfunction]
+pub fn current_traceparent() -> Option<String> {
+    #[cfg(feature = "opentelemetry")]
+    {
+        crate::otel::current_traceparent().map(|traceparent| traceparent.to_string())
+    }
+    #[cfg(not(feature = "opentelemetry"))]
+    {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::info_span;
+
+    use super::*;
+    use crate::{reset_parent_context, tests::initialize_tracing};
+
+    const HEADER: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn test_parse_traceparent() {
+        let traceparent = TraceParent::parse(HEADER).unwrap();
+        assert_eq!(0x4bf92f3577b34da6a3ce929d0e0e4736, traceparent.trace_id);
+        assert_eq!(0x00f067aa0ba902b7, traceparent.span_id);
+        assert_eq!(1, traceparent.flags);
+        assert_eq!(HEADER, traceparent.to_string());
+
+        assert!(TraceParent::parse(&format!("{HEADER}-extra")).is_none());
+        assert!(TraceParent::parse(&HEADER.replacen("00", "01", 1)).is_some());
+        assert!(TraceParent::parse(&HEADER.replacen("00", "ff", 1)).is_none());
+        assert!(TraceParent::parse(&HEADER.to_uppercase()).is_none());
+        assert!(
+            TraceParent::parse("00-00000000000000000000000000000000-00f067aa0ba902b7-01").is_none()
+        );
+    }
+
+    #[test]
+    fn test_parse_baggage() {
+        let baggage = parse_baggage("customer.tier=gold , note=a%20b%2Cc;prop=1,,").unwrap();
+        let expected = BTreeMap::from([
+            ("customer.tier".to_owned(), "gold".to_owned()),
+            ("note".to_owned(), "a b,c".to_owned()),
+        ]);
+        assert_eq!(expected, baggage);
+
+        assert!(parse_baggage("=value").is_none());
+        assert!(parse_baggage("key=%zz").is_none());
+    }
+
+    #[test]
+    fn test_set_traceparent() {
+        let (py_layer, _dispatcher) = initialize_tracing();
+
+        Python::with_gil(|py| {
+            assert!(
+                set_traceparent(py, &"nonsense".to_object(py).into_bound(py))
+                    .unwrap_err()
+                    .is_instance_of::<PyValueError>(py)
+            );
+
+            let token = set_traceparent(py, &HEADER.to_object(py).into_bound(py)).unwrap();
+            info_span!("incoming").in_scope(|| assert_eq!(None, current_traceparent()));
+            reset_parent_context(py, &token).unwrap();
+
+            assert_eq!(
+                vec![Some(HEADER.to_owned())],
+                py_layer.borrow(py).parent_contexts
+            );
+        });
+    }
+}
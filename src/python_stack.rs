@@ -0,0 +1,91 @@
+//! Capturing the Python call stack that led to a Rust span, for profiling
+//! mixed Python and Rust code.
+
+use pyo3::{intern, prelude::*};
+use serde_json::{json, Value};
+
+/// Returns the innermost `depth` frames of the calling thread's Python stack,
+/// innermost first, as objects with their `file`, `line` and `function`, or
+/// `None` if the thread isn't running Python code.
+///
+/// A thread that called into Rust keeps its Python frames while it runs Rust
+/// code, even with the GIL released, so they're found when it's acquired
+/// again to forward the span.
+pub(crate) fn capture(py: Python<'_>, depth: usize) -> Option<Value> {
+    if depth == 0 {
+        return None;
+    }
+    let mut frame = py
+        .import_bound(intern!(py, "sys"))
+        .and_then(|sys| sys.call_method1(intern!(py, "_getframe"), (0,)))
+        .ok()?;
+    let mut frames = Vec::with_capacity(depth);
+    while frames.len() < depth && !frame.is_none() {
+        let code = frame.getattr(intern!(py, "f_code")).ok()?;
+        let function = code
+            .getattr(intern!(py, "co_qualname"))
+            .or_else(|_| code.getattr(intern!(py, "co_name")))
+            .and_then(|name| name.extract::<String>())
+            .ok()?;
+        frames.push(json!({
+            "file": code.getattr(intern!(py, "co_filename")).and_then(|file| file.extract::<String>()).ok()?,
+            "line": frame.getattr(intern!(py, "f_lineno")).and_then(|line| line.extract::<Option<u32>>()).ok()?,
+            "function": function,
+        }));
+        frame = frame.getattr(intern!(py, "f_back")).ok()?;
+    }
+    (!frames.is_empty()).then(|| frames.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::{prelude::*, types::PyModule};
+    use tracing::info_span;
+
+    use crate::tests::initialize_tracing_with;
+
+    #[pyfunction]
+    fn rust_work() {
+        let _span = info_span!("rust work").entered();
+    }
+
+    #[test]
+    fn test_python_stack() {
+        let (py_layer, _dispatcher) = initialize_tracing_with(|bridge| bridge.with_python_stack(2));
+
+        let _outside_python = info_span!("outside python").entered();
+        Python::with_gil(|py| {
+            let module = PyModule::from_code_bound(
+                py,
+                "
+def handler(rust_work):
+    return inner(rust_work)
+
+def inner(rust_work):
+    rust_work()
+",
+                "views.py",
+                "views",
+            )
+            .unwrap();
+            let rust_work = wrap_pyfunction!(rust_work, &module).unwrap();
+            module
+                .getattr("handler")
+                .unwrap()
+                .call1((rust_work,))
+                .unwrap();
+        });
+
+        Python::with_gil(|py| {
+            let borrowed = py_layer.borrow(py);
+            assert!(borrowed.new_spans[0].get("python_stack").is_none());
+            assert_eq!(
+                serde_json::json!([
+                    {"file": "views.py", "line": 6, "function": "inner"},
+                    {"file": "views.py", "line": 3, "function": "handler"},
+                ]),
+                borrowed.new_spans[1]["python_stack"]
+            );
+        });
+    }
+}
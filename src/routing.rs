@@ -0,0 +1,114 @@
+//! Routing of spans and events to different Python layers by target, set with
+//! [`crate::PythonCallbackLayerBridge::with_route`].
+
+use std::sync::{Arc, RwLock};
+
+use pyo3::prelude::*;
+
+use crate::Callbacks;
+
+/// A Python layer receiving the spans and events of the targets under `prefix`.
+pub(crate) struct Route {
+    prefix: String,
+    pub(crate) callbacks: Arc<RwLock<Arc<Callbacks>>>,
+}
+
+impl Route {
+    pub(crate) fn new(prefix: String, py_impl: Bound<'_, PyAny>) -> Route {
+        let callbacks = Callbacks::resolve(&py_impl);
+        Route {
+            prefix,
+            callbacks: Arc::new(RwLock::new(Arc::new(callbacks))),
+        }
+    }
+
+    /// Whether `target` is the route's prefix or a module under it.
+    fn matches(&self, target: &str) -> bool {
+        target
+            .strip_prefix(&*self.prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+    }
+}
+
+/// The route a span was sent to, stored in its extensions: the index of one of
+/// the bridge's routes, or `None` for its own Python layer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct RouteIndex(pub(crate) Option<usize>);
+
+/// Returns the index of the route with the longest prefix matching `target`.
+pub(crate) fn find(routes: &[Route], target: &str) -> Option<usize> {
+    routes
+        .iter()
+        .enumerate()
+        .filter(|(_, route)| route.matches(target))
+        .max_by_key(|(_, route)| route.prefix.len())
+        .map(|(index, _)| index)
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{info, info_span};
+    use tracing_subscriber::{prelude::*, Registry};
+
+    use crate::{tests::prepare_python, PythonCallbackLayerBridge};
+
+    use super::*;
+
+    #[test]
+    fn test_routes() {
+        prepare_python();
+        let (bridge, default, db, pool) = Python::with_gil(|py| {
+            let module = PyModule::from_code_bound(
+                py,
+                "
+import json
+
+class TargetLayer:
+    def __init__(self, name):
+        self.name = name
+        self.events = []
+
+    def on_new_span(self, span_attrs, span_id):
+        return self.name
+
+    def on_event(self, event, state):
+        self.events.append((json.loads(event)['metadata']['target'], state))
+",
+                "target_layer.py",
+                "target_layer",
+            )
+            .unwrap();
+            let layer = module.getattr("TargetLayer").unwrap();
+            let default = layer.call1(("default",)).unwrap();
+            let db = layer.call1(("db",)).unwrap();
+            let pool = layer.call1(("pool",)).unwrap();
+            let bridge = PythonCallbackLayerBridge::new(default.clone())
+                .with_route("my_crate::db", db.clone())
+                .with_route("my_crate::db::pool", pool.clone());
+            (bridge, default.unbind(), db.unbind(), pool.unbind())
+        });
+        let _guard = tracing::subscriber::set_default(Registry::default().with(bridge));
+
+        info_span!(target: "my_crate::db", "query").in_scope(|| {
+            info!(target: "my_crate::db::query", "planned");
+            info!(target: "my_crate::dbx", "elsewhere");
+        });
+        info!(target: "my_crate::db::pool", "acquired");
+
+        Python::with_gil(|py| {
+            let events = |layer: &Py<PyAny>| {
+                layer
+                    .getattr(py, "events")
+                    .unwrap()
+                    .extract::<Vec<(String, Option<String>)>>(py)
+                    .unwrap()
+            };
+            assert_eq!(
+                vec![("my_crate::db::query".to_owned(), Some("db".to_owned()))],
+                events(&db)
+            );
+            assert_eq!(vec![("my_crate::dbx".to_owned(), None)], events(&default));
+            assert_eq!(vec![("my_crate::db::pool".to_owned(), None)], events(&pool));
+        });
+    }
+}
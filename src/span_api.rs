@@ -0,0 +1,597 @@
+//! Python-callable functions that operate on live Rust spans.
+//!
+//! These are plain `#[pyfunction]`s; native extensions expose them to Python
+//! with [`crate::add_functions`] or individually with `wrap_pyfunction!`.
+//! Called from the Python layer's callbacks, they act on the subscriber of the
+//! bridge calling them.
+
+use std::iter;
+
+use pyo3::{
+    exceptions::{PyKeyError, PyLookupError, PyValueError},
+    prelude::*,
+    pybacked::PyBackedStr,
+    types::PyDict,
+};
+use tracing_core::{
+    field::{FieldSet, Value},
+    span, Dispatch, Event, Level, Metadata,
+};
+use tracing_subscriber::{registry::LookupSpan, Registry};
+
+use crate::{callback_dispatch, callsite, fields::PyFieldValue};
+
+fn parse_span_id(span_id: &str) -> PyResult<u64> {
+    match span_id.trim().parse::<u64>() {
+        Ok(0) => Err(PyValueError::new_err("span id must be non-zero")),
+        Ok(raw) => Ok(raw),
+        Err(_) => Err(PyValueError::new_err(
+            "span id must be an int, a numeric string or a callback's span id",
+        )),
+    }
+}
+
+/// Accepts a span id as an `int` or numeric `str` registry id, like the `id` of
+/// [`current_span_info`], or as the JSON-serialized `str` form passed to Python
+/// callbacks, e.g. `"[5]"`.
+///
+/// The callback form is resolved through the current bridge, since bridges in
+/// deterministic mode or with unique span ids number spans themselves, and
+/// raises `LookupError` if there's no bridge to resolve it.
+pub(crate) fn extract_span_id(span_id: &Bound<'_, PyAny>) -> PyResult<span::Id> {
+    let raw = match span_id.extract::<u64>() {
+        Ok(raw) => raw,
+        Err(_) => {
+            let text = span_id.extract::<PyBackedStr>()?;
+            let text = text.trim();
+            if let Some(payload_id) = text.strip_prefix('[').and_then(|id| id.strip_suffix(']')) {
+                let payload_id = parse_span_id(payload_id)?;
+                return crate::with_current_bridge(|bridge| bridge.registry_span_id(payload_id))
+                    .flatten()
+                    .ok_or_else(|| PyLookupError::new_err(format!("no live span with id {text}")));
+            }
+            parse_span_id(text)?
+        }
+    };
+
+    if raw == 0 {
+        return Err(PyValueError::new_err("span id must be non-zero"));
+    }
+    Ok(span::Id::from_u64(raw))
+}
+
+/// Looks up the metadata for a live span in `dispatch`'s [`Registry`].
+pub(crate) fn span_metadata(
+    dispatch: &Dispatch,
+    span_id: &span::Id,
+) -> PyResult<&'static Metadata<'static>> {
+    let registry = dispatch
+        .downcast_ref::<Registry>()
+        .ok_or_else(|| PyLookupError::new_err("the current subscriber has no span registry"))?;
+    registry
+        .span_data(span_id)
+        .map(|data| tracing_subscriber::registry::SpanData::metadata(&data))
+        .ok_or_else(|| {
+            PyLookupError::new_err(format!("no live span with id {}", span_id.into_u64()))
+        })
+}
+
+fn record_field(
+    dispatch: &Dispatch,
+    span_id: &span::Id,
+    fields: &FieldSet,
+    key: &str,
+    value: &Bound<'_, PyAny>,
+) -> PyResult<()> {
+    let field = fields
+        .field(key)
+        .ok_or_else(|| PyKeyError::new_err(format!("span has no field named {key:?}")))?;
+    let value = PyFieldValue::extract(value)?;
+    let values = [(&field, Some(value.as_value()))];
+    let value_set = fields.value_set(&values);
+
+    dispatch.record(span_id, &span::Record::new(&value_set));
+    Ok(())
+}
+
+/// Records `value` as the field `key` on the current contextual Rust span.
+///
+/// Like `Span::record`, the field must have been declared when the span was
+/// created (e.g. `#[instrument(fields(key))]`). Raises `LookupError` if there is
+/// no current span and `KeyError` if the span has no such field.
+#[pyfunction]
+pub fn record_current(key: &str, value: &Bound<'_, PyAny>) -> PyResult<()> {
+    let dispatch = callback_dispatch::current();
+    let current = dispatch.current_span();
+    let (Some(span_id), Some(metadata)) = (current.id(), current.metadata()) else {
+        return Err(PyLookupError::new_err("there is no current span"));
+    };
+
+    record_field(&dispatch, span_id, metadata.fields(), key, value)
+}
+
+/// Records `value` as the field `key` on the live Rust span with id `span_id`.
+///
+/// `span_id` may be an `int` registry id or the `str` id passed to the Python
+/// layer's callbacks, e.g. `"[5]"`. Raises `LookupError` if the span isn't live
+/// and `KeyError` if the span has no such field.
+#[pyfunction]
+pub fn record(span_id: &Bound<'_, PyAny>, key: &str, value: &Bound<'_, PyAny>) -> PyResult<()> {
+    let span_id = extract_span_id(span_id)?;
+
+    let dispatch = callback_dispatch::current();
+    let metadata = span_metadata(&dispatch, &span_id)?;
+    record_field(&dispatch, &span_id, metadata.fields(), key, value)
+}
+
+/// Emits an event at `level` whose parent is the live Rust span with id
+/// `span_id`, regardless of which span is current.
+///
+/// The event's target is `"python"` and its fields are `message` followed by
+/// `fields`. `span_id` may be an `int` registry id or the `str` id passed to
+/// the Python layer's callbacks, e.g. `"[5]"`. Raises `ValueError` for an
+/// unknown level and `LookupError` if the span isn't live.
+#[pyfunction]
+#[pyo3(signature = (span_id, level, message, **fields))]
+pub fn event_in_span(
+    span_id: &Bound<'_, PyAny>,
+    level: &str,
+    message: &str,
+    fields: Option<&Bound<'_, PyDict>>,
+) -> PyResult<()> {
+    let span_id = extract_span_id(span_id)?;
+    let level = level
+        .parse::<Level>()
+        .map_err(|_| PyValueError::new_err(format!("unknown level {level:?}")))?;
+
+    let mut names = Vec::new();
+    let mut values = Vec::new();
+    for (name, value) in fields.into_iter().flat_map(|fields| fields.iter()) {
+        names.push(name.extract::<String>()?);
+        values.push(PyFieldValue::extract(&value)?);
+    }
+
+    let metadata = callsite::event_metadata(level, &names);
+
+    let dispatch = callback_dispatch::current();
+    span_metadata(&dispatch, &span_id)?;
+
+    let Some(metadata) = metadata else {
+        return Ok(());
+    };
+    if !dispatch.enabled(metadata) {
+        return Ok(());
+    }
+
+    let values = iter::once(Some(&message as &dyn Value))
+        .chain(values.iter().map(|value| Some(value.as_value())))
+        .collect::<Vec<_>>();
+    let value_set = metadata.fields().value_set_all(&values);
+    dispatch.event(&Event::new_child_of(span_id, metadata, &value_set));
+    Ok(())
+}
+
+/// Links the live Rust span with id `span_id` to the span with id
+/// `follows_id`, like `Span::follows_from`.
+///
+/// Links record that a span was caused by another span that isn't its parent,
+/// such as each of the messages a batch consumer processes. Both ids may be an
+/// `int` registry id or the `str` id passed to the Python layer's callbacks,
+/// e.g. `"[5]"`. Raises `LookupError` if `span_id` isn't live.
+#[pyfunction]
+pub fn add_link(span_id: &Bound<'_, PyAny>, follows_id: &Bound<'_, PyAny>) -> PyResult<()> {
+    let span_id = extract_span_id(span_id)?;
+    let follows_id = extract_span_id(follows_id)?;
+
+    let dispatch = callback_dispatch::current();
+    span_metadata(&dispatch, &span_id)?;
+    dispatch.record_follows_from(&span_id, &follows_id);
+    Ok(())
+}
+
+/// Returns a `dict` describing the current contextual Rust span, or `None` if
+/// there isn't one.
+///
+/// The `dict` has the span's `id`, `name`, `target`, `level` and the names of
+/// its declared `fields`.
+#[pyfunction]
+pub fn current_span_info(py: Python<'_>) -> PyResult<Option<Bound<'_, PyDict>>> {
+    let current = callback_dispatch::current().current_span();
+    let (Some(span_id), Some(metadata)) = (current.id(), current.metadata()) else {
+        return Ok(None);
+    };
+
+    let info = PyDict::new_bound(py);
+    info.set_item("id", span_id.into_u64())?;
+    info.set_item("name", metadata.name())?;
+    info.set_item("target", metadata.target())?;
+    info.set_item("level", metadata.level().as_str())?;
+    info.set_item(
+        "fields",
+        metadata
+            .fields()
+            .iter()
+            .map(|field| field.name())
+            .collect::<Vec<_>>(),
+    )?;
+    Ok(Some(info))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tracing::{info_span, warn_span};
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::{
+        tests::{initialize_tracing, prepare_python},
+        PythonCallbackLayerBridge,
+    };
+
+    /// A Python layer keeping the span ids its callbacks were passed, by span
+    /// name, and using the names as the spans' states.
+    const ID_LAYER: &str = "
+import json
+
+span_ids = {}
+calls = []
+
+class IdLayer:
+    def on_new_span(self, span_attrs, span_id):
+        name = json.loads(span_attrs)['metadata']['name']
+        span_ids[name] = span_id
+        return name
+
+    def on_event(self, event, state):
+        calls.append(('event', json.loads(event)['message'], state))
+
+    def on_follows_from(self, span_id, follows_id, state):
+        calls.append(('link', span_id, follows_id))
+
+    def on_record(self, span_id, values, state):
+        calls.append(('record', span_id, json.loads(values)))
+";
+
+    /// Installs a bridge with an `IdLayer` and returns its module.
+    fn initialize_id_layer(
+        configure: impl FnOnce(PythonCallbackLayerBridge) -> PythonCallbackLayerBridge,
+    ) -> (Py<PyModule>, tracing::dispatcher::DefaultGuard) {
+        prepare_python();
+        let (module, bridge) = Python::with_gil(|py| {
+            let module =
+                PyModule::from_code_bound(py, ID_LAYER, "id_layer.py", "id_layer").unwrap();
+            let layer = module.getattr("IdLayer").unwrap().call0().unwrap();
+            (
+                module.unbind(),
+                configure(PythonCallbackLayerBridge::new(layer)),
+            )
+        });
+        let dispatcher = tracing_subscriber::registry().with(bridge).set_default();
+        (module, dispatcher)
+    }
+
+    fn callback_span_id<'py>(module: &Bound<'py, PyModule>, name: &str) -> Bound<'py, PyAny> {
+        module.getattr("span_ids").unwrap().get_item(name).unwrap()
+    }
+
+    #[test]
+    fn test_record_from_python() {
+        let (py_layer, _dispatcher) = initialize_tracing();
+
+        let span = info_span!("outer", user_id = tracing::field::Empty, size = 0);
+        let span_id = span.id().unwrap().into_u64();
+        span.in_scope(|| {
+            Python::with_gil(|py| {
+                record_current("user_id", &42_i64.into_py(py).into_bound(py)).unwrap();
+                record(
+                    &span_id.to_string().into_py(py).into_bound(py),
+                    "size",
+                    &1.5_f64.into_py(py).into_bound(py),
+                )
+                .unwrap();
+                assert!(record_current("missing", &true.into_py(py).into_bound(py))
+                    .unwrap_err()
+                    .is_instance_of::<PyKeyError>(py));
+            });
+        });
+        drop(span);
+
+        Python::with_gil(|py| {
+            assert!(record(
+                &0_u64.into_py(py).into_bound(py),
+                "size",
+                &1_i64.into_py(py).into_bound(py)
+            )
+            .unwrap_err()
+            .is_instance_of::<PyValueError>(py));
+            assert!(record(
+                &span_id.into_py(py).into_bound(py),
+                "size",
+                &1_i64.into_py(py).into_bound(py)
+            )
+            .unwrap_err()
+            .is_instance_of::<PyLookupError>(py));
+
+            let borrowed = py_layer.borrow(py);
+            let expected_records = vec![(json!({"user_id": 42}), 0), (json!({"size": 1.5}), 0)];
+            assert_eq!(&expected_records, &borrowed.span_records);
+        });
+    }
+
+    #[test]
+    fn test_record_onto_callback_span_id() {
+        for unique_span_ids in [false, true] {
+            let (module, _dispatcher) =
+                initialize_id_layer(|bridge| bridge.with_unique_span_ids(unique_span_ids));
+
+            // With unique span ids, the bridge numbers the second span 2.
+            drop(info_span!("first"));
+            let span = info_span!("request", size = tracing::field::Empty);
+            let expected_id = match unique_span_ids {
+                true => "[2]".to_owned(),
+                false => json!([span.id().unwrap().into_u64()]).to_string(),
+            };
+            Python::with_gil(|py| {
+                let module = module.bind(py);
+                let span_id = callback_span_id(module, "request");
+                assert_eq!(expected_id, span_id.extract::<String>().unwrap());
+
+                record(&span_id, "size", &3_i64.into_py(py).into_bound(py)).unwrap();
+                let (_, recorded_id, values): (String, String, Bound<'_, PyAny>) = module
+                    .getattr("calls")
+                    .unwrap()
+                    .get_item(0)
+                    .unwrap()
+                    .extract()
+                    .unwrap();
+                assert_eq!(expected_id, recorded_id);
+                assert_eq!(
+                    3,
+                    values.get_item("size").unwrap().extract::<i64>().unwrap()
+                );
+            });
+
+            drop(span);
+            Python::with_gil(|py| {
+                let span_id = callback_span_id(module.bind(py), "request");
+                assert!(record(&span_id, "size", &1_i64.into_py(py).into_bound(py))
+                    .unwrap_err()
+                    .is_instance_of::<PyLookupError>(py));
+            });
+        }
+
+        // Without a bridge, a callback's span id can't be told apart from a
+        // registry id.
+        let _dispatcher = tracing_subscriber::registry().set_default();
+        let span = info_span!("request", size = tracing::field::Empty);
+        Python::with_gil(|py| {
+            let span_id = format!("[{}]", span.id().unwrap().into_u64());
+            assert!(extract_span_id(&span_id.into_py(py).into_bound(py))
+                .unwrap_err()
+                .is_instance_of::<PyLookupError>(py));
+        });
+    }
+
+    #[test]
+    fn test_event_in_span() {
+        let (py_layer, _dispatcher) = initialize_tracing();
+
+        let outer = info_span!("outer");
+        let outer_id = outer.id().unwrap().into_u64();
+        outer.in_scope(|| {
+            warn_span!("inner").in_scope(|| {
+                Python::with_gil(|py| {
+                    let fields = PyDict::new_bound(py);
+                    fields.set_item("rows", 3).unwrap();
+                    fields.set_item("cached", false).unwrap();
+                    event_in_span(
+                        &outer_id.into_py(py).into_bound(py),
+                        "warn",
+                        "slow query",
+                        Some(&fields),
+                    )
+                    .unwrap();
+
+                    assert!(event_in_span(
+                        &outer_id.into_py(py).into_bound(py),
+                        "loud",
+                        "bad level",
+                        None
+                    )
+                    .unwrap_err()
+                    .is_instance_of::<PyValueError>(py));
+                });
+            });
+        });
+
+        Python::with_gil(|py| {
+            let borrowed = py_layer.borrow(py);
+            let expected_events = vec![("slow query".to_owned(), "WARN".to_owned(), 0)];
+            let expected_fields = vec![json!({"rows": 3, "cached": false})];
+            assert_eq!(&expected_events, &borrowed.events);
+            assert_eq!(&expected_fields, &borrowed.event_fields);
+        });
+    }
+
+    #[test]
+    fn test_event_in_callback_span() {
+        let (module, _dispatcher) = initialize_id_layer(|bridge| bridge.with_unique_span_ids(true));
+
+        let request = info_span!("request");
+        warn_span!("other").in_scope(|| {
+            Python::with_gil(|py| {
+                let span_id = callback_span_id(module.bind(py), "request");
+                event_in_span(&span_id, "info", "handled", None).unwrap();
+            });
+        });
+        drop(request);
+
+        Python::with_gil(|py| {
+            let calls = module.bind(py).getattr("calls").unwrap();
+            let (kind, message, state): (String, String, String) =
+                calls.get_item(0).unwrap().extract().unwrap();
+            assert_eq!(("event", "handled"), (&*kind, &*message));
+            // The event's parent is the span it was emitted in, not the
+            // current one.
+            assert_eq!("request", state);
+        });
+    }
+
+    #[test]
+    fn test_span_api_from_callbacks() {
+        prepare_python();
+        let (module, bridge) = Python::with_gil(|py| {
+            let module = PyModule::from_code_bound(
+                py,
+                "
+import json
+
+span_ids = {}
+calls = []
+
+class CallingLayer:
+    def on_new_span(self, span_attrs, span_id):
+        name = json.loads(span_attrs)['metadata']['name']
+        span_ids[name] = span_id
+        if name == 'request':
+            record(span_id, 'user', 'alice')
+        else:
+            add_link(span_id, span_ids['request'])
+        return name
+
+    def on_follows_from(self, span_id, follows_id, state):
+        calls.append(('link', span_id, follows_id))
+
+    def on_record(self, span_id, values, state):
+        calls.append(('record', span_id, json.loads(values)))
+
+    def on_event(self, event, state):
+        message = json.loads(event)['message']
+        calls.append(('event', message, state))
+        if message == 'handling':
+            record_current('status', 200)
+            event_in_span(span_ids['request'], 'info', 'from callback')
+",
+                "calling_layer.py",
+                "calling_layer",
+            )
+            .unwrap();
+            for function in [
+                pyo3::wrap_pyfunction!(record, &module).unwrap(),
+                pyo3::wrap_pyfunction!(record_current, &module).unwrap(),
+                pyo3::wrap_pyfunction!(event_in_span, &module).unwrap(),
+                pyo3::wrap_pyfunction!(add_link, &module).unwrap(),
+            ] {
+                module.add_function(function).unwrap();
+            }
+            let layer = module.getattr("CallingLayer").unwrap().call0().unwrap();
+            (
+                module.unbind(),
+                PythonCallbackLayerBridge::new(layer).with_unique_span_ids(true),
+            )
+        });
+        let _dispatcher = tracing_subscriber::registry().with(bridge).set_default();
+
+        let request = info_span!(
+            "request",
+            user = tracing::field::Empty,
+            status = tracing::field::Empty
+        );
+        request.in_scope(|| {
+            tracing::info!("handling");
+            drop(info_span!("handler"));
+        });
+        drop(request);
+
+        Python::with_gil(|py| {
+            let calls = module.bind(py).getattr("calls").unwrap();
+            assert_eq!(
+                "[('record', '[1]', {'user': 'alice'}), \
+                 ('event', 'handling', 'request'), \
+                 ('record', '[1]', {'status': 200}), \
+                 ('event', 'from callback', 'request'), \
+                 ('link', '[2]', '[1]')]",
+                calls.to_string()
+            );
+        });
+    }
+
+    #[test]
+    fn test_current_span_info() {
+        let (_py_layer, _dispatcher) = initialize_tracing();
+
+        Python::with_gil(|py| {
+            assert!(current_span_info(py).unwrap().is_none());
+
+            let span = warn_span!("lookup", key = "k", hit = tracing::field::Empty);
+            span.in_scope(|| {
+                let info = current_span_info(py).unwrap().unwrap();
+                let get = |key: &str| info.get_item(key).unwrap().unwrap();
+
+                assert_eq!(
+                    span.id().unwrap().into_u64(),
+                    get("id").extract::<u64>().unwrap()
+                );
+                assert_eq!("lookup", get("name").extract::<String>().unwrap());
+                assert_eq!(module_path!(), get("target").extract::<String>().unwrap());
+                assert_eq!("WARN", get("level").extract::<String>().unwrap());
+                assert_eq!(
+                    vec!["key", "hit"],
+                    get("fields").extract::<Vec<String>>().unwrap()
+                );
+            });
+        });
+    }
+
+    #[test]
+    fn test_links() {
+        let (py_layer, _dispatcher) = initialize_tracing();
+
+        let producer = info_span!("producer");
+        let consumer = info_span!("consumer");
+        consumer.follows_from(&producer);
+        let (producer_id, consumer_id) = (
+            producer.id().unwrap().into_u64(),
+            consumer.id().unwrap().into_u64(),
+        );
+
+        Python::with_gil(|py| {
+            let other = info_span!("other producer");
+            let other_id = other.id().unwrap().into_u64();
+            add_link(
+                &consumer_id.to_string().to_object(py).into_bound(py),
+                &other_id.to_object(py).into_bound(py),
+            )
+            .unwrap();
+
+            assert_eq!(
+                vec![(consumer_id, producer_id, 1), (consumer_id, other_id, 1)],
+                py_layer.borrow(py).links
+            );
+        });
+    }
+
+    #[test]
+    fn test_link_callback_spans() {
+        let (module, _dispatcher) = initialize_id_layer(|bridge| bridge.with_unique_span_ids(true));
+
+        let producer = info_span!("producer");
+        let consumer = info_span!("consumer");
+        Python::with_gil(|py| {
+            let module = module.bind(py);
+            add_link(
+                &callback_span_id(module, "consumer"),
+                &callback_span_id(module, "producer"),
+            )
+            .unwrap();
+
+            let calls = module.getattr("calls").unwrap();
+            assert_eq!(
+                ("link".to_owned(), "[2]".to_owned(), "[1]".to_owned()),
+                calls.get_item(0).unwrap().extract().unwrap()
+            );
+        });
+        drop((producer, consumer));
+    }
+}
@@ -0,0 +1,137 @@
+//! Carrying the span context a unit of work was started in to the Rust threads
+//! it continues on, so their spans stay part of the same request.
+
+use pyo3::prelude::*;
+use tracing::Span;
+use tracing_core::{dispatcher, Dispatch};
+use tracing_subscriber::{registry::LookupSpan, Registry};
+
+use crate::{context::CarriedContext, PythonLayerState};
+
+/// The current span, subscriber and Python context, captured with
+/// [`SpanContext::current`] to be moved to another thread and re-entered there
+/// with [`SpanContext::in_scope`].
+///
+/// Threads don't inherit the span a request is in, nor a subscriber set with
+/// [`dispatcher::set_default`], so spans created by work spawned onto them are
+/// otherwise unrelated root spans to the Python layer. Within
+/// [`SpanContext::in_scope`], new spans are children of the captured span, so
+/// they carry its baggage and their events the Python state of the span they
+/// are in. Root spans pick up the parent context and baggage that were set in
+/// the Python context which captured it.
+pub struct SpanContext {
+    dispatch: Dispatch,
+    span: Span,
+    python: CarriedContext,
+}
+
+impl SpanContext {
+    /// Captures the current span and subscriber, and the parent context and
+    /// baggage set in the calling Python context.
+    pub fn current() -> SpanContext {
+        SpanContext {
+            dispatch: dispatcher::get_default(Dispatch::clone),
+            span: Span::current(),
+            python: Python::with_gil(CarriedContext::current),
+        }
+    }
+
+    /// The captured span, which is disabled if no span was current.
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    /// Returns the state the Python layer's `on_new_span` returned for the
+    /// captured span, if it has one.
+    pub fn state(&self, py: Python<'_>) -> Option<Py<PyAny>> {
+        let id = self.span.id()?;
+        let registry = self.dispatch.downcast_ref::<Registry>()?;
+        let span = registry.span(&id)?;
+        let extensions = span.extensions();
+        Some(extensions.get::<PythonLayerState>()?.clone_ref(py))
+    }
+
+    /// Runs `f` with the captured subscriber as the default and the captured
+    /// span entered, carrying the captured Python context to spans created on
+    /// this thread while it runs.
+    pub fn in_scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        let python = Python::with_gil(|py| self.python.clone_ref(py));
+        dispatcher::with_default(&self.dispatch, || {
+            let _carried = python.carry();
+            self.span.in_scope(f)
+        })
+    }
+}
+
+impl Clone for SpanContext {
+    fn clone(&self) -> SpanContext {
+        SpanContext {
+            dispatch: self.dispatch.clone(),
+            span: self.span.clone(),
+            python: Python::with_gil(|py| self.python.clone_ref(py)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use pyo3::{prelude::*, types::PyString};
+    use tracing::{info, info_span, Span};
+    use tracing_subscriber::{registry::LookupSpan, Registry};
+
+    use super::SpanContext;
+    use crate::{
+        context::{reset_parent_context, set_parent_context},
+        tests::initialize_tracing,
+    };
+
+    #[test]
+    fn test_span_context_on_other_thread() {
+        let (py_layer, _dispatcher) = initialize_tracing();
+
+        let request = info_span!("request");
+        let context = request.in_scope(SpanContext::current);
+        let rooted = Python::with_gil(|py| {
+            let token =
+                set_parent_context(py, PyString::new_bound(py, "request-1").as_any()).unwrap();
+            let rooted = SpanContext::current();
+            reset_parent_context(py, &token).unwrap();
+            rooted
+        });
+
+        let worker_parent = thread::spawn(move || {
+            rooted.in_scope(|| info_span!("rooted worker").in_scope(|| {}));
+            context.in_scope(|| {
+                let worker = info_span!("worker");
+                worker.in_scope(|| info!("working"));
+                let parent = tracing::dispatcher::get_default(|dispatch| {
+                    let registry = dispatch.downcast_ref::<Registry>()?;
+                    let span = registry.span(&worker.id()?)?;
+                    span.parent().map(|parent| parent.id())
+                });
+                (parent, Span::current().id())
+            })
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!((request.id(), request.id()), worker_parent);
+        Python::with_gil(|py| {
+            let context = request.in_scope(SpanContext::current);
+            let state: u16 = context.state(py).unwrap().extract(py).unwrap();
+            assert_eq!(0, state);
+
+            let borrowed = py_layer.borrow(py);
+            assert_eq!(
+                vec![None, Some("request-1".to_owned()), None],
+                borrowed.parent_contexts
+            );
+            assert_eq!(
+                vec![("working".to_owned(), "INFO".to_owned(), 2)],
+                borrowed.events
+            );
+        });
+    }
+}
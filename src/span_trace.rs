@@ -0,0 +1,107 @@
+//! Captures of the active Rust spans for Python, using `tracing-error`.
+
+use pyo3::{
+    prelude::*,
+    types::{PyDict, PyList},
+};
+use tracing_error::SpanTrace;
+
+/// The Rust spans that were active when [`capture_span_trace`] was called.
+///
+/// Its `str()` is formatted like `tracing-error`'s span traces, e.g. to add to
+/// a Python exception with `exc.add_note(str(trace))`.
+#[pyclass(frozen, module = "pyo3_python_tracing_subscriber")]
+pub struct RustSpanTrace(SpanTrace);
+
+#[pymethods]
+impl RustSpanTrace {
+    /// The active spans, innermost first, as `dict`s with their `name`,
+    /// `target`, `level`, formatted `fields`, and `file` and `line` if known.
+    #[getter]
+    fn frames<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        let frames = PyList::empty_bound(py);
+        let mut result = Ok(());
+        self.0.with_spans(|metadata, fields| {
+            result = (|| {
+                let frame = PyDict::new_bound(py);
+                frame.set_item("name", metadata.name())?;
+                frame.set_item("target", metadata.target())?;
+                frame.set_item("level", metadata.level().as_str())?;
+                frame.set_item("fields", fields)?;
+                frame.set_item("file", metadata.file())?;
+                frame.set_item("line", metadata.line())?;
+                frames.append(frame)
+            })();
+            result.is_ok()
+        });
+        result.map(|_| frames)
+    }
+
+    fn __len__(&self) -> usize {
+        let mut len = 0;
+        self.0.with_spans(|_, _| {
+            len += 1;
+            true
+        });
+        len
+    }
+
+    fn __str__(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<RustSpanTrace with {} spans>", self.__len__())
+    }
+}
+
+/// Captures the Rust spans active on the current thread, e.g. to attach to a
+/// Python exception raised from a callback into Python.
+///
+/// Requires a subscriber with [`tracing_error::ErrorLayer`], which the
+/// `initialize_*` functions include. Otherwise the trace is empty.
+#[pyfunction]
+pub fn capture_span_trace() -> RustSpanTrace {
+    RustSpanTrace(SpanTrace::capture())
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{info_span, warn_span};
+    use tracing_error::ErrorLayer;
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::tests::prepare_python;
+
+    #[test]
+    fn test_capture_span_trace() {
+        prepare_python();
+        let _dispatcher = tracing_subscriber::registry()
+            .with(ErrorLayer::default())
+            .set_default();
+
+        let trace = info_span!("request", user = "alice")
+            .in_scope(|| warn_span!("query", rows = 3).in_scope(capture_span_trace));
+
+        Python::with_gil(|py| {
+            let frames = trace.frames(py).unwrap();
+            assert_eq!(2, trace.__len__());
+            let item = |index: usize, key: &str| {
+                frames
+                    .get_item(index)
+                    .unwrap()
+                    .get_item(key)
+                    .unwrap()
+                    .to_string()
+            };
+            assert_eq!("query", item(0, "name"));
+            assert_eq!("WARN", item(0, "level"));
+            assert_eq!("rows=3", item(0, "fields"));
+            assert_eq!("request", item(1, "name"));
+            assert_eq!(module_path!(), item(1, "target"));
+            assert_eq!("user=\"alice\"", item(1, "fields"));
+            assert!(trace.__str__().contains("query"));
+        });
+    }
+}
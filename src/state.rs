@@ -0,0 +1,162 @@
+//! The state the Python layer returns for a span, readable by other layers.
+
+use pyo3::prelude::*;
+
+/// The object the Python layer's `on_new_span` returned for a span, stored in
+/// the span's [`tracing_subscriber::registry::Extensions`] so other layers in
+/// the same registry can read it, e.g. to tag spans with ids minted by the
+/// Python side.
+///
+/// It stays there until the registry frees the span, after every layer's
+/// `on_close`, so it can be read while the span closes, including by events
+/// emitted from the Python layer's `on_close`.
+///
+/// Spans sent to a Python layer set with
+/// [`crate::PythonCallbackLayerBridge::with_route`] store the state that
+/// layer returned.
+#[derive(Debug)]
+pub struct PythonLayerState(Py<PyAny>);
+
+impl PythonLayerState {
+    pub(crate) fn new(state: Py<PyAny>) -> PythonLayerState {
+        PythonLayerState(state)
+    }
+
+    pub fn get(&self) -> &Py<PyAny> {
+        &self.0
+    }
+
+    pub fn bind<'py>(&self, py: Python<'py>) -> &Bound<'py, PyAny> {
+        self.0.bind(py)
+    }
+
+    pub fn clone_ref(&self, py: Python<'_>) -> Py<PyAny> {
+        self.0.clone_ref(py)
+    }
+
+    pub fn into_inner(self) -> Py<PyAny> {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tracing::info_span;
+    use tracing_core::{span, Subscriber};
+    use tracing_subscriber::{layer::Context, prelude::*, registry::LookupSpan, Layer};
+
+    use super::*;
+    use crate::{
+        tests::{prepare_python, PythonLayer},
+        PythonCallbackLayerBridge,
+    };
+
+    /// A Rust layer recording the Python state of the spans it enters and
+    /// closes.
+    struct StateReader(Arc<Mutex<Vec<u16>>>);
+
+    impl StateReader {
+        fn record<S>(&self, id: &span::Id, ctx: Context<'_, S>)
+        where
+            S: Subscriber + for<'a> LookupSpan<'a>,
+        {
+            let span = ctx.span(id).unwrap();
+            let extensions = span.extensions();
+            let state = extensions.get::<PythonLayerState>().unwrap();
+            let state = Python::with_gil(|py| state.bind(py).extract().unwrap());
+            self.0.lock().unwrap().push(state);
+        }
+    }
+
+    impl<S> Layer<S> for StateReader
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+            self.record(id, ctx);
+        }
+
+        fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+            self.record(&id, ctx);
+        }
+    }
+
+    #[test]
+    fn test_python_layer_state() {
+        prepare_python();
+        let bridge = Python::with_gil(|py| {
+            PythonCallbackLayerBridge::new(Bound::new(py, PythonLayer::new()).unwrap().into_any())
+        });
+        let states = Arc::new(Mutex::new(Vec::new()));
+        let _dispatcher = tracing_subscriber::registry()
+            .with(bridge)
+            .with(StateReader(states.clone()))
+            .set_default();
+
+        info_span!("outer").in_scope(|| info_span!("inner").in_scope(|| {}));
+
+        assert_eq!(vec![0, 1, 1, 0], *states.lock().unwrap());
+    }
+
+    #[test]
+    fn test_state_during_and_after_close() {
+        prepare_python();
+        let (module, bridge) = Python::with_gil(|py| {
+            let module = PyModule::from_code_bound(
+                py,
+                "
+import json
+import weakref
+
+states = []
+seen = []
+
+class State:
+    def __init__(self, name):
+        self.name = name
+
+class ClosingLayer:
+    def on_new_span(self, span_attrs, span_id):
+        state = State(json.loads(span_attrs)['metadata']['name'])
+        states.append(weakref.ref(state))
+        return state
+
+    def on_event(self, event, state):
+        seen.append((json.loads(event)['message'], state.name))
+
+    def on_close(self, span_id, state):
+        event_in_span(span_id, 'info', 'closing')
+",
+                "closing_layer.py",
+                "closing_layer",
+            )
+            .unwrap();
+            module
+                .add_function(pyo3::wrap_pyfunction!(crate::event_in_span, &module).unwrap())
+                .unwrap();
+            let layer = module.getattr("ClosingLayer").unwrap().call0().unwrap();
+            (module.unbind(), PythonCallbackLayerBridge::new(layer))
+        });
+        let _dispatcher = tracing_subscriber::registry().with(bridge).set_default();
+
+        drop(info_span!("request"));
+
+        Python::with_gil(|py| {
+            let module = module.bind(py);
+            assert_eq!(
+                vec![("closing".to_owned(), "request".to_owned())],
+                module
+                    .getattr("seen")
+                    .unwrap()
+                    .extract::<Vec<(String, String)>>()
+                    .unwrap()
+            );
+            // The registry freed the span once `on_close` returned, dropping
+            // the last reference to its state.
+            let state = module.getattr("states").unwrap().get_item(0).unwrap();
+            assert!(state.call0().unwrap().is_none());
+        });
+    }
+}
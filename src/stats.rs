@@ -0,0 +1,371 @@
+//! Counters the bridge keeps about its own operation, for Python to inspect.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+use pyo3::{prelude::*, types::PyDict};
+
+use crate::{backpressure::Pressure, with_current_bridge};
+
+/// The last exception a method of the Python layer raised.
+#[derive(Clone)]
+struct LastError {
+    callback: &'static str,
+    exc_type: String,
+    message: String,
+}
+
+/// How many of the most recent calls [`Latency::p99`] is computed over.
+const RECENT_CALLS: usize = 1024;
+
+/// How long calls to a method of the Python layer took.
+struct Latency {
+    count: u64,
+    total: Duration,
+    min: Duration,
+    max: Duration,
+    recent: VecDeque<Duration>,
+}
+
+impl Latency {
+    fn new() -> Latency {
+        Latency {
+            count: 0,
+            total: Duration::ZERO,
+            min: Duration::MAX,
+            max: Duration::ZERO,
+            recent: VecDeque::with_capacity(RECENT_CALLS),
+        }
+    }
+
+    fn record(&mut self, elapsed: Duration) {
+        self.count += 1;
+        self.total += elapsed;
+        self.min = self.min.min(elapsed);
+        self.max = self.max.max(elapsed);
+        if self.recent.len() == RECENT_CALLS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(elapsed);
+    }
+
+    /// The 99th percentile of the most recent calls.
+    fn p99(&self) -> Duration {
+        let mut recent = Vec::from(self.recent.clone());
+        recent.sort_unstable();
+        let index = (recent.len() * 99).div_ceil(100).saturating_sub(1);
+        recent.get(index).copied().unwrap_or_default()
+    }
+
+    fn to_py_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let latency = PyDict::new_bound(py);
+        latency.set_item("count", self.count)?;
+        latency.set_item("min", self.min.as_secs_f64())?;
+        latency.set_item("mean", self.total.as_secs_f64() / self.count as f64)?;
+        latency.set_item("p99", self.p99().as_secs_f64())?;
+        latency.set_item("max", self.max.as_secs_f64())?;
+        Ok(latency)
+    }
+}
+
+#[derive(Default)]
+struct ErrorStats {
+    counts: BTreeMap<&'static str, u64>,
+    last: Option<LastError>,
+}
+
+/// The counters of a [`crate::PythonCallbackLayerBridge`].
+#[derive(Default)]
+pub(crate) struct BridgeStats {
+    errors: Mutex<ErrorStats>,
+    panics: Mutex<BTreeMap<&'static str, u64>>,
+    lookup_failures: Mutex<BTreeMap<&'static str, u64>>,
+    slow_calls: Mutex<BTreeMap<&'static str, u64>>,
+    latencies: Mutex<BTreeMap<&'static str, Latency>>,
+    pressure: Pressure,
+}
+
+impl BridgeStats {
+    /// Counts an exception raised by the Python layer's `callback` method.
+    pub(crate) fn record_error(&self, py: Python<'_>, callback: &'static str, err: &PyErr) {
+        let exc_type = err
+            .get_type_bound(py)
+            .getattr("__qualname__")
+            .and_then(|name| name.extract())
+            .unwrap_or_default();
+        let message = err
+            .value_bound(py)
+            .str()
+            .map(|message| message.to_string())
+            .unwrap_or_default();
+
+        let mut errors = self.errors.lock().unwrap_or_else(PoisonError::into_inner);
+        *errors.counts.entry(callback).or_default() += 1;
+        errors.last = Some(LastError {
+            callback,
+            exc_type,
+            message,
+        });
+    }
+
+    /// Counts a panic caught while forwarding to the Python layer's `callback`
+    /// method.
+    pub(crate) fn record_panic(&self, callback: &'static str) {
+        let mut panics = self.panics.lock().unwrap_or_else(PoisonError::into_inner);
+        *panics.entry(callback).or_default() += 1;
+    }
+
+    /// Counts a span the subscriber couldn't look up while the bridge forwarded
+    /// `callback` for it.
+    pub(crate) fn record_lookup_failure(&self, callback: &'static str) {
+        let mut lookup_failures = self
+            .lookup_failures
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        *lookup_failures.entry(callback).or_default() += 1;
+    }
+
+    /// Records that a call to the Python layer's `callback` method took
+    /// `elapsed`.
+    pub(crate) fn record_latency(&self, callback: &'static str, elapsed: Duration) {
+        self.latencies
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(callback)
+            .or_insert_with(Latency::new)
+            .record(elapsed);
+    }
+
+    /// Counts a call to the Python layer's `callback` method that was over the
+    /// slow callback threshold.
+    pub(crate) fn record_slow_call(&self, callback: &'static str) {
+        let mut slow_calls = self
+            .slow_calls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        *slow_calls.entry(callback).or_default() += 1;
+    }
+
+    /// Returns the backpressure the Python layer signaled.
+    pub(crate) fn pressure(&self) -> &Pressure {
+        &self.pressure
+    }
+
+    /// Returns the `callback`, exception `type` name and `message` of the last
+    /// exception the Python layer raised, if any.
+    pub(crate) fn last_error<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyDict>>> {
+        let errors = self.errors.lock().unwrap_or_else(PoisonError::into_inner);
+        errors
+            .last
+            .as_ref()
+            .map(|last| {
+                let last_error = PyDict::new_bound(py);
+                last_error.set_item("callback", last.callback)?;
+                last_error.set_item("type", &last.exc_type)?;
+                last_error.set_item("message", &last.message)?;
+                Ok(last_error)
+            })
+            .transpose()
+    }
+
+    pub(crate) fn to_py_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let stats = PyDict::new_bound(py);
+        let errors = self.errors.lock().unwrap_or_else(PoisonError::into_inner);
+        stats.set_item("errors", errors.counts.clone())?;
+        drop(errors);
+        stats.set_item("last_error", self.last_error(py)?)?;
+        let panics = self.panics.lock().unwrap_or_else(PoisonError::into_inner);
+        stats.set_item("panics", panics.clone())?;
+        drop(panics);
+        let lookup_failures = self
+            .lookup_failures
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        stats.set_item("lookup_failures", lookup_failures.clone())?;
+        let slow_calls = self
+            .slow_calls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        stats.set_item("slow_calls", slow_calls.clone())?;
+        let latency = PyDict::new_bound(py);
+        let latencies = self
+            .latencies
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        for (callback, callback_latency) in latencies.iter() {
+            latency.set_item(callback, callback_latency.to_py_dict(py)?)?;
+        }
+        stats.set_item("latency", latency)?;
+        self.pressure.insert_stats(&stats)?;
+        Ok(stats)
+    }
+}
+
+/// Returns the counters of the [`crate::PythonCallbackLayerBridge`] in the
+/// current subscriber as a `dict`, or `None` if it has none.
+///
+/// - `errors` maps the names of the Python layer's methods to how many
+///   exceptions they raised.
+/// - `last_error` is the `callback`, exception `type` name and `message` of the
+///   last exception, or `None`.
+/// - `panics` maps the names of the Python layer's methods to how many panics
+///   were caught and dropped while forwarding data to them.
+/// - `lookup_failures` maps them to how many spans the subscriber couldn't look
+///   up when forwarding data to them, which were forwarded without state.
+/// - `slow_calls` maps them to how many of their calls took longer than the
+///   threshold set with
+///   [`crate::PythonCallbackLayerBridge::with_slow_callback_threshold`].
+/// - `latency` maps them to the `count` of their calls and the `min`, `mean`,
+///   `p99` and `max` seconds those took. The 99th percentile is of the last
+///   1024 calls.
+/// - `backpressure` is whether the Python layer asked the bridge to slow down
+///   by returning `Backpressure.SLOW_DOWN`, and `backpressure_dropped` how many
+///   events the bridge dropped while it was.
+#[pyfunction]
+pub fn get_bridge_stats(py: Python<'_>) -> PyResult<Option<Bound<'_, PyDict>>> {
+    with_current_bridge(|bridge| bridge.stats(py)).transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use pyo3::exceptions::PyRuntimeWarning;
+    use tracing::{info, info_span};
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::{tests::prepare_python, PythonCallbackLayerBridge};
+
+    #[test]
+    fn test_error_counts() {
+        prepare_python();
+        let bridge = Python::with_gil(|py| {
+            let module = PyModule::from_code_bound(
+                py,
+                "
+class FailingLayer:
+    def on_new_span(self, span_attrs, span_id):
+        return 1
+
+    def on_event(self, event, state):
+        raise KeyError('missing')
+",
+                "failing_layer.py",
+                "failing_layer",
+            )
+            .unwrap();
+            let py_layer = module.getattr("FailingLayer").unwrap().call0().unwrap();
+            PythonCallbackLayerBridge::new(py_layer)
+        });
+        let _dispatcher = tracing_subscriber::registry().with(bridge).set_default();
+
+        info_span!("failing").in_scope(|| {
+            info!("first");
+            info!("second");
+        });
+
+        Python::with_gil(|py| {
+            let stats = get_bridge_stats(py).unwrap().unwrap();
+            let errors = stats
+                .get_item("errors")
+                .unwrap()
+                .unwrap()
+                .extract::<BTreeMap<String, u64>>()
+                .unwrap();
+            assert_eq!(BTreeMap::from([("on_event".to_owned(), 2)]), errors);
+            let last_error = stats
+                .get_item("last_error")
+                .unwrap()
+                .unwrap()
+                .extract::<BTreeMap<String, String>>()
+                .unwrap();
+            assert_eq!("on_event", last_error["callback"]);
+            assert_eq!("KeyError", last_error["type"]);
+            assert_eq!("'missing'", last_error["message"]);
+        });
+    }
+
+    #[test]
+    fn test_slow_calls() {
+        prepare_python();
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let bridge = Python::with_gil(|py| {
+            let module = PyModule::from_code_bound(
+                py,
+                "
+import time
+
+class SlowLayer:
+    def on_new_span(self, span_attrs, span_id):
+        return 1
+
+    def on_event(self, event, state):
+        time.sleep(0.02)
+",
+                "slow_layer.py",
+                "slow_layer",
+            )
+            .unwrap();
+            let py_layer = module.getattr("SlowLayer").unwrap().call0().unwrap();
+            let warnings = warnings.clone();
+            PythonCallbackLayerBridge::new(py_layer)
+                .with_slow_callback_threshold(Duration::from_millis(10))
+                .with_error_hook(move |py, callback, err| {
+                    assert!(err.is_instance_of::<PyRuntimeWarning>(py));
+                    warnings.lock().unwrap().push(callback.to_owned());
+                })
+        });
+        let _dispatcher = tracing_subscriber::registry().with(bridge).set_default();
+
+        info_span!("slow").in_scope(|| info!("exported"));
+
+        assert_eq!(vec!["on_event"], *warnings.lock().unwrap());
+        Python::with_gil(|py| {
+            let stats = get_bridge_stats(py).unwrap().unwrap();
+            let slow_calls = stats
+                .get_item("slow_calls")
+                .unwrap()
+                .unwrap()
+                .extract::<BTreeMap<String, u64>>()
+                .unwrap();
+            assert_eq!(BTreeMap::from([("on_event".to_owned(), 1)]), slow_calls);
+        });
+    }
+
+    #[test]
+    fn test_latency() {
+        let mut latency = Latency::new();
+        for millis in 1..=200 {
+            latency.record(Duration::from_millis(millis));
+        }
+        assert_eq!(Duration::from_millis(198), latency.p99());
+
+        let stats = BridgeStats::default();
+        stats.record_latency("on_event", Duration::from_millis(10));
+        stats.record_latency("on_event", Duration::from_millis(30));
+        prepare_python();
+        Python::with_gil(|py| {
+            let stats = stats.to_py_dict(py).unwrap();
+            let on_event = stats
+                .get_item("latency")
+                .unwrap()
+                .unwrap()
+                .get_item("on_event")
+                .unwrap()
+                .extract::<BTreeMap<String, f64>>()
+                .unwrap();
+            assert_eq!(2.0, on_event["count"]);
+            assert_eq!(0.01, on_event["min"]);
+            assert_eq!(0.02, on_event["mean"]);
+            assert_eq!(0.03, on_event["p99"]);
+            assert_eq!(0.03, on_event["max"]);
+        });
+    }
+}
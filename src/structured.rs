@@ -0,0 +1,168 @@
+//! Fields recorded with `valuable`, serialized as nested JSON rather than
+//! their `Debug` output.
+//!
+//! `tracing` only passes `valuable` values to layers when built with
+//! `RUSTFLAGS="--cfg tracing_unstable"`, so this module is only compiled then,
+//! with the `valuable` feature.
+
+use serde_json::{Map, Value as Json};
+use tracing_core::field::{Field, Visit as FieldVisit};
+use tracing_subscriber::field::RecordFields;
+use valuable::{Fields, NamedValues, Valuable, Value, Visit};
+
+use crate::log_bridge::LOG_FIELDS;
+
+/// Converts `value` to JSON: structs and maps as objects, lists and tuples as
+/// arrays, and enum variants with fields as objects with the variant's name
+/// as their only key, like `serde` does.
+pub(crate) fn to_json(value: Value<'_>) -> Json {
+    match value {
+        Value::Bool(value) => value.into(),
+        Value::Char(value) => value.to_string().into(),
+        Value::F32(value) => value.into(),
+        Value::F64(value) => value.into(),
+        Value::I8(value) => value.into(),
+        Value::I16(value) => value.into(),
+        Value::I32(value) => value.into(),
+        Value::I64(value) => value.into(),
+        Value::Isize(value) => value.into(),
+        Value::U8(value) => value.into(),
+        Value::U16(value) => value.into(),
+        Value::U32(value) => value.into(),
+        Value::U64(value) => value.into(),
+        Value::Usize(value) => value.into(),
+        Value::I128(value) => {
+            i64::try_from(value).map_or_else(|_| value.to_string().into(), Json::from)
+        }
+        Value::U128(value) => {
+            u64::try_from(value).map_or_else(|_| value.to_string().into(), Json::from)
+        }
+        Value::String(value) => value.into(),
+        Value::Path(value) => value.display().to_string().into(),
+        Value::Error(value) => value.to_string().into(),
+        Value::Unit => Json::Null,
+        Value::Listable(listable) => Json::Array(collect(listable).values),
+        Value::Tuplable(tuplable) => Json::Array(collect(tuplable).values),
+        Value::Mappable(mappable) => Json::Object(collect(mappable).object),
+        Value::Structable(structable) => {
+            let collected = collect(structable);
+            match structable.definition().fields() {
+                Fields::Named(_) => Json::Object(collected.object),
+                Fields::Unnamed(_) => Json::Array(collected.values),
+            }
+        }
+        Value::Enumerable(enumerable) => {
+            let variant = enumerable.variant();
+            let collected = collect(enumerable);
+            let fields = match variant.fields() {
+                Fields::Named(_) => Json::Object(collected.object),
+                Fields::Unnamed(0) => return variant.name().into(),
+                Fields::Unnamed(1) => collected.values.into_iter().next().unwrap_or_default(),
+                Fields::Unnamed(_) => Json::Array(collected.values),
+            };
+            Json::Object(Map::from_iter([(variant.name().to_owned(), fields)]))
+        }
+        value => format!("{value:?}").into(),
+    }
+}
+
+/// Collects the values and named fields or entries a [`Valuable`] visits.
+#[derive(Default)]
+struct Collected {
+    values: Vec<Json>,
+    object: Map<String, Json>,
+}
+
+fn collect(value: &(impl Valuable + ?Sized)) -> Collected {
+    let mut collected = Collected::default();
+    value.visit(&mut collected);
+    collected
+}
+
+impl Visit for Collected {
+    fn visit_value(&mut self, value: Value<'_>) {
+        self.values.push(to_json(value));
+    }
+
+    fn visit_named_fields(&mut self, named_values: &NamedValues<'_>) {
+        for (field, value) in named_values {
+            self.object.insert(field.name().to_owned(), to_json(*value));
+        }
+    }
+
+    fn visit_unnamed_fields(&mut self, values: &[Value<'_>]) {
+        self.values
+            .extend(values.iter().map(|value| to_json(*value)));
+    }
+
+    fn visit_entry(&mut self, key: Value<'_>, value: Value<'_>) {
+        let key = match key {
+            Value::String(key) => key.to_owned(),
+            key => to_json(key).to_string(),
+        };
+        self.object.insert(key, to_json(value));
+    }
+}
+
+/// A [`FieldVisit`] that only collects the fields recorded with `valuable`.
+#[derive(Default)]
+struct StructuredFields(Vec<(&'static str, Json)>);
+
+impl FieldVisit for StructuredFields {
+    fn record_value(&mut self, field: &Field, value: Value<'_>) {
+        self.0.push((field.name(), to_json(value)));
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+}
+
+/// Returns the fields of `fields` recorded with `valuable`, as JSON.
+pub(crate) fn structured_fields(fields: &impl RecordFields) -> Vec<(&'static str, Json)> {
+    let mut structured = StructuredFields::default();
+    fields.record(&mut structured);
+    structured.0.retain(|(name, _)| !LOG_FIELDS.contains(name));
+    structured.0
+}
+
+/// Replaces the `Debug` strings that `tracing-serde` serializes fields
+/// recorded with `valuable` as in `payload` with their nested structure.
+pub(crate) fn insert_structured_fields(payload: &mut Json, fields: &impl RecordFields) {
+    for (name, value) in structured_fields(fields) {
+        payload[name] = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use pyo3::prelude::*;
+    use serde_json::json;
+    use tracing::{field, info, info_span};
+
+    use crate::{tests::initialize_tracing_with, PayloadMode};
+
+    #[test]
+    fn test_valuable_fields() {
+        let rows = BTreeMap::from([("alice", vec![1, 2]), ("bob", vec![])]);
+        for payload_mode in [PayloadMode::Json, PayloadMode::StructlogEventDict] {
+            let (py_layer, _dispatcher) =
+                initialize_tracing_with(|bridge| bridge.with_payload_mode(payload_mode));
+
+            info_span!("scope").in_scope(|| {
+                info!(
+                    rows = field::valuable(&rows),
+                    pair = field::valuable(&(1, "a")),
+                    "query"
+                )
+            });
+
+            Python::with_gil(|py| {
+                let borrowed = py_layer.borrow(py);
+                let fields = &borrowed.event_fields[0];
+                assert_eq!(json!({"alice": [1, 2], "bob": []}), fields["rows"]);
+                assert_eq!(json!([1, "a"]), fields["pair"]);
+            });
+        }
+    }
+}
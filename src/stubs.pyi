@@ -0,0 +1,291 @@
+# Type stubs for the functions and classes `add_functions` adds to a native
+# extension module, and for the methods the bridge calls on a Python layer.
+#
+# Written by `write_type_stubs(path)`. Functions and classes marked with a
+# feature are only added when the extension is built with it.
+
+import os
+from contextvars import Token
+from types import TracebackType
+from typing import Any, Iterator, Mapping, Optional, Protocol, Union
+
+# A span id, as an `int` or a numeric `str`.
+SpanId = Union[str, int]
+
+class PayloadBuffer:
+    """An event payload in `PayloadMode::Buffer`, read with `memoryview`."""
+
+    def __len__(self) -> int: ...
+    def __bytes__(self) -> bytes: ...
+    def __buffer__(self, flags: int, /) -> memoryview: ...
+
+# What `on_event` receives as its first argument, depending on the bridge's
+# `PayloadMode`: a JSON `str` (`Json`), a `dict` (`Dict`, `StructlogEventDict`),
+# a `PayloadBuffer` (`Buffer`), or gzip-compressed `bytes` with
+# `encoding="gzip"` over the compression threshold.
+EventPayload = Union[str, bytes, dict[str, Any], PayloadBuffer]
+
+class Backpressure:
+    """What callbacks return to ask the bridge to slow down, or to clear it."""
+
+    SLOW_DOWN: Backpressure
+    CLEAR: Backpressure
+
+class Layer(Protocol):
+    """The methods the bridge calls on a Python layer, if it has them.
+
+    Every method is optional. Subclass this to have a type checker check a
+    layer's method signatures.
+    """
+
+    def on_attach(self, bridge_id: str) -> None: ...
+    def on_new_span(
+        self, span_attrs: str, span_id: str, parent_context: Any = None
+    ) -> Any:
+        """Returns the span's state, passed back to the other methods."""
+    def on_event(
+        self, event: EventPayload, state: Any, encoding: Optional[str] = None
+    ) -> Optional[Backpressure]: ...
+    def on_trace_event(
+        self, event: EventPayload, state: Any, encoding: Optional[str] = None
+    ) -> Optional[Backpressure]: ...
+    def on_debug_event(
+        self, event: EventPayload, state: Any, encoding: Optional[str] = None
+    ) -> Optional[Backpressure]: ...
+    def on_info_event(
+        self, event: EventPayload, state: Any, encoding: Optional[str] = None
+    ) -> Optional[Backpressure]: ...
+    def on_warn_event(
+        self, event: EventPayload, state: Any, encoding: Optional[str] = None
+    ) -> Optional[Backpressure]: ...
+    def on_error_event(
+        self, event: EventPayload, state: Any, encoding: Optional[str] = None
+    ) -> Optional[Backpressure]: ...
+    def on_orphan_event(self, event: EventPayload) -> Optional[Backpressure]: ...
+    def on_record(self, span_id: str, values: str, state: Any) -> None: ...
+    def on_follows_from(self, span_id: str, follows_id: str, state: Any) -> None: ...
+    def on_close(
+        self, span_id: str, state: Any, overhead: Optional[float] = None
+    ) -> None: ...
+    def on_bridge_error(self, exc: BaseException, context: str) -> None: ...
+    def on_tick(self, stats: dict[str, Any]) -> Optional[Backpressure]: ...
+    def on_metrics(self, metrics: list[dict[str, Any]]) -> None: ...
+    def on_subtree(self, span_id: str, tree: dict[str, Any], rendered: str) -> None: ...
+    def flush(self) -> None: ...
+    def shutdown(self) -> None: ...
+
+class FanoutLayer:
+    """A layer forwarding each call to several Python layers, in order."""
+
+    def __init__(self, layers: list[Any]) -> None: ...
+    @property
+    def layers(self) -> list[Any]: ...
+    def on_event(self, *args: Any, **kwargs: Any) -> Optional[Backpressure]: ...
+    def on_new_span(self, *args: Any, **kwargs: Any) -> tuple[Any, ...]: ...
+    def on_close(self, *args: Any, **kwargs: Any) -> None: ...
+    def on_record(self, *args: Any, **kwargs: Any) -> None: ...
+    def on_follows_from(self, *args: Any, **kwargs: Any) -> None: ...
+    def on_bridge_error(self, *args: Any, **kwargs: Any) -> None: ...
+    def on_tick(self, *args: Any, **kwargs: Any) -> Optional[Backpressure]: ...
+    def on_metrics(self, *args: Any, **kwargs: Any) -> None: ...
+    def on_subtree(self, *args: Any, **kwargs: Any) -> None: ...
+    def flush(self, *args: Any, **kwargs: Any) -> None: ...
+    def shutdown(self, *args: Any, **kwargs: Any) -> None: ...
+
+class CapturingLayer:
+    """A layer recording spans and events as `dict`s, for tests."""
+
+    spans: list[dict[str, Any]]
+    events: list[dict[str, Any]]
+    def __init__(self) -> None: ...
+    def on_new_span(
+        self, span_attrs: str, _span_id: str, _parent_context: Any = None
+    ) -> dict[str, Any]: ...
+    def on_record(
+        self, _span_id: str, values: str, state: Optional[dict[str, Any]]
+    ) -> None: ...
+    def on_event(self, event: str, state: Optional[dict[str, Any]]) -> None: ...
+    def on_close(self, _span_id: str, state: Optional[dict[str, Any]]) -> None: ...
+    def events_matching(
+        self,
+        level: Optional[str] = None,
+        target: Optional[str] = None,
+        message: Optional[str] = None,
+        name: Optional[str] = None,
+    ) -> list[dict[str, Any]]: ...
+    def spans_matching(
+        self,
+        name: Optional[str] = None,
+        target: Optional[str] = None,
+        level: Optional[str] = None,
+    ) -> list[dict[str, Any]]: ...
+    def clear(self) -> None: ...
+
+class CapturedRustTracing:
+    """A context manager capturing Rust tracing on the current thread."""
+
+    def __enter__(self) -> CapturingLayer: ...
+    def __exit__(
+        self,
+        exc_type: Optional[type[BaseException]],
+        exc_value: Optional[BaseException],
+        traceback: Optional[TracebackType],
+    ) -> bool: ...
+
+class RecordReader:
+    """An iterator over the records of a stream, as `dict`s."""
+
+    def __iter__(self) -> Iterator[dict[str, Any]]: ...
+    def __next__(self) -> dict[str, Any]: ...
+
+def initialize_tracing(
+    py_impl: Any,
+    filter: Optional[str] = None,
+    jsonl_path: Optional[Union[str, os.PathLike[str]]] = None,
+    config_path: Optional[Union[str, os.PathLike[str]]] = None,
+    config: Optional[dict[str, Any]] = None,
+    routes: Optional[dict[str, Any]] = None,
+) -> None: ...
+def initialize_logging(
+    filter: Optional[str] = None,
+    levels: Optional[Mapping[str, int]] = None,
+    logger_names: Optional[Mapping[str, str]] = None,
+) -> None: ...
+def initialize_opentelemetry(
+    instrumentation_name: str, filter: Optional[str] = None
+) -> None: ...
+def initialize_sentry(filter: Optional[str] = None) -> None: ...
+def initialize_structlog(filter: Optional[str] = None) -> None: ...
+def initialize_loguru(
+    filter: Optional[str] = None, levels: Optional[Mapping[str, str]] = None
+) -> None: ...
+def initialize_datadog(
+    service: Optional[str] = None, filter: Optional[str] = None
+) -> None: ...
+def initialize_stats(filter: Optional[str] = None) -> None: ...
+def init_with_fmt(
+    py_impl: Any,
+    fmt_options: Optional[dict[str, Any]] = None,
+    filter: Optional[str] = None,
+) -> None: ...
+def set_level(filter: str) -> None: ...
+def pause() -> bool: ...
+def resume() -> bool: ...
+def mark_thread_untraced(untraced: bool = True) -> None: ...
+def current_bridge_id() -> Optional[str]: ...
+def current_session_id() -> Optional[str]: ...
+def bridge_names() -> list[str]: ...
+def flush(name: str) -> None: ...
+def set_filter(name: str, filter: Optional[str]) -> None: ...
+def rebuild_interest_cache() -> None: ...
+def start_chrome_trace(
+    path: Union[str, os.PathLike[str]], viztracer: bool = False
+) -> None: ...
+def stop_chrome_trace() -> None: ...
+def enable_console_tracing(level: str = "debug", ansi: bool = True) -> None: ...
+def disable_console_tracing() -> None: ...
+def start_flamegraph() -> None: ...
+def stop_flamegraph() -> None: ...
+def dump_flamegraph(path: Union[str, os.PathLike[str]]) -> None: ...
+def start_flight_recorder(capacity: int = 1000) -> None: ...
+def stop_flight_recorder() -> None: ...
+def dump_recent(clear: bool = False) -> list[dict[str, Any]]: ...
+def get_tracing_stats() -> dict[str, Any]: ...
+def stop_stream() -> None: ...
+def record_current(key: str, value: Any) -> None: ...
+def record(span_id: SpanId, key: str, value: Any) -> None: ...
+def event_in_span(span_id: SpanId, level: str, message: str, **fields: Any) -> None: ...
+def add_link(span_id: SpanId, follows_id: SpanId) -> None: ...
+def set_parent_context(context: Any) -> Token[Any]: ...
+def reset_parent_context(token: Token[Any]) -> None: ...
+def set_traceparent(traceparent: str) -> Token[Any]: ...
+def current_traceparent() -> Optional[str]: ...
+def set_baggage(baggage: Union[Mapping[str, str], str]) -> Token[Any]: ...
+def reset_baggage(token: Token[Any]) -> None: ...
+def current_baggage() -> dict[str, str]: ...
+def current_span_state() -> Any: ...
+def current_span_info() -> Optional[dict[str, Any]]: ...
+def get_bridge_stats() -> Optional[dict[str, Any]]: ...
+def get_metrics() -> Optional[list[dict[str, Any]]]: ...
+def prometheus_metrics() -> Optional[str]: ...
+def list_callsites() -> Optional[list[dict[str, Any]]]: ...
+def span_lifecycle_audit() -> Optional[dict[str, Any]]: ...
+def set_callsite_enabled(callsite_id: int, enabled: bool) -> bool: ...
+def health_check() -> dict[str, Any]: ...
+def dump_active_spans() -> Optional[list[dict[str, Any]]]: ...
+def generate_synthetic_trace(
+    spans: int = 100,
+    events_per_span: int = 10,
+    depth: int = 3,
+    fields: int = 4,
+    threads: int = 1,
+) -> float: ...
+def benchmark_bridge(iterations: int = 1000, payload_size: int = 64) -> dict[str, float]: ...
+def captured_rust_tracing(filter: str = "trace") -> CapturedRustTracing: ...
+def write_type_stubs(path: Union[str, os.PathLike[str]]) -> None: ...
+
+# Unix only.
+def start_socket_stream(
+    path: Union[str, os.PathLike[str]], framing: str = "lines"
+) -> None: ...
+def listen_socket_stream(
+    path: Union[str, os.PathLike[str]], framing: str = "lines"
+) -> RecordReader: ...
+def start_fd_stream(fd: int, framing: str = "length_prefixed") -> None: ...
+def create_stream_pipe(framing: str = "length_prefixed") -> tuple[RecordReader, int]: ...
+
+# The `appender` and `otlp` features.
+class TracingGuard:
+    """Flushes and stops a background writer when closed or exited."""
+
+    def close(self) -> None: ...
+    def __enter__(self) -> TracingGuard: ...
+    def __exit__(
+        self,
+        exc_type: Optional[type[BaseException]],
+        exc_value: Optional[BaseException],
+        traceback: Optional[TracebackType],
+    ) -> None: ...
+
+# The `appender` feature.
+def initialize_tracing_with_file(
+    py_impl: Any,
+    directory: Union[str, os.PathLike[str]],
+    prefix: str,
+    rotation: str = "daily",
+    filter: Optional[str] = None,
+) -> TracingGuard: ...
+
+# The `otlp` feature.
+def initialize_otlp(
+    service_name: str,
+    endpoint: Optional[str] = None,
+    headers: Optional[Mapping[str, str]] = None,
+    py_impl: Any = None,
+    filter: Optional[str] = None,
+) -> TracingGuard: ...
+
+# The `arrow` feature.
+class ArrowRecordBatch:
+    """A batch of collected records, exported through the Arrow PyCapsule
+    interface, e.g. to `pyarrow.record_batch(batch)`."""
+
+    def __len__(self) -> int: ...
+    def __arrow_c_schema__(self) -> object: ...
+    def __arrow_c_array__(
+        self, requested_schema: Optional[object] = None
+    ) -> tuple[object, object]: ...
+
+def start_arrow_collection() -> None: ...
+def stop_arrow_collection() -> None: ...
+def take_arrow_batch() -> ArrowRecordBatch: ...
+
+# The `span-trace` feature.
+class RustSpanTrace:
+    """The Rust spans active where it was captured."""
+
+    @property
+    def frames(self) -> list[dict[str, Any]]: ...
+    def __len__(self) -> int: ...
+
+def capture_span_trace() -> RustSpanTrace: ...
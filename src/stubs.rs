@@ -0,0 +1,53 @@
+//! Type stubs for the Python-facing functions and classes, and for the layer
+//! methods the bridge calls, so type checkers can check Python code using them.
+
+use std::{fs, path::PathBuf};
+
+use pyo3::prelude::*;
+
+/// The stubs, kept next to this module.
+pub(crate) const STUBS: &str = include_str!("stubs.pyi");
+
+/// Writes type stubs to `path`, e.g. the `.pyi` file next to a native
+/// extension module, for the functions and classes [`crate::add_functions`]
+/// adds to it and a `Layer` protocol with the methods the bridge calls.
+///
+/// Extensions that define functions of their own can append theirs to the
+/// file. Raises `OSError` if it can't be written.
+#[pyfunction]
+pub fn write_type_stubs(path: PathBuf) -> PyResult<()> {
+    fs::write(path, STUBS)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::{prelude::*, types::PyModule};
+
+    use super::STUBS;
+    use crate::{add_functions, tests::prepare_python};
+
+    #[test]
+    fn test_stubs_cover_module() {
+        prepare_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new_bound(py, "stubbed").unwrap();
+            add_functions(&module).unwrap();
+            let ast = py.import_bound("ast").unwrap();
+            let tree = ast.call_method1("parse", (STUBS,)).unwrap();
+            let stubbed: Vec<String> = tree
+                .getattr("body")
+                .unwrap()
+                .iter()
+                .unwrap()
+                .filter_map(|node| node.unwrap().getattr("name").ok()?.extract().ok())
+                .collect();
+            for name in module.dir() {
+                let name = name.extract::<String>().unwrap();
+                if !name.starts_with("__") {
+                    assert!(stubbed.contains(&name), "{name} has no stub");
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,294 @@
+//! The spans and events under each root span, collected while they're open and
+//! passed to the Python layer's `on_subtree` in one call when the root closes,
+//! for consumers that show people one summary per operation.
+
+use std::{
+    fmt::{self, Write},
+    time::{Duration, Instant},
+};
+
+use pyo3::prelude::*;
+use serde_json::{json, Map, Value};
+use tracing_core::{
+    field::{Field, Visit},
+    span, Event, Level,
+};
+
+use crate::payload;
+
+/// The message and other fields of a span or event, formatted with `Debug`
+/// like `tracing-tree` and the `fmt` layer show them.
+#[derive(Default)]
+struct FieldsText {
+    message: Option<String>,
+    fields: Vec<(&'static str, String)>,
+}
+
+impl Visit for FieldsText {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.message = Some(value.to_owned()),
+            name => self.fields.push((name, format!("{value:?}"))),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "message" => self.message = Some(format!("{value:?}")),
+            name => self.fields.push((name, format!("{value:?}"))),
+        }
+    }
+}
+
+fn write_fields(out: &mut String, fields: &[(&'static str, String)]) {
+    for (index, (name, value)) in fields.iter().enumerate() {
+        let separator = if index == 0 { "" } else { " " };
+        let _ = write!(out, "{separator}{name}={value}");
+    }
+}
+
+fn fields_json(fields: &[(&'static str, String)]) -> Value {
+    let fields: Map<String, Value> = fields
+        .iter()
+        .map(|(name, value)| ((*name).to_owned(), value.clone().into()))
+        .collect();
+    fields.into()
+}
+
+/// An event or a closed child span of a [`Node`].
+enum Entry {
+    Event {
+        level: Level,
+        message: String,
+        fields: Vec<(&'static str, String)>,
+    },
+    Span(Node),
+}
+
+/// A span with the events emitted in it and its closed children, stored in its
+/// extensions while it's open.
+pub(crate) struct Node {
+    name: &'static str,
+    target: &'static str,
+    level: Level,
+    fields: Vec<(&'static str, String)>,
+    started: Instant,
+    duration: Duration,
+    /// The events and children, with when they were emitted or created.
+    entries: Vec<(Instant, Entry)>,
+}
+
+impl Node {
+    pub(crate) fn new(attrs: &span::Attributes<'_>, now: Instant) -> Node {
+        let mut fields = FieldsText::default();
+        attrs.record(&mut fields);
+        let metadata = attrs.metadata();
+        Node {
+            name: metadata.name(),
+            target: metadata.target(),
+            level: *metadata.level(),
+            fields: fields.fields,
+            started: now,
+            duration: Duration::ZERO,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Adds the values recorded to the span after it was created.
+    pub(crate) fn record(&mut self, values: &span::Record<'_>) {
+        let mut fields = FieldsText::default();
+        values.record(&mut fields);
+        for (name, value) in fields.fields {
+            match self.fields.iter_mut().find(|(field, _)| *field == name) {
+                Some((_, previous)) => *previous = value,
+                None => self.fields.push((name, value)),
+            }
+        }
+    }
+
+    pub(crate) fn push_event(&mut self, event: &Event<'_>, now: Instant) {
+        let mut fields = FieldsText::default();
+        event.record(&mut fields);
+        self.entries.push((
+            now,
+            Entry::Event {
+                level: *event.metadata().level(),
+                message: fields.message.unwrap_or_default(),
+                fields: fields.fields,
+            },
+        ));
+    }
+
+    pub(crate) fn push_child(&mut self, child: Node) {
+        self.entries.push((child.started, Entry::Span(child)));
+    }
+
+    /// Records how long the span was open and puts its children in the order
+    /// they were created among its events.
+    pub(crate) fn close(&mut self, now: Instant) {
+        self.duration = now.saturating_duration_since(self.started);
+        self.entries.sort_by_key(|(at, _)| *at);
+    }
+
+    fn write_header(&self, out: &mut String) {
+        out.push_str(self.name);
+        if !self.fields.is_empty() {
+            out.push('{');
+            write_fields(out, &self.fields);
+            out.push('}');
+        }
+        let _ = write!(out, " [{:?}]", self.duration);
+    }
+
+    fn write_entries(&self, out: &mut String, prefix: &str) {
+        for (index, (_, entry)) in self.entries.iter().enumerate() {
+            let last = index + 1 == self.entries.len();
+            out.push_str(prefix);
+            out.push_str(if last { "└─ " } else { "├─ " });
+            match entry {
+                Entry::Event {
+                    level,
+                    message,
+                    fields,
+                } => {
+                    let _ = write!(out, "{level} {message}");
+                    if !fields.is_empty() {
+                        out.push(' ');
+                        write_fields(out, fields);
+                    }
+                    out.push('\n');
+                }
+                Entry::Span(child) => {
+                    child.write_header(out);
+                    out.push('\n');
+                    let indent = if last { "   " } else { "│  " };
+                    child.write_entries(out, &format!("{prefix}{indent}"));
+                }
+            }
+        }
+    }
+
+    /// Renders the subtree as text, one line per span and event, indented
+    /// under their parents like `tracing-tree` does.
+    pub(crate) fn render(&self) -> String {
+        let mut out = String::new();
+        self.write_header(&mut out);
+        out.push('\n');
+        self.write_entries(&mut out, "");
+        out
+    }
+
+    fn to_json(&self) -> Value {
+        let children: Vec<Value> = self
+            .entries
+            .iter()
+            .map(|(_, entry)| match entry {
+                Entry::Event {
+                    level,
+                    message,
+                    fields,
+                } => json!({
+                    "kind": "event",
+                    "level": level.as_str(),
+                    "message": message,
+                    "fields": fields_json(fields),
+                }),
+                Entry::Span(child) => child.to_json(),
+            })
+            .collect();
+        json!({
+            "kind": "span",
+            "name": self.name,
+            "target": self.target,
+            "level": self.level.as_str(),
+            "fields": fields_json(&self.fields),
+            "duration": self.duration.as_secs_f64(),
+            "children": children,
+        })
+    }
+
+    /// Returns the subtree as nested `dict`s: the span's `kind` (`"span"`),
+    /// `name`, `target`, `level`, `fields`, `duration` in seconds and
+    /// `children`, its events (of `kind` `"event"`, with their `level`,
+    /// `message` and `fields`) and child spans in the order they happened.
+    pub(crate) fn to_py(&self, py: Python<'_>) -> PyObject {
+        payload::json_to_py(py, &self.to_json())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pyo3::prelude::*;
+    use tracing::{debug, info, info_span, warn};
+    use tracing_subscriber::prelude::*;
+
+    use crate::{tests::prepare_python, MockClock, PythonCallbackLayerBridge};
+
+    #[test]
+    fn test_rendered_subtrees() {
+        prepare_python();
+        let clock = MockClock::new();
+        let (subtrees, bridge) = Python::with_gil(|py| {
+            let module = PyModule::from_code_bound(
+                py,
+                "
+subtrees = []
+
+class SummaryLayer:
+    def on_subtree(self, span_id, tree, rendered):
+        subtrees.append((tree, rendered))
+",
+                "summary_layer.py",
+                "summary_layer",
+            )
+            .unwrap();
+            let layer = module.getattr("SummaryLayer").unwrap().call0().unwrap();
+            (
+                module.getattr("subtrees").unwrap().unbind(),
+                PythonCallbackLayerBridge::new(layer)
+                    .with_clock(clock.clone())
+                    .with_rendered_subtrees(true),
+            )
+        });
+        let _dispatcher = tracing_subscriber::registry().with(bridge).set_default();
+
+        let request = info_span!("request", path = "/users", status = tracing::field::Empty);
+        request.in_scope(|| {
+            info!("handling");
+            info_span!("query", table = "users").in_scope(|| {
+                clock.advance(Duration::from_millis(3));
+                debug!(rows = 3, "fetched");
+            });
+            warn!(retries = 1, "slow");
+        });
+        request.record("status", 200);
+        clock.advance(Duration::from_millis(2));
+        drop(request);
+
+        Python::with_gil(|py| {
+            let subtrees = subtrees.bind(py);
+            assert_eq!(1, subtrees.len().unwrap());
+            let (tree, rendered): (Bound<'_, PyAny>, String) =
+                subtrees.get_item(0).unwrap().extract().unwrap();
+            assert_eq!(
+                "request{path=\"/users\" status=200} [5ms]\n\
+                 ├─ INFO handling\n\
+                 ├─ query{table=\"users\"} [3ms]\n\
+                 │  └─ DEBUG fetched rows=3\n\
+                 └─ WARN slow retries=1\n",
+                rendered
+            );
+            let query = tree.get_item("children").unwrap().get_item(1).unwrap();
+            assert_eq!("query", query.get_item("name").unwrap().to_string());
+            let fetched = query.get_item("children").unwrap().get_item(0).unwrap();
+            assert_eq!("event", fetched.get_item("kind").unwrap().to_string());
+            assert_eq!("fetched", fetched.get_item("message").unwrap().to_string());
+            assert_eq!(
+                "{'rows': '3'}",
+                fetched.get_item("fields").unwrap().to_string()
+            );
+        });
+    }
+}
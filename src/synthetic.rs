@@ -0,0 +1,155 @@
+//! A reproducible source of Rust spans and events, for testing the
+//! correctness and throughput of Python layers.
+
+use std::{thread, time::Instant};
+
+use pyo3::{exceptions::PyValueError, prelude::*};
+use tracing::{info, info_span};
+use tracing_core::dispatcher;
+
+/// The most fields [`generate_synthetic_trace`] can add to each span and
+/// event, since a callsite's fields are fixed at compile time.
+const MAX_FIELDS: usize = 8;
+
+/// The shape of the spans and events each thread emits.
+#[derive(Clone, Copy)]
+struct Workload {
+    spans: usize,
+    events_per_span: usize,
+    depth: usize,
+    fields: usize,
+}
+
+impl Workload {
+    fn field(&self, index: usize, value: u64) -> Option<u64> {
+        (index < self.fields).then_some(value)
+    }
+
+    fn run(&self, thread: usize) {
+        let mut emitted = 0;
+        while emitted < self.spans {
+            self.emit_chain(thread, 0, &mut emitted);
+        }
+    }
+
+    /// Emits a span at `level` with its events, inside which it emits the
+    /// rest of the chain down to `depth`.
+    fn emit_chain(&self, thread: usize, level: usize, emitted: &mut usize) {
+        let index = *emitted as u64;
+        *emitted += 1;
+        let f = |field| self.field(field, index * MAX_FIELDS as u64 + field as u64);
+        let span = info_span!(
+            target: "synthetic",
+            "synthetic_span",
+            thread,
+            nesting = level,
+            index,
+            field_0 = f(0),
+            field_1 = f(1),
+            field_2 = f(2),
+            field_3 = f(3),
+            field_4 = f(4),
+            field_5 = f(5),
+            field_6 = f(6),
+            field_7 = f(7),
+        );
+        let _entered = span.enter();
+        for event in 0..self.events_per_span {
+            let f = |field| self.field(field, event as u64 * MAX_FIELDS as u64 + field as u64);
+            info!(
+                target: "synthetic",
+                event,
+                field_0 = f(0),
+                field_1 = f(1),
+                field_2 = f(2),
+                field_3 = f(3),
+                field_4 = f(4),
+                field_5 = f(5),
+                field_6 = f(6),
+                field_7 = f(7),
+                "synthetic event"
+            );
+        }
+        if level + 1 < self.depth && *emitted < self.spans {
+            self.emit_chain(thread, level + 1, emitted);
+        }
+    }
+}
+
+/// Emits `spans` spans with `events_per_span` events each from each of
+/// `threads` Rust threads, and returns how many seconds it took.
+///
+/// Spans are nested in chains `depth` spans deep, and each span and event has
+/// `fields` integer fields (at most 8) besides its indices, all with target
+/// `synthetic`. The spans and events are the same on every call, so Python
+/// layer authors can test their layers against a reproducible load. The
+/// threads use the current subscriber, and the GIL is released while they
+/// run.
+#[pyfunction]
+#[pyo3(signature = (spans = 100, events_per_span = 10, depth = 3, fields = 4, threads = 1))]
+pub fn generate_synthetic_trace(
+    py: Python<'_>,
+    spans: usize,
+    events_per_span: usize,
+    depth: usize,
+    fields: usize,
+    threads: usize,
+) -> PyResult<f64> {
+    if fields > MAX_FIELDS {
+        return Err(PyValueError::new_err(format!(
+            "fields must be at most {MAX_FIELDS}"
+        )));
+    }
+    if depth == 0 || threads == 0 {
+        return Err(PyValueError::new_err("depth and threads must be positive"));
+    }
+    let workload = Workload {
+        spans,
+        events_per_span,
+        depth,
+        fields,
+    };
+    let dispatch = dispatcher::get_default(|dispatch| dispatch.clone());
+    Ok(py.allow_threads(|| {
+        let start = Instant::now();
+        thread::scope(|scope| {
+            for thread in 0..threads {
+                let dispatch = &dispatch;
+                scope.spawn(move || {
+                    dispatcher::with_default(dispatch, || workload.run(thread));
+                });
+            }
+        });
+        start.elapsed().as_secs_f64()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::initialize_tracing;
+
+    #[test]
+    fn test_generate_synthetic_trace() {
+        let (py_layer, _dispatcher) = initialize_tracing();
+
+        Python::with_gil(|py| {
+            generate_synthetic_trace(py, 5, 2, 3, 2, 2).unwrap();
+            assert!(generate_synthetic_trace(py, 1, 1, 1, MAX_FIELDS + 1, 1).is_err());
+
+            let borrowed = py_layer.borrow(py);
+            assert_eq!(10, borrowed.new_spans.len());
+            assert_eq!(20, borrowed.event_fields.len());
+            assert_eq!(10, borrowed.closed_spans.len());
+            let roots = borrowed
+                .new_spans
+                .iter()
+                .filter(|span| span["nesting"] == 0)
+                .count();
+            assert_eq!(4, roots);
+            let event = &borrowed.event_fields[0];
+            assert_eq!(1, event["field_1"]);
+            assert!(event.get("field_2").is_none());
+        });
+    }
+}
@@ -0,0 +1,166 @@
+//! A worker thread calling the Python layer's `on_tick` and `on_metrics`
+//! methods periodically.
+
+use std::{
+    sync::{Arc, Condvar, Mutex, PoisonError, RwLock},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use pyo3::{intern, prelude::*};
+
+use crate::{internal, metrics::Metrics, stats::BridgeStats, Callbacks};
+
+/// Whether the worker was asked to stop, and the condition it waits on until
+/// the next tick or that request.
+type StopSignal = (Mutex<bool>, Condvar);
+
+/// The worker thread of a bridge built with
+/// [`crate::PythonCallbackLayerBridge::with_tick_interval`], stopped when the
+/// bridge is shut down or dropped.
+pub(crate) struct Ticker {
+    stop: Arc<StopSignal>,
+    thread: JoinHandle<()>,
+}
+
+impl Ticker {
+    /// Starts calling the Python layer's `on_tick(stats)` every `interval`,
+    /// with the bridge's counters like [`crate::get_bridge_stats`] returns
+    /// them, and its `on_metrics(metrics)` if it has one, with the metrics like
+    /// [`crate::get_metrics`] returns them.
+    pub(crate) fn start(
+        callbacks: Arc<RwLock<Arc<Callbacks>>>,
+        stats: Arc<BridgeStats>,
+        metrics: Arc<Metrics>,
+        interval: Duration,
+    ) -> Ticker {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread = thread::Builder::new()
+            .name("python-tracing-tick".to_owned())
+            .spawn({
+                let stop = stop.clone();
+                move || run(&callbacks, &stats, &metrics, interval, &stop)
+            })
+            .expect("failed to spawn the tick thread");
+        Ticker { stop, thread }
+    }
+
+    pub(crate) fn stop(&self) {
+        let (stopped, condvar) = &*self.stop;
+        *stopped.lock().unwrap_or_else(PoisonError::into_inner) = true;
+        condvar.notify_all();
+    }
+
+    pub(crate) fn is_alive(&self) -> bool {
+        !self.thread.is_finished()
+    }
+}
+
+impl Drop for Ticker {
+    fn drop(&mut self) {
+        // The thread may be waiting for the GIL, which the dropping thread may
+        // hold, so it isn't joined.
+        self.stop();
+    }
+}
+
+fn run(
+    callbacks: &RwLock<Arc<Callbacks>>,
+    stats: &BridgeStats,
+    metrics: &Metrics,
+    interval: Duration,
+    stop: &StopSignal,
+) {
+    let (stopped, condvar) = stop;
+    loop {
+        let guard = stopped.lock().unwrap_or_else(PoisonError::into_inner);
+        let (guard, _) = condvar
+            .wait_timeout_while(guard, interval, |stopped| !*stopped)
+            .unwrap_or_else(PoisonError::into_inner);
+        if *guard {
+            return;
+        }
+        drop(guard);
+
+        Python::with_gil(|py| {
+            let py_impl = Callbacks::cached(callbacks).py_impl.clone_ref(py);
+            let py_impl = py_impl.bind(py);
+            if let Ok(on_tick) = py_impl.getattr(intern!(py, "on_tick")) {
+                match stats
+                    .to_py_dict(py)
+                    .and_then(|stats| on_tick.call1((stats,)))
+                {
+                    Ok(returned) => stats.pressure().signal(&returned),
+                    Err(err) => {
+                        stats.record_error(py, "on_tick", &err);
+                        tracing::warn!(target: internal::TARGET, error = %err, "on_tick raised");
+                    }
+                }
+            }
+            if let Ok(on_metrics) = py_impl.getattr(intern!(py, "on_metrics")) {
+                if let Err(err) = metrics
+                    .to_py_list(py)
+                    .and_then(|metrics| on_metrics.call1((metrics,)))
+                {
+                    stats.record_error(py, "on_metrics", &err);
+                    tracing::warn!(target: internal::TARGET, error = %err, "on_metrics raised");
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use pyo3::types::PyList;
+
+    use super::*;
+    use crate::{tests::prepare_python, PythonCallbackLayerBridge};
+
+    fn wait_until(condition: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !condition() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_on_tick() {
+        prepare_python();
+        let (bridge, ticks) = Python::with_gil(|py| {
+            let module = PyModule::from_code_bound(
+                py,
+                "
+class TickingLayer:
+    def __init__(self):
+        self.ticks = []
+
+    def on_tick(self, stats):
+        self.ticks.append(stats)
+",
+                "ticking_layer.py",
+                "ticking_layer",
+            )
+            .unwrap();
+            let py_layer = module.getattr("TickingLayer").unwrap().call0().unwrap();
+            let ticks = py_layer.getattr("ticks").unwrap().unbind();
+            let bridge = PythonCallbackLayerBridge::new(py_layer)
+                .with_tick_interval(Duration::from_millis(10));
+            (bridge, ticks)
+        });
+
+        wait_until(|| Python::with_gil(|py| ticks.bind(py).len().unwrap() >= 2));
+        Python::with_gil(|py| {
+            let ticks = ticks.bind(py).downcast::<PyList>().unwrap();
+            assert!(ticks.len() >= 2);
+            assert!(ticks.get_item(0).unwrap().get_item("errors").is_ok());
+        });
+
+        bridge.shutdown().unwrap();
+        let ticker = bridge.ticker.as_ref().unwrap();
+        wait_until(|| !ticker.is_alive());
+        assert!(!ticker.is_alive());
+    }
+}
@@ -0,0 +1,98 @@
+//! `i128` and `u128` fields as exact numbers, which `tracing-serde` otherwise
+//! passes as their `Debug` strings, using `serde_json`'s `arbitrary_precision`
+//! feature.
+
+use std::fmt;
+
+use pyo3::{prelude::*, types::PyLong};
+use serde_json::Number;
+use tracing_core::field::{Field, Visit};
+use tracing_subscriber::field::RecordFields;
+
+use crate::log_bridge::LOG_FIELDS;
+
+/// A [`Visit`] that only collects the fields recorded as 128-bit integers.
+#[derive(Default)]
+struct WideInts(Vec<(&'static str, Number)>);
+
+impl Visit for WideInts {
+    fn record_i128(&mut self, field: &Field, value: i128) {
+        let number = Number::from_i128(value).expect("arbitrary precision numbers hold any i128");
+        self.0.push((field.name(), number));
+    }
+
+    fn record_u128(&mut self, field: &Field, value: u128) {
+        let number = Number::from_u128(value).expect("arbitrary precision numbers hold any u128");
+        self.0.push((field.name(), number));
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn fmt::Debug) {}
+}
+
+/// Returns the fields of `fields` recorded as 128-bit integers.
+pub(crate) fn wide_ints(fields: &impl RecordFields) -> Vec<(&'static str, Number)> {
+    let mut wide_ints = WideInts::default();
+    fields.record(&mut wide_ints);
+    wide_ints.0.retain(|(name, _)| !LOG_FIELDS.contains(name));
+    wide_ints.0
+}
+
+/// Replaces the strings that `tracing-serde` serializes fields recorded as
+/// 128-bit integers as in `payload` with the numbers.
+pub(crate) fn insert_wide_ints(payload: &mut serde_json::Value, fields: &impl RecordFields) {
+    for (name, number) in wide_ints(fields) {
+        payload[name] = number.into();
+    }
+}
+
+/// Converts an integer `number` too wide for 64 bits to a Python `int`, or
+/// returns `None` if it has a fraction or exponent.
+pub(crate) fn wide_int_to_py(py: Python<'_>, number: &Number) -> Option<PyObject> {
+    let digits = number.as_str();
+    if !digits
+        .bytes()
+        .all(|byte| byte == b'-' || byte.is_ascii_digit())
+    {
+        return None;
+    }
+    let int = py.get_type_bound::<PyLong>().call1((digits,)).ok()?;
+    Some(int.unbind())
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::prelude::*;
+    use serde_json::json;
+    use tracing::{info, info_span};
+
+    use crate::{tests::initialize_tracing_with, PayloadMode};
+
+    #[test]
+    fn test_wide_ints() {
+        for payload_mode in [
+            PayloadMode::Json,
+            PayloadMode::Dict,
+            PayloadMode::StructlogEventDict,
+        ] {
+            let (py_layer, _dispatcher) =
+                initialize_tracing_with(|bridge| bridge.with_payload_mode(payload_mode));
+
+            info_span!("scope")
+                .in_scope(|| info!(id = u128::MAX, offset = i128::MIN, small = 7_u128, "wide"));
+
+            Python::with_gil(|py| {
+                let borrowed = py_layer.borrow(py);
+                let fields = &borrowed.event_fields[0];
+                assert_eq!(
+                    "340282366920938463463374607431768211455",
+                    fields["id"].to_string()
+                );
+                assert_eq!(
+                    "-170141183460469231731687303715884105728",
+                    fields["offset"].to_string()
+                );
+                assert_eq!(json!(7), fields["small"]);
+            });
+        }
+    }
+}